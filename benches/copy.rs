@@ -0,0 +1,62 @@
+//! Compares the standard copier (`fs::copy`) with the io_uring copier of the native backend.
+//!
+//! Run with `cargo bench --features io-uring -- [DIRECTORY] [SIZE_MIB]`. Point `DIRECTORY` at the
+//! share to measure, it defaults to the temporary directory; `SIZE_MIB` defaults to 256.
+
+use netfs_unlker::backend::{FileOps, NativeFs};
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Number of copies per copier; the fastest one counts.
+const ROUNDS: u32 = 5;
+
+fn main() {
+    // `cargo bench` passes `--bench` to the benchmark
+    let args: Vec<String> = env::args()
+        .skip(1)
+        .filter(|a| !a.starts_with("--"))
+        .collect();
+    let base = args.first().map_or_else(env::temp_dir, PathBuf::from);
+    let size_mib: usize = args
+        .get(1)
+        .map_or(256, |s| s.parse().expect("SIZE_MIB is a number"));
+
+    let dir = tempfile::tempdir_in(&base).expect("failed to create the benchmark directory");
+    let source = dir.path().join("source");
+    let mut file = fs::File::create(&source).unwrap();
+    let chunk: Vec<u8> = (0..1024 * 1024).map(|i| (i % 251) as u8).collect();
+    for _ in 0..size_mib {
+        file.write_all(&chunk).unwrap();
+    }
+    file.sync_all().unwrap();
+
+    let target = dir.path().join("target");
+    let standard = measure(&source, &target, |from, to| fs::copy(from, to).unwrap());
+    let fs = NativeFs::default();
+    let uring = measure(&source, &target, |from, to| fs.copy(from, to).unwrap());
+
+    println!("{} MiB in {}", size_mib, base.display());
+    for (name, elapsed) in [("standard", standard), ("io_uring", uring)] {
+        println!(
+            "{:<10} {:>8.1} ms {:>8.1} MiB/s",
+            name,
+            elapsed.as_secs_f64() * 1000.0,
+            size_mib as f64 / elapsed.as_secs_f64()
+        );
+    }
+}
+
+fn measure(source: &Path, target: &Path, copy: impl Fn(&Path, &Path) -> u64) -> Duration {
+    (0..ROUNDS)
+        .map(|_| {
+            let _ = fs::remove_file(target);
+            let started = Instant::now();
+            copy(source, target);
+            started.elapsed()
+        })
+        .min()
+        .unwrap()
+}
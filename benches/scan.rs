@@ -0,0 +1,69 @@
+//! Compares the sequential scan with the fast scan, which probes each file with a single open and
+//! several files at a time.
+//!
+//! Run with `cargo bench --bench scan -- [DIRECTORY] [FILES] [JOBS]`. Point `DIRECTORY` at the share to
+//! measure, it defaults to the temporary directory; `FILES` defaults to 2000 and `JOBS` to 16.
+
+use netfs_unlker::backend::{NativeFs, NativeLocks};
+use netfs_unlker::scan::Scanner;
+use std::env;
+use std::fs;
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// Number of scans per mode; the fastest one counts.
+const ROUNDS: u32 = 3;
+
+fn main() {
+    // `cargo bench` passes `--bench` to the benchmark
+    let args: Vec<String> = env::args()
+        .skip(1)
+        .filter(|a| !a.starts_with("--"))
+        .collect();
+    let base = args.first().map_or_else(env::temp_dir, PathBuf::from);
+    let files: usize = args
+        .get(1)
+        .map_or(2000, |s| s.parse().expect("FILES is a number"));
+    let jobs: NonZeroUsize = args.get(2).map_or(NonZeroUsize::new(16).unwrap(), |s| {
+        s.parse().expect("JOBS is a positive number")
+    });
+
+    let dir = tempfile::tempdir_in(&base).expect("failed to create the benchmark directory");
+    for i in 0..files {
+        let subdirectory = dir.path().join(format!("{:03}", i % 100));
+        fs::create_dir_all(&subdirectory).unwrap();
+        fs::write(subdirectory.join(format!("{}.dat", i)), b"data").unwrap();
+    }
+
+    let scanner = Scanner::new(NativeFs::default(), NativeLocks::default());
+    let sequential = measure(|| {
+        let report = scanner.scan_directory(dir.path(), true).unwrap();
+        assert_eq!(report.scanned, files);
+    });
+    let fast = measure(|| {
+        let report = scanner.scan_directory_fast(dir.path(), true, jobs).unwrap();
+        assert_eq!(report.scanned, files);
+    });
+
+    println!("{} files in {}, {} jobs", files, base.display(), jobs);
+    for (name, elapsed) in [("sequential", sequential), ("fast", fast)] {
+        println!(
+            "{:<10} {:>8.1} ms {:>10.0} files/s",
+            name,
+            elapsed.as_secs_f64() * 1000.0,
+            files as f64 / elapsed.as_secs_f64()
+        );
+    }
+}
+
+fn measure(scan: impl Fn()) -> Duration {
+    (0..ROUNDS)
+        .map(|_| {
+            let started = Instant::now();
+            scan();
+            started.elapsed()
+        })
+        .min()
+        .unwrap()
+}
@@ -0,0 +1,775 @@
+//! # Agent Module
+//!
+//! This module contains the remote agent, a small REST API through which a central orchestrator scans
+//! and repairs paths on many NFS clients, and the `AgentClient` that drives it. It is available with the
+//! `agent` cargo feature.
+//!
+//! Every request carries the token of the agent as `Authorization: Bearer <token>`. The API is:
+//!
+//! * `GET /v1/health` answers the version of the agent: `{"ok": true, "version": "0.2.3"}`.
+//! * `POST /v1/scan` with `{"path": "/mnt/share", "recursive": true}` answers the lock inventory of the
+//!   path: `{"ok": true, "report": {...}}`.
+//! * `POST /v1/repair` with the same body repairs the path and streams its progress as JSON lines
+//!   (`application/x-ndjson`), one `AgentEvent` per line, ending with a `done` event holding the
+//!   summary or an `error` event.
+//!
+//! Other requests are answered with a 4xx status and `{"ok": false, "error": "..."}`. The agent only
+//! serves absolute paths that still lie below its roots once symbolic links are resolved, and speaks
+//! plain HTTP: beyond a trusted network, expose it behind a TLS-terminating proxy.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::path::Path;
+//! use netfs_unlker::agent::{AgentClient, AgentEvent};
+//!
+//! let client = AgentClient::new("http://nfs-client-17:7480", "s3cret").unwrap();
+//! let summary = client
+//!     .repair(Path::new("/mnt/share"), true, |event| {
+//!         if let AgentEvent::FileFailed { path, detail, .. } = event {
+//!             eprintln!("{}: {:?}", path, detail);
+//!         }
+//!     })
+//!     .unwrap();
+//! println!("{} repaired, {} failed", summary.repaired, summary.failed);
+//! ```
+
+use crate::progress::{ProgressEvent, ProgressObserver};
+use crate::report::{FileOutcome, RepairReport, ReportSummary};
+use crate::scan::ScanReport;
+use reqwest::blocking::{Client, Response};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+use tracing::{debug, info, warn};
+
+/// Default port of the agent.
+pub const DEFAULT_PORT: u16 = 7480;
+
+/// Default number of connections the agent serves at a time.
+pub const DEFAULT_MAX_CONNECTIONS: usize = 64;
+
+/// Largest accepted request body.
+const MAX_BODY_LEN: usize = 64 * 1024;
+
+/// Largest accepted request line and headers together.
+const MAX_HEADER_BYTES: u64 = 16 * 1024;
+
+/// Largest accepted number of request headers.
+const MAX_HEADERS: usize = 64;
+
+/// Time a client may take to send its request.
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Interval at which the server checks whether to keep running while no client connects.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Timeout of establishing a connection to an agent.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Body of the `scan` and `repair` requests.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PathRequest {
+    /// File or directory to operate on.
+    pub path: PathBuf,
+    /// Also operate on the files in subdirectories.
+    #[serde(default)]
+    pub recursive: bool,
+}
+
+/// Line of the progress stream of a repair.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum AgentEvent {
+    /// The agent started processing the file at `path`.
+    FileStarted { path: String },
+    /// The repair of the file entered the pipeline stage `stage`.
+    StageChanged { path: String, stage: String },
+    /// `bytes` more bytes of the file were copied.
+    BytesCopied { path: String, bytes: u64 },
+    /// The agent finished the file with an outcome that is not a failure, such as `repaired`.
+    FileFinished {
+        path: String,
+        outcome: String,
+        detail: Option<String>,
+        duration_secs: f64,
+    },
+    /// The repair of the file failed or timed out.
+    FileFailed {
+        path: String,
+        outcome: String,
+        detail: Option<String>,
+        duration_secs: f64,
+    },
+    /// The repair finished; the last event of a successful request.
+    Done { summary: ReportSummary },
+    /// The repair could not be run, e.g. because the path does not exist; the last event.
+    Error { error: String },
+}
+
+impl From<&ProgressEvent<'_>> for AgentEvent {
+    fn from(event: &ProgressEvent<'_>) -> Self {
+        let finished = |path: &Path, outcome: &FileOutcome, duration: Duration| {
+            (
+                path.to_string_lossy().into_owned(),
+                outcome.name().to_string(),
+                outcome.detail(),
+                duration.as_secs_f64(),
+            )
+        };
+        match *event {
+            ProgressEvent::FileStarted { path } => AgentEvent::FileStarted {
+                path: path.to_string_lossy().into_owned(),
+            },
+            ProgressEvent::StageChanged { path, stage } => AgentEvent::StageChanged {
+                path: path.to_string_lossy().into_owned(),
+                stage: stage.to_string(),
+            },
+            ProgressEvent::BytesCopied { path, bytes } => AgentEvent::BytesCopied {
+                path: path.to_string_lossy().into_owned(),
+                bytes,
+            },
+            ProgressEvent::FileFinished {
+                path,
+                outcome,
+                duration,
+            } => {
+                let (path, outcome, detail, duration_secs) = finished(path, outcome, duration);
+                AgentEvent::FileFinished {
+                    path,
+                    outcome,
+                    detail,
+                    duration_secs,
+                }
+            }
+            ProgressEvent::FileFailed {
+                path,
+                outcome,
+                duration,
+            } => {
+                let (path, outcome, detail, duration_secs) = finished(path, outcome, duration);
+                AgentEvent::FileFailed {
+                    path,
+                    outcome,
+                    detail,
+                    duration_secs,
+                }
+            }
+        }
+    }
+}
+
+/// Operations the agent serves, run on the thread of the request.
+///
+/// Implementations decide how scans and repairs are configured, e.g. with the repair options and
+/// the audit log of the command line.
+pub trait AgentHandler: Sync {
+    /// Inventories the locks of the file or directory at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the path does not exist or cannot be read.
+    fn scan(&self, path: &Path, recursive: bool) -> io::Result<ScanReport>;
+
+    /// Repairs the file or the files in the directory at `path`, reporting the progress to `progress`,
+    /// typically with `Repairer::with_progress_observer`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the path does not exist or cannot be read.
+    fn repair(
+        &self,
+        path: &Path,
+        recursive: bool,
+        progress: ProgressStream,
+    ) -> io::Result<RepairReport>;
+
+    /// Resolves a requested `path` to the path the handler operates on, which the agent checks against
+    /// its roots and then passes to `scan` or `repair`.
+    ///
+    /// The default implementation canonicalizes the path, resolving symbolic links.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the path does not exist or cannot be resolved; the request is refused.
+    fn resolve(&self, path: &Path) -> io::Result<PathBuf> {
+        fs::canonicalize(path)
+    }
+}
+
+impl<H: AgentHandler + ?Sized> AgentHandler for &H {
+    fn scan(&self, path: &Path, recursive: bool) -> io::Result<ScanReport> {
+        (**self).scan(path, recursive)
+    }
+
+    fn repair(
+        &self,
+        path: &Path,
+        recursive: bool,
+        progress: ProgressStream,
+    ) -> io::Result<RepairReport> {
+        (**self).repair(path, recursive, progress)
+    }
+
+    fn resolve(&self, path: &Path) -> io::Result<PathBuf> {
+        (**self).resolve(path)
+    }
+}
+
+/// Progress observer streaming the events of a repair to the client that requested it.
+///
+/// A client that disconnects does not stop the repair; the remaining events are dropped.
+pub struct ProgressStream {
+    stream: Arc<Mutex<TcpStream>>,
+}
+
+impl ProgressStream {
+    fn send(&self, event: &AgentEvent) {
+        let mut stream = self.stream.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = write_event(&mut *stream, event) {
+            debug!("Failed to stream a progress event: {}", e);
+        }
+    }
+}
+
+impl ProgressObserver for ProgressStream {
+    fn on_event(&self, event: &ProgressEvent<'_>) {
+        self.send(&AgentEvent::from(event));
+    }
+}
+
+/// Listening agent.
+pub struct AgentServer {
+    listener: TcpListener,
+    token: String,
+    roots: Vec<PathBuf>,
+    max_connections: usize,
+}
+
+impl AgentServer {
+    /// Binds the agent to `address`, accepting requests that carry `token`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` of kind `InvalidInput` if the token is empty, or any other `Err` if the address
+    /// cannot be bound.
+    pub fn bind(address: impl ToSocketAddrs, token: impl Into<String>) -> io::Result<Self> {
+        let token = token.into();
+        if token.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the agent token is empty",
+            ));
+        }
+        let listener = TcpListener::bind(address)?;
+        Ok(AgentServer {
+            listener,
+            token,
+            roots: Vec::new(),
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+        })
+    }
+
+    /// Only serves paths below one of `roots`; every absolute path is served if no roots are given.
+    /// Requested paths are compared after `AgentHandler::resolve`, so the roots have to be resolved
+    /// the same way, e.g. canonicalized with `fs::canonicalize`.
+    pub fn with_roots(mut self, roots: Vec<PathBuf>) -> Self {
+        self.roots = roots;
+        self
+    }
+
+    /// Serves at most `max_connections` connections at a time; further clients are answered with
+    /// `503 Service Unavailable` right away.
+    pub fn with_max_connections(mut self, max_connections: usize) -> Self {
+        self.max_connections = max_connections;
+        self
+    }
+
+    /// Returns the address the agent is listening on.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Serves requests with `handler`, each on its own thread, as long as `running` returns `true`,
+    /// then waits for the requests in progress to finish.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the listening socket cannot be polled.
+    pub fn serve<H: AgentHandler>(self, handler: H, running: impl Fn() -> bool) -> io::Result<()> {
+        self.listener.set_nonblocking(true)?;
+        info!("Agent listening on {}", self.listener.local_addr()?);
+        let connection = Connection {
+            handler,
+            token: self.token,
+            roots: self.roots,
+        };
+        let active = AtomicUsize::new(0);
+        // Leaving the scope waits for the requests in progress
+        thread::scope(|scope| {
+            while running() {
+                match self.listener.accept() {
+                    Ok((stream, peer)) if active.load(Ordering::SeqCst) >= self.max_connections => {
+                        warn!("Too many agent connections, refusing {}", peer);
+                        refuse_busy(stream);
+                    }
+                    Ok((stream, peer)) => {
+                        let (connection, active) = (&connection, &active);
+                        active.fetch_add(1, Ordering::SeqCst);
+                        scope.spawn(move || {
+                            connection.serve(stream, peer);
+                            active.fetch_sub(1, Ordering::SeqCst);
+                        });
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => thread::sleep(POLL_INTERVAL),
+                    Err(e) => warn!("Failed to accept an agent connection: {}", e),
+                }
+            }
+            debug!("Waiting for the agent requests in progress");
+        });
+        Ok(())
+    }
+}
+
+/// Settings shared by the connections of an agent.
+struct Connection<H> {
+    handler: H,
+    token: String,
+    roots: Vec<PathBuf>,
+}
+
+impl<H: AgentHandler> Connection<H> {
+    /// Answers the single request of a client.
+    fn serve(&self, stream: TcpStream, peer: SocketAddr) {
+        if let Err(e) = self.answer(stream, peer) {
+            debug!("Failed to answer the agent request of {}: {}", peer, e);
+        }
+    }
+
+    fn answer(&self, mut stream: TcpStream, peer: SocketAddr) -> io::Result<()> {
+        stream.set_nonblocking(false)?;
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        let request = match HttpRequest::read(&mut BufReader::new(&stream)) {
+            Ok(request) => request,
+            Err(e) if e.kind() == io::ErrorKind::InvalidInput => {
+                return respond_error(&mut stream, 431, e.to_string())
+            }
+            Err(e) => return respond_error(&mut stream, 400, e.to_string()),
+        };
+        debug!(
+            "Agent request from {}: {} {}",
+            peer, request.method, request.path
+        );
+
+        let authorized = request
+            .authorization
+            .as_deref()
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .is_some_and(|token| tokens_match(token.trim(), &self.token));
+        if !authorized {
+            warn!("Unauthorized agent request from {}", peer);
+            return respond_error(&mut stream, 401, "missing or invalid token".to_string());
+        }
+
+        match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/v1/health") => respond(
+                &mut stream,
+                200,
+                &serde_json::json!({ "ok": true, "version": env!("CARGO_PKG_VERSION") }),
+            ),
+            ("POST", "/v1/scan") => {
+                let Some(body) = self.path_request(&mut stream, &request.body)? else {
+                    return Ok(());
+                };
+                info!("Scan requested by {}: ({})", peer, body.path.display());
+                match self.handler.scan(&body.path, body.recursive) {
+                    Ok(report) => respond(
+                        &mut stream,
+                        200,
+                        &serde_json::json!({ "ok": true, "report": report }),
+                    ),
+                    Err(e) => respond_error(&mut stream, status_of(&e), e.to_string()),
+                }
+            }
+            ("POST", "/v1/repair") => {
+                let Some(body) = self.path_request(&mut stream, &request.body)? else {
+                    return Ok(());
+                };
+                info!("Repair requested by {}: ({})", peer, body.path.display());
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/x-ndjson\r\nConnection: close\r\n\r\n"
+                )?;
+                let progress = ProgressStream {
+                    stream: Arc::new(Mutex::new(stream)),
+                };
+                let shared = Arc::clone(&progress.stream);
+                let last = match self.handler.repair(&body.path, body.recursive, progress) {
+                    Ok(report) => AgentEvent::Done {
+                        summary: report.summary(),
+                    },
+                    Err(e) => AgentEvent::Error {
+                        error: e.to_string(),
+                    },
+                };
+                let mut stream = shared.lock().unwrap_or_else(|e| e.into_inner());
+                write_event(&mut *stream, &last)
+            }
+            (_, "/v1/health" | "/v1/scan" | "/v1/repair") => {
+                respond_error(&mut stream, 405, "method not allowed".to_string())
+            }
+            _ => respond_error(&mut stream, 404, "no such endpoint".to_string()),
+        }
+    }
+
+    /// Parses the body of a `scan` or `repair` request and checks that its path may be served.
+    ///
+    /// # Returns
+    ///
+    /// Returns the request with its path resolved, or `None` if the request was refused; the client
+    /// has been answered.
+    fn path_request(&self, stream: &mut TcpStream, body: &[u8]) -> io::Result<Option<PathRequest>> {
+        let mut request: PathRequest = match serde_json::from_slice(body) {
+            Ok(request) => request,
+            Err(e) => {
+                respond_error(stream, 400, format!("invalid request: {}", e))?;
+                return Ok(None);
+            }
+        };
+        let resolved = match self.resolve(&request.path) {
+            Ok(resolved) => resolved,
+            Err(e) => {
+                warn!(
+                    "Agent request for an unresolvable path refused ({}): {}",
+                    request.path.display(),
+                    e
+                );
+                respond_error(stream, status_of(&e), e.to_string())?;
+                return Ok(None);
+            }
+        };
+        if !self.is_served(&resolved) {
+            warn!(
+                "Agent request outside of the roots refused: ({})",
+                request.path.display()
+            );
+            respond_error(
+                stream,
+                403,
+                "path is outside of the agent roots".to_string(),
+            )?;
+            return Ok(None);
+        }
+        request.path = resolved;
+        Ok(Some(request))
+    }
+
+    /// Resolves a requested path through the handler; relative paths and paths with `..` components
+    /// are refused before they are resolved.
+    fn resolve(&self, path: &Path) -> io::Result<PathBuf> {
+        if !path.is_absolute()
+            || path
+                .components()
+                .any(|component| component == Component::ParentDir)
+        {
+            return Err(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "path is not absolute or contains `..`",
+            ));
+        }
+        self.handler.resolve(path)
+    }
+
+    fn is_served(&self, path: &Path) -> bool {
+        self.roots.is_empty() || self.roots.iter().any(|root| path.starts_with(root))
+    }
+}
+
+/// Request line, authorization and body of an HTTP request.
+struct HttpRequest {
+    method: String,
+    path: String,
+    authorization: Option<String>,
+    body: Vec<u8>,
+}
+
+impl HttpRequest {
+    /// Reads an HTTP/1.x request with an optional `Content-Length` body.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` of kind `InvalidInput` if the request line and headers are longer than
+    /// `MAX_HEADER_BYTES`, or of kind `InvalidData` if the request is malformed.
+    fn read(reader: &mut impl BufRead) -> io::Result<Self> {
+        let invalid =
+            |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+        let mut left = MAX_HEADER_BYTES;
+        let mut line = String::new();
+        read_head_line(reader, &mut line, &mut left)?;
+        let mut parts = line.split_whitespace();
+        let (Some(method), Some(target), Some(version)) =
+            (parts.next(), parts.next(), parts.next())
+        else {
+            return Err(invalid("malformed request line"));
+        };
+        if !version.starts_with("HTTP/1.") {
+            return Err(invalid("unsupported HTTP version"));
+        }
+        let method = method.to_string();
+        let path = target.split('?').next().unwrap_or_default().to_string();
+
+        let mut authorization = None;
+        let mut content_length = 0;
+        for _ in 0..=MAX_HEADERS {
+            if read_head_line(reader, &mut line, &mut left)? == 0 {
+                return Err(invalid("incomplete request"));
+            }
+            let header = line.trim_end();
+            if header.is_empty() {
+                let mut body = vec![0; content_length];
+                reader.read_exact(&mut body)?;
+                return Ok(HttpRequest {
+                    method,
+                    path,
+                    authorization,
+                    body,
+                });
+            }
+            let Some((name, value)) = header.split_once(':') else {
+                return Err(invalid("malformed header"));
+            };
+            let value = value.trim();
+            if name.eq_ignore_ascii_case("authorization") {
+                authorization = Some(value.to_string());
+            } else if name.eq_ignore_ascii_case("content-length") {
+                content_length = value
+                    .parse()
+                    .ok()
+                    .filter(|len| *len <= MAX_BODY_LEN)
+                    .ok_or_else(|| invalid("invalid or too large Content-Length"))?;
+            } else if name.eq_ignore_ascii_case("transfer-encoding") {
+                return Err(invalid("chunked request bodies are not supported"));
+            }
+        }
+        Err(invalid("too many headers"))
+    }
+}
+
+/// Reads the next line of the request line and headers into `line`, counting it against the `left`
+/// bytes they may still take.
+///
+/// # Errors
+///
+/// Returns an `Err` of kind `InvalidInput` if the line does not end within the bytes left.
+fn read_head_line(
+    reader: &mut impl BufRead,
+    line: &mut String,
+    left: &mut u64,
+) -> io::Result<usize> {
+    line.clear();
+    let read = (&mut *reader).take(*left).read_line(line)?;
+    *left -= read as u64;
+    if *left == 0 && !line.ends_with('\n') {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "request headers too large",
+        ));
+    }
+    Ok(read)
+}
+
+/// Answers a client over the connection limit with `503 Service Unavailable`, without waiting on it.
+fn refuse_busy(mut stream: TcpStream) {
+    // The short answer fits into the socket buffer, so a client that does not read cannot block
+    let _ = stream.set_nonblocking(true);
+    let _ = respond_error(&mut stream, 503, "too many connections".to_string());
+}
+
+/// Compares the token of a request in constant time, so it cannot be guessed byte by byte.
+fn tokens_match(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Maps the error of a scan to an HTTP status.
+fn status_of(e: &io::Error) -> u16 {
+    match e.kind() {
+        io::ErrorKind::NotFound => 404,
+        io::ErrorKind::PermissionDenied => 403,
+        _ => 500,
+    }
+}
+
+fn respond(stream: &mut TcpStream, status: u16, body: &serde_json::Value) -> io::Result<()> {
+    let body = body.to_string();
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        401 => "Unauthorized",
+        403 => "Forbidden",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        431 => "Request Header Fields Too Large",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    };
+    let challenge = match status {
+        401 => "WWW-Authenticate: Bearer\r\n",
+        _ => "",
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n{}",
+        status,
+        reason,
+        body.len(),
+        challenge,
+        body
+    )
+}
+
+fn respond_error(stream: &mut TcpStream, status: u16, error: String) -> io::Result<()> {
+    respond(
+        stream,
+        status,
+        &serde_json::json!({ "ok": false, "error": error }),
+    )
+}
+
+fn write_event(writer: &mut impl Write, event: &AgentEvent) -> io::Result<()> {
+    writeln!(writer, "{}", serde_json::to_string(event)?)?;
+    writer.flush()
+}
+
+/// Client of a remote agent.
+pub struct AgentClient {
+    client: Client,
+    base_url: String,
+    token: String,
+}
+
+impl AgentClient {
+    /// Creates a client of the agent at `base_url`, e.g. `http://nfs-client-17:7480`, authenticating
+    /// with `token`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the HTTP client cannot be set up.
+    pub fn new(base_url: impl Into<String>, token: impl Into<String>) -> io::Result<Self> {
+        // Repairs of large trees stream for a long time, so only connecting is timed out
+        let client = Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .timeout(None)
+            .build()
+            .map_err(io::Error::other)?;
+        Ok(AgentClient {
+            client,
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            token: token.into(),
+        })
+    }
+
+    /// Returns the version of the agent.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the agent cannot be reached or refuses the request.
+    pub fn health(&self) -> io::Result<String> {
+        let response = self.send(self.client.get(self.url("/v1/health")))?;
+        let body: serde_json::Value = response.json().map_err(io::Error::other)?;
+        Ok(body["version"].as_str().unwrap_or_default().to_string())
+    }
+
+    /// Inventories the locks of `path` on the agent and returns the report as JSON, as written by
+    /// `ScanReport::write_json`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the agent cannot be reached or refuses the request, or the path cannot be
+    /// scanned.
+    pub fn scan(&self, path: &Path, recursive: bool) -> io::Result<serde_json::Value> {
+        let request = self.client.post(self.url("/v1/scan")).json(&PathRequest {
+            path: path.to_path_buf(),
+            recursive,
+        });
+        let mut body: serde_json::Value = self.send(request)?.json().map_err(io::Error::other)?;
+        Ok(body["report"].take())
+    }
+
+    /// Repairs `path` on the agent, passing every progress event to `on_event` as it arrives, and
+    /// returns the summary of the repair.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the agent cannot be reached or refuses the request, the path cannot be
+    /// repaired, or the connection breaks off before the repair finished.
+    pub fn repair(
+        &self,
+        path: &Path,
+        recursive: bool,
+        mut on_event: impl FnMut(&AgentEvent),
+    ) -> io::Result<ReportSummary> {
+        let request = self.client.post(self.url("/v1/repair")).json(&PathRequest {
+            path: path.to_path_buf(),
+            recursive,
+        });
+        let response = self.send(request)?;
+        for line in BufReader::new(response).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let event: AgentEvent = serde_json::from_str(&line)?;
+            on_event(&event);
+            match event {
+                AgentEvent::Done { summary } => return Ok(summary),
+                AgentEvent::Error { error } => return Err(io::Error::other(error)),
+                _ => {}
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "the agent closed the connection before the repair finished",
+        ))
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    /// Sends a request with the token and turns an error status into an `Err`.
+    fn send(&self, request: reqwest::blocking::RequestBuilder) -> io::Result<Response> {
+        let response = request
+            .bearer_auth(&self.token)
+            .send()
+            .map_err(io::Error::other)?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response);
+        }
+
+        let error = response
+            .json::<serde_json::Value>()
+            .ok()
+            .and_then(|body| body["error"].as_str().map(str::to_string))
+            .unwrap_or_else(|| status.to_string());
+        let kind = match status {
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => io::ErrorKind::PermissionDenied,
+            StatusCode::NOT_FOUND => io::ErrorKind::NotFound,
+            StatusCode::BAD_REQUEST => io::ErrorKind::InvalidInput,
+            _ => io::ErrorKind::Other,
+        };
+        Err(io::Error::new(kind, error))
+    }
+}
@@ -0,0 +1,291 @@
+//! # Audit Module
+//!
+//! This module contains the `AuditSink` trait the repair engine reports every repair attempt to,
+//! and `JsonLinesAuditLog`, an append-only audit log with one JSON object per line.
+//! Every line carries the SHA-256 hash of the previous line, so removing or editing a record
+//! breaks the chain and is detected by `verify_audit_log`.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::path::Path;
+//! use netfs_unlker::audit::JsonLinesAuditLog;
+//! use netfs_unlker::backend::{NativeFs, NativeLocks};
+//! use netfs_unlker::{RepairOptions, Repairer};
+//!
+//! let audit_log = JsonLinesAuditLog::open(Path::new("/var/log/netfs-unlker/audit.jsonl")).unwrap();
+//! let repairer = Repairer::new(NativeFs::default(), NativeLocks::default(), RepairOptions::default())
+//!     .with_audit_sink(audit_log);
+//! let report = repairer.repair_directory(Path::new("/mnt/share"), true);
+//! ```
+
+use crate::backend::LockInfo;
+use crate::format::{serialize_optional_path, serialize_path, serialize_time};
+use serde::{Serialize, Serializer};
+use sha2::{Digest, Sha256};
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// Hash chained to the first record of a log.
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Result of a repair attempt, as recorded in the audit trail.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditOutcome {
+    /// The file was repaired and verified.
+    Repaired,
+    /// The file was replaced, but the verification of the end state failed.
+    Unverified,
+    /// The repair failed; the original file was kept.
+    Failed,
+    /// The repair failed repeatedly and the file was moved to the quarantine directory.
+    Quarantined,
+    /// The repair was given up on after the per-file timeout.
+    #[serde(rename = "timed_out")]
+    TimedOut,
+}
+
+/// State of the original file before the repair.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct OriginalFile {
+    /// Inode number, if the platform provides it.
+    pub inode: Option<u64>,
+    /// Size in bytes.
+    pub size: u64,
+    /// Last modification time.
+    #[serde(serialize_with = "serialize_optional_time")]
+    pub mtime: Option<SystemTime>,
+    /// Hex-encoded SHA-256 checksum of the content, if the file was copied.
+    pub checksum: Option<String>,
+}
+
+/// Audit record of a single repair attempt.
+#[derive(Debug, Clone, Serialize)]
+pub struct AuditRecord {
+    /// Time the repair attempt finished.
+    #[serde(serialize_with = "serialize_time")]
+    pub timestamp: SystemTime,
+    /// Path of the repaired file.
+    #[serde(serialize_with = "serialize_path")]
+    pub path: PathBuf,
+    /// State of the file before the repair, if it could be read.
+    pub original: Option<OriginalFile>,
+    /// Inode number of the file after the repair, if known.
+    pub new_inode: Option<u64>,
+    /// Lock that was held on the file, if it could be queried.
+    pub lock: Option<LockInfo>,
+    /// Path the file was moved to, if it was quarantined.
+    #[serde(serialize_with = "serialize_optional_path")]
+    pub quarantined_to: Option<PathBuf>,
+    /// Result of the attempt.
+    pub outcome: AuditOutcome,
+    /// Error or verification failure for unsuccessful attempts.
+    pub detail: Option<String>,
+}
+
+/// Receiver of the audit records written by the repair engine.
+///
+/// A record is written for every locked file the engine tried to repair; files that were not
+/// locked or were skipped are not audited.
+pub trait AuditSink {
+    /// Persists a record. An `Err` is logged by the engine and does not fail the repair.
+    fn record(&self, record: &AuditRecord) -> io::Result<()>;
+}
+
+/// Shares a sink between several repairers, which then write to the same log.
+impl<T: AuditSink + ?Sized> AuditSink for Arc<T> {
+    fn record(&self, record: &AuditRecord) -> io::Result<()> {
+        (**self).record(record)
+    }
+}
+
+/// Append-only audit log writing one hash-chained JSON object per line.
+///
+/// Records are appended under an exclusive lock on the file, and the chain continues from the last
+/// line in the file at that time, so several processes can write to the same log.
+#[derive(Debug)]
+pub struct JsonLinesAuditLog {
+    state: Mutex<LogState>,
+}
+
+#[derive(Debug)]
+struct LogState {
+    file: File,
+    last_hash: String,
+    /// Length of the log after `last_hash` was read or written; another length means another
+    /// writer appended to the log in the meantime.
+    len: u64,
+}
+
+/// A record as written to the log, followed by the hash of the previous line.
+#[derive(Serialize)]
+struct ChainedRecord<'a> {
+    #[serde(flatten)]
+    record: &'a AuditRecord,
+    prev_hash: &'a str,
+}
+
+impl JsonLinesAuditLog {
+    /// Opens the audit log at `path` for appending, creating it if it does not exist.
+    ///
+    /// The hash chain continues from the last line of an existing log, which is read when the
+    /// first record is written.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the log cannot be opened.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .read(true)
+            .open(path)?;
+
+        Ok(JsonLinesAuditLog {
+            state: Mutex::new(LogState {
+                file,
+                last_hash: GENESIS_HASH.to_string(),
+                len: 0,
+            }),
+        })
+    }
+}
+
+impl AuditSink for JsonLinesAuditLog {
+    fn record(&self, record: &AuditRecord) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        // Keeps other writers of the log out between reading the chain head and appending
+        state.file.lock()?;
+        let appended = state.append(record);
+        let unlocked = state.file.unlock();
+        appended.and(unlocked)
+    }
+}
+
+impl LogState {
+    /// Appends `record`, chained to the last line of the log.
+    fn append(&mut self, record: &AuditRecord) -> io::Result<()> {
+        if self.file.metadata()?.len() != self.len {
+            self.read_last_hash()?;
+        }
+        let line = serde_json::to_string(&ChainedRecord {
+            record,
+            prev_hash: &self.last_hash,
+        })?;
+
+        writeln!(self.file, "{}", line)?;
+        self.file.sync_data()?;
+        self.last_hash = hash_line(&line);
+        self.len = self.file.metadata()?.len();
+        Ok(())
+    }
+
+    /// Reads the hash of the last line of the log and the length of the log.
+    fn read_last_hash(&mut self) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(0))?;
+        let mut last_hash = GENESIS_HASH.to_string();
+        let mut len = 0;
+        let mut reader = BufReader::new(&self.file);
+        let mut line = String::new();
+        loop {
+            line.clear();
+            match reader.read_line(&mut line)? {
+                0 => break,
+                read => len += read as u64,
+            }
+            let line = line.trim_end_matches('\n');
+            if !line.is_empty() {
+                last_hash = hash_line(line);
+            }
+        }
+        self.last_hash = last_hash;
+        self.len = len;
+        Ok(())
+    }
+}
+
+/// Checks the hash chain of an audit log written by `JsonLinesAuditLog`.
+///
+/// # Returns
+///
+/// Returns `Ok(None)` if the chain is intact, or `Ok(Some(line))` with the 1-based number of the
+/// first line whose `prev_hash` does not match the preceding line.
+///
+/// # Errors
+///
+/// Returns an `Err` if the log cannot be read.
+pub fn verify_audit_log(path: &Path) -> io::Result<Option<usize>> {
+    let mut expected = GENESIS_HASH.to_string();
+    for (index, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+
+        let prev_hash = serde_json::from_str::<serde_json::Value>(&line)
+            .ok()
+            .and_then(|value| value["prev_hash"].as_str().map(str::to_string));
+        if prev_hash.as_deref() != Some(expected.as_str()) {
+            return Ok(Some(index + 1));
+        }
+        expected = hash_line(&line);
+    }
+    Ok(None)
+}
+
+/// A file moved to the quarantine directory, as recorded in an audit log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuarantinedFile {
+    /// Original path of the file.
+    pub path: PathBuf,
+    /// Path of the file in the quarantine directory.
+    pub destination: PathBuf,
+}
+
+/// Lists the quarantined files recorded in an audit log written by `JsonLinesAuditLog`, oldest first.
+///
+/// Lines that cannot be parsed are skipped.
+///
+/// # Errors
+///
+/// Returns an `Err` if the log cannot be read.
+pub fn quarantined_files(path: &Path) -> io::Result<Vec<QuarantinedFile>> {
+    let mut files = Vec::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        let value = match serde_json::from_str::<serde_json::Value>(&line?) {
+            Ok(value) if value["outcome"] == "quarantined" => value,
+            _ => continue,
+        };
+        if let (Some(path), Some(destination)) =
+            (value["path"].as_str(), value["quarantined_to"].as_str())
+        {
+            files.push(QuarantinedFile {
+                path: PathBuf::from(path),
+                destination: PathBuf::from(destination),
+            });
+        }
+    }
+    Ok(files)
+}
+
+/// Computes the hex-encoded SHA-256 hash of a log line.
+fn hash_line(line: &str) -> String {
+    Sha256::digest(line.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn serialize_optional_time<S: Serializer>(
+    time: &Option<SystemTime>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match time {
+        Some(time) => serialize_time(time, serializer),
+        None => serializer.serialize_none(),
+    }
+}
@@ -0,0 +1,664 @@
+//! # Backend Module
+//!
+//! This module contains the `FileOps` and `LockOps` traits the repair engine is generic over,
+//! together with the native implementations: POSIX `fcntl` locks on Unix and `LockFileEx` on Windows.
+//! `NativeFs` and `NativeLocks` name the implementation for the current platform.
+//! Alternative backends (for example the in-memory `mock` backend) implement the same traits,
+//! which allows the repair pipeline to run without a real network mount.
+
+#[cfg(unix)]
+mod posix;
+#[cfg(windows)]
+mod windows;
+
+#[cfg(unix)]
+pub use posix::{PosixFs, PosixLocks};
+#[cfg(windows)]
+pub use windows::{WindowsFs, WindowsLocks};
+
+use crate::deadline;
+use crate::throttle::{Throttle, ThrottledReader};
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// `FileOps` implementation for the current platform.
+#[cfg(unix)]
+pub type NativeFs = PosixFs;
+/// `LockOps` implementation for the current platform.
+#[cfg(unix)]
+pub type NativeLocks = PosixLocks;
+/// `FileOps` implementation for the current platform.
+#[cfg(windows)]
+pub type NativeFs = WindowsFs;
+/// `LockOps` implementation for the current platform.
+#[cfg(windows)]
+pub type NativeLocks = WindowsLocks;
+
+/// Kind of a filesystem entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileKind {
+    /// A regular file.
+    File,
+    /// A directory.
+    Directory,
+    /// Anything else (sockets, devices, fifos, ...).
+    Other,
+}
+
+/// Metadata of a filesystem entry, as needed by the repair engine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileMetadata {
+    /// Kind of the entry. Symbolic links are followed.
+    pub kind: FileKind,
+    /// Size of the entry in bytes.
+    pub len: u64,
+    /// Last modification time, if the platform provides it.
+    pub modified: Option<SystemTime>,
+    /// Inode number, if the platform provides it. A replaced file gets a new inode.
+    pub inode: Option<u64>,
+    /// Device the entry lives on, if the platform provides it. Each mounted filesystem has its own.
+    pub device: Option<u64>,
+}
+
+/// Format of an access control list.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AclKind {
+    /// An NFSv4 ACL, as exposed by the Linux NFS client in the `system.nfs4_acl` attribute.
+    Nfs4,
+    /// A POSIX draft ACL, as exposed in the `system.posix_acl_access` attribute.
+    Posix,
+}
+
+/// Access control list of a file, carried over from the original to the repaired file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Acl {
+    /// Format of the list.
+    pub kind: AclKind,
+    /// Encoded list as read from the filesystem, opaque to the repair engine.
+    pub data: Vec<u8>,
+}
+
+/// Type of a held lock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LockType {
+    /// A shared (read) lock.
+    Shared,
+    /// An exclusive (write) lock.
+    Exclusive,
+}
+
+/// Mechanism behind a held lock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LockKind {
+    /// A byte-range record lock (`fcntl` locally, NLM or NFSv4 locks on NFS, `LockFileEx` on Windows).
+    #[default]
+    Record,
+    /// An SMB lease or oplock held by another client of a CIFS mount. Leases cover the whole file and
+    /// are invisible to the record lock probe; they are only looked for through `LockOps::is_leased`.
+    SmbLease,
+}
+
+/// Kind of the filesystem a path lives on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FilesystemKind {
+    /// A network filesystem such as NFS or SMB/CIFS.
+    Network,
+    /// A local filesystem such as ext4 or xfs.
+    Local,
+    /// The filesystem type could not be determined.
+    Unknown,
+}
+
+/// Locking mode in effect for a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LockingMode {
+    /// Locks are advisory; reading a locked file does not block.
+    Advisory,
+    /// Locks are enforced by the kernel; reading a locked region blocks or fails.
+    Mandatory,
+}
+
+/// Entries of a directory, read lazily so large directories are never held in memory at once.
+pub type DirEntries<'a> = Box<dyn Iterator<Item = io::Result<PathBuf>> + 'a>;
+
+/// Description of a lock held on a file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LockInfo {
+    /// Mechanism behind the lock.
+    pub kind: LockKind,
+    /// Type of the lock.
+    pub lock_type: LockType,
+    /// Offset of the first locked byte.
+    pub start: u64,
+    /// Number of locked bytes, or `None` if the lock extends to the end of the file.
+    pub len: Option<u64>,
+    /// PID of the lock holder, if known. Remote holders on NFS are usually reported without a PID.
+    pub pid: Option<u32>,
+}
+
+impl LockInfo {
+    /// Describes an SMB lease found by `LockOps::is_leased`, which covers the whole file.
+    pub(crate) fn smb_lease() -> Self {
+        LockInfo {
+            kind: LockKind::SmbLease,
+            lock_type: LockType::Exclusive,
+            start: 0,
+            len: None,
+            pid: None,
+        }
+    }
+}
+
+/// File operations used by the repair engine.
+pub trait FileOps {
+    /// Returns the metadata of the entry at `path`, following symbolic links.
+    fn metadata(&self, path: &Path) -> io::Result<FileMetadata>;
+
+    /// Returns the metadata of the entry at `path` without following a symbolic link, which is
+    /// reported as `FileKind::Other`.
+    ///
+    /// The default implementation suits backends without symbolic links and returns `metadata`.
+    fn symlink_metadata(&self, path: &Path) -> io::Result<FileMetadata> {
+        self.metadata(path)
+    }
+
+    /// Returns the paths of the entries of the directory at `path`, read as the iterator advances.
+    fn read_dir(&self, path: &Path) -> io::Result<DirEntries<'_>>;
+
+    /// Opens the file at `path` for reading.
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + '_>>;
+
+    /// Copies the content of `from` into `to`, returning the number of bytes copied.
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<u64>;
+
+    /// Copies like `copy`, but reads `from` without blocking on mandatory locks,
+    /// retrying until `timeout` expires.
+    ///
+    /// The default implementation falls back to `copy`.
+    fn copy_nonblocking(&self, from: &Path, to: &Path, timeout: Duration) -> io::Result<u64> {
+        let _ = timeout;
+        self.copy(from, to)
+    }
+
+    /// Copies like `copy`, pacing the bytes copied with `throttle`.
+    ///
+    /// The default implementation copies with `copy` and charges the bytes to `throttle` afterwards.
+    fn copy_throttled(&self, from: &Path, to: &Path, throttle: &Throttle) -> io::Result<u64> {
+        let copied = self.copy(from, to)?;
+        throttle.acquire(copied);
+        Ok(copied)
+    }
+
+    /// Writes `contents` into a new file at `to`, with the permissions of the file `like`,
+    /// returning the number of bytes written.
+    ///
+    /// The default implementation reports `Unsupported`, upon which the engine stages the copy on disk.
+    fn write_like(&self, to: &Path, contents: &[u8], like: &Path) -> io::Result<u64> {
+        let _ = (to, contents, like);
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    /// Reads the access control list of the file at `path`, or `None` if the file has none beyond
+    /// its permission bits.
+    ///
+    /// The default implementation reports `Unsupported`, upon which the engine does not carry ACLs over.
+    fn read_acl(&self, path: &Path) -> io::Result<Option<Acl>> {
+        let _ = path;
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    /// Replaces the access control list of the file at `path` with `acl`.
+    ///
+    /// The default implementation reports `Unsupported`.
+    fn write_acl(&self, path: &Path, acl: &Acl) -> io::Result<()> {
+        let _ = (path, acl);
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    /// Atomically renames `from` to `to`, replacing `to` if it exists.
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    /// Removes the file at `path`.
+    fn remove_file(&self, path: &Path) -> io::Result<()>;
+
+    /// Flushes the content and metadata of the file at `path` to stable storage.
+    ///
+    /// The default implementation does nothing.
+    fn sync_file(&self, _path: &Path) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Flushes the entries of the directory at `path` to stable storage, so a file created or
+    /// renamed in it survives a crash.
+    ///
+    /// The default implementation does nothing.
+    fn sync_dir(&self, _path: &Path) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Returns the kind of the filesystem the entry at `path` lives on.
+    ///
+    /// The default implementation reports an unknown filesystem.
+    fn filesystem_kind(&self, _path: &Path) -> io::Result<FilesystemKind> {
+        Ok(FilesystemKind::Unknown)
+    }
+
+    /// Creates a fresh local directory used to stage copies of locked files.
+    fn create_staging_dir(&self) -> io::Result<PathBuf>;
+
+    /// Removes a staging directory created by `create_staging_dir` together with its content.
+    fn remove_staging_dir(&self, path: &Path) -> io::Result<()>;
+}
+
+/// What a single probe of a file found, for inventories that need the size and the lock of every file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileProbe {
+    /// Kind of the entry. Symbolic links are not followed and count as `FileKind::Other`.
+    pub kind: FileKind,
+    /// Size of the entry in bytes.
+    pub len: u64,
+    /// A lock held on the file, or `None` if it is not locked. Only regular files are probed.
+    pub lock: Option<LockInfo>,
+    /// Locking mode in effect for the file; only determined for locked files, advisory otherwise.
+    pub mode: LockingMode,
+}
+
+/// Lock operations used by the repair engine.
+pub trait LockOps {
+    /// Checks if the file at `path` is locked.
+    fn is_locked(&self, path: &Path) -> io::Result<bool>;
+
+    /// Unlocks the file at `path`.
+    fn unlock(&self, path: &Path) -> io::Result<()>;
+
+    /// Returns a lock held on the file at `path`, or `None` if the file is not locked.
+    fn lock_info(&self, path: &Path) -> io::Result<Option<LockInfo>>;
+
+    /// Returns all locks held on the file at `path`, ordered by their start offset.
+    ///
+    /// The default implementation reports the single lock returned by `lock_info`.
+    fn locked_ranges(&self, path: &Path) -> io::Result<Vec<LockInfo>> {
+        Ok(self.lock_info(path)?.into_iter().collect())
+    }
+
+    /// Unlocks `len` bytes of the file at `path` starting at `start`; `None` as `len` means until EOF.
+    ///
+    /// The native implementations can only release the locks of the current process, not those of
+    /// other processes or clients. The default implementation reports the operation as unsupported.
+    fn unlock_range(&self, _path: &Path, _start: u64, _len: Option<u64>) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    /// Returns the locking mode in effect for the file at `path`.
+    ///
+    /// The default implementation reports advisory locking.
+    fn locking_mode(&self, _path: &Path) -> io::Result<LockingMode> {
+        Ok(LockingMode::Advisory)
+    }
+
+    /// Checks whether another client seems to hold an SMB lease on the file at `path`, which neither
+    /// `is_locked` nor `lock_info` report. The probe cannot tell a lease held elsewhere from a mount
+    /// that holds no caching oplock for the file, so it is only used to decide on a lease break.
+    ///
+    /// The default implementation reports the file as not leased.
+    fn is_leased(&self, _path: &Path) -> io::Result<bool> {
+        Ok(false)
+    }
+
+    /// Asks the server to break the SMB leases other clients hold on the file at `path`, waiting until
+    /// the holders flushed their cached writes and gave the leases up.
+    ///
+    /// The default implementation reports the operation as unsupported.
+    fn break_lease(&self, _path: &Path) -> io::Result<()> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    /// Checks whether the holder of `lock` on the file at `path` is still alive, so replacing the file
+    /// could corrupt work in progress.
+    ///
+    /// The default implementation cannot tell and reports the holder as gone.
+    fn is_holder_alive(&self, _path: &Path, _lock: &LockInfo) -> io::Result<bool> {
+        Ok(false)
+    }
+
+    /// Probes the entry at `path` for its kind, size and lock with as few round trips to the server as
+    /// possible, as a combination of `FileOps::metadata`, `lock_info` and `locking_mode` would take.
+    ///
+    /// The default implementation reports the operation as unsupported, upon which those are called
+    /// one after the other.
+    fn probe(&self, _path: &Path) -> io::Result<FileProbe> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+/// Status of the clients owning the locks on a file, as known to the server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HolderStatus {
+    /// An owning client is still registered with the server, so the lock may be in use.
+    Live,
+    /// The owning clients are gone, e.g. NFSv3 clients that rebooted without releasing their NLM locks.
+    Stale,
+    /// The server cannot tell, e.g. because it does not report the lock or its owner.
+    Unknown,
+}
+
+impl HolderStatus {
+    /// Returns the name of the status as used in the logs.
+    pub fn name(&self) -> &'static str {
+        match self {
+            HolderStatus::Live => "live",
+            HolderStatus::Stale => "stale",
+            HolderStatus::Unknown => "unknown",
+        }
+    }
+}
+
+/// Server-side classification of lock owners, consulted by the repair engine before repairing a lock.
+///
+/// Implementations ask the NFS server (for example the ONTAP REST API) whether the clients owning the
+/// locks on a file are still registered. With a classification configured, only stale locks are
+/// repaired unless `RepairOptions::force` is set.
+pub trait LockStatusQuery {
+    /// Returns the status of the clients owning the locks on the file at `path`.
+    fn holder_status(&self, path: &Path) -> io::Result<HolderStatus>;
+}
+
+/// Server-side lock breaking, tried by the repair engine before the copy-based repair.
+///
+/// Implementations talk to the storage system that owns the file (for example the ONTAP REST API)
+/// and release the locks held on it without replacing the file.
+pub trait LockBreaker {
+    /// Breaks all locks held on the file at `path`, returning the number of locks that were broken.
+    ///
+    /// An `Err` means the server-side API is unavailable; the caller falls back to the copy-based repair.
+    fn break_locks(&self, path: &Path) -> io::Result<usize>;
+}
+
+impl<T: FileOps + ?Sized> FileOps for &T {
+    fn metadata(&self, path: &Path) -> io::Result<FileMetadata> {
+        (**self).metadata(path)
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> io::Result<FileMetadata> {
+        (**self).symlink_metadata(path)
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<DirEntries<'_>> {
+        (**self).read_dir(path)
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + '_>> {
+        (**self).open(path)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<u64> {
+        (**self).copy(from, to)
+    }
+
+    fn copy_nonblocking(&self, from: &Path, to: &Path, timeout: Duration) -> io::Result<u64> {
+        (**self).copy_nonblocking(from, to, timeout)
+    }
+
+    fn copy_throttled(&self, from: &Path, to: &Path, throttle: &Throttle) -> io::Result<u64> {
+        (**self).copy_throttled(from, to, throttle)
+    }
+
+    fn write_like(&self, to: &Path, contents: &[u8], like: &Path) -> io::Result<u64> {
+        (**self).write_like(to, contents, like)
+    }
+
+    fn read_acl(&self, path: &Path) -> io::Result<Option<Acl>> {
+        (**self).read_acl(path)
+    }
+
+    fn write_acl(&self, path: &Path, acl: &Acl) -> io::Result<()> {
+        (**self).write_acl(path, acl)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        (**self).rename(from, to)
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        (**self).remove_file(path)
+    }
+
+    fn sync_file(&self, path: &Path) -> io::Result<()> {
+        (**self).sync_file(path)
+    }
+
+    fn sync_dir(&self, path: &Path) -> io::Result<()> {
+        (**self).sync_dir(path)
+    }
+
+    fn filesystem_kind(&self, path: &Path) -> io::Result<FilesystemKind> {
+        (**self).filesystem_kind(path)
+    }
+
+    fn create_staging_dir(&self) -> io::Result<PathBuf> {
+        (**self).create_staging_dir()
+    }
+
+    fn remove_staging_dir(&self, path: &Path) -> io::Result<()> {
+        (**self).remove_staging_dir(path)
+    }
+}
+
+impl<T: LockOps + ?Sized> LockOps for &T {
+    fn is_locked(&self, path: &Path) -> io::Result<bool> {
+        (**self).is_locked(path)
+    }
+
+    fn unlock(&self, path: &Path) -> io::Result<()> {
+        (**self).unlock(path)
+    }
+
+    fn lock_info(&self, path: &Path) -> io::Result<Option<LockInfo>> {
+        (**self).lock_info(path)
+    }
+
+    fn locked_ranges(&self, path: &Path) -> io::Result<Vec<LockInfo>> {
+        (**self).locked_ranges(path)
+    }
+
+    fn unlock_range(&self, path: &Path, start: u64, len: Option<u64>) -> io::Result<()> {
+        (**self).unlock_range(path, start, len)
+    }
+
+    fn locking_mode(&self, path: &Path) -> io::Result<LockingMode> {
+        (**self).locking_mode(path)
+    }
+
+    fn is_leased(&self, path: &Path) -> io::Result<bool> {
+        (**self).is_leased(path)
+    }
+
+    fn break_lease(&self, path: &Path) -> io::Result<()> {
+        (**self).break_lease(path)
+    }
+
+    fn is_holder_alive(&self, path: &Path, lock: &LockInfo) -> io::Result<bool> {
+        (**self).is_holder_alive(path, lock)
+    }
+
+    fn probe(&self, path: &Path) -> io::Result<FileProbe> {
+        (**self).probe(path)
+    }
+}
+
+/// Reads the metadata of `path` through `std::fs`, shared by the native backends.
+fn std_metadata(path: &Path) -> io::Result<FileMetadata> {
+    let owned = path.to_path_buf();
+    deadline::run(move || fs::metadata(owned)).map(file_metadata)
+}
+
+/// Returns the metadata of the entry at `path` without following a symbolic link within the per-file
+/// deadline, shared by the native backends.
+fn std_symlink_metadata(path: &Path) -> io::Result<FileMetadata> {
+    let owned = path.to_path_buf();
+    deadline::run(move || fs::symlink_metadata(owned)).map(file_metadata)
+}
+
+/// Converts `std` metadata; symbolic links that were not followed count as `FileKind::Other`.
+fn file_metadata(metadata: fs::Metadata) -> FileMetadata {
+    let kind = if metadata.is_file() {
+        FileKind::File
+    } else if metadata.is_dir() {
+        FileKind::Directory
+    } else {
+        FileKind::Other
+    };
+
+    FileMetadata {
+        kind,
+        len: metadata.len(),
+        modified: metadata.modified().ok(),
+        inode: std_inode(&metadata),
+        device: std_device(&metadata),
+    }
+}
+
+/// Returns the inode number of a file on Unix.
+#[cfg(unix)]
+fn std_inode(metadata: &fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.ino())
+}
+
+/// Returns the inode number of a file; `std` does not expose the file index on Windows.
+#[cfg(not(unix))]
+fn std_inode(_metadata: &fs::Metadata) -> Option<u64> {
+    None
+}
+
+/// Returns the device a file lives on on Unix.
+#[cfg(unix)]
+fn std_device(metadata: &fs::Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.dev())
+}
+
+/// Returns the device a file lives on; `std` does not expose the volume serial number on Windows.
+#[cfg(not(unix))]
+fn std_device(_metadata: &fs::Metadata) -> Option<u64> {
+    None
+}
+
+/// Lists the entries of the directory at `path` through `std::fs`, shared by the native backends.
+/// Only opening the directory is bound by the per-file deadline, reading the entries is not.
+fn std_read_dir(path: &Path) -> io::Result<DirEntries<'static>> {
+    let path = path.to_path_buf();
+    let entries = deadline::run(move || fs::read_dir(path))?;
+    Ok(Box::new(entries.map(|entry| entry.map(|e| e.path()))))
+}
+
+/// Opens the file at `path` for reading through `std::fs`, shared by the native backends.
+/// Opening the file and every read are bound by the per-file deadline.
+fn std_open(path: &Path) -> io::Result<Box<dyn Read>> {
+    let path = path.to_path_buf();
+    let file = deadline::run(move || File::open(path))?;
+    Ok(Box::new(DeadlineReader {
+        file: Some(file),
+        chunk: Vec::new(),
+        pos: 0,
+    }))
+}
+
+/// Number of bytes a `DeadlineReader` reads from the file at a time.
+const READ_CHUNK: u64 = 1024 * 1024;
+
+/// Reader of a file opened by `std_open`, which reads the file in chunks of `READ_CHUNK` bytes
+/// within the per-file deadline.
+///
+/// The file moves to the worker thread for every chunk, so it is lost with a read that timed out,
+/// and the reads after it fail as well.
+struct DeadlineReader {
+    file: Option<File>,
+    chunk: Vec<u8>,
+    pos: usize,
+}
+
+impl Read for DeadlineReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos == self.chunk.len() {
+            let Some(mut file) = self.file.take() else {
+                return Err(deadline::timed_out());
+            };
+            let mut chunk = mem::take(&mut self.chunk);
+            let (file, chunk) = deadline::run(move || {
+                chunk.clear();
+                let read = (&mut file).take(READ_CHUNK).read_to_end(&mut chunk);
+                Ok((file, read.map(|_| chunk)))
+            })?;
+            self.file = Some(file);
+            self.chunk = chunk?;
+            self.pos = 0;
+        }
+        let read = (&self.chunk[self.pos..]).read(buf)?;
+        self.pos += read;
+        Ok(read)
+    }
+}
+
+/// Copies `from` into `to` through `std::fs`, streaming the content through `throttle`,
+/// shared by the native backends. The permissions of `from` are carried over like `fs::copy` does.
+fn std_copy_throttled(from: &Path, to: &Path, throttle: &Throttle) -> io::Result<u64> {
+    let (from, to, throttle) = (from.to_path_buf(), to.to_path_buf(), throttle.clone());
+    deadline::run(move || {
+        let source = File::open(from)?;
+        let permissions = source.metadata()?.permissions();
+        let mut target = File::create(to)?;
+        let copied = io::copy(&mut ThrottledReader::new(source, &throttle), &mut target)?;
+        target.set_permissions(permissions)?;
+        Ok(copied)
+    })
+}
+
+/// Copies `from` into `to` with `fs::copy` within the per-file deadline, shared by the native backends.
+fn std_copy(from: &Path, to: &Path) -> io::Result<u64> {
+    let (from, to) = (from.to_path_buf(), to.to_path_buf());
+    deadline::run(move || fs::copy(from, to))
+}
+
+/// Writes `contents` into a new file at `to` within the per-file deadline, carrying over the
+/// permissions of `like`, shared by the native backends.
+fn std_write_like(to: &Path, contents: &[u8], like: &Path) -> io::Result<u64> {
+    let (to, contents, like) = (to.to_path_buf(), contents.to_vec(), like.to_path_buf());
+    deadline::run(move || {
+        let permissions = fs::metadata(like)?.permissions();
+        let mut target = File::create(to)?;
+        target.write_all(&contents)?;
+        target.set_permissions(permissions)?;
+        Ok(contents.len() as u64)
+    })
+}
+
+/// Removes the file at `path` within the per-file deadline, shared by the native backends.
+fn std_remove_file(path: &Path) -> io::Result<()> {
+    let path = path.to_path_buf();
+    deadline::run(move || fs::remove_file(path))
+}
+
+/// Flushes the file at `path` with `File::sync_all` within the per-file deadline, shared by the
+/// native backends. Windows requires the file to be opened for writing to flush it; elsewhere it is
+/// opened for reading, so copies of read-only files can be flushed without privileges.
+fn std_sync_file(path: &Path) -> io::Result<()> {
+    let path = path.to_path_buf();
+    deadline::run(move || {
+        let mut options = OpenOptions::new();
+        #[cfg(windows)]
+        options.write(true);
+        #[cfg(not(windows))]
+        options.read(true);
+        options.open(path)?.sync_all()
+    })
+}
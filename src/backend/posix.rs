@@ -0,0 +1,473 @@
+//! POSIX implementation of the backend traits, using `fcntl` record locks.
+
+use super::{
+    std_copy, std_copy_throttled, std_metadata, std_open, std_read_dir, std_remove_file,
+    std_symlink_metadata, std_sync_file, std_write_like, Acl, DirEntries, FileKind, FileMetadata,
+    FileOps, FileProbe, FilesystemKind, LockInfo, LockOps, LockingMode,
+};
+use crate::deadline;
+use crate::throttle::Throttle;
+use crate::{locks, mounts, proc_locks};
+use std::env;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::unix::ffi::{OsStrExt, OsStringExt};
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Set-group-ID permission bit (`S_ISGID`).
+const SETGID_BIT: u32 = 0o2000;
+/// Group execute permission bit (`S_IXGRP`).
+const GROUP_EXECUTE_BIT: u32 = 0o0010;
+
+/// Pause between read attempts on a file blocked by a mandatory lock.
+const NONBLOCKING_RETRY_DELAY: Duration = Duration::from_millis(50);
+
+/// `FileOps` implementation backed by `std::fs`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PosixFs;
+
+impl FileOps for PosixFs {
+    fn metadata(&self, path: &Path) -> io::Result<FileMetadata> {
+        std_metadata(path)
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> io::Result<FileMetadata> {
+        std_symlink_metadata(path)
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<DirEntries<'_>> {
+        std_read_dir(path)
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + '_>> {
+        std_open(path)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<u64> {
+        #[cfg(all(target_os = "linux", feature = "io-uring"))]
+        match uring_copy(from, to) {
+            Err(e) if e.kind() == io::ErrorKind::Unsupported => {}
+            copied => return copied,
+        }
+        std_copy(from, to)
+    }
+
+    fn copy_throttled(&self, from: &Path, to: &Path, throttle: &Throttle) -> io::Result<u64> {
+        std_copy_throttled(from, to, throttle)
+    }
+
+    fn copy_nonblocking(&self, from: &Path, to: &Path, timeout: Duration) -> io::Result<u64> {
+        let (from, to) = (from.to_path_buf(), to.to_path_buf());
+        deadline::run(move || copy_nonblocking(&from, &to, timeout))
+    }
+
+    fn write_like(&self, to: &Path, contents: &[u8], like: &Path) -> io::Result<u64> {
+        std_write_like(to, contents, like)
+    }
+
+    fn read_acl(&self, path: &Path) -> io::Result<Option<Acl>> {
+        let path = path.to_path_buf();
+        deadline::run(move || read_acl(&path))
+    }
+
+    fn write_acl(&self, path: &Path, acl: &Acl) -> io::Result<()> {
+        let (path, acl) = (path.to_path_buf(), acl.clone());
+        deadline::run(move || write_acl(&path, &acl))
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let (from, to) = (from.to_path_buf(), to.to_path_buf());
+        deadline::run(move || fs::rename(from, to))
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        std_remove_file(path)
+    }
+
+    fn sync_file(&self, path: &Path) -> io::Result<()> {
+        std_sync_file(path)
+    }
+
+    fn sync_dir(&self, path: &Path) -> io::Result<()> {
+        let path = path.to_path_buf();
+        deadline::run(move || File::open(path)?.sync_all())
+    }
+
+    fn filesystem_kind(&self, path: &Path) -> io::Result<FilesystemKind> {
+        let path = path.to_path_buf();
+        deadline::run(move || fs_kind(&path))
+    }
+
+    fn create_staging_dir(&self) -> io::Result<PathBuf> {
+        let template = env::temp_dir().join("netfs-unlker.XXXXXX");
+        let mut bytes = template.as_os_str().as_bytes().to_vec();
+        bytes.push(0);
+
+        let ret = unsafe { libc::mkdtemp(bytes.as_mut_ptr() as *mut libc::c_char) };
+        if ret.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+
+        bytes.pop(); // Drop the trailing NUL
+        Ok(PathBuf::from(OsString::from_vec(bytes)))
+    }
+
+    fn remove_staging_dir(&self, path: &Path) -> io::Result<()> {
+        fs::remove_dir_all(path)
+    }
+}
+
+/// Copies `from` into `to` through io_uring within the per-file deadline. The permissions of `from`
+/// are carried over like `fs::copy` does.
+///
+/// Returns an `Err` of kind `Unsupported` if io_uring is not available, so the caller falls back to
+/// the standard copier.
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+fn uring_copy(from: &Path, to: &Path) -> io::Result<u64> {
+    let (from, to) = (from.to_path_buf(), to.to_path_buf());
+    deadline::run(move || {
+        let source = File::open(&from)?;
+        let permissions = source.metadata()?.permissions();
+        let target = File::create(&to)?;
+        match crate::uring::copy(&source, &target) {
+            Ok(copied) => {
+                target.set_permissions(permissions)?;
+                Ok(copied)
+            }
+            Err(e) => Err(e),
+        }
+    })
+}
+
+/// `LockOps` implementation backed by POSIX `fcntl` record locks.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PosixLocks;
+
+impl LockOps for PosixLocks {
+    fn is_locked(&self, path: &Path) -> io::Result<bool> {
+        with_file(path, |file| Ok(locks::is_file_locked(file)))
+    }
+
+    fn unlock(&self, path: &Path) -> io::Result<()> {
+        with_file(path, locks::unlock)
+    }
+
+    fn lock_info(&self, path: &Path) -> io::Result<Option<LockInfo>> {
+        with_file(path, locks::get_lock_info)
+    }
+
+    fn locked_ranges(&self, path: &Path) -> io::Result<Vec<LockInfo>> {
+        with_file(path, locks::get_locked_ranges)
+    }
+
+    fn unlock_range(&self, path: &Path, start: u64, len: Option<u64>) -> io::Result<()> {
+        with_file(path, move |file| locks::unlock_range(file, start, len))
+    }
+
+    fn locking_mode(&self, path: &Path) -> io::Result<LockingMode> {
+        let path = path.to_path_buf();
+        deadline::run(move || locking_mode(&path))
+    }
+
+    fn is_leased(&self, path: &Path) -> io::Result<bool> {
+        with_file(path, |file| Ok(is_smb_leased(file)))
+    }
+
+    fn break_lease(&self, path: &Path) -> io::Result<()> {
+        // Opening the file for writing makes the server recall the conflicting leases; the open
+        // returns once the holders acknowledged the break or the server gave up waiting for them
+        let path = path.to_path_buf();
+        deadline::run(move || OpenOptions::new().write(true).open(path).map(drop))
+    }
+
+    fn is_holder_alive(&self, path: &Path, lock: &LockInfo) -> io::Result<bool> {
+        let (path, pid) = (path.to_path_buf(), lock.pid);
+        deadline::run(move || proc_locks::is_holder_alive(&path, pid))
+    }
+
+    fn probe(&self, path: &Path) -> io::Result<FileProbe> {
+        let path = path.to_path_buf();
+        deadline::run(move || probe(&path))
+    }
+}
+
+/// Probes `path` through a single descriptor: the open neither follows symbolic links nor blocks on
+/// fifos or mandatory locks, the size comes from `fstat` on the descriptor (answered from the
+/// attributes the NFS open returned), and `F_GETLK` runs on the same descriptor.
+fn probe(path: &Path) -> io::Result<FileProbe> {
+    let file = match OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NOFOLLOW | libc::O_NONBLOCK)
+        .open(path)
+    {
+        Ok(file) => file,
+        // Symbolic links, sockets and device nodes without a driver are not files to probe
+        Err(e)
+            if matches!(
+                e.raw_os_error(),
+                Some(libc::ELOOP | libc::ENXIO | libc::ENODEV)
+            ) =>
+        {
+            return Ok(FileProbe {
+                kind: FileKind::Other,
+                len: 0,
+                lock: None,
+                mode: LockingMode::Advisory,
+            })
+        }
+        Err(e) => return Err(e),
+    };
+
+    let metadata = file.metadata()?;
+    let kind = if metadata.is_file() {
+        FileKind::File
+    } else if metadata.is_dir() {
+        FileKind::Directory
+    } else {
+        FileKind::Other
+    };
+    let lock = match kind {
+        FileKind::File => locks::get_lock_info(&file)?,
+        _ => None,
+    };
+    let mode = match lock {
+        Some(_) => locking_mode_of(path, metadata.permissions().mode())?,
+        None => LockingMode::Advisory,
+    };
+
+    Ok(FileProbe {
+        kind,
+        len: metadata.len(),
+        lock,
+        mode,
+    })
+}
+
+/// Opens the file at `path` and runs an `fcntl` call on it within the per-file deadline.
+fn with_file<T, F>(path: &Path, operation: F) -> io::Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&File) -> io::Result<T> + Send + 'static,
+{
+    let path = path.to_path_buf();
+    deadline::run(move || operation(&File::open(path)?))
+}
+
+/// Detects mandatory locking, which needs the setgid bit without group execute on the file
+/// and a filesystem mounted with the `mand` option.
+fn locking_mode(path: &Path) -> io::Result<LockingMode> {
+    locking_mode_of(path, fs::metadata(path)?.permissions().mode())
+}
+
+/// Detects mandatory locking on the file at `path` with the permission bits `mode`.
+fn locking_mode_of(path: &Path, mode: u32) -> io::Result<LockingMode> {
+    if mode & SETGID_BIT == 0 || mode & GROUP_EXECUTE_BIT != 0 {
+        return Ok(LockingMode::Advisory);
+    }
+
+    match mounts::find_mount(path)? {
+        Some(mount) if mount.options.iter().any(|o| o == "mand") => Ok(LockingMode::Mandatory),
+        _ => Ok(LockingMode::Advisory),
+    }
+}
+
+/// Copies `from` into `to`, reading `from` with `O_NONBLOCK` and retrying reads blocked by a
+/// mandatory lock until `timeout` expires.
+fn copy_nonblocking(from: &Path, to: &Path, timeout: Duration) -> io::Result<u64> {
+    let deadline = Instant::now() + timeout;
+    let mut source = OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NONBLOCK)
+        .open(from)?;
+    let mut target = File::create(to)?;
+    target.set_permissions(source.metadata()?.permissions())?;
+
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut copied = 0u64;
+    loop {
+        match source.read(&mut buf) {
+            Ok(0) => return Ok(copied),
+            Ok(n) => {
+                target.write_all(&buf[..n])?;
+                copied += n as u64;
+            }
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                if Instant::now() >= deadline {
+                    return Err(io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        "timed out reading a file held by a mandatory lock",
+                    ));
+                }
+                thread::sleep(NONBLOCKING_RETRY_DELAY);
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Filesystem magic numbers (`statfs.f_type`) of network filesystems.
+#[cfg(target_os = "linux")]
+const NETWORK_FS_MAGICS: &[i64] = &[
+    0x6969,      // NFS_SUPER_MAGIC
+    0x517B,      // SMB_SUPER_MAGIC
+    0xFF53_4D42, // CIFS_SUPER_MAGIC
+    0xFE53_4D42, // SMB2_SUPER_MAGIC
+    0x5346_414F, // AFS_SUPER_MAGIC
+    0x6B41_4653, // AFS_FS_MAGIC (kAFS)
+    0x7375_7245, // CODA_SUPER_MAGIC
+    0x0102_1997, // V9FS_MAGIC
+    0x00C3_6400, // CEPH_SUPER_MAGIC
+    0x0BD0_0BD0, // LUSTRE_SUPER_MAGIC
+    0x4750_4653, // GPFS_SUPER_MAGIC
+];
+
+/// Filesystem magic numbers (`statfs.f_type`) of SMB/CIFS mounts.
+#[cfg(target_os = "linux")]
+const SMB_FS_MAGICS: &[i64] = &[
+    0x517B,      // SMB_SUPER_MAGIC
+    0xFF53_4D42, // CIFS_SUPER_MAGIC
+    0xFE53_4D42, // SMB2_SUPER_MAGIC
+];
+
+/// Checks whether another client seems to hold an SMB lease on `file`, which the record lock probe
+/// cannot see.
+///
+/// The CIFS client only grants a local read lease while the server granted it a caching lease or
+/// oplock, and the server refuses those while another client caches writes to the file. The client
+/// also refuses read leases on `cache=none` mounts, against servers with oplocks disabled, and while
+/// any other client has the file open, so a refusal is no proof of a lease and never makes the file
+/// count as locked. Files on other filesystems, and files whose lease state cannot be probed (e.g.
+/// owned by another user), count as not leased.
+#[cfg(target_os = "linux")]
+fn is_smb_leased(file: &File) -> bool {
+    let mut buf: libc::statfs = unsafe { std::mem::zeroed() };
+    let ret = unsafe { libc::fstatfs(file.as_raw_fd(), &mut buf) };
+    if ret == -1 || !SMB_FS_MAGICS.contains(&(buf.f_type as i64)) {
+        return false;
+    }
+    locks::is_read_lease_refused(file).unwrap_or(false)
+}
+
+/// Checks for SMB leases; leases can only be probed on Linux.
+#[cfg(not(target_os = "linux"))]
+fn is_smb_leased(_file: &File) -> bool {
+    false
+}
+
+/// Extended attributes the kernel exposes ACLs in, in the order they are looked up. NFS mounts only
+/// expose the NFSv4 ACL, local filesystems only the POSIX ACL.
+#[cfg(target_os = "linux")]
+const ACL_ATTRIBUTES: [(super::AclKind, &std::ffi::CStr); 2] = [
+    (super::AclKind::Nfs4, c"system.nfs4_acl"),
+    (super::AclKind::Posix, c"system.posix_acl_access"),
+];
+
+/// Reads the ACL of `path` from its extended attributes. A file without either attribute, or on a
+/// filesystem without ACL support, has no ACL.
+#[cfg(target_os = "linux")]
+fn read_acl(path: &Path) -> io::Result<Option<Acl>> {
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes())?;
+    for (kind, name) in ACL_ATTRIBUTES {
+        match get_xattr(&path, name) {
+            Ok(data) => return Ok(Some(Acl { kind, data })),
+            Err(e) if matches!(e.raw_os_error(), Some(libc::ENODATA | libc::EOPNOTSUPP)) => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(None)
+}
+
+/// Reads ACLs; extended attributes are only read on Linux.
+#[cfg(not(target_os = "linux"))]
+fn read_acl(_path: &Path) -> io::Result<Option<Acl>> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Replaces the ACL of `path` by setting the extended attribute of its kind.
+#[cfg(target_os = "linux")]
+fn write_acl(path: &Path, acl: &Acl) -> io::Result<()> {
+    let path = std::ffi::CString::new(path.as_os_str().as_bytes())?;
+    let (_, name) = ACL_ATTRIBUTES
+        .iter()
+        .find(|(kind, _)| *kind == acl.kind)
+        .ok_or_else(|| io::Error::from(io::ErrorKind::InvalidInput))?;
+    let ret = unsafe {
+        libc::setxattr(
+            path.as_ptr(),
+            name.as_ptr(),
+            acl.data.as_ptr().cast(),
+            acl.data.len(),
+            0,
+        )
+    };
+    match ret {
+        -1 => Err(io::Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+/// Writes ACLs; extended attributes are only written on Linux.
+#[cfg(not(target_os = "linux"))]
+fn write_acl(_path: &Path, _acl: &Acl) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Reads the value of the extended attribute `name` of `path`, growing the buffer if the attribute
+/// grows between sizing and reading it.
+#[cfg(target_os = "linux")]
+fn get_xattr(path: &std::ffi::CStr, name: &std::ffi::CStr) -> io::Result<Vec<u8>> {
+    loop {
+        let size = unsafe { libc::getxattr(path.as_ptr(), name.as_ptr(), std::ptr::null_mut(), 0) };
+        if size < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut value = vec![0u8; size as usize];
+        let read = unsafe {
+            libc::getxattr(
+                path.as_ptr(),
+                name.as_ptr(),
+                value.as_mut_ptr().cast(),
+                value.len(),
+            )
+        };
+        if read >= 0 {
+            value.truncate(read as usize);
+            return Ok(value);
+        }
+        let error = io::Error::last_os_error();
+        if error.raw_os_error() != Some(libc::ERANGE) {
+            return Err(error);
+        }
+    }
+}
+
+/// Classifies the filesystem of `path` by its `statfs` magic number.
+#[cfg(target_os = "linux")]
+fn fs_kind(path: &Path) -> io::Result<FilesystemKind> {
+    let mut bytes = path.as_os_str().as_bytes().to_vec();
+    bytes.push(0);
+
+    let mut buf: libc::statfs = unsafe { std::mem::zeroed() };
+    let ret = unsafe { libc::statfs(bytes.as_ptr() as *const libc::c_char, &mut buf) };
+    if ret == -1 {
+        return Err(io::Error::last_os_error());
+    }
+
+    match NETWORK_FS_MAGICS.contains(&(buf.f_type as i64)) {
+        true => Ok(FilesystemKind::Network),
+        false => Ok(FilesystemKind::Local),
+    }
+}
+
+/// Classifies the filesystem of `path`; magic numbers are only available on Linux.
+#[cfg(not(target_os = "linux"))]
+fn fs_kind(_path: &Path) -> io::Result<FilesystemKind> {
+    Ok(FilesystemKind::Unknown)
+}
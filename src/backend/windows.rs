@@ -0,0 +1,162 @@
+//! Windows implementation of the backend traits, using `LockFileEx` byte-range locks.
+
+use super::{
+    std_copy, std_copy_throttled, std_metadata, std_open, std_read_dir, std_remove_file,
+    std_symlink_metadata, std_sync_file, std_write_like, DirEntries, FileMetadata, FileOps,
+    FilesystemKind, LockInfo, LockKind, LockOps, LockType, LockingMode,
+};
+use crate::deadline;
+use crate::throttle::Throttle;
+use crate::win32;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Counter making staging directory names unique within the process.
+static STAGING_DIRS: AtomicU32 = AtomicU32::new(0);
+
+/// `FileOps` implementation backed by `std::fs` and `MoveFileExW`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WindowsFs;
+
+impl FileOps for WindowsFs {
+    fn metadata(&self, path: &Path) -> io::Result<FileMetadata> {
+        std_metadata(path)
+    }
+
+    fn symlink_metadata(&self, path: &Path) -> io::Result<FileMetadata> {
+        std_symlink_metadata(path)
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<DirEntries<'_>> {
+        std_read_dir(path)
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + '_>> {
+        std_open(path)
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<u64> {
+        std_copy(from, to)
+    }
+
+    fn copy_throttled(&self, from: &Path, to: &Path, throttle: &Throttle) -> io::Result<u64> {
+        std_copy_throttled(from, to, throttle)
+    }
+
+    fn write_like(&self, to: &Path, contents: &[u8], like: &Path) -> io::Result<u64> {
+        std_write_like(to, contents, like)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let (from, to) = (from.to_path_buf(), to.to_path_buf());
+        deadline::run(move || win32::move_file_replace(&from, &to))
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        std_remove_file(path)
+    }
+
+    fn sync_file(&self, path: &Path) -> io::Result<()> {
+        std_sync_file(path)
+    }
+
+    fn filesystem_kind(&self, path: &Path) -> io::Result<FilesystemKind> {
+        let path = path.to_path_buf();
+        deadline::run(
+            move || match win32::is_remote_path(&fs::canonicalize(path)?) {
+                true => Ok(FilesystemKind::Network),
+                false => Ok(FilesystemKind::Local),
+            },
+        )
+    }
+
+    fn create_staging_dir(&self) -> io::Result<PathBuf> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or(0);
+
+        loop {
+            let path = env::temp_dir().join(format!(
+                "netfs-unlker.{}.{}.{}",
+                process::id(),
+                nanos,
+                STAGING_DIRS.fetch_add(1, Ordering::Relaxed)
+            ));
+            match fs::create_dir(&path) {
+                Ok(()) => return Ok(path),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn remove_staging_dir(&self, path: &Path) -> io::Result<()> {
+        fs::remove_dir_all(path)
+    }
+}
+
+/// `LockOps` implementation backed by `LockFileEx`/`UnlockFileEx`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct WindowsLocks;
+
+impl LockOps for WindowsLocks {
+    fn is_locked(&self, path: &Path) -> io::Result<bool> {
+        with_file(path, |file| Ok(win32::is_file_locked(file)))
+    }
+
+    fn unlock(&self, path: &Path) -> io::Result<()> {
+        with_file(path, win32::unlock)
+    }
+
+    fn lock_info(&self, path: &Path) -> io::Result<Option<LockInfo>> {
+        with_file(path, |file| {
+            if !win32::is_file_locked(file) {
+                return Ok(None);
+            }
+
+            // LockFileEx cannot report the holder, only whether a shared lock would still be granted
+            let lock_type = match win32::is_file_share_locked(file) {
+                true => LockType::Exclusive,
+                false => LockType::Shared,
+            };
+            Ok(Some(LockInfo {
+                kind: LockKind::Record,
+                lock_type,
+                start: 0,
+                len: None,
+                pid: None,
+            }))
+        })
+    }
+
+    fn unlock_range(&self, path: &Path, start: u64, len: Option<u64>) -> io::Result<()> {
+        with_file(path, move |file| {
+            let len = match len {
+                Some(len) => len,
+                None => file.metadata()?.len().saturating_sub(start),
+            };
+            win32::unlock_range(file, start, len)
+        })
+    }
+
+    fn locking_mode(&self, _path: &Path) -> io::Result<LockingMode> {
+        // Byte-range locks taken with LockFileEx are always enforced
+        Ok(LockingMode::Mandatory)
+    }
+}
+
+/// Opens the file at `path` and runs a lock call on it within the per-file deadline.
+fn with_file<T, F>(path: &Path, operation: F) -> io::Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce(&File) -> io::Result<T> + Send + 'static,
+{
+    let path = path.to_path_buf();
+    deadline::run(move || operation(&File::open(path)?))
+}
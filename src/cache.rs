@@ -0,0 +1,225 @@
+//! # Probe Cache Module
+//!
+//! This module contains `ProbeCache`, a JSON index of the files a directory sweep found unlocked, keyed
+//! by path and recording their size, modification time and inode. A later sweep skips the lock probe of
+//! a file whose metadata is unchanged, which saves one round trip to the filer per file on nightly
+//! sweeps over millions of mostly idle files.
+//!
+//! Taking a lock does not modify a file, so an unchanged file may have been locked since it was
+//! probed. Entries therefore expire after a maximum age, after which the file is probed again.
+
+use crate::backend::FileMetadata;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Version of the index format, bumped on incompatible changes.
+const FORMAT_VERSION: u32 = 1;
+
+/// Default time after which a file is probed again even if it is unchanged.
+pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/// Metadata of a file found unlocked, and when it was probed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct CachedProbe {
+    len: u64,
+    /// Modification time since the Unix epoch.
+    modified_secs: u64,
+    modified_nanos: u32,
+    inode: Option<u64>,
+    /// Time of the probe in seconds since the Unix epoch.
+    probed: u64,
+}
+
+/// Index as read from disk.
+#[derive(Deserialize)]
+struct CacheFile {
+    version: u32,
+    files: HashMap<String, CachedProbe>,
+}
+
+/// Index as written to disk.
+#[derive(Serialize)]
+struct CacheFileRef<'a> {
+    version: u32,
+    files: &'a HashMap<String, CachedProbe>,
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    files: HashMap<String, CachedProbe>,
+    // Paths looked up or recorded since the cache was opened, kept when pruning
+    visited: HashSet<String>,
+}
+
+/// Persistent index of the files found unlocked by earlier sweeps.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::Path;
+/// use netfs_unlker::backend::{NativeFs, NativeLocks};
+/// use netfs_unlker::cache::ProbeCache;
+/// use netfs_unlker::{RepairOptions, Repairer};
+///
+/// let cache = ProbeCache::open(Path::new("/var/cache/netfs-unlker/probes.json")).unwrap();
+/// let repairer = Repairer::new(NativeFs::default(), NativeLocks::default(), RepairOptions::default())
+///     .with_probe_cache(cache);
+/// let report = repairer.repair_directory(Path::new("/mnt/share"), true);
+/// ```
+#[derive(Debug)]
+pub struct ProbeCache {
+    path: PathBuf,
+    max_age: Duration,
+    state: Mutex<CacheState>,
+}
+
+impl ProbeCache {
+    /// Creates an empty index that is saved to `path`, replacing any index there. Entries expire
+    /// after `DEFAULT_MAX_AGE`.
+    pub fn new(path: &Path) -> Self {
+        ProbeCache {
+            path: path.to_path_buf(),
+            max_age: DEFAULT_MAX_AGE,
+            state: Mutex::new(CacheState::default()),
+        }
+    }
+
+    /// Opens the index at `path`, starting empty if it does not exist yet. Entries expire after
+    /// `DEFAULT_MAX_AGE`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the index cannot be read, of kind `InvalidData` or `UnexpectedEof` if it is
+    /// damaged or not an index of this version.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let files = match File::open(path) {
+            Ok(file) => {
+                let index: CacheFile = serde_json::from_reader(BufReader::new(file))?;
+                if index.version != FORMAT_VERSION {
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("unsupported cache version {}", index.version),
+                    ));
+                }
+                index.files
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e),
+        };
+
+        let cache = ProbeCache::new(path);
+        cache.state().files = files;
+        Ok(cache)
+    }
+
+    /// Sets the time after which a file is probed again even if it is unchanged.
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = max_age;
+        self
+    }
+
+    /// Returns the path of the index.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Checks whether the file at `path` was found unlocked within the maximum age and is unchanged
+    /// since, going by `metadata`.
+    pub fn is_unchanged(&self, path: &Path, metadata: &FileMetadata) -> bool {
+        let Some(key) = path.to_str() else {
+            return false;
+        };
+        let mut state = self.state();
+        state.visited.insert(key.to_string());
+        let Some(cached) = state.files.get(key) else {
+            return false;
+        };
+
+        let expired = now_secs().saturating_sub(cached.probed) >= self.max_age.as_secs();
+        !expired && Some(cached) == probe(metadata, cached.probed).as_ref()
+    }
+
+    /// Records the file at `path` as found unlocked with the given metadata. Files whose path is not
+    /// valid UTF-8, or whose modification time is unknown, are not recorded.
+    pub fn record_unlocked(&self, path: &Path, metadata: &FileMetadata) {
+        let (Some(key), Some(probe)) = (path.to_str(), probe(metadata, now_secs())) else {
+            return;
+        };
+        let mut state = self.state();
+        state.visited.insert(key.to_string());
+        state.files.insert(key.to_string(), probe);
+    }
+
+    /// Removes the file at `path`, so it is probed on the next sweep.
+    pub fn forget(&self, path: &Path) {
+        if let Some(key) = path.to_str() {
+            self.state().files.remove(key);
+        }
+    }
+
+    /// Removes the entries below `directory` (only its direct children without `recursive`) that
+    /// were not visited since the cache was opened, e.g. files deleted since the last sweep.
+    pub fn prune(&self, directory: &Path, recursive: bool) {
+        let mut state = self.state();
+        let CacheState { files, visited } = &mut *state;
+        files.retain(|key, _| {
+            let path = Path::new(key);
+            let below = match recursive {
+                true => path.starts_with(directory),
+                false => path.parent() == Some(directory),
+            };
+            !below || visited.contains(key)
+        });
+    }
+
+    /// Writes the index back to its path, replacing the previous one atomically.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the index cannot be written.
+    pub fn save(&self) -> io::Result<()> {
+        let state = self.state();
+        let mut tmp_name = self.path.file_name().unwrap_or_default().to_os_string();
+        tmp_name.push(".tmp");
+        let tmp_path = self.path.with_file_name(tmp_name);
+
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        serde_json::to_writer(
+            &mut writer,
+            &CacheFileRef {
+                version: FORMAT_VERSION,
+                files: &state.files,
+            },
+        )?;
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        fs::rename(&tmp_path, &self.path)
+    }
+
+    fn state(&self) -> MutexGuard<'_, CacheState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Builds the entry of a file probed at `probed`, or `None` if its modification time is unknown.
+fn probe(metadata: &FileMetadata, probed: u64) -> Option<CachedProbe> {
+    let modified = metadata.modified?.duration_since(UNIX_EPOCH).ok()?;
+    Some(CachedProbe {
+        len: metadata.len,
+        modified_secs: modified.as_secs(),
+        modified_nanos: modified.subsec_nanos(),
+        inode: metadata.inode,
+        probed,
+    })
+}
+
+fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |now| now.as_secs())
+}
@@ -0,0 +1,980 @@
+//! # CLI Module
+//!
+//! This module contains the command-line interface definition: the subcommands, their arguments
+//! and the parsers of argument values.
+
+#[cfg(unix)]
+use crate::control::{self, Request};
+#[cfg(unix)]
+use crate::daemon;
+use crate::logging::{LogFormat, LogRotation, LogTarget};
+use bytesize::ByteSize;
+use clap::{ArgGroup, Args, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use netfs_unlker::options::{
+    SortOrder, Target, TempNaming, DEFAULT_QUARANTINE_AFTER, DEFAULT_STALE_HANDLE_RETRIES,
+    DEFAULT_TMP_PREFIX, DEFAULT_TMP_SUFFIX,
+};
+use netfs_unlker::strategy::CopyStrategy;
+use netfs_unlker::walk::{Traversal, WalkOrder};
+use std::num::NonZeroUsize;
+use std::path::PathBuf;
+use std::time::Duration;
+
+const EXIT_CODES_HELP: &str = "Exit codes:
+  0  nothing to do, no locked files were found
+  1  usage error
+  2  some files were repaired or quarantined (scan: locked files were found;
+     cleanup, undo: files were removed or restored)
+  3  some files could not be repaired, verified, removed or restored";
+
+/// Command-line interface definition.
+///
+/// Running without a subcommand is a deprecated alias for `repair`.
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+#[command(propagate_version = true)]
+#[command(after_help = EXIT_CODES_HELP)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    #[command(flatten)]
+    pub logging: LogArgs,
+
+    #[command(
+        flatten,
+        next_help_heading = "Repair options without a subcommand (deprecated, use `repair`)"
+    )]
+    pub repair: RepairCommandArgs,
+}
+
+/// Subcommands of the tool.
+#[derive(Subcommand)]
+pub enum Command {
+    /// Repair locked files.
+    Repair(RepairCommandArgs),
+    /// List the locked files without repairing them.
+    Scan(ScanArgs),
+    /// Keep repairing locked files at a fixed interval until stopped.
+    Watch(WatchArgs),
+    /// Run `watch` as a background service, configured from a file.
+    #[cfg(unix)]
+    Daemon(DaemonArgs),
+    /// Remove the temporary files left behind by interrupted repairs.
+    Cleanup(CleanupArgs),
+    /// Produce an inventory of locked files without repairing them.
+    Report(ReportArgs),
+    /// Move quarantined files back to their original location.
+    Undo(UndoArgs),
+    /// Sample the locks under the targets at a fixed interval and append them to a history file.
+    Monitor(MonitorArgs),
+    /// List the files the samples of `monitor` found locked most often.
+    Offenders(OffendersArgs),
+    /// Send a command to the control socket of a running `watch`.
+    #[cfg(unix)]
+    Ctl(CtlArgs),
+    /// Serve scans and repairs below the targets to remote orchestrators over an authenticated REST API.
+    #[cfg(feature = "agent")]
+    Agent(AgentArgs),
+    /// Scan or repair a path through a remote agent.
+    #[cfg(feature = "agent")]
+    Remote(RemoteArgs),
+    /// Generate shell completions.
+    Completions(CompletionsArgs),
+    /// Generate man pages.
+    Man(ManArgs),
+}
+
+/// Logging options, accepted by every subcommand.
+#[derive(Args)]
+pub struct LogArgs {
+    /// Enable verbose output.
+    /// Specify this using `-v` or `--verbose`.
+    #[arg(
+        global = true,
+        short,
+        long,
+        value_name = "VERBOSE",
+        default_value = "false"
+    )]
+    pub verbose: bool,
+
+    /// Format of the log output.
+    /// Specify this using `--log-format <FORMAT>`.
+    #[arg(global = true, long, value_enum, value_name = "FORMAT", default_value_t = LogFormat::Human)]
+    pub log_format: LogFormat,
+
+    /// Destination of the log output: `stderr`, `syslog`, `journald` or `file:<PATH>`.
+    /// Specify this using `--log-target <TARGET>`.
+    #[arg(global = true, long, value_name = "TARGET", default_value = "stderr")]
+    pub log_target: LogTarget,
+
+    /// Rotation period of the `file:<PATH>` log target.
+    /// Specify this using `--log-rotation <ROTATION>`.
+    #[arg(global = true, long, value_enum, value_name = "ROTATION", default_value_t = LogRotation::Daily)]
+    pub log_rotation: LogRotation,
+
+    /// Number of rotated log files to keep; all are kept if not specified.
+    /// Specify this using `--log-max-files <COUNT>`.
+    #[arg(global = true, long, value_name = "COUNT")]
+    pub log_max_files: Option<usize>,
+}
+
+/// Files and directories to operate on.
+#[derive(Args)]
+#[group(skip)]
+#[command(group = ArgGroup::new("target").args(["file", "directory", "paths"]).multiple(true).required(true))]
+pub struct TargetArgs {
+    /// Path to locked file; may be repeated.
+    /// Specify this using `-f <FILE>` or `--file <FILE>`.
+    /// If specified, the program will attempt to repair the locked file.
+    #[arg(short, long, value_name = "FILE")]
+    pub file: Vec<PathBuf>,
+
+    /// Path to a directory containing locked files; may be repeated.
+    /// Specify this using `-d <DIRECTORY>` or `--directory <DIRECTORY>`.
+    /// If specified, the program will attempt to repair all locked files within the directory.
+    #[arg(short, long, value_name = "DIRECTORY")]
+    pub directory: Vec<PathBuf>,
+
+    /// Files or directories, told apart by what they are on disk.
+    #[arg(value_name = "PATH")]
+    pub paths: Vec<PathBuf>,
+
+    /// Recursively search for locked files within the specified directory.
+    /// Specify this using `-r` or `--recursive`.
+    #[arg(short, long, value_name = "RECURSIVE", default_value = "false")]
+    pub recursive: bool,
+
+    /// Order in which a recursive search descends into subdirectories.
+    /// Specify this using `--traversal <ORDER>`, e.g. `--traversal dfs` to finish a subtree before its siblings.
+    #[arg(long, value_enum, value_name = "ORDER", default_value_t = TraversalArg::Bfs)]
+    pub traversal: TraversalArg,
+
+    /// List every directory sorted by name, so that runs over an unchanged tree visit it in the same order.
+    /// Specify this using `--ordered-walk`.
+    #[arg(long, value_name = "ORDERED_WALK", default_value = "false")]
+    pub ordered_walk: bool,
+
+    /// Do not descend into directories on another filesystem than the searched directory, e.g. other mounts below `/mnt`.
+    /// Specify this using `-x` or `--one-file-system`.
+    #[arg(
+        short = 'x',
+        long,
+        value_name = "ONE_FILE_SYSTEM",
+        default_value = "false"
+    )]
+    pub one_file_system: bool,
+}
+
+impl TargetArgs {
+    /// Returns the order in which directories are walked.
+    pub fn walk_order(&self) -> WalkOrder {
+        WalkOrder {
+            traversal: self.traversal.into(),
+            sorted: self.ordered_walk,
+        }
+    }
+
+    /// Returns the targets in the order files, directories, then bare paths.
+    pub fn targets(&self) -> Vec<Target> {
+        let files = self.file.iter().cloned().map(Target::File);
+        let directories = self.directory.iter().cloned().map(Target::Directory);
+        let paths = self.paths.iter().cloned().map(|path| match path.is_dir() {
+            true => Target::Directory(path),
+            false => Target::File(path),
+        });
+        files.chain(directories).chain(paths).collect()
+    }
+}
+
+/// Arguments of the `scan` subcommand.
+#[derive(Args)]
+pub struct ScanArgs {
+    #[command(flatten)]
+    pub target: TargetArgs,
+
+    #[command(flatten)]
+    pub fast: FastScanArgs,
+
+    /// Also list files without a record lock whose SMB lease probe is refused on CIFS mounts.
+    /// Specify this using `--smb-leases`.
+    #[arg(long, value_name = "SMB_LEASES", default_value = "false")]
+    pub smb_leases: bool,
+}
+
+/// Options of the inventories that probe many files in parallel.
+#[derive(Args)]
+pub struct FastScanArgs {
+    /// Probe every file of a directory with a single open, several files at a time, for faster inventories of high-latency mounts.
+    /// Specify this using `--fast`.
+    #[arg(long, value_name = "FAST", default_value = "false")]
+    pub fast: bool,
+
+    /// Number of files probed at a time by a fast scan.
+    /// Specify this using `--jobs <COUNT>`.
+    #[arg(long, value_name = "COUNT", default_value = "16", requires = "fast")]
+    pub jobs: NonZeroUsize,
+}
+
+/// Arguments of the `repair` subcommand.
+#[derive(Args)]
+pub struct RepairCommandArgs {
+    #[command(flatten)]
+    pub repair: RepairArgs,
+
+    /// Format of the statistics printed to stdout at the end of the run.
+    /// Specify this using `--stats <FORMAT>`.
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t = StatsFormat::Text)]
+    pub stats: StatsFormat,
+
+    /// Number of the slowest files listed in the statistics.
+    /// Specify this using `--slowest <COUNT>`.
+    #[arg(long, value_name = "COUNT", default_value = "5")]
+    pub slowest: usize,
+
+    /// Show a progress line on stderr while repairing, if stderr is a terminal.
+    /// Specify this using `--progress`.
+    #[arg(long, value_name = "PROGRESS", default_value = "false")]
+    pub progress: bool,
+
+    /// Write a per-file report in this format, to stdout unless `--output` is given.
+    /// Specify this using `--format <FORMAT>`.
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub format: Option<OutputFormat>,
+
+    /// Write the per-file report to a file instead of stdout.
+    /// Specify this using `-o <FILE>` or `--output <FILE>`.
+    #[arg(short, long, value_name = "FILE")]
+    pub output: Option<PathBuf>,
+}
+
+/// Copy strategy of the repair, see `CopyStrategy`.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum CopyStrategyArg {
+    /// Copy the staged file next to the original.
+    SameDirectory,
+    /// Rename the staged file next to the original, copying it if that crosses filesystems.
+    Move,
+    /// Rewrite small files through memory, and copy larger files like `same-directory`.
+    Auto,
+}
+
+/// Order of a directory sweep, see `SortOrder`.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum SortOrderArg {
+    /// In the order the files are found.
+    None,
+    /// Smallest files first.
+    SizeAsc,
+    /// Largest files first.
+    SizeDesc,
+    /// Least recently modified files first.
+    MtimeAsc,
+    /// Most recently modified files first.
+    MtimeDesc,
+}
+
+impl From<SortOrderArg> for SortOrder {
+    fn from(order: SortOrderArg) -> Self {
+        match order {
+            SortOrderArg::None => SortOrder::None,
+            SortOrderArg::SizeAsc => SortOrder::SizeAsc,
+            SortOrderArg::SizeDesc => SortOrder::SizeDesc,
+            SortOrderArg::MtimeAsc => SortOrder::MtimeAsc,
+            SortOrderArg::MtimeDesc => SortOrder::MtimeDesc,
+        }
+    }
+}
+
+/// Traversal of a recursive walk, see `Traversal`.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum TraversalArg {
+    /// Breadth-first: all entries of a directory before those of its subdirectories.
+    Bfs,
+    /// Depth-first: a subdirectory as soon as it is found.
+    Dfs,
+}
+
+impl From<TraversalArg> for Traversal {
+    fn from(traversal: TraversalArg) -> Self {
+        match traversal {
+            TraversalArg::Bfs => Traversal::BreadthFirst,
+            TraversalArg::Dfs => Traversal::DepthFirst,
+        }
+    }
+}
+
+/// Format of the statistics printed at the end of a repair run.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum StatsFormat {
+    Text,
+    Json,
+    /// Do not print the statistics.
+    None,
+}
+
+/// Repair options, shared by the `repair` and `watch` subcommands.
+#[derive(Args)]
+pub struct RepairArgs {
+    #[command(flatten)]
+    pub target: TargetArgs,
+
+    /// Treat skipped and quarantined files as failures in the exit code.
+    /// Specify this using `--strict`.
+    #[arg(long, value_name = "STRICT", default_value = "false")]
+    pub strict: bool,
+
+    /// Compare checksums of the staged copy and the repaired file after the repair.
+    /// Specify this using `--verify-checksum`.
+    #[arg(long, value_name = "VERIFY_CHECKSUM", default_value = "false")]
+    pub verify_checksum: bool,
+
+    /// Try to release only the locked byte ranges before replacing the whole file.
+    /// Locks of other processes or NFS/SMB clients cannot be released this way, so such files
+    /// are still replaced.
+    /// Specify this using `--release-ranges`.
+    #[arg(long, value_name = "RELEASE_RANGES", default_value = "false")]
+    pub release_ranges: bool,
+
+    /// Probe files without a record lock on CIFS mounts for SMB leases, and break the lease instead of replacing the file.
+    /// Only reliable on mounts with read-caching oplocks; on `cache=none` mounts every file probes as leased.
+    /// Specify this using `--smb-leases`.
+    #[arg(long, value_name = "SMB_LEASES", default_value = "false")]
+    pub smb_leases: bool,
+
+    /// Seconds to retry reading a file held by a mandatory lock before giving up.
+    /// Specify this using `--mandatory-timeout <SECONDS>`.
+    #[arg(long, value_name = "SECONDS", default_value = "30")]
+    pub mandatory_timeout: u64,
+
+    /// Give up on a file whose filesystem calls take longer than this duration, e.g. `2min`,
+    /// and continue with the next file. The call in progress is not cancelled: it keeps running in
+    /// the background until the filesystem responds.
+    /// Specify this using `--file-timeout <DURATION>`.
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+    pub file_timeout: Option<Duration>,
+
+    /// Number of times a step is retried after failing with a stale NFS file handle (`ESTALE`).
+    /// Specify this using `--stale-retries <COUNT>`.
+    #[arg(long, value_name = "COUNT", default_value_t = DEFAULT_STALE_HANDLE_RETRIES)]
+    pub stale_retries: u32,
+
+    /// Repair files whose lock holder is still alive, which are skipped by default.
+    /// Specify this using `--force`.
+    #[arg(long, value_name = "FORCE", default_value = "false")]
+    pub force: bool,
+
+    /// Stop the sweep at the first subdirectory that cannot be read, instead of reporting it as failed.
+    /// Specify this using `--stop-on-error`.
+    #[arg(long, value_name = "STOP_ON_ERROR", default_value = "false")]
+    pub stop_on_error: bool,
+
+    /// How the staged copy is moved next to the original before the final rename.
+    /// Specify this using `--copy-strategy <STRATEGY>`.
+    #[arg(long, value_enum, value_name = "STRATEGY", default_value_t = CopyStrategyArg::SameDirectory)]
+    pub copy_strategy: CopyStrategyArg,
+
+    /// Largest file repaired through memory with `--copy-strategy auto`, e.g. `64KiB`.
+    /// Specify this using `--in-memory-threshold <SIZE>`.
+    #[arg(long, value_name = "SIZE", default_value = "1MiB")]
+    pub in_memory_threshold: ByteSize,
+
+    /// Do not flush the repaired file and its directory to stable storage, trading crash safety for speed.
+    /// Specify this using `--no-fsync`.
+    #[arg(long, value_name = "NO_FSYNC", default_value = "false")]
+    pub no_fsync: bool,
+
+    /// Do not carry the NFSv4 or POSIX ACL of a file over to the repaired file; it then gets the default ACL of its directory.
+    /// Specify this using `--skip-acls`.
+    #[arg(long, value_name = "SKIP_ACLS", default_value = "false")]
+    pub skip_acls: bool,
+
+    /// Order in which a directory sweep repairs the files it found.
+    /// Specify this using `--sort <ORDER>`, e.g. `--sort mtime-desc` to repair recently modified files first.
+    #[arg(long, value_enum, value_name = "ORDER", default_value_t = SortOrderArg::None)]
+    pub sort: SortOrderArg,
+
+    #[command(flatten)]
+    pub temp_naming: TempNamingArgs,
+
+    /// Wait for another run on the same target to finish instead of exiting.
+    /// Specify this using `--wait-for-lock`.
+    #[arg(
+        long,
+        value_name = "WAIT_FOR_LOCK",
+        default_value = "false",
+        conflicts_with = "force_run"
+    )]
+    pub wait_for_lock: bool,
+
+    /// Run even if another run on the same target holds the run lock.
+    /// Specify this using `--force-run`.
+    #[arg(long, value_name = "FORCE_RUN", default_value = "false")]
+    pub force_run: bool,
+
+    /// Lock file guarding against overlapping runs, by default named after the target in the temporary directory.
+    /// Specify this using `--run-lock <PATH>`.
+    #[arg(long, value_name = "PATH")]
+    pub run_lock: Option<PathBuf>,
+
+    #[cfg(unix)]
+    #[command(flatten)]
+    pub privileges: PrivilegeArgs,
+
+    /// Also repair files on local (non-network) filesystems, which are skipped by default.
+    /// Specify this using `--allow-local`.
+    #[arg(long, value_name = "ALLOW_LOCAL", default_value = "false")]
+    pub allow_local: bool,
+
+    /// Maximum number of bytes copied per second, with an optional `K`, `M` or `G` suffix (powers of 1024).
+    /// Specify this using `--bwlimit <RATE>`, e.g. `--bwlimit 20M`.
+    #[arg(long, value_name = "RATE", value_parser = parse_byte_rate)]
+    pub bwlimit: Option<u64>,
+
+    /// Maximum number of files processed per second during a directory sweep.
+    /// Specify this using `--file-rate <FILES>`.
+    #[arg(long, value_name = "FILES", value_parser = parse_file_rate)]
+    pub file_rate: Option<f64>,
+
+    /// Only repair files of at least this size during a directory sweep, e.g. `1MiB`.
+    /// Specify this using `--min-size <SIZE>`.
+    #[arg(long, value_name = "SIZE")]
+    pub min_size: Option<ByteSize>,
+
+    /// Only repair files of at most this size during a directory sweep, e.g. `10GB`.
+    /// Specify this using `--max-size <SIZE>`.
+    #[arg(long, value_name = "SIZE")]
+    pub max_size: Option<ByteSize>,
+
+    /// Only repair files modified within this duration during a directory sweep, e.g. `24h`.
+    /// Specify this using `--newer-than <DURATION>`.
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+    pub newer_than: Option<Duration>,
+
+    /// Only repair files not modified within this duration during a directory sweep, e.g. `7days`.
+    /// Specify this using `--older-than <DURATION>`.
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration)]
+    pub older_than: Option<Duration>,
+
+    /// Only repair files whose name matches this pattern during a directory sweep, e.g. `*.db`; repeat for several.
+    /// Specify this using `--include <GLOB>`.
+    #[arg(long, value_name = "GLOB")]
+    pub include: Vec<String>,
+
+    /// Pass over files whose name matches this pattern during a directory sweep; repeat for several.
+    /// Specify this using `--exclude <GLOB>`.
+    #[arg(long, value_name = "GLOB")]
+    pub exclude: Vec<String>,
+
+    /// Append an audit record of every repaired file to this JSON lines file.
+    /// Specify this using `--audit-log <PATH>`.
+    #[arg(long, value_name = "PATH")]
+    pub audit_log: Option<PathBuf>,
+
+    /// Keep an index of the files found unlocked, and do not probe them again while they are unchanged.
+    /// Specify this using `--cache-path <FILE>` or the `NETFS_UNLKER_CACHE_PATH` environment variable.
+    #[arg(long, value_name = "FILE", env = "NETFS_UNLKER_CACHE_PATH")]
+    pub cache_path: Option<PathBuf>,
+
+    /// Probe every file, ignoring the index given by `--cache-path`.
+    /// Specify this using `--no-cache`.
+    #[arg(long, value_name = "NO_CACHE", default_value = "false")]
+    pub no_cache: bool,
+
+    /// Probe a file again after this duration even if it is unchanged, e.g. `24h`, as taking a lock does not modify the file.
+    /// Specify this using `--cache-max-age <DURATION>`.
+    #[arg(long, value_name = "DURATION", value_parser = humantime::parse_duration, default_value = "7days")]
+    pub cache_max_age: Duration,
+
+    /// Shell command run before a locked file is repaired; a failure aborts the repair of that file.
+    /// The file path is passed as `$1` and in `NETFS_UNLKER_PATH`.
+    /// Specify this using `--pre-hook <CMD>`.
+    #[arg(long, value_name = "CMD")]
+    pub pre_hook: Option<String>,
+
+    /// Shell command run after the repair of a locked file, also when the repair failed.
+    /// The file path is passed as `$1` and in `NETFS_UNLKER_PATH`, the outcome in `NETFS_UNLKER_OUTCOME`.
+    /// Specify this using `--post-hook <CMD>`.
+    #[arg(long, value_name = "CMD")]
+    pub post_hook: Option<String>,
+
+    /// Existing directory files are moved to when their repair keeps failing.
+    /// Specify this using `--quarantine-dir <DIRECTORY>`.
+    #[arg(long, value_name = "DIRECTORY")]
+    pub quarantine_dir: Option<PathBuf>,
+
+    /// Number of failed repair attempts after which a file is quarantined.
+    /// Specify this using `--quarantine-after <COUNT>`.
+    #[arg(
+        long,
+        value_name = "COUNT",
+        default_value_t = DEFAULT_QUARANTINE_AFTER,
+        value_parser = clap::value_parser!(u32).range(1..),
+        requires = "quarantine_dir"
+    )]
+    pub quarantine_after: u32,
+
+    /// Existing directory a copy of every file is saved to right before it is replaced.
+    /// Specify this using `--backup-dir <DIRECTORY>`.
+    #[arg(long, value_name = "DIRECTORY")]
+    pub backup_dir: Option<PathBuf>,
+
+    /// Base URL of the ONTAP cluster used to break locks server-side.
+    /// Specify this using `--ontap-url <URL>`.
+    /// If the API is unavailable, the program falls back to the copy-based repair.
+    #[cfg(feature = "ontap")]
+    #[arg(
+        long,
+        value_name = "URL",
+        requires_all = ["ontap_user", "ontap_password", "ontap_svm", "ontap_volume", "ontap_mount"]
+    )]
+    pub ontap_url: Option<String>,
+
+    /// User name for the ONTAP REST API.
+    #[cfg(feature = "ontap")]
+    #[arg(long, value_name = "USER")]
+    pub ontap_user: Option<String>,
+
+    /// Password for the ONTAP REST API.
+    #[cfg(feature = "ontap")]
+    #[arg(
+        long,
+        value_name = "PASSWORD",
+        env = "NETFS_UNLKER_ONTAP_PASSWORD",
+        hide_env_values = true
+    )]
+    pub ontap_password: Option<String>,
+
+    /// Name of the SVM serving the volume.
+    #[cfg(feature = "ontap")]
+    #[arg(long, value_name = "SVM")]
+    pub ontap_svm: Option<String>,
+
+    /// Name of the ONTAP volume.
+    #[cfg(feature = "ontap")]
+    #[arg(long, value_name = "VOLUME")]
+    pub ontap_volume: Option<String>,
+
+    /// Local mount point of the ONTAP volume.
+    #[cfg(feature = "ontap")]
+    #[arg(long, value_name = "DIRECTORY")]
+    pub ontap_mount: Option<PathBuf>,
+
+    /// Accept invalid TLS certificates of the ONTAP cluster.
+    #[cfg(feature = "ontap")]
+    #[arg(long, default_value = "false")]
+    pub ontap_insecure: bool,
+
+    /// Ask the ONTAP cluster whether the NFS clients owning the locks are still connected, and only
+    /// repair stale locks of clients that are gone; use `--force` to repair the others as well.
+    /// Specify this using `--check-clients`.
+    #[cfg(feature = "ontap")]
+    #[arg(long, default_value = "false", requires = "ontap_url")]
+    pub check_clients: bool,
+
+    /// URL the run summary is POSTed to when files were repaired or failed.
+    /// Specify this using `--webhook-url <URL>`.
+    #[cfg(feature = "webhook")]
+    #[arg(long, value_name = "URL")]
+    pub webhook_url: Option<String>,
+
+    /// Payload template of the webhook; `@<path>` reads the template from a file.
+    /// Placeholders such as `{{repaired}}`, `{{failed}}` and `{{target}}` are replaced with the run summary.
+    #[cfg(feature = "webhook")]
+    #[arg(long, value_name = "TEMPLATE", requires = "webhook_url")]
+    pub webhook_template: Option<String>,
+
+    /// Only notify when more than this many files were repaired; failures are always notified.
+    #[cfg(feature = "webhook")]
+    #[arg(
+        long,
+        value_name = "COUNT",
+        default_value_t = 0,
+        requires = "webhook_url"
+    )]
+    pub webhook_threshold: usize,
+}
+
+impl RepairArgs {
+    /// Returns the copy strategy, with the in-memory threshold of `auto`.
+    pub fn copy_strategy(&self) -> CopyStrategy {
+        match self.copy_strategy {
+            CopyStrategyArg::SameDirectory => CopyStrategy::SameDirectory,
+            CopyStrategyArg::Move => CopyStrategy::Move,
+            CopyStrategyArg::Auto => CopyStrategy::Auto {
+                in_memory_threshold: self.in_memory_threshold.as_u64(),
+            },
+        }
+    }
+}
+
+/// Arguments of the `watch` subcommand.
+#[derive(Args)]
+pub struct WatchArgs {
+    #[command(flatten)]
+    pub repair: RepairArgs,
+
+    /// Time between two sweeps, e.g. `5min`.
+    /// Specify this using `--interval <DURATION>`.
+    #[arg(
+        long,
+        value_name = "DURATION",
+        default_value = "1min",
+        value_parser = humantime::parse_duration
+    )]
+    pub interval: Duration,
+
+    /// Accept commands of `netfs_unlker ctl` on this Unix domain socket.
+    /// Specify this using `--control-socket [PATH]`; the path defaults to `/run/netfs-unlker.sock`.
+    #[cfg(unix)]
+    #[arg(
+        long,
+        value_name = "PATH",
+        num_args = 0..=1,
+        default_missing_value = control::DEFAULT_SOCKET
+    )]
+    pub control_socket: Option<PathBuf>,
+}
+
+/// Arguments of `watch` read from the configuration file of `daemon`.
+#[cfg(unix)]
+#[derive(Parser)]
+#[command(name = "config", no_binary_name = true, disable_help_flag = true)]
+pub struct WatchConfig {
+    #[command(flatten)]
+    pub watch: WatchArgs,
+}
+
+/// Arguments of the `daemon` subcommand.
+#[cfg(unix)]
+#[derive(Args)]
+pub struct DaemonArgs {
+    /// Configuration file holding the arguments of `watch`, one per line, e.g. `--directory /mnt/share`.
+    /// It is read again on `SIGHUP`.
+    /// Specify this using `--config <FILE>`.
+    #[arg(long, value_name = "FILE")]
+    pub config: PathBuf,
+
+    /// File the PID of the daemon is written to, locked while the daemon runs.
+    /// Specify this using `--pid-file <FILE>`.
+    #[arg(long, value_name = "FILE", default_value = daemon::DEFAULT_PID_FILE)]
+    pub pid_file: PathBuf,
+
+    /// Stay in the foreground instead of detaching, for service managers such as launchd or runit.
+    /// Specify this using `--foreground`.
+    #[arg(long, value_name = "FOREGROUND", default_value = "false")]
+    pub foreground: bool,
+}
+
+/// Arguments of the `cleanup` subcommand.
+#[derive(Args)]
+pub struct CleanupArgs {
+    /// Path to a directory to clean up.
+    /// Specify this using `-d <DIRECTORY>` or `--directory <DIRECTORY>`.
+    #[arg(short, long, value_name = "DIRECTORY", required = true)]
+    pub directory: PathBuf,
+
+    /// Recursively clean up the specified directory.
+    /// Specify this using `-r` or `--recursive`.
+    #[arg(short, long, value_name = "RECURSIVE", default_value = "false")]
+    pub recursive: bool,
+
+    /// Only list the temporary files that would be removed.
+    /// Specify this using `--dry-run`.
+    #[arg(long, value_name = "DRY_RUN", default_value = "false")]
+    pub dry_run: bool,
+
+    #[command(flatten)]
+    pub temp_naming: TempNamingArgs,
+
+    #[cfg(unix)]
+    #[command(flatten)]
+    pub privileges: PrivilegeArgs,
+}
+
+/// Naming of the temporary copies, shared by the repairing subcommands and `cleanup`.
+#[derive(Args)]
+pub struct TempNamingArgs {
+    /// Prefix of the temporary copy written next to the original file.
+    /// Specify this using `--tmp-prefix <PREFIX>`.
+    #[arg(long, value_name = "PREFIX", default_value = DEFAULT_TMP_PREFIX)]
+    pub tmp_prefix: String,
+
+    /// Suffix of the temporary copy written next to the original file.
+    /// Specify this using `--tmp-suffix <SUFFIX>`.
+    #[arg(long, value_name = "SUFFIX", default_value = DEFAULT_TMP_SUFFIX)]
+    pub tmp_suffix: String,
+}
+
+impl TempNamingArgs {
+    /// Returns the naming of the temporary copies.
+    pub fn naming(&self) -> TempNaming {
+        TempNaming::new(&self.tmp_prefix, &self.tmp_suffix)
+    }
+}
+
+/// Privileges of the subcommands that modify files, shared by the repairing subcommands, `cleanup`
+/// and `undo`.
+#[cfg(unix)]
+#[derive(Args)]
+pub struct PrivilegeArgs {
+    /// Run with root privileges, which is refused by default.
+    /// Specify this using `--allow-root`.
+    #[arg(long, value_name = "ALLOW_ROOT", default_value = "false")]
+    pub allow_root: bool,
+
+    /// Switch to this user, given by name or ID, once the log, the run lock, the audit log and the control
+    /// socket are open.
+    /// Specify this using `--run-as <USER>`.
+    #[arg(long, value_name = "USER")]
+    pub run_as: Option<String>,
+}
+
+/// Arguments of the `undo` subcommand.
+#[derive(Args)]
+pub struct UndoArgs {
+    /// Audit log of the runs whose quarantined files are restored.
+    /// Specify this using `--audit-log <PATH>`.
+    #[arg(long, value_name = "PATH", required = true)]
+    pub audit_log: PathBuf,
+
+    /// Only list the files that would be restored.
+    /// Specify this using `--dry-run`.
+    #[arg(long, value_name = "DRY_RUN", default_value = "false")]
+    pub dry_run: bool,
+
+    #[cfg(unix)]
+    #[command(flatten)]
+    pub privileges: PrivilegeArgs,
+}
+
+/// Arguments of the `monitor` subcommand.
+#[derive(Args)]
+pub struct MonitorArgs {
+    #[command(flatten)]
+    pub target: TargetArgs,
+
+    #[command(flatten)]
+    pub fast: FastScanArgs,
+
+    /// File the samples are appended to, one JSON object per line.
+    /// Specify this using `--history <FILE>`.
+    #[arg(long, value_name = "FILE", required = true)]
+    pub history: PathBuf,
+
+    /// Time between two samples, e.g. `5min`.
+    /// Specify this using `--interval <DURATION>`.
+    #[arg(
+        long,
+        value_name = "DURATION",
+        default_value = "5min",
+        value_parser = humantime::parse_duration
+    )]
+    pub interval: Duration,
+}
+
+/// Arguments of the `offenders` subcommand.
+#[derive(Args)]
+pub struct OffendersArgs {
+    /// History file written by `monitor`.
+    /// Specify this using `--history <FILE>`.
+    #[arg(long, value_name = "FILE", required = true)]
+    pub history: PathBuf,
+
+    /// Period of the history to rank, counted back from now, e.g. `24h`.
+    /// Specify this using `--since <DURATION>`.
+    #[arg(
+        long,
+        value_name = "DURATION",
+        default_value = "7days",
+        value_parser = humantime::parse_duration
+    )]
+    pub since: Duration,
+
+    /// Number of files listed.
+    /// Specify this using `--limit <COUNT>`.
+    #[arg(long, value_name = "COUNT", default_value = "20")]
+    pub limit: usize,
+
+    /// Output format of the list.
+    /// Specify this using `--format <FORMAT>`.
+    #[arg(long, value_enum, value_name = "FORMAT", default_value_t = OffendersFormat::Text)]
+    pub format: OffendersFormat,
+}
+
+/// Output format of the `offenders` subcommand.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum OffendersFormat {
+    Text,
+    Json,
+}
+
+/// Arguments of the `report` subcommand.
+#[derive(Args)]
+pub struct ReportArgs {
+    /// Path to a single file to inspect.
+    #[arg(short, long, value_name = "FILE", conflicts_with = "directory")]
+    pub file: Option<PathBuf>,
+
+    /// Path to a directory to inspect.
+    #[arg(
+        short,
+        long,
+        value_name = "DIRECTORY",
+        required_unless_present = "file"
+    )]
+    pub directory: Option<PathBuf>,
+
+    /// Recursively inspect the specified directory.
+    #[arg(short, long, default_value = "false")]
+    pub recursive: bool,
+
+    /// Also list files without a record lock whose SMB lease probe is refused on CIFS mounts.
+    #[arg(long, default_value = "false")]
+    pub smb_leases: bool,
+
+    /// Output format of the report.
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+
+    /// Write the report to a file instead of stdout.
+    #[arg(short, long, value_name = "FILE")]
+    pub output: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub fast: FastScanArgs,
+}
+
+/// Output format of generated reports.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Csv,
+}
+
+/// Arguments of the `ctl` subcommand.
+#[cfg(unix)]
+#[derive(Args)]
+pub struct CtlArgs {
+    /// Path of the control socket.
+    #[arg(long, value_name = "PATH", default_value = control::DEFAULT_SOCKET)]
+    pub socket: PathBuf,
+
+    #[command(subcommand)]
+    pub request: Request,
+}
+
+/// Arguments of the `agent` subcommand.
+#[cfg(feature = "agent")]
+#[derive(Args)]
+pub struct AgentArgs {
+    /// Options of the requested repairs; only paths below the targets are served.
+    #[command(flatten)]
+    pub repair: RepairArgs,
+
+    /// Address and port to listen on, e.g. `0.0.0.0:7480`.
+    /// Specify this using `--listen <ADDRESS>`.
+    #[arg(long, value_name = "ADDRESS", default_value = "127.0.0.1:7480")]
+    pub listen: String,
+
+    /// File holding the token clients have to send as `Authorization: Bearer <token>`.
+    /// Specify this using `--token-file <FILE>` or the `NETFS_UNLKER_AGENT_TOKEN_FILE` environment variable.
+    #[arg(long, value_name = "FILE", env = "NETFS_UNLKER_AGENT_TOKEN_FILE")]
+    pub token_file: PathBuf,
+}
+
+/// Arguments of the `remote` subcommand.
+#[cfg(feature = "agent")]
+#[derive(Args)]
+pub struct RemoteArgs {
+    /// Base URL of the agent, e.g. `http://nfs-client-17:7480`.
+    /// Specify this using `--url <URL>` or the `NETFS_UNLKER_AGENT_URL` environment variable.
+    #[arg(long, value_name = "URL", env = "NETFS_UNLKER_AGENT_URL")]
+    pub url: String,
+
+    /// File holding the token of the agent.
+    /// Specify this using `--token-file <FILE>` or the `NETFS_UNLKER_AGENT_TOKEN_FILE` environment variable.
+    #[arg(long, value_name = "FILE", env = "NETFS_UNLKER_AGENT_TOKEN_FILE")]
+    pub token_file: PathBuf,
+
+    #[command(subcommand)]
+    pub request: RemoteRequest,
+}
+
+/// Request sent to a remote agent.
+#[cfg(feature = "agent")]
+#[derive(Subcommand)]
+pub enum RemoteRequest {
+    /// Print the version of the agent.
+    Health,
+    /// Inventory the locks under a path and print the report as JSON.
+    Scan {
+        /// File or directory to inspect.
+        path: PathBuf,
+        /// Recursively inspect the directory.
+        #[arg(short, long)]
+        recursive: bool,
+    },
+    /// Repair a file or the files in a directory, logging the progress, and print the summary as JSON.
+    Repair {
+        /// File or directory to repair.
+        path: PathBuf,
+        /// Recursively repair the directory.
+        #[arg(short, long)]
+        recursive: bool,
+    },
+}
+
+/// Arguments of the `completions` subcommand.
+#[derive(Args)]
+pub struct CompletionsArgs {
+    /// Shell to generate the completions for.
+    #[arg(value_enum)]
+    pub shell: Shell,
+
+    /// Write the completions to a file in this directory instead of stdout.
+    /// Specify this using `-o <DIRECTORY>` or `--output-dir <DIRECTORY>`.
+    #[arg(short, long, value_name = "DIRECTORY")]
+    pub output_dir: Option<PathBuf>,
+}
+
+/// Arguments of the `man` subcommand.
+#[derive(Args)]
+pub struct ManArgs {
+    /// Write a man page for the tool and one for every subcommand to this directory, instead of
+    /// writing the page of the tool to stdout.
+    /// Specify this using `-o <DIRECTORY>` or `--output-dir <DIRECTORY>`.
+    #[arg(short, long, value_name = "DIRECTORY")]
+    pub output_dir: Option<PathBuf>,
+}
+
+/// Parses a byte rate such as `512K` or `20M` into bytes per second.
+fn parse_byte_rate(value: &str) -> Result<u64, String> {
+    let (digits, multiplier) = match value.char_indices().last() {
+        Some((i, 'k' | 'K')) => (&value[..i], 1 << 10),
+        Some((i, 'm' | 'M')) => (&value[..i], 1 << 20),
+        Some((i, 'g' | 'G')) => (&value[..i], 1 << 30),
+        _ => (value, 1),
+    };
+
+    match digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+    {
+        Some(rate) if rate > 0 => Ok(rate),
+        _ => Err(format!("invalid byte rate: {}", value)),
+    }
+}
+
+/// Parses a positive number of files per second.
+fn parse_file_rate(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(rate) if rate.is_finite() && rate > 0.0 => Ok(rate),
+        _ => Err(format!("invalid file rate: {}", value)),
+    }
+}
@@ -0,0 +1,122 @@
+//! # Contention Module
+//!
+//! This module contains the `monitor` subcommand, which samples the locks under its targets at a fixed
+//! interval into a history file, and the `offenders` subcommand, which ranks the files of that history
+//! found locked most often.
+
+use crate::cli::{MonitorArgs, OffendersArgs, OffendersFormat};
+use crate::{scan_directory, shutdown, EXIT_NOTHING_TO_DO, EXIT_USAGE_ERROR};
+use netfs_unlker::backend::{NativeFs, NativeLocks};
+use netfs_unlker::monitor::{read_history, LockHistory, LockSample, OffenderReport};
+use netfs_unlker::options::Target;
+use netfs_unlker::scan::{ScanError, ScanReport, Scanner};
+use std::io;
+use std::time::{Instant, SystemTime};
+use tracing::{debug, error, info, warn};
+
+/// Samples the locks every `--interval` until a shutdown is requested and returns the process exit code.
+///
+/// A sample that cannot be written is logged and the next one is taken at the next interval.
+pub fn run_monitor(args: &MonitorArgs) -> i32 {
+    let history = match LockHistory::open(&args.history) {
+        Ok(history) => history,
+        Err(e) => {
+            error!(
+                "Failed to open the history file ({}): {}",
+                args.history.display(),
+                e
+            );
+            return EXIT_USAGE_ERROR;
+        }
+    };
+    let scanner = Scanner::new(NativeFs::default(), NativeLocks::default())
+        .with_walk_order(args.target.walk_order())
+        .with_one_file_system(args.target.one_file_system);
+
+    shutdown::install();
+    info!(
+        "Monitoring with an interval of {}",
+        humantime::format_duration(args.interval)
+    );
+    loop {
+        let started = Instant::now();
+        let sample = LockSample::from_report(&scan(&scanner, args), SystemTime::now());
+        match history.record(&sample) {
+            Ok(()) => debug!(
+                "Sampled {} files, {} locked, {} errors",
+                sample.scanned,
+                sample.locked.len(),
+                sample.errors
+            ),
+            Err(e) => error!(
+                "Failed to write the sample ({}): {}",
+                args.history.display(),
+                e
+            ),
+        }
+
+        let wait = (started + args.interval).saturating_duration_since(Instant::now());
+        if shutdown::requested() || !shutdown::wait(wait) {
+            break;
+        }
+    }
+
+    info!("Shutting down");
+    EXIT_NOTHING_TO_DO
+}
+
+/// Scans every target; a target that cannot be scanned is recorded as an error of the sample.
+fn scan(scanner: &Scanner<NativeFs, NativeLocks>, args: &MonitorArgs) -> ScanReport {
+    let mut report = ScanReport::default();
+    for target in args.target.targets() {
+        let scanned = match &target {
+            Target::File(file_path) => scanner.scan_file(file_path),
+            Target::Directory(directory_path) => {
+                scan_directory(scanner, directory_path, args.target.recursive, &args.fast)
+            }
+        };
+        match scanned {
+            Ok(scanned) => report.merge(scanned),
+            Err(e) => {
+                warn!("Failed to scan ({}): {}", target.path().display(), e);
+                report.errors.push(ScanError {
+                    path: target.path().to_path_buf(),
+                    error: e.to_string(),
+                });
+            }
+        }
+    }
+    report
+}
+
+/// Runs the `offenders` subcommand and returns the process exit code.
+pub fn run_offenders(args: &OffendersArgs) -> i32 {
+    let since = SystemTime::now()
+        .checked_sub(args.since)
+        .unwrap_or(SystemTime::UNIX_EPOCH);
+    let samples = match read_history(&args.history, since) {
+        Ok(samples) => samples,
+        Err(e) => {
+            error!(
+                "Failed to read the history file ({}): {}",
+                args.history.display(),
+                e
+            );
+            return EXIT_USAGE_ERROR;
+        }
+    };
+
+    let report = OffenderReport::new(&samples, since, args.limit);
+    let mut stdout = io::stdout().lock();
+    let written = match args.format {
+        OffendersFormat::Text => report.write_text(&mut stdout),
+        OffendersFormat::Json => report.write_json(&mut stdout),
+    };
+    match written {
+        Ok(()) => EXIT_NOTHING_TO_DO,
+        Err(e) => {
+            error!("Failed to write the offenders: {}", e);
+            EXIT_USAGE_ERROR
+        }
+    }
+}
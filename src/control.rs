@@ -0,0 +1,293 @@
+//! # Control Module
+//!
+//! This module contains the control socket of the `watch` subcommand and the client used by the `ctl` subcommand.
+//! Clients connect to a Unix domain socket and send one JSON request per line, for example
+//! `{"command": "repair", "path": "/mnt/share/data.db"}`, and receive one JSON response per line.
+//!
+//! `status`, `pause`, `resume` and `scan` are answered by the socket thread; `repair` is handed to the
+//! watch loop, which runs it between scheduled sweeps.
+
+use clap::Subcommand;
+use netfs_unlker::backend::{NativeFs, NativeLocks};
+use netfs_unlker::scan::Scanner;
+use netfs_unlker::{RepairReport, ReportSummary};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+use tracing::{debug, info, warn};
+
+/// Default path of the control socket.
+pub const DEFAULT_SOCKET: &str = "/run/netfs-unlker.sock";
+
+/// Request sent to the control socket.
+#[derive(Debug, Clone, Serialize, Deserialize, Subcommand)]
+#[serde(tag = "command", rename_all = "lowercase")]
+pub enum Request {
+    /// Inventory the locks under a path without repairing anything.
+    Scan {
+        /// File or directory to inspect.
+        path: PathBuf,
+        /// Recursively inspect the directory.
+        #[arg(short, long)]
+        #[serde(default)]
+        recursive: bool,
+    },
+    /// Repair a file or the files in a directory right away.
+    Repair {
+        /// File or directory to repair.
+        path: PathBuf,
+        /// Recursively repair the directory.
+        #[arg(short, long)]
+        #[serde(default)]
+        recursive: bool,
+    },
+    /// Report the state of the watch loop.
+    Status,
+    /// Skip the scheduled sweeps until `resume`.
+    Pause,
+    /// Resume the scheduled sweeps.
+    Resume,
+}
+
+/// State of the watch loop, as reported by `status`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct WatchStatus {
+    /// Whether the scheduled sweeps are paused.
+    pub paused: bool,
+    /// Whether a sweep or an ad-hoc repair is running.
+    pub busy: bool,
+    /// Number of finished scheduled sweeps.
+    pub sweeps: u64,
+    /// Time the last scheduled sweep finished, in RFC 3339 format.
+    pub last_sweep: Option<String>,
+    /// Totals of the last scheduled sweep.
+    pub last_summary: Option<ReportSummary>,
+}
+
+impl WatchStatus {
+    /// Records a finished scheduled sweep.
+    pub fn record_sweep(&mut self, report: &RepairReport) {
+        self.sweeps += 1;
+        self.last_sweep = Some(humantime::format_rfc3339_seconds(SystemTime::now()).to_string());
+        self.last_summary = Some(report.summary());
+    }
+}
+
+/// Ad-hoc repair requested through the control socket.
+pub struct RepairJob {
+    /// File or directory to repair.
+    pub path: PathBuf,
+    /// Whether to repair the files in subdirectories as well.
+    pub recursive: bool,
+    reply: Sender<io::Result<ReportSummary>>,
+}
+
+impl RepairJob {
+    /// Sends the result of the repair back to the client.
+    pub fn finish(self, result: io::Result<RepairReport>) {
+        let _ = self.reply.send(result.map(|report| report.summary()));
+    }
+}
+
+/// Listening control socket; the socket file is removed when dropped.
+pub struct ControlServer {
+    path: PathBuf,
+    jobs: Receiver<RepairJob>,
+    status: Arc<Mutex<WatchStatus>>,
+    paused: Arc<AtomicBool>,
+}
+
+impl ControlServer {
+    /// Binds the control socket at `path` and starts accepting clients in a background thread.
+    ///
+    /// A stale socket file left behind by a previous run is replaced. The socket is only accessible
+    /// by the owner, as it allows repairing arbitrary files.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if another instance is listening on `path` or the socket cannot be bound.
+    pub fn bind(path: &Path) -> io::Result<Self> {
+        if path.exists() {
+            if UnixStream::connect(path).is_ok() {
+                return Err(io::Error::new(
+                    io::ErrorKind::AddrInUse,
+                    "another instance is listening on the control socket",
+                ));
+            }
+            fs::remove_file(path)?;
+        }
+
+        let listener = UnixListener::bind(path)?;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        info!("Listening on control socket: {}", path.display());
+
+        let (jobs_sender, jobs) = mpsc::channel();
+        let status = Arc::new(Mutex::new(WatchStatus::default()));
+        let paused = Arc::new(AtomicBool::new(false));
+        let handler = Handler {
+            jobs: jobs_sender,
+            status: Arc::clone(&status),
+            paused: Arc::clone(&paused),
+        };
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let handler = handler.clone();
+                        thread::spawn(move || handler.serve(stream));
+                    }
+                    Err(e) => warn!("Failed to accept a control connection: {}", e),
+                }
+            }
+        });
+
+        Ok(ControlServer {
+            path: path.to_path_buf(),
+            jobs,
+            status,
+            paused,
+        })
+    }
+
+    /// Waits up to `timeout` for an ad-hoc repair.
+    pub fn next_job(&self, timeout: Duration) -> Option<RepairJob> {
+        self.jobs.recv_timeout(timeout).ok()
+    }
+
+    /// Checks whether the scheduled sweeps are paused.
+    pub fn paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    /// Updates the state reported by `status`.
+    pub fn update_status(&self, update: impl FnOnce(&mut WatchStatus)) {
+        update(&mut self.status.lock().unwrap_or_else(|e| e.into_inner()));
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Serves the requests of control clients.
+#[derive(Clone)]
+struct Handler {
+    jobs: Sender<RepairJob>,
+    status: Arc<Mutex<WatchStatus>>,
+    paused: Arc<AtomicBool>,
+}
+
+impl Handler {
+    /// Answers the requests of one client until it disconnects.
+    fn serve(&self, stream: UnixStream) {
+        let mut writer = match stream.try_clone() {
+            Ok(writer) => writer,
+            Err(e) => return warn!("Failed to serve a control connection: {}", e),
+        };
+
+        for line in BufReader::new(stream).lines() {
+            let line = match line {
+                Ok(line) if line.trim().is_empty() => continue,
+                Ok(line) => line,
+                Err(_) => break,
+            };
+
+            let response = match serde_json::from_str::<Request>(&line) {
+                Ok(request) => {
+                    debug!("Control request: {:?}", request);
+                    self.handle(request)
+                }
+                Err(e) => error_response(format!("invalid request: {}", e)),
+            };
+            if writeln!(writer, "{}", response).is_err() {
+                break;
+            }
+        }
+    }
+
+    fn handle(&self, request: Request) -> serde_json::Value {
+        match request {
+            Request::Status => {
+                let status = self.status.lock().unwrap_or_else(|e| e.into_inner());
+                ok_response("status", &*status)
+            }
+            Request::Pause => {
+                self.paused.store(true, Ordering::SeqCst);
+                self.update_paused(true);
+                info!("Scheduled sweeps paused through the control socket");
+                ok_response("paused", true)
+            }
+            Request::Resume => {
+                self.paused.store(false, Ordering::SeqCst);
+                self.update_paused(false);
+                info!("Scheduled sweeps resumed through the control socket");
+                ok_response("paused", false)
+            }
+            Request::Scan { path, recursive } => {
+                let scanner = Scanner::new(NativeFs::default(), NativeLocks::default());
+                let report = match path.is_dir() {
+                    true => scanner.scan_directory(&path, recursive),
+                    false => scanner.scan_file(&path),
+                };
+                match report {
+                    Ok(report) => ok_response("report", report),
+                    Err(e) => error_response(e.to_string()),
+                }
+            }
+            Request::Repair { path, recursive } => {
+                let (reply, result) = mpsc::channel();
+                let job = RepairJob {
+                    path,
+                    recursive,
+                    reply,
+                };
+                if self.jobs.send(job).is_err() {
+                    return error_response("the watch loop is shutting down".to_string());
+                }
+                match result.recv() {
+                    Ok(Ok(summary)) => ok_response("summary", summary),
+                    Ok(Err(e)) => error_response(e.to_string()),
+                    Err(_) => error_response("the watch loop is shutting down".to_string()),
+                }
+            }
+        }
+    }
+
+    fn update_paused(&self, paused: bool) {
+        self.status.lock().unwrap_or_else(|e| e.into_inner()).paused = paused;
+    }
+}
+
+fn ok_response(name: &str, value: impl Serialize) -> serde_json::Value {
+    let mut response = serde_json::json!({ "ok": true });
+    response[name] = serde_json::to_value(value).unwrap_or_default();
+    response
+}
+
+fn error_response(error: String) -> serde_json::Value {
+    serde_json::json!({ "ok": false, "error": error })
+}
+
+/// Sends a request to the control socket at `path` and returns the response.
+///
+/// # Errors
+///
+/// Returns an `Err` if the socket cannot be reached or the response cannot be read.
+pub fn send(path: &Path, request: &Request) -> io::Result<serde_json::Value> {
+    let mut stream = UnixStream::connect(path)?;
+    writeln!(stream, "{}", serde_json::to_string(request)?)?;
+
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line)?;
+    Ok(serde_json::from_str(&line)?)
+}
@@ -0,0 +1,281 @@
+//! # Daemon Module
+//!
+//! This module contains the `daemon` subcommand: `watch` run as a background service on hosts without
+//! systemd. The daemon detaches from its terminal with a double fork, keeps a locked PID file, and on
+//! `SIGHUP` reopens its log file and reloads the `watch` arguments from its configuration file.
+//!
+//! The configuration file holds one argument per line. A line starting with `-` is an option, followed
+//! by its value if it takes one; any other line is a target path. Blank lines and lines starting with
+//! `#` are ignored:
+//!
+//! ```text
+//! --directory /mnt/share
+//! --recursive
+//! --interval 5min
+//! ```
+
+use crate::cli::{DaemonArgs, WatchArgs, WatchConfig};
+use crate::logging::LogTarget;
+use crate::{shutdown, watch, EXIT_NOTHING_TO_DO, EXIT_USAGE_ERROR};
+use clap::Parser;
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{self, PipeWriter, Read, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::{env, process, ptr};
+use tracing::{debug, error, info, warn};
+
+/// Default path of the PID file.
+pub const DEFAULT_PID_FILE: &str = "/run/netfs-unlker.pid";
+
+/// Configuration file and startup handshake of a running daemon.
+pub struct Service {
+    config: PathBuf,
+    readiness: Option<PipeWriter>,
+}
+
+impl Service {
+    /// Reads the `watch` arguments from the configuration file again.
+    ///
+    /// # Returns
+    ///
+    /// Returns `None` if the file cannot be read or holds invalid arguments; the error is logged.
+    pub fn reload_config(&self) -> Option<WatchArgs> {
+        match read_config(&self.config) {
+            Ok(args) => Some(args),
+            Err(e) => {
+                error!(
+                    "Failed to reload the configuration ({}): {}",
+                    self.config.display(),
+                    e
+                );
+                None
+            }
+        }
+    }
+
+    /// Tells the process that started the daemon that it is up, upon which that process exits
+    /// successfully. Does nothing in the foreground.
+    pub fn ready(&mut self) {
+        if let Some(mut readiness) = self.readiness.take() {
+            if let Err(e) = readiness.write_all(&[0]) {
+                warn!("Failed to report the startup of the daemon: {}", e);
+            }
+        }
+    }
+}
+
+/// Runs `watch` as a daemon until a shutdown is requested and returns the process exit code.
+///
+/// Unless in the foreground, the calling process waits until the daemon finished starting up and exits
+/// with `0`, or with `1` if it failed to start; the daemon logs why.
+pub fn run_daemon(args: &DaemonArgs, log_target: &LogTarget) -> i32 {
+    // The daemon runs from the root directory, so a relative path would not resolve on reload
+    let config = match fs::canonicalize(&args.config) {
+        Ok(config) => config,
+        Err(e) => {
+            error!(
+                "Failed to read the configuration ({}): {}",
+                args.config.display(),
+                e
+            );
+            return EXIT_USAGE_ERROR;
+        }
+    };
+    let watch_args = match read_config(&config) {
+        Ok(watch_args) => watch_args,
+        Err(e) => {
+            error!("Invalid configuration ({}): {}", config.display(), e);
+            return EXIT_USAGE_ERROR;
+        }
+    };
+    let mut pid_file = match PidFile::lock(&args.pid_file) {
+        Ok(pid_file) => pid_file,
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+            error!(
+                "Another daemon holds the PID file ({})",
+                args.pid_file.display()
+            );
+            return EXIT_USAGE_ERROR;
+        }
+        Err(e) => {
+            error!(
+                "Failed to open the PID file ({}): {}",
+                args.pid_file.display(),
+                e
+            );
+            return EXIT_USAGE_ERROR;
+        }
+    };
+
+    let readiness = match args.foreground {
+        true => None,
+        false => {
+            if matches!(log_target, LogTarget::Stderr) {
+                warn!("Logging to stderr, which is discarded once detached; use --log-target");
+            }
+            match detach() {
+                Ok(readiness) => Some(readiness),
+                Err(e) => {
+                    error!("Failed to detach the daemon: {}", e);
+                    return EXIT_USAGE_ERROR;
+                }
+            }
+        }
+    };
+    if let Err(e) = pid_file.write_pid() {
+        error!(
+            "Failed to write the PID file ({}): {}",
+            pid_file.path.display(),
+            e
+        );
+        return EXIT_USAGE_ERROR;
+    }
+
+    shutdown::install_reload();
+    info!("Daemon started with PID {}", process::id());
+    let mut service = Service { config, readiness };
+    watch::run(&watch_args, Some(&mut service))
+}
+
+/// Reads the `watch` arguments from the configuration file at `path`.
+fn read_config(path: &Path) -> io::Result<WatchArgs> {
+    let args = parse_config(&fs::read_to_string(path)?);
+    match WatchConfig::try_parse_from(args) {
+        Ok(config) => Ok(config.watch),
+        Err(e) => {
+            let message = e.to_string();
+            let message = message.lines().next().unwrap_or_default();
+            Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                message.trim_start_matches("error: ").to_string(),
+            ))
+        }
+    }
+}
+
+/// Splits the content of a configuration file into command-line arguments.
+fn parse_config(content: &str) -> Vec<OsString> {
+    let mut args = Vec::new();
+    for line in content.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        match line.split_once(char::is_whitespace) {
+            Some((option, value)) if option.starts_with('-') => {
+                args.push(option.into());
+                args.push(value.trim_start().into());
+            }
+            _ => args.push(line.into()),
+        }
+    }
+    args
+}
+
+/// Detaches the process from its terminal and session with a double fork.
+///
+/// The calling process does not return: it waits for the daemon to report its startup through the
+/// returned pipe, and exits.
+fn detach() -> io::Result<PipeWriter> {
+    let (mut reader, writer) = io::pipe()?;
+    match unsafe { libc::fork() } {
+        -1 => return Err(io::Error::last_os_error()),
+        0 => {}
+        child => {
+            drop(writer);
+            // The first child exits as soon as it forked the daemon
+            unsafe { libc::waitpid(child, ptr::null_mut(), 0) };
+            let mut status = [0u8; 1];
+            match reader.read(&mut status) {
+                Ok(1) => process::exit(EXIT_NOTHING_TO_DO),
+                _ => {
+                    error!("The daemon failed to start; see its log");
+                    process::exit(EXIT_USAGE_ERROR);
+                }
+            }
+        }
+    }
+    drop(reader);
+
+    // A new session without a controlling terminal, led by the first child; the daemon is not its
+    // leader, so it can never acquire a terminal again
+    if unsafe { libc::setsid() } == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    match unsafe { libc::fork() } {
+        -1 => return Err(io::Error::last_os_error()),
+        0 => {}
+        _ => unsafe { libc::_exit(0) },
+    }
+
+    env::set_current_dir("/")?;
+    let null = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open("/dev/null")?;
+    for fd in [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+        if unsafe { libc::dup2(null.as_raw_fd(), fd) } == -1 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    debug!("Detached from the terminal");
+    Ok(writer)
+}
+
+/// PID file of the daemon, locked while it runs and removed when dropped.
+///
+/// The lock, not the PID, tells whether a daemon is running, so a PID file left behind by a crash does
+/// not keep the next daemon from starting.
+struct PidFile {
+    file: File,
+    path: PathBuf,
+}
+
+impl PidFile {
+    /// Opens and locks the PID file at `path`, creating it if needed. The lock is inherited when
+    /// detaching, so it is taken before, while errors still reach the terminal.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` of kind `WouldBlock` if another daemon holds the lock, or any other `Err` if
+    /// the file cannot be opened or locked.
+    fn lock(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => return Err(io::ErrorKind::WouldBlock.into()),
+            Err(TryLockError::Error(e)) => return Err(e),
+        }
+        Ok(PidFile {
+            file,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Writes the PID of the current process, i.e. the daemon once detached.
+    fn write_pid(&mut self) -> io::Result<()> {
+        self.file.set_len(0)?;
+        writeln!(self.file, "{}", process::id())?;
+        self.file.sync_all()
+    }
+}
+
+impl Drop for PidFile {
+    fn drop(&mut self) {
+        // Removed while still locked, so a daemon starting meanwhile cannot lock the removed file
+        if let Err(e) = fs::remove_file(&self.path) {
+            debug!(
+                "Failed to remove the PID file ({}): {}",
+                self.path.display(),
+                e
+            );
+        }
+        let _ = self.file.unlock();
+    }
+}
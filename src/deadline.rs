@@ -0,0 +1,93 @@
+//! # Deadline Module
+//!
+//! This module contains the per-file deadline of the repair engine. The engine starts a deadline for
+//! the current thread before it processes a file, and the backends run their blocking filesystem calls
+//! through `run`, which moves the call to a worker thread and gives up on it once the deadline passes.
+//! A call stuck on a dead export therefore only costs the remaining time of its file.
+
+use std::cell::Cell;
+use std::io::{self, Error};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Time the cleanup of a file is given once its deadline has passed.
+const CLEANUP_GRACE: Duration = Duration::from_secs(10);
+
+thread_local! {
+    static DEADLINE: Cell<Option<Instant>> = const { Cell::new(None) };
+}
+
+/// Deadline of the current thread, restored to the previous one when dropped.
+pub(crate) struct DeadlineGuard {
+    previous: Option<Instant>,
+}
+
+impl Drop for DeadlineGuard {
+    fn drop(&mut self) {
+        DEADLINE.with(|deadline| deadline.set(self.previous));
+    }
+}
+
+/// Starts a deadline `timeout` from now for the current thread; `None` runs without a deadline.
+pub(crate) fn start(timeout: Option<Duration>) -> DeadlineGuard {
+    let previous = DEADLINE.with(|deadline| deadline.replace(timeout.map(|t| Instant::now() + t)));
+    DeadlineGuard { previous }
+}
+
+/// Extends the deadline of the current thread to at least `CLEANUP_GRACE` from now, for removing
+/// what a file that timed out left behind; without a deadline there is nothing to extend.
+pub(crate) fn grace() -> DeadlineGuard {
+    let previous = DEADLINE.with(|deadline| {
+        let grace = deadline
+            .get()
+            .map(|d| d.max(Instant::now() + CLEANUP_GRACE));
+        deadline.replace(grace)
+    });
+    DeadlineGuard { previous }
+}
+
+/// Checks whether the deadline of the current thread has passed.
+pub(crate) fn expired() -> bool {
+    DEADLINE
+        .with(Cell::get)
+        .is_some_and(|deadline| Instant::now() >= deadline)
+}
+
+/// Runs a blocking operation within the deadline of the current thread.
+///
+/// Without a deadline, the operation runs on the current thread. Otherwise it runs on a worker thread,
+/// and an error of kind `TimedOut` is returned once the deadline passes. The worker is left behind
+/// in that case; it finishes on its own if the filesystem ever responds.
+pub(crate) fn run<T, F>(operation: F) -> io::Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> io::Result<T> + Send + 'static,
+{
+    let deadline = match DEADLINE.with(Cell::get) {
+        Some(deadline) => deadline,
+        None => return operation(),
+    };
+    let remaining = deadline.saturating_duration_since(Instant::now());
+    if remaining.is_zero() {
+        return Err(timed_out());
+    }
+
+    let (sender, receiver) = mpsc::channel();
+    thread::Builder::new()
+        .name("netfs-unlker-io".to_string())
+        .spawn(move || {
+            let _ = sender.send(operation());
+        })?;
+
+    match receiver.recv_timeout(remaining) {
+        Ok(result) => result,
+        Err(RecvTimeoutError::Timeout) => Err(timed_out()),
+        Err(RecvTimeoutError::Disconnected) => Err(Error::other("filesystem worker panicked")),
+    }
+}
+
+/// Returns the error of an operation given up on once the deadline passed.
+pub(crate) fn timed_out() -> Error {
+    Error::new(io::ErrorKind::TimedOut, "per-file timeout expired")
+}
@@ -0,0 +1,81 @@
+//! # Repair Error Module
+//!
+//! This module contains the error type returned by the repair process for a single file.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// Error describing why the repair of a single file failed.
+#[derive(Debug)]
+pub enum RepairError {
+    /// An I/O operation failed.
+    Io(io::Error),
+    /// The file was modified by another process while it was being repaired.
+    /// The original file is left untouched.
+    ConcurrentModification,
+    /// The pre-repair hook failed; the file was left untouched.
+    PreHook(io::Error),
+    /// The post-repair hook failed; the file may already have been replaced.
+    PostHook(io::Error),
+    /// A stage kept failing with a stale NFS file handle, also after re-resolving the path.
+    StaleHandle(io::Error),
+    /// A directory of the sweep could not be read for lack of permissions.
+    PermissionDenied(io::Error),
+    /// A directory of the sweep could not be read.
+    ReadError(io::Error),
+    /// A policy file of the sweep could not be read or parsed; the files it covers were passed over.
+    InvalidPolicy(io::Error),
+    /// The SMB lease on the file could not be broken; the file was left untouched.
+    LeaseBreak(io::Error),
+}
+
+impl RepairError {
+    /// Classifies the error returned when reading a directory of the sweep.
+    pub(crate) fn unreadable(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::PermissionDenied => RepairError::PermissionDenied(e),
+            _ => RepairError::ReadError(e),
+        }
+    }
+}
+
+impl fmt::Display for RepairError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RepairError::Io(e) => write!(f, "{}", e),
+            RepairError::ConcurrentModification => {
+                write!(f, "file was modified concurrently during the repair")
+            }
+            RepairError::PreHook(e) => write!(f, "pre-repair hook failed: {}", e),
+            RepairError::PostHook(e) => write!(f, "post-repair hook failed: {}", e),
+            RepairError::StaleHandle(e) => write!(f, "file handle stayed stale: {}", e),
+            RepairError::PermissionDenied(e) => write!(f, "directory not readable: {}", e),
+            RepairError::ReadError(e) => write!(f, "failed to read directory: {}", e),
+            RepairError::InvalidPolicy(e) => write!(f, "invalid policy file: {}", e),
+            RepairError::LeaseBreak(e) => write!(f, "failed to break the SMB lease: {}", e),
+        }
+    }
+}
+
+impl Error for RepairError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RepairError::Io(e)
+            | RepairError::PreHook(e)
+            | RepairError::PostHook(e)
+            | RepairError::StaleHandle(e)
+            | RepairError::PermissionDenied(e)
+            | RepairError::ReadError(e)
+            | RepairError::InvalidPolicy(e)
+            | RepairError::LeaseBreak(e) => Some(e),
+            RepairError::ConcurrentModification => None,
+        }
+    }
+}
+
+impl From<io::Error> for RepairError {
+    fn from(e: io::Error) -> Self {
+        RepairError::Io(e)
+    }
+}
@@ -0,0 +1,94 @@
+//! # Output Format Module
+//!
+//! This module contains helpers shared by the report serializers: the CSV layout, lossy path
+//! serialization, durations in seconds and RFC 3339 timestamps.
+
+use crate::backend::{LockKind, LockType};
+use serde::{Deserialize, Deserializer, Serializer};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Columns of the CSV reports, shared by the scan and the repair reports so they can be loaded
+/// into the same spreadsheet.
+pub(crate) const CSV_COLUMNS: [&str; 8] = [
+    "path",
+    "size",
+    "lock_type",
+    "pid",
+    "action",
+    "duration",
+    "error",
+    "lock_kind",
+];
+
+/// Returns the name of a lock type as used in the reports.
+pub(crate) fn lock_type_name(lock_type: LockType) -> &'static str {
+    match lock_type {
+        LockType::Shared => "shared",
+        LockType::Exclusive => "exclusive",
+    }
+}
+
+/// Returns the name of a lock kind as used in the reports.
+pub(crate) fn lock_kind_name(kind: LockKind) -> &'static str {
+    match kind {
+        LockKind::Record => "record",
+        LockKind::SmbLease => "smb_lease",
+    }
+}
+
+/// Writes a single CSV row, quoting fields that contain separators, quotes or line breaks.
+pub(crate) fn write_csv_row<W: Write>(writer: &mut W, fields: &[&str]) -> io::Result<()> {
+    let row: Vec<String> = fields
+        .iter()
+        .map(|field| {
+            if field.contains([',', '"', '\n', '\r']) {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            } else {
+                field.to_string()
+            }
+        })
+        .collect();
+    writeln!(writer, "{}", row.join(","))
+}
+
+/// Serializes a path as a string, replacing invalid UTF-8 sequences.
+pub(crate) fn serialize_path<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&path.to_string_lossy())
+}
+
+/// Serializes an optional path as a string or `null`, replacing invalid UTF-8 sequences.
+pub(crate) fn serialize_optional_path<S: Serializer>(
+    path: &Option<PathBuf>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match path {
+        Some(path) => serialize_path(path, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Serializes a duration as fractional seconds.
+pub(crate) fn serialize_secs<S: Serializer>(
+    duration: &Duration,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
+
+/// Serializes a point in time as an RFC 3339 timestamp in UTC.
+pub(crate) fn serialize_time<S: Serializer>(
+    time: &SystemTime,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(&humantime::format_rfc3339_nanos(*time))
+}
+
+/// Deserializes an RFC 3339 timestamp written by `serialize_time`.
+pub(crate) fn deserialize_time<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<SystemTime, D::Error> {
+    let timestamp = String::deserialize(deserializer)?;
+    humantime::parse_rfc3339(&timestamp).map_err(serde::de::Error::custom)
+}
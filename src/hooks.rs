@@ -0,0 +1,129 @@
+//! # Hooks Module
+//!
+//! This module contains the `Hooks` trait the repair engine calls around the repair of every locked file,
+//! for example to stop the service consuming a file before it is replaced and to start it again afterwards,
+//! and `CommandHooks`, which runs shell commands.
+
+use crate::report::FileOutcome;
+use std::io;
+use std::path::Path;
+use std::process::Command;
+
+/// Environment variable holding the path of the file a hook command runs for.
+pub const PATH_ENV: &str = "NETFS_UNLKER_PATH";
+/// Environment variable holding the outcome of the repair, set for post-repair hook commands.
+pub const OUTCOME_ENV: &str = "NETFS_UNLKER_OUTCOME";
+
+/// Callbacks run around the repair of a locked file.
+///
+/// `pre_repair` runs once a file was found locked, before anything is changed; an `Err` aborts the
+/// repair of that file. `post_repair` runs after every repair whose `pre_repair` succeeded, including
+/// failed ones, so a stopped service is always started again; an `Err` marks the file as failed.
+pub trait Hooks {
+    /// Called before the locked file at `path` is repaired.
+    fn pre_repair(&self, path: &Path) -> io::Result<()>;
+
+    /// Called after the repair of the file at `path` finished with `outcome`.
+    fn post_repair(&self, path: &Path, outcome: &FileOutcome) -> io::Result<()>;
+}
+
+/// `Hooks` implementation running shell commands.
+///
+/// The commands run through `sh -c` (`cmd /C` on Windows). The file path is passed as the first
+/// positional argument (`$1`) and in the `NETFS_UNLKER_PATH` environment variable; the post-repair
+/// command also gets the outcome (`repaired`, `unverified`, `quarantined`, `timed_out` or `failed`) in
+/// `NETFS_UNLKER_OUTCOME`.
+/// A command exiting with a non-zero status counts as a failed hook.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::path::Path;
+/// use netfs_unlker::backend::{NativeFs, NativeLocks};
+/// use netfs_unlker::hooks::CommandHooks;
+/// use netfs_unlker::{RepairOptions, Repairer};
+///
+/// let hooks = CommandHooks::new(
+///     Some("systemctl stop consumer.service".to_string()),
+///     Some("systemctl start consumer.service".to_string()),
+/// );
+/// let repairer = Repairer::new(NativeFs::default(), NativeLocks::default(), RepairOptions::default())
+///     .with_hooks(hooks);
+/// let report = repairer.repair_file(Path::new("/mnt/share/data.db"));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CommandHooks {
+    pre_repair: Option<String>,
+    post_repair: Option<String>,
+}
+
+impl CommandHooks {
+    /// Creates hooks running the given commands; `None` skips the hook.
+    pub fn new(pre_repair: Option<String>, post_repair: Option<String>) -> Self {
+        CommandHooks {
+            pre_repair,
+            post_repair,
+        }
+    }
+}
+
+impl Hooks for CommandHooks {
+    fn pre_repair(&self, path: &Path) -> io::Result<()> {
+        match &self.pre_repair {
+            Some(command) => run(command, path, None),
+            None => Ok(()),
+        }
+    }
+
+    fn post_repair(&self, path: &Path, outcome: &FileOutcome) -> io::Result<()> {
+        let outcome = match outcome {
+            FileOutcome::Repaired => "repaired",
+            FileOutcome::RepairedButUnverified(_) => "unverified",
+            FileOutcome::Quarantined { .. } => "quarantined",
+            FileOutcome::TimedOut => "timed_out",
+            _ => "failed",
+        };
+
+        match &self.post_repair {
+            Some(command) => run(command, path, Some(outcome)),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Runs a hook command for `path` and waits for it to finish.
+fn run(command: &str, path: &Path, outcome: Option<&str>) -> io::Result<()> {
+    let mut process = shell(command, path);
+    process.env(PATH_ENV, path);
+    if let Some(outcome) = outcome {
+        process.env(OUTCOME_ENV, outcome);
+    }
+
+    let status = process.status()?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(io::Error::other(format!(
+            "hook command `{}` failed: {}",
+            command, status
+        ))),
+    }
+}
+
+#[cfg(unix)]
+fn shell(command: &str, path: &Path) -> Command {
+    let mut process = Command::new("sh");
+    // `$0` is the hook name, the path becomes `$1`
+    process
+        .arg("-c")
+        .arg(command)
+        .arg("netfs-unlker-hook")
+        .arg(path);
+    process
+}
+
+#[cfg(windows)]
+fn shell(command: &str, path: &Path) -> Command {
+    let mut process = Command::new("cmd");
+    process.arg("/C").arg(command).arg(path);
+    process
+}
@@ -3,29 +3,67 @@
 //! This module contains functions for repairing files that are locked by the NetApp storage system.
 //! It provides functionalities to handle single files or all files within a directory, managing file operations like copying, renaming, and unlocking.
 
+#[cfg(unix)]
 extern crate libc;
-extern crate log;
 
-mod fcntl;
-
-use log::{debug, error, info, warn};
-use std::collections::VecDeque;
-use std::fs::{copy, read_dir, rename, File};
-use std::io::{self, Error};
-use std::path::{Path, PathBuf};
-use tempfile::tempdir;
-
-const INVALID_UTF8: &str = "[Invalid UTF-8]";
-const DEVIDER: &str = "#############################\n";
+#[cfg(feature = "agent")]
+pub mod agent;
+pub mod audit;
+pub mod backend;
+pub mod cache;
+mod deadline;
+pub mod error;
+mod format;
+pub mod hooks;
+#[cfg(unix)]
+pub mod locks;
+pub mod maintenance;
+pub mod mock;
+pub mod monitor;
+#[cfg(unix)]
+mod mounts;
+#[cfg(feature = "webhook")]
+pub mod notify;
+#[cfg(feature = "ontap")]
+pub mod ontap;
+pub mod options;
+pub mod policy;
+#[cfg(unix)]
+mod proc_locks;
+pub mod progress;
+pub mod repair;
+pub mod report;
+pub mod run_guard;
+pub mod scan;
+pub mod strategy;
+pub mod throttle;
+#[cfg(all(target_os = "linux", feature = "io-uring"))]
+mod uring;
+pub mod walk;
+#[cfg(windows)]
+mod win32;
+
+pub use error::RepairError;
+pub use options::RepairOptions;
+pub use repair::Repairer;
+pub use report::{
+    FileOutcome, FileReport, RepairReport, ReportSummary, RunStatistics, SkipReason, SlowFile,
+    VerificationFailure,
+};
+
+use backend::{NativeFs, NativeLocks};
+use std::io;
+use std::path::Path;
 
 /// Repairs all files in the specified directory.
 ///
 /// This function iterates over each file in the directory and attempts to repair it if it is locked.
-/// It logs the process and returns an `io::Result<()>` indicating the outcome.
+/// It logs the process and returns a `RepairReport` with the outcome for every processed file.
+/// A failure to repair a single file is recorded in the report and does not stop the run.
 ///
 /// # Errors
 ///
-/// Returns an `Err` if the specified directory path does not exist or if any file in the directory cannot be processed.
+/// Returns an `Err` if the specified directory path does not exist or cannot be read.
 ///
 /// # Examples
 ///
@@ -37,40 +75,37 @@ const DEVIDER: &str = "#############################\n";
 /// let recursive = false;
 /// repair_files_in_directory(dir_path, recursive);
 /// ```
-pub fn repair_files_in_directory(directory_path: &Path, recursive: bool) -> io::Result<()> {
-    if !directory_path.is_dir() {
-        error!(
-            "Such directory not found: ({})",
-            directory_path.to_str().unwrap_or(INVALID_UTF8)
-        );
-        return Err(Error::from_raw_os_error(libc::ENOENT));
-    }
-
-    let mut buf: VecDeque<PathBuf> = VecDeque::new();
-    buf.push_back(directory_path.to_path_buf());
-
-    while let Some(queue_path) = buf.pop_front() {
-        let paths: Vec<PathBuf> = read_dir(queue_path)?.map(|x| x.unwrap().path()).collect();
-        for path in paths {
-            if path.is_dir() && recursive {
-                buf.push_back(path.clone());
-            } else {
-                unlock_netapp_file(&path)?;
-            }
-        }
-    }
+pub fn repair_files_in_directory(
+    directory_path: &Path,
+    recursive: bool,
+) -> io::Result<RepairReport> {
+    repair_files_in_directory_with_options(directory_path, recursive, &RepairOptions::default())
+}
 
-    Ok(())
+/// Repairs all files in the specified directory using the given options.
+///
+/// Behaves like `repair_files_in_directory`, with the repair process tuned by `options`.
+///
+/// # Errors
+///
+/// Returns an `Err` if the specified directory path does not exist or cannot be read.
+pub fn repair_files_in_directory_with_options(
+    directory_path: &Path,
+    recursive: bool,
+    options: &RepairOptions,
+) -> io::Result<RepairReport> {
+    native_repairer(options).repair_directory(directory_path, recursive)
 }
 
 /// Repairs a single file that is specified by the path.
 ///
 /// If the file is locked, this function will attempt to unlock and restore it.
 /// Logs are provided at each step to monitor the process.
+/// The outcome, including a failed repair, is returned as a single-entry `RepairReport`.
 ///
 /// # Errors
 ///
-/// Returns an `Err` if the file does not exist or if the repair process fails.
+/// Returns an `Err` if the file does not exist.
 ///
 /// # Examples
 ///
@@ -81,96 +116,25 @@ pub fn repair_files_in_directory(directory_path: &Path, recursive: bool) -> io::
 /// let file_path = Path::new("/path/to/file.txt");
 /// repair_file(file_path);
 /// ```
-pub fn repair_file(file_path: &Path) -> io::Result<()> {
-    debug!("{}", DEVIDER);
-    unlock_netapp_file(file_path)
+pub fn repair_file(file_path: &Path) -> io::Result<RepairReport> {
+    repair_file_with_options(file_path, &RepairOptions::default())
 }
 
-/// Handles the actual unlocking and repairing process for a locked file.
+/// Repairs a single file using the given options.
 ///
-/// Detailed logs are written to track the progress and any errors encountered during the process.
-/// It involves copying the file to a temporary location, unlocking it, and then replacing the original file.
+/// Behaves like `repair_file`, with the repair process tuned by `options`.
 ///
 /// # Errors
 ///
-/// Returns an `Err` if any step in the repair process fails, including invalid file path or access errors.
-fn unlock_netapp_file(file_path: &Path) -> io::Result<()> {
-    debug!(
-        "Start unlocking file: ({})",
-        file_path.to_str().unwrap_or(INVALID_UTF8)
-    );
-
-    let dir = tempdir()?;
-
-    if !file_path.is_file() {
-        warn!(
-            "This is not a file name: ({})",
-            file_path.to_str().unwrap_or(INVALID_UTF8)
-        );
-        return Ok(());
-    }
-
-    let netapp_file = File::open(file_path)?;
-
-    if !fcntl::is_file_locked(&netapp_file) {
-        info!(
-            "File is not locked: ({})",
-            file_path.to_str().unwrap_or(INVALID_UTF8)
-        );
-        return Ok(());
-    }
-
-    let tmp_file_name = file_path
-        .file_name()
-        .and_then(|f| f.to_str())
-        .map(|s: &str| format!(".tmp.{}", s))
-        .ok_or_else(|| {
-            error!(
-                "Wrong format of file name ({})",
-                file_path.to_str().unwrap_or(INVALID_UTF8)
-            );
-            Error::from_raw_os_error(libc::ENOENT)
-        })?;
-
-    let local_tmp_file_path = dir.path().join(&tmp_file_name);
-
-    debug!(
-        "Copy from netapp: netapp ({}) -> local ({})",
-        file_path.to_str().unwrap_or(INVALID_UTF8),
-        local_tmp_file_path.to_str().unwrap_or(INVALID_UTF8)
-    );
-
-    copy(file_path, &local_tmp_file_path)?;
-
-    let tmp_file = File::open(&local_tmp_file_path)?;
-    debug!(
-        "Unlock file: ({})",
-        local_tmp_file_path.to_str().unwrap_or(INVALID_UTF8)
-    );
-    fcntl::unlock(&tmp_file)?;
-
-    let netapp_tmp_file_path = file_path
-        .parent()
-        .ok_or_else(|| Error::from_raw_os_error(libc::EINVAL))?
-        .join(&tmp_file_name);
-
-    debug!(
-        "Copy to back tmp path: local ({}) -> netapp ({})",
-        local_tmp_file_path.to_str().unwrap_or(INVALID_UTF8),
-        netapp_tmp_file_path.to_str().unwrap_or(INVALID_UTF8)
-    );
-    copy(&local_tmp_file_path, &netapp_tmp_file_path)?;
-    debug!(
-        "Atomic file rename: netapp({}) -> netapp ({})",
-        netapp_tmp_file_path.to_str().unwrap_or(INVALID_UTF8),
-        file_path.to_str().unwrap_or(INVALID_UTF8)
-    );
-    rename(&netapp_tmp_file_path, file_path)?;
-
-    info!(
-        "Successfully unlocked: ({})",
-        file_path.to_str().unwrap_or(INVALID_UTF8)
-    );
+/// Returns an `Err` if the file does not exist.
+pub fn repair_file_with_options(
+    file_path: &Path,
+    options: &RepairOptions,
+) -> io::Result<RepairReport> {
+    native_repairer(options).repair_file(file_path)
+}
 
-    Ok(())
+/// Creates a repair engine operating on the real filesystem.
+fn native_repairer(options: &RepairOptions) -> Repairer<NativeFs, NativeLocks> {
+    Repairer::new(NativeFs::default(), NativeLocks::default(), options.clone())
 }
@@ -0,0 +1,459 @@
+//! # Locks Module
+//!
+//! This module contains the low-level POSIX record lock primitives (`fcntl` with `F_SETLK` and
+//! `F_GETLK`) the POSIX backend is built on, for callers that want to probe, take or
+//! clear locks themselves. It is available on Unix.
+//!
+//! Record locks belong to a process: a process never conflicts with its own locks, and closing any
+//! descriptor of a file releases all locks the process holds on it. The probes therefore only
+//! report locks held by other processes, or by other clients of a network filesystem.
+//!
+//! A `FileLockGuard` holds a lock for a scope and releases it when dropped, including on early returns.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::fs::File;
+//! use std::time::Duration;
+//! use netfs_unlker::backend::LockType;
+//! use netfs_unlker::locks::{self, TryLock};
+//!
+//! let file = File::options().read(true).write(true).open("/mnt/share/data.db").unwrap();
+//! match locks::lock_with_timeout(&file, LockType::Exclusive, Duration::from_secs(5)).unwrap() {
+//!     TryLock::Acquired => println!("locked"),
+//!     TryLock::Conflict(Some(lock)) => println!("held by {:?}", lock.pid),
+//!     TryLock::Conflict(None) => println!("held by another process"),
+//! }
+//!
+//! let guard = locks::FileLockGuard::try_lock(&file, LockType::Exclusive).unwrap();
+//! // Update the file; the lock is released when the guard goes out of scope.
+//! # drop(guard);
+//! ```
+
+extern crate libc;
+
+use crate::backend::{LockInfo, LockKind, LockType};
+use std::fs::File;
+use std::io::{Error, ErrorKind, Result};
+use std::mem;
+use std::os::unix::io::AsRawFd;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Upper bound of the locks collected by `get_locked_ranges`.
+const MAX_LOCKED_RANGES: usize = 1024;
+
+/// Pause between two attempts of `lock_with_timeout`.
+const RETRY_DELAY: Duration = Duration::from_millis(20);
+
+/// Lock command of the `FileLockGuard`: open file description locks where available.
+#[cfg(target_os = "linux")]
+const GUARD_LOCK_CMD: libc::c_int = libc::F_OFD_SETLK;
+#[cfg(not(target_os = "linux"))]
+const GUARD_LOCK_CMD: libc::c_int = libc::F_SETLK;
+
+/// Outcome of a lock attempt that does not wait for the lock.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TryLock {
+    /// The lock was taken; it is held until it is unlocked or the process closes the file.
+    Acquired,
+    /// Another process holds a conflicting lock, described if it could still be queried afterwards.
+    Conflict(Option<LockInfo>),
+}
+
+/// Lock on a whole file that is released when the guard is dropped.
+///
+/// On Linux the guard takes an open file description (OFD) lock, which belongs to the `File` rather
+/// than to the process: it conflicts with locks taken through other descriptors of the same process,
+/// and closing another descriptor of the file does not release it. Elsewhere it takes a process lock.
+#[derive(Debug)]
+#[must_use = "the lock is released as soon as the guard is dropped"]
+pub struct FileLockGuard<'a> {
+    file: &'a File,
+    lock_type: LockType,
+}
+
+impl<'a> FileLockGuard<'a> {
+    /// Locks the whole file without waiting.
+    ///
+    /// # Arguments
+    ///
+    /// * `file` - A reference to the `File` to be locked, opened for reading (shared) or writing (exclusive).
+    /// * `lock_type` - Type of the lock to take.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` of kind `WouldBlock` if a conflicting lock is held, or any other `Err` if
+    /// locking failed for another reason.
+    pub fn try_lock(file: &'a File, lock_type: LockType) -> Result<Self> {
+        match lock_whole_file(file, GUARD_LOCK_CMD, lock_type)? {
+            true => Ok(FileLockGuard { file, lock_type }),
+            false => Err(Error::from_raw_os_error(libc::EWOULDBLOCK)),
+        }
+    }
+
+    /// Locks the whole file, retrying until the lock is granted or `timeout` expires.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` of kind `WouldBlock` if a conflicting lock is still held once the timeout
+    /// expired, or any other `Err` if locking failed for another reason.
+    pub fn lock_with_timeout(
+        file: &'a File,
+        lock_type: LockType,
+        timeout: Duration,
+    ) -> Result<Self> {
+        let started = Instant::now();
+        loop {
+            match Self::try_lock(file, lock_type) {
+                Err(e) if e.kind() == ErrorKind::WouldBlock && started.elapsed() < timeout => {
+                    thread::sleep(RETRY_DELAY)
+                }
+                outcome => return outcome,
+            }
+        }
+    }
+
+    /// Returns the type of the lock held.
+    pub fn lock_type(&self) -> LockType {
+        self.lock_type
+    }
+
+    /// Returns the locked file.
+    pub fn file(&self) -> &'a File {
+        self.file
+    }
+
+    /// Turns an exclusive lock into a shared one, without a window in which the file is unlocked.
+    /// A shared lock is left as it is.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the lock cannot be converted; the exclusive lock is then still held.
+    pub fn downgrade(&mut self) -> Result<()> {
+        if self.lock_type == LockType::Shared {
+            return Ok(());
+        }
+
+        set_lock(self.file, GUARD_LOCK_CMD, libc::F_RDLCK, 0, 0)?;
+        self.lock_type = LockType::Shared;
+        Ok(())
+    }
+
+    /// Drops the guard but keeps the lock, which is then held until the file is closed or unlocked.
+    pub fn forget(self) {
+        mem::forget(self);
+    }
+}
+
+impl Drop for FileLockGuard<'_> {
+    fn drop(&mut self) {
+        let _ = set_lock(self.file, GUARD_LOCK_CMD, libc::F_UNLCK, 0, 0);
+    }
+}
+
+/// Tries to take a shared (read) lock on the whole file without waiting.
+///
+/// # Arguments
+///
+/// * `file` - A reference to the `File` to be locked, opened for reading.
+///
+/// # Returns
+///
+/// Returns `TryLock::Acquired` if the lock was taken, `TryLock::Conflict` if another process holds an
+/// exclusive lock, or an `Err` if locking failed for another reason.
+pub fn try_lock_shared(file: &File) -> Result<TryLock> {
+    try_lock(file, LockType::Shared)
+}
+
+/// Tries to take an exclusive (write) lock on the whole file without waiting.
+///
+/// # Arguments
+///
+/// * `file` - A reference to the `File` to be locked, opened for writing.
+///
+/// # Returns
+///
+/// Returns `TryLock::Acquired` if the lock was taken, `TryLock::Conflict` if another process holds any
+/// lock on the file, or an `Err` if locking failed for another reason.
+pub fn try_lock_exclusive(file: &File) -> Result<TryLock> {
+    try_lock(file, LockType::Exclusive)
+}
+
+/// Takes a lock on the whole file, retrying until it is granted or `timeout` expires.
+///
+/// # Arguments
+///
+/// * `file` - A reference to the `File` to be locked, opened for reading (shared) or writing (exclusive).
+/// * `lock_type` - Type of the lock to take.
+/// * `timeout` - How long to keep retrying.
+///
+/// # Returns
+///
+/// Returns `TryLock::Acquired` if the lock was taken in time, the last `TryLock::Conflict` once the
+/// timeout expired, or an `Err` if locking failed for another reason.
+pub fn lock_with_timeout(file: &File, lock_type: LockType, timeout: Duration) -> Result<TryLock> {
+    let started = Instant::now();
+    loop {
+        match try_lock(file, lock_type)? {
+            TryLock::Conflict(_) if started.elapsed() < timeout => thread::sleep(RETRY_DELAY),
+            outcome => return Ok(outcome),
+        }
+    }
+}
+
+/// Unlocks a file that was previously locked.
+///
+/// # Arguments
+///
+/// * `file` - A reference to the `File` that needs to be unlocked.
+///
+/// # Returns
+///
+/// This function returns a `Result` which is `Ok` if the file was successfully unlocked, or an `Err`
+/// if an error occurred during unlocking.
+pub fn unlock(file: &File) -> Result<()> {
+    set_lock(file, libc::F_SETLK, libc::F_UNLCK, 0, 0)
+}
+
+/// Unlocks a byte range of a file.
+///
+/// Only the locks of the calling process are released: unlocking a range locked by another
+/// process, or by another client of a network filesystem, succeeds without releasing its lock.
+///
+/// # Arguments
+///
+/// * `file` - A reference to the `File` that needs to be unlocked.
+/// * `start` - Offset of the first byte of the range.
+/// * `len` - Length of the range; `None` means until EOF.
+///
+/// # Returns
+///
+/// This function returns a `Result` which is `Ok` if the range was successfully unlocked, or an `Err`
+/// if an error occurred during unlocking, of kind `InvalidInput` if the range does not fit into the
+/// offsets of `fcntl`.
+pub fn unlock_range(file: &File, start: u64, len: Option<u64>) -> Result<()> {
+    let offset = |value: u64| {
+        i64::try_from(value).map_err(|_| Error::new(ErrorKind::InvalidInput, "range out of bounds"))
+    };
+    set_lock(
+        file,
+        libc::F_SETLK,
+        libc::F_UNLCK,
+        offset(start)?,
+        len.map_or(Ok(0), offset)?,
+    )
+}
+
+/// Checks if a file is locked.
+///
+/// # Arguments
+///
+/// * `file` - A reference to the `File` to be checked.
+///
+/// # Returns
+///
+/// Returns `true` if the file is locked, `false` otherwise.
+pub fn is_file_locked(file: &File) -> bool {
+    file.metadata()
+        .and_then(|m| is_file_locked_internal(file, m.len() as i64))
+        .unwrap_or(false)
+}
+
+/// Returns the lock that would prevent an exclusive lock on the whole file.
+///
+/// # Arguments
+///
+/// * `file` - A reference to the `File` to be checked.
+///
+/// # Returns
+///
+/// Returns `Ok(Some(lock))` describing the conflicting lock (type, byte range and holder PID),
+/// `Ok(None)` if the file is not locked, or an `Err` if the lock cannot be queried.
+pub fn get_lock_info(file: &File) -> Result<Option<LockInfo>> {
+    let fl = probe(file, 0, file.metadata()?.len() as i64)?;
+    match fl.l_type == libc::F_UNLCK as i16 {
+        true => Ok(None),
+        false => Ok(Some(lock_info(&fl))),
+    }
+}
+
+/// Returns all locks held by other processes on a file, ordered by their start offset.
+///
+/// `F_GETLK` reports a single conflicting lock per call, so the file is probed repeatedly,
+/// splitting the searched range around every lock found until no conflicting lock is left.
+///
+/// # Arguments
+///
+/// * `file` - A reference to the `File` to be checked.
+///
+/// # Returns
+///
+/// Returns the list of conflicting locks, or an `Err` if the locks cannot be queried.
+pub fn get_locked_ranges(file: &File) -> Result<Vec<LockInfo>> {
+    let mut ranges: Vec<libc::flock> = Vec::new();
+    // Ranges still to be searched as (start, end); `None` as end means until EOF
+    let mut pending: Vec<(i64, Option<i64>)> = vec![(0, None)];
+
+    while let Some((start, end)) = pending.pop() {
+        if ranges.len() >= MAX_LOCKED_RANGES {
+            break;
+        }
+
+        let fl = probe(file, start, end.map_or(0, |end| end - start))?;
+        if fl.l_type == libc::F_UNLCK as i16 {
+            continue;
+        }
+
+        let lock_end = match fl.l_len {
+            0 => None,
+            len => Some(fl.l_start + len),
+        };
+        if fl.l_start > start {
+            pending.push((start, Some(fl.l_start)));
+        }
+        if let Some(lock_end) = lock_end {
+            if end.is_none() || end > Some(lock_end) {
+                pending.push((lock_end, end));
+            }
+        }
+        ranges.push(fl);
+    }
+
+    ranges.sort_by_key(|fl| fl.l_start);
+    Ok(ranges.iter().map(lock_info).collect())
+}
+
+/// Checks if a read lease on a file is refused because of a conflicting open or lease.
+///
+/// # Arguments
+///
+/// * `file` - A reference to the `File` to be checked, opened for reading.
+///
+/// # Returns
+///
+/// Returns `Ok(true)` if the lease is refused with `EAGAIN`, `Ok(false)` if it was granted (it is
+/// released again right away), or an `Err` if leases cannot be taken on the file, e.g. `EACCES` if
+/// the file belongs to another user.
+#[cfg(target_os = "linux")]
+pub fn is_read_lease_refused(file: &File) -> Result<bool> {
+    let ret = unsafe { libc::fcntl(file.as_raw_fd(), libc::F_SETLEASE, libc::F_RDLCK) };
+    if ret == 0 {
+        unsafe { libc::fcntl(file.as_raw_fd(), libc::F_SETLEASE, libc::F_UNLCK) };
+        return Ok(false);
+    }
+
+    match Error::last_os_error().raw_os_error() {
+        Some(libc::EAGAIN) => Ok(true),
+        _ => Err(Error::last_os_error()),
+    }
+}
+
+/// Tries to take a lock of the given type on the whole file, reporting the holder on a conflict.
+fn try_lock(file: &File, lock_type: LockType) -> Result<TryLock> {
+    match lock_whole_file(file, libc::F_SETLK, lock_type)? {
+        true => Ok(TryLock::Acquired),
+        false => Ok(TryLock::Conflict(get_lock_info(file).ok().flatten())),
+    }
+}
+
+/// Takes a lock of the given type on the whole file with `cmd`, returning `false` on a conflict.
+fn lock_whole_file(file: &File, cmd: libc::c_int, lock_type: LockType) -> Result<bool> {
+    let l_type = match lock_type {
+        LockType::Shared => libc::F_RDLCK,
+        LockType::Exclusive => libc::F_WRLCK,
+    };
+
+    match set_lock(file, cmd, l_type, 0, 0) {
+        Ok(()) => Ok(true),
+        Err(e) if is_conflict(&e) => Ok(false),
+        Err(e) => Err(e),
+    }
+}
+
+/// Internal function to apply a file lock.
+///
+/// # Arguments
+///
+/// * `file` - A reference to the `File` to be locked or unlocked.
+/// * `cmd` - `F_SETLK` for a process lock, or `F_OFD_SETLK` for an open file description lock.
+/// * `l_type` - `F_RDLCK`, `F_WRLCK` or `F_UNLCK`.
+/// * `start` - Offset of the first byte of the range
+/// * `len` - Length of the range; 0 means until EOF
+///
+/// # Returns
+///
+/// Returns a `Result` which is `Ok` if the lock operation was successful, or an `Err` if an error occurred.
+fn set_lock(
+    file: &File,
+    cmd: libc::c_int,
+    l_type: libc::c_int,
+    start: i64,
+    len: i64,
+) -> Result<()> {
+    let fl = libc::flock {
+        l_whence: 0,           // Offset from the start of the file
+        l_start: start,        // Start of the lock
+        l_len: len,            // Length of the lock; 0 means until EOF
+        l_type: l_type as i16, // Type of lock
+        l_pid: 0,              // PID of the process holding the lock
+    };
+
+    let ret = unsafe { libc::fcntl(file.as_raw_fd(), cmd, &fl) };
+    match ret {
+        -1 => Err(Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+/// Checks whether a lock error means that another process holds a conflicting lock.
+fn is_conflict(e: &Error) -> bool {
+    // POSIX allows either errno for a conflicting lock
+    matches!(e.raw_os_error(), Some(libc::EACCES) | Some(libc::EAGAIN))
+}
+
+fn is_file_locked_internal(file: &File, size: i64) -> Result<bool> {
+    match probe(file, 0, size) {
+        Ok(fl) => Ok(fl.l_type != libc::F_UNLCK as i16), // F_UNLCK means nothing would block us
+        Err(e) => match e.raw_os_error() {
+            Some(libc::EACCES) => Ok(true), // Handle access error as would-block error
+            _ => Ok(false),
+        },
+    }
+}
+
+fn probe(file: &File, start: i64, len: i64) -> Result<libc::flock> {
+    let mut fl = libc::flock {
+        l_whence: 0,                  // Offset from the start of the file
+        l_start: start,               // Start of the lock
+        l_len: len,                   // Length of the lock; 0 means until EOF
+        l_type: libc::F_WRLCK as i16, // Write lock conflicts with any other lock
+        l_pid: 0,                     // PID of the process holding the lock
+    };
+
+    let ret = unsafe { libc::fcntl(file.as_raw_fd(), libc::F_GETLK, &mut fl) };
+    match ret {
+        -1 => Err(Error::last_os_error()),
+        _ => Ok(fl),
+    }
+}
+
+/// Converts a lock reported by `F_GETLK` into a `LockInfo`.
+fn lock_info(fl: &libc::flock) -> LockInfo {
+    LockInfo {
+        kind: LockKind::Record,
+        lock_type: match fl.l_type as libc::c_int {
+            libc::F_RDLCK => LockType::Shared,
+            _ => LockType::Exclusive,
+        },
+        start: fl.l_start as u64,
+        len: match fl.l_len {
+            0 => None, // Locked until EOF
+            len => Some(len as u64),
+        },
+        pid: match fl.l_pid {
+            pid if pid > 0 => Some(pid as u32),
+            _ => None,
+        },
+    }
+}
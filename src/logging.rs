@@ -0,0 +1,360 @@
+//! # Logging Module
+//!
+//! This module contains the logging setup of the command-line tool: the log format, and the log target
+//! the events are written to (stderr, syslog, journald or a rotated log file). The log file can be reopened
+//! while running, after an external tool such as logrotate moved it away.
+
+use clap::ValueEnum;
+use std::fmt;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Mutex, OnceLock};
+use tracing::level_filters::LevelFilter;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{Layer, Registry};
+
+/// Format of the log output.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum LogFormat {
+    /// Human-readable lines with the span context of every event.
+    Human,
+    /// One JSON object per event, including the fields of the enclosing spans.
+    Json,
+}
+
+/// Destination of the log output.
+#[derive(Clone, Debug)]
+pub enum LogTarget {
+    /// Standard error.
+    Stderr,
+    /// The local syslog daemon.
+    Syslog,
+    /// The systemd journal, with the span and event fields as journal fields.
+    Journald,
+    /// A log file, rotated according to `LogRotation`.
+    File(PathBuf),
+}
+
+impl FromStr for LogTarget {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "stderr" => Ok(LogTarget::Stderr),
+            "syslog" => Ok(LogTarget::Syslog),
+            "journald" => Ok(LogTarget::Journald),
+            _ => match value.strip_prefix("file:") {
+                Some(path) if !path.is_empty() => Ok(LogTarget::File(PathBuf::from(path))),
+                _ => Err(format!(
+                    "invalid log target: {} (expected stderr, syslog, journald or file:<path>)",
+                    value
+                )),
+            },
+        }
+    }
+}
+
+impl fmt::Display for LogTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LogTarget::Stderr => write!(f, "stderr"),
+            LogTarget::Syslog => write!(f, "syslog"),
+            LogTarget::Journald => write!(f, "journald"),
+            LogTarget::File(path) => write!(f, "file:{}", path.display()),
+        }
+    }
+}
+
+/// Rotation period of the log file target.
+#[derive(Clone, Copy, ValueEnum)]
+pub enum LogRotation {
+    /// Start a new log file every minute.
+    Minutely,
+    /// Start a new log file every hour.
+    Hourly,
+    /// Start a new log file every day.
+    Daily,
+    /// Always write to the same log file.
+    Never,
+}
+
+impl From<LogRotation> for Rotation {
+    fn from(rotation: LogRotation) -> Self {
+        match rotation {
+            LogRotation::Minutely => Rotation::MINUTELY,
+            LogRotation::Hourly => Rotation::HOURLY,
+            LogRotation::Daily => Rotation::DAILY,
+            LogRotation::Never => Rotation::NEVER,
+        }
+    }
+}
+
+/// Settings of the log output.
+pub struct LogConfig {
+    /// Format of the log output; ignored for journald, which receives structured fields.
+    pub format: LogFormat,
+    /// Maximum level of the logged events.
+    pub level: LevelFilter,
+    /// Destination of the log output.
+    pub target: LogTarget,
+    /// Rotation period of the log file target.
+    pub rotation: LogRotation,
+    /// Number of rotated log files to keep, or `None` to keep all of them.
+    pub max_files: Option<usize>,
+}
+
+/// Installs the global tracing subscriber for the given configuration.
+///
+/// # Errors
+///
+/// Returns an `Err` if the log target cannot be opened or is not supported on this platform.
+pub fn init(config: &LogConfig) -> io::Result<()> {
+    let layer = match &config.target {
+        LogTarget::Stderr => fmt_layer(
+            config.format,
+            BoxMakeWriter::new(io::stderr),
+            io::stderr().is_terminal(),
+            true,
+        ),
+        LogTarget::File(path) => {
+            let directory = match path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent.to_path_buf(),
+                _ => PathBuf::from("."),
+            };
+            let file_name = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid log file"))?;
+
+            fs::create_dir_all(&directory)?;
+
+            let mut log_file = LogFile {
+                directory,
+                file_name: file_name.to_string(),
+                rotation: config.rotation,
+                max_files: config.max_files,
+                appender: Mutex::new(None),
+            };
+            log_file.appender = Mutex::new(Some(log_file.open()?));
+            let log_file = LOG_FILE.get_or_init(|| log_file);
+            fmt_layer(config.format, BoxMakeWriter::new(log_file), false, true)
+        }
+        #[cfg(unix)]
+        LogTarget::Syslog => {
+            syslog::open();
+            fmt_layer(
+                config.format,
+                BoxMakeWriter::new(syslog::Syslog),
+                false,
+                false,
+            )
+        }
+        #[cfg(unix)]
+        LogTarget::Journald => tracing_journald::layer()?
+            .with_syslog_identifier(IDENTIFIER.to_string())
+            .boxed(),
+        #[cfg(not(unix))]
+        target => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("log target {} is not supported on this platform", target),
+            ))
+        }
+    };
+
+    tracing_subscriber::registry()
+        .with(layer.with_filter(config.level))
+        .init();
+    Ok(())
+}
+
+/// Reopens the log file, e.g. after logrotate moved it away; the events of other log targets are not
+/// affected. The previous log file is kept if the new one cannot be opened.
+///
+/// # Errors
+///
+/// Returns an `Err` if the log file cannot be opened.
+#[cfg(unix)]
+pub fn reopen() -> io::Result<()> {
+    if let Some(log_file) = LOG_FILE.get() {
+        let appender = log_file.open()?;
+        *log_file.appender.lock().unwrap_or_else(|e| e.into_inner()) = Some(appender);
+    }
+    Ok(())
+}
+
+/// Log file target, once logging is set up.
+static LOG_FILE: OnceLock<LogFile> = OnceLock::new();
+
+/// Log file target whose appender can be replaced while events are written.
+struct LogFile {
+    directory: PathBuf,
+    file_name: String,
+    rotation: LogRotation,
+    max_files: Option<usize>,
+    appender: Mutex<Option<RollingFileAppender>>,
+}
+
+impl LogFile {
+    /// Opens a new appender, creating the log file if it was moved away.
+    fn open(&self) -> io::Result<RollingFileAppender> {
+        let mut builder = RollingFileAppender::builder()
+            .rotation(self.rotation.into())
+            .filename_prefix(&self.file_name);
+        if let Some(max_files) = self.max_files {
+            builder = builder.max_log_files(max_files);
+        }
+        builder.build(&self.directory).map_err(io::Error::other)
+    }
+}
+
+impl<'a> MakeWriter<'a> for &'static LogFile {
+    type Writer = LogFileWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        LogFileWriter {
+            log_file: self,
+            buf: Vec::new(),
+        }
+    }
+}
+
+/// Buffers one formatted event and appends it to the current log file when dropped, so that an event
+/// is never split between the old and the new file.
+struct LogFileWriter {
+    log_file: &'static LogFile,
+    buf: Vec<u8>,
+}
+
+impl Write for LogFileWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buf.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for LogFileWriter {
+    fn drop(&mut self) {
+        let mut appender = self
+            .log_file
+            .appender
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        if let Some(appender) = appender.as_mut() {
+            let _ = appender.write_all(&self.buf);
+        }
+    }
+}
+
+/// Identifier of the log messages in syslog and the journal.
+#[cfg(unix)]
+const IDENTIFIER: &str = "netfs_unlker";
+
+/// Builds the formatting layer writing to `writer`.
+///
+/// Colors are only used on terminals, and timestamps are left out where the target adds its own.
+fn fmt_layer(
+    format: LogFormat,
+    writer: BoxMakeWriter,
+    ansi: bool,
+    time: bool,
+) -> Box<dyn Layer<Registry> + Send + Sync> {
+    let layer = tracing_subscriber::fmt::layer()
+        .with_writer(writer)
+        .with_ansi(ansi);
+    match (format, time) {
+        (LogFormat::Human, true) => layer.boxed(),
+        (LogFormat::Human, false) => layer.without_time().boxed(),
+        (LogFormat::Json, true) => layer.json().with_current_span(true).boxed(),
+        (LogFormat::Json, false) => layer.json().with_current_span(true).without_time().boxed(),
+    }
+}
+
+#[cfg(unix)]
+mod syslog {
+    //! Writer sending every formatted event to the local syslog daemon with `syslog(3)`.
+
+    use std::ffi::CString;
+    use std::io::{self, Write};
+    use tracing::{Level, Metadata};
+    use tracing_subscriber::fmt::MakeWriter;
+
+    /// Opens the connection to syslog, tagging the messages with the program name and PID.
+    pub fn open() {
+        unsafe { libc::openlog(c"netfs_unlker".as_ptr(), libc::LOG_PID, libc::LOG_USER) };
+    }
+
+    /// `MakeWriter` that creates one `SyslogWriter` per event.
+    pub struct Syslog;
+
+    impl<'a> MakeWriter<'a> for Syslog {
+        type Writer = SyslogWriter;
+
+        fn make_writer(&'a self) -> Self::Writer {
+            SyslogWriter::new(libc::LOG_INFO)
+        }
+
+        fn make_writer_for(&'a self, meta: &Metadata<'_>) -> Self::Writer {
+            SyslogWriter::new(match *meta.level() {
+                Level::ERROR => libc::LOG_ERR,
+                Level::WARN => libc::LOG_WARNING,
+                Level::INFO => libc::LOG_INFO,
+                Level::DEBUG | Level::TRACE => libc::LOG_DEBUG,
+            })
+        }
+    }
+
+    /// Buffers one formatted event and sends it as a single syslog message when dropped.
+    pub struct SyslogWriter {
+        priority: libc::c_int,
+        buf: Vec<u8>,
+    }
+
+    impl SyslogWriter {
+        fn new(priority: libc::c_int) -> Self {
+            SyslogWriter {
+                priority,
+                buf: Vec::new(),
+            }
+        }
+    }
+
+    impl Write for SyslogWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.buf.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Drop for SyslogWriter {
+        fn drop(&mut self) {
+            let message: Vec<u8> = self
+                .buf
+                .trim_ascii_end()
+                .iter()
+                .copied()
+                .filter(|&b| b != 0)
+                .collect();
+            if message.is_empty() {
+                return;
+            }
+
+            let message = CString::new(message).expect("NUL bytes were removed");
+            unsafe { libc::syslog(self.priority, c"%s".as_ptr(), message.as_ptr()) };
+        }
+    }
+}
@@ -1,85 +1,891 @@
 //! A command-line tool to repair locked files using the `netfs-unlker` library.
 //!
-//! This tool uses `clap` for command-line argument parsing and `log` for logging.
-//! It provides an option to specify a single file or a directory containing multiple files
-//! for repair operations. The actual repair functions are hypothetically provided by the `netfs-unlker` library.
-
-use clap::Parser;
-use log::LevelFilter;
-use log::{error, info};
-use simple_logger::SimpleLogger;
-use std::path::PathBuf;
+//! This tool uses `clap` for command-line argument parsing and `tracing` for logging.
+//! It is organized around subcommands: `repair`, `scan`, `watch`, `daemon`, `cleanup`, `report`, `undo`,
+//! `monitor`, `offenders` and `ctl`, `agent` and `remote` with the `agent` feature, plus `completions`
+//! and `man` to generate shell completions and man pages for packagers.
+//! Running without a subcommand is a deprecated alias for `repair`.
+//!
+//! The process exit code summarizes the run:
+//!
+//! * `0` - nothing to do, no locked files were found
+//! * `1` - usage error (bad arguments, missing file or directory)
+//! * `2` - some files were repaired or quarantined (`scan`: locked files were found; `cleanup`, `undo`:
+//!   files were removed or restored)
+//! * `3` - some files could not be repaired, verified, removed or restored (with `--strict`, skipped
+//!   and quarantined files count as failures)
+//!
+//! `watch`, `daemon`, `monitor` and `agent` exit with `0` once they were stopped.
+
+mod cli;
+mod contention;
+#[cfg(unix)]
+mod control;
+#[cfg(unix)]
+mod daemon;
+mod logging;
+#[cfg(unix)]
+mod privileges;
+mod progress_bar;
+#[cfg(feature = "agent")]
+mod remote;
+mod shutdown;
+#[cfg(all(unix, feature = "systemd"))]
+mod systemd;
+mod watch;
+
+use clap::{CommandFactory, Parser};
+use cli::{
+    CleanupArgs, Cli, Command, CompletionsArgs, FastScanArgs, ManArgs, OutputFormat, RepairArgs,
+    RepairCommandArgs, ReportArgs, ScanArgs, StatsFormat, TargetArgs, UndoArgs,
+};
+#[cfg(unix)]
+use cli::{CtlArgs, PrivilegeArgs};
+use logging::LogConfig;
+use netfs_unlker::audit::{quarantined_files, JsonLinesAuditLog};
+use netfs_unlker::backend::{NativeFs, NativeLocks};
+use netfs_unlker::cache::ProbeCache;
+use netfs_unlker::hooks::CommandHooks;
+use netfs_unlker::maintenance::Maintenance;
+#[cfg(feature = "webhook")]
+use netfs_unlker::notify::{WebhookConfig, WebhookNotifier};
+#[cfg(feature = "ontap")]
+use netfs_unlker::ontap::{OntapConfig, OntapLockBreaker};
+use netfs_unlker::options::Target;
+use netfs_unlker::run_guard::RunGuard;
+use netfs_unlker::scan::{ScanReport, Scanner};
+use netfs_unlker::{RepairOptions, RepairReport, Repairer};
+#[cfg(unix)]
+use privileges::Identity;
+use progress_bar::ProgressBar;
+use std::fs::File;
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
 use std::process;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::level_filters::LevelFilter;
+use tracing::{error, info, warn};
 
-/// Command-line interface definition.
-#[derive(Parser)]
-#[command(author, version, about, long_about = None)]
-#[command(propagate_version = true)]
-struct Cli {
-    /// Path to locked file.
-    /// Specify this using `-f <FILE>` or `--file <FILE>`.
-    /// If specified, the program will attempt to repair the locked file.
-    #[arg(short, long, value_name = "FILE")]
-    file: Option<PathBuf>,
-
-    /// Path to a directory containing locked files.
-    /// Specify this using `-d <DIRECTORY>` or `--directory <DIRECTORY>`.
-    /// If specified, the program will attempt to repair all locked files within the directory.
-    #[arg(short, long, value_name = "DIRECTORY")]
-    directory: Option<PathBuf>,
-
-    /// Recursively search for locked files within the specified directory.
-    /// Specify this using `-r` or `--recursive`.
-    #[arg(short, long, value_name = "RECURSIVE", default_value = "false")]
-    recursive: bool,
+/// Name of the executable, used in the generated completions and man pages.
+const BIN_NAME: &str = env!("CARGO_BIN_NAME");
 
-    /// Enable verbose output.
-    /// Specify this using `-v` or `--verbose`.
-    #[arg(short, long, value_name = "VERBOSE", default_value = "false")]
-    verbose: bool,
+/// Exit code: nothing to do, no locked files were found.
+const EXIT_NOTHING_TO_DO: i32 = 0;
+/// Exit code: invalid command-line usage or missing target.
+const EXIT_USAGE_ERROR: i32 = 1;
+/// Exit code: at least one file was repaired or quarantined and nothing failed.
+const EXIT_REPAIRED: i32 = 2;
+/// Exit code: at least one file could not be repaired or verified.
+const EXIT_FAILURES: i32 = 3;
+
+/// Repair engine configured from the command-line arguments.
+pub struct Engine {
+    repairer: Repairer<NativeFs, NativeLocks>,
+    #[cfg(feature = "webhook")]
+    notifier: Option<WebhookNotifier>,
 }
 
-fn main() {
-    // Parse command-line arguments.
-    let args = Cli::parse();
+impl Engine {
+    /// Builds the repair engine for the given arguments.
+    ///
+    /// # Returns
+    ///
+    /// Returns `None` if the arguments are invalid or a component cannot be set up; the error is logged.
+    fn from_args(args: &RepairArgs) -> Option<Self> {
+        Self::with_audit_log(args, open_audit_log(args).ok()?)
+    }
 
-    let default_log_level = if args.verbose {
-        LevelFilter::Debug
-    } else {
-        LevelFilter::Info
+    /// Builds the repair engine for the given arguments like `from_args`, recording to `audit_log`
+    /// instead of opening the log of `--audit-log` again.
+    ///
+    /// # Returns
+    ///
+    /// Returns `None` if the arguments are invalid or a component cannot be set up; the error is logged.
+    fn with_audit_log(
+        args: &RepairArgs,
+        audit_log: Option<Arc<JsonLinesAuditLog>>,
+    ) -> Option<Self> {
+        let options = RepairOptions {
+            verify_checksum: args.verify_checksum,
+            release_ranges: args.release_ranges,
+            smb_leases: args.smb_leases,
+            mandatory_lock_timeout: Duration::from_secs(args.mandatory_timeout),
+            allow_local: args.allow_local,
+            max_bytes_per_sec: args.bwlimit,
+            max_files_per_sec: args.file_rate,
+            min_size: args.min_size.map(|size| size.as_u64()),
+            max_size: args.max_size.map(|size| size.as_u64()),
+            newer_than: args.newer_than,
+            older_than: args.older_than,
+            quarantine_dir: args.quarantine_dir.clone(),
+            quarantine_after: args.quarantine_after,
+            file_timeout: args.file_timeout,
+            stale_handle_retries: args.stale_retries,
+            force: args.force,
+            temp_naming: args.temp_naming.naming(),
+            stop_on_error: args.stop_on_error,
+            copy_strategy: args.copy_strategy(),
+            fsync: !args.no_fsync,
+            sort_order: args.sort.into(),
+            walk_order: args.target.walk_order(),
+            one_file_system: args.target.one_file_system,
+            preserve_acls: !args.skip_acls,
+            include: args.include.clone(),
+            exclude: args.exclude.clone(),
+            backup_dir: args.backup_dir.clone(),
+        };
+
+        if !options.temp_naming.is_valid() {
+            error!("Invalid temporary file naming: --tmp-prefix and --tmp-suffix must not both be empty or contain a path separator");
+            return None;
+        }
+
+        if let Some(quarantine_dir) = &args.quarantine_dir {
+            if !quarantine_dir.is_dir() {
+                error!(
+                    "Quarantine directory does not exist: {}",
+                    quarantine_dir.display()
+                );
+                return None;
+            }
+        }
+
+        if let Some(backup_dir) = &args.backup_dir {
+            if !backup_dir.is_dir() {
+                error!("Backup directory does not exist: {}", backup_dir.display());
+                return None;
+            }
+        }
+
+        let mut repairer = Repairer::new(NativeFs::default(), NativeLocks::default(), options);
+
+        if let Some(audit_log) = audit_log {
+            repairer = repairer.with_audit_sink(audit_log);
+        }
+
+        if let Some(path) = args.cache_path.as_ref().filter(|_| !args.no_cache) {
+            let cache = match ProbeCache::open(path) {
+                Ok(cache) => cache,
+                Err(e)
+                    if matches!(
+                        e.kind(),
+                        io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof
+                    ) =>
+                {
+                    warn!(
+                        "Discarding the damaged probe cache ({}): {}",
+                        path.display(),
+                        e
+                    );
+                    ProbeCache::new(path)
+                }
+                Err(e) => {
+                    error!("Failed to open the probe cache ({}): {}", path.display(), e);
+                    return None;
+                }
+            };
+            repairer = repairer.with_probe_cache(cache.with_max_age(args.cache_max_age));
+        }
+
+        if args.pre_hook.is_some() || args.post_hook.is_some() {
+            repairer = repairer.with_hooks(CommandHooks::new(
+                args.pre_hook.clone(),
+                args.post_hook.clone(),
+            ));
+        }
+
+        #[cfg(feature = "ontap")]
+        if let Some(config) = ontap_config(args) {
+            match OntapLockBreaker::new(config) {
+                Ok(lock_breaker) if args.check_clients => {
+                    repairer = repairer
+                        .with_lock_status(lock_breaker.clone())
+                        .with_lock_breaker(lock_breaker)
+                }
+                Ok(lock_breaker) => repairer = repairer.with_lock_breaker(lock_breaker),
+                Err(e) => {
+                    error!("Failed to set up the ONTAP client: {}", e);
+                    return None;
+                }
+            }
+        }
+
+        Some(Engine {
+            repairer,
+            #[cfg(feature = "webhook")]
+            notifier: match webhook_notifier(args) {
+                Ok(notifier) => notifier,
+                Err(e) => {
+                    error!("Failed to set up the webhook: {}", e);
+                    return None;
+                }
+            },
+        })
+    }
+
+    /// Repairs the files and directories given on the command line.
+    ///
+    /// # Returns
+    ///
+    /// Returns `None` if the targets could not be processed; the error is logged.
+    fn sweep(&self, target: &TargetArgs) -> Option<RepairReport> {
+        match target.targets().as_slice() {
+            // Single file specified.
+            [Target::File(file_path)] => {
+                info!("Processing single file: {}", file_path.display());
+                // Attempt to repair the specified file.
+                self.repairer
+                    .repair_file(file_path)
+                    .map_err(|e| error!("Failed to repair file: {}", e))
+                    .ok()
+            }
+            // Directory specified.
+            [Target::Directory(directory_path)] => {
+                info!("Processing directory: {}", directory_path.display());
+                // Attempt to repair all files within the specified directory.
+                self.repairer
+                    .repair_directory(directory_path, target.recursive)
+                    .map_err(|e| error!("Failed to repair files in directory: {}", e))
+                    .ok()
+            }
+            targets => {
+                info!("Processing {} targets", targets.len());
+                self.repairer
+                    .repair_targets(targets, target.recursive)
+                    .map_err(|e| error!("Failed to repair targets: {}", e))
+                    .ok()
+            }
+        }
+    }
+
+    /// Logs the totals of a sweep and sends the webhook notification, if configured.
+    fn summarize(&self, report: &RepairReport, target: &TargetArgs) {
+        info!(
+            "Done: {} repaired, {} unverified, {} not locked, {} skipped, {} quarantined, {} failed, {} timed out",
+            report.repaired(),
+            report.unverified(),
+            report.not_locked(),
+            report.skipped(),
+            report.quarantined(),
+            report.failed(),
+            report.timed_out()
+        );
+
+        #[cfg(feature = "webhook")]
+        if let Some(notifier) = &self.notifier {
+            let summary = report.summary();
+            let target = target
+                .targets()
+                .iter()
+                .map(|target| target.path().display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ");
+            if notifier.should_notify(&summary) {
+                if let Err(e) = notifier.notify(&target, &summary) {
+                    error!("Failed to send the webhook notification: {}", e);
+                }
+            }
+        }
+        #[cfg(not(feature = "webhook"))]
+        let _ = target;
+    }
+}
+
+/// Opens the audit log given with `--audit-log`, if any.
+///
+/// # Returns
+///
+/// Returns `Err` if the log cannot be opened; the error is logged.
+fn open_audit_log(args: &RepairArgs) -> Result<Option<Arc<JsonLinesAuditLog>>, ()> {
+    let Some(path) = &args.audit_log else {
+        return Ok(None);
     };
+    match JsonLinesAuditLog::open(path) {
+        Ok(audit_log) => Ok(Some(Arc::new(audit_log))),
+        Err(e) => {
+            error!("Failed to open the audit log ({}): {}", path.display(), e);
+            Err(())
+        }
+    }
+}
 
-    // Initialize the logger.
-    SimpleLogger::new()
-        .with_level(default_log_level)
-        .init()
-        .unwrap();
-
-    // Handle the specified command-line options.
-    match (&args.file, &args.directory, &args.recursive) {
-        // Single file specified.
-        (Some(file_path), None, _) => {
-            info!("Processing single file: {}", file_path.display());
-            // Attempt to repair the specified file.
-            if let Err(e) = netfs_unlker::repair_file(file_path) {
-                error!("Failed to repair file: {}", e);
-                process::exit(1);
+/// Takes the run lock of every target unless `--force-run` is given, or the single lock given with
+/// `--run-lock`.
+///
+/// The locks are taken in the order of their paths, so runs over overlapping targets cannot wait
+/// on each other.
+///
+/// # Returns
+///
+/// Returns `Err` if another run holds a lock or a lock cannot be taken; the error is logged.
+fn acquire_run_guards(args: &RepairArgs) -> Result<Vec<RunGuard>, ()> {
+    reacquire_run_guards(args, Vec::new()).map_err(|_| ())
+}
+
+/// Takes the run locks for the targets of `args` like `acquire_run_guards`, keeping the guards in
+/// `held` that are still needed and releasing the others.
+///
+/// # Returns
+///
+/// Returns `Err` with `held` if a lock cannot be taken; the error is logged.
+fn reacquire_run_guards(
+    args: &RepairArgs,
+    mut held: Vec<RunGuard>,
+) -> Result<Vec<RunGuard>, Vec<RunGuard>> {
+    if args.force_run {
+        warn!("Running without the run lock; overlapping runs may repair the same files");
+        return Ok(Vec::new());
+    }
+
+    let mut paths = match &args.run_lock {
+        Some(path) => vec![path.clone()],
+        None => args
+            .target
+            .targets()
+            .iter()
+            .map(|target| RunGuard::default_path(target.path()))
+            .collect(),
+    };
+    paths.sort();
+    paths.dedup();
+
+    let (mut kept, mut taken) = (Vec::new(), Vec::new());
+    for path in paths {
+        // The same process cannot take a lock it already holds through another file
+        if let Some(i) = held.iter().position(|guard| guard.path() == path) {
+            kept.push(held.swap_remove(i));
+            continue;
+        }
+        match acquire_run_guard(&path, args.wait_for_lock) {
+            Ok(guard) => taken.push(guard),
+            Err(()) => {
+                held.extend(kept);
+                return Err(held);
             }
         }
-        // Directory specified.
-        (None, Some(directory_path), &recursive) => {
-            info!("Processing directory: {}", directory_path.display());
-            // Attempt to repair all files within the specified directory.
-            if let Err(e) = netfs_unlker::repair_files_in_directory(directory_path, recursive) {
-                error!("Failed to repair files in directory: {}", e);
-                process::exit(1);
+    }
+    kept.extend(taken);
+    Ok(kept)
+}
+
+/// Takes the run lock at `path`, logging why it could not be taken.
+fn acquire_run_guard(path: &Path, wait: bool) -> Result<RunGuard, ()> {
+    if wait {
+        info!("Waiting for the run lock ({})", path.display());
+    }
+
+    match RunGuard::acquire(path, wait) {
+        Ok(guard) => Ok(guard),
+        Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+            error!(
+                "Another run holds the run lock ({}); use --wait-for-lock or --force-run",
+                path.display()
+            );
+            Err(())
+        }
+        Err(e) => {
+            error!("Failed to take the run lock ({}): {}", path.display(), e);
+            Err(())
+        }
+    }
+}
+
+/// Refuses a run as root without `--allow-root` and looks up the user of `--run-as`.
+///
+/// # Returns
+///
+/// Returns `Err` if the run is refused or the user does not exist; the error is logged.
+#[cfg(unix)]
+fn check_privileges(args: &PrivilegeArgs) -> Result<Option<Identity>, ()> {
+    let identity = match &args.run_as {
+        Some(user) => match Identity::lookup(user) {
+            Ok(identity) => Some(identity),
+            Err(e) => {
+                error!("Failed to look up the user of --run-as ({}): {}", user, e);
+                return Err(());
             }
+        },
+        None => None,
+    };
+
+    let stays_root = identity.as_ref().is_none_or(Identity::is_root);
+    if privileges::is_root() && stays_root && !args.allow_root {
+        error!("Refusing to run as root; use --run-as <USER> to switch to an unprivileged user, or --allow-root");
+        return Err(());
+    }
+    Ok(identity)
+}
+
+/// Switches to the user of `--run-as`, if any.
+///
+/// # Returns
+///
+/// Returns `Err` if the user cannot be switched; the error is logged.
+#[cfg(unix)]
+fn drop_privileges(identity: Option<&Identity>) -> Result<(), ()> {
+    let Some(identity) = identity else {
+        return Ok(());
+    };
+
+    match identity.switch() {
+        Ok(()) => {
+            info!("Switched to user {}", identity.name());
+            Ok(())
         }
-        // Neither a single file nor a directory specified.
-        _ => {
-            error!("Please specify either a file path or a directory path");
-            println!("Usage: -f <file path> or -d <directory path>");
-            process::exit(1);
+        Err(e) => {
+            error!("Failed to switch to user {}: {}", identity.name(), e);
+            Err(())
         }
     }
 }
+
+/// Runs the `repair` subcommand, printing the statistics of the run, and returns the process exit code.
+fn run_repair(args: &RepairCommandArgs) -> i32 {
+    #[cfg(unix)]
+    let identity = match check_privileges(&args.repair.privileges) {
+        Ok(identity) => identity,
+        Err(()) => return EXIT_USAGE_ERROR,
+    };
+    let _guards = match acquire_run_guards(&args.repair) {
+        Ok(guard) => guard,
+        Err(()) => return EXIT_USAGE_ERROR,
+    };
+    let mut engine = match Engine::from_args(&args.repair) {
+        Some(engine) => engine,
+        None => return EXIT_USAGE_ERROR,
+    };
+    #[cfg(unix)]
+    if drop_privileges(identity.as_ref()).is_err() {
+        return EXIT_USAGE_ERROR;
+    }
+    if args.progress && io::stderr().is_terminal() {
+        engine.repairer = engine
+            .repairer
+            .with_progress_observer(ProgressBar::default());
+    }
+    let report = match engine.sweep(&args.repair.target) {
+        Some(report) => report,
+        None => return EXIT_USAGE_ERROR,
+    };
+    engine.summarize(&report, &args.repair.target);
+
+    let statistics = report.statistics(args.slowest);
+    let written = match args.stats {
+        StatsFormat::Text => statistics.write_text(&mut io::stdout().lock()),
+        StatsFormat::Json => statistics.write_json(&mut io::stdout().lock()),
+        StatsFormat::None => Ok(()),
+    };
+    if let Err(e) = written {
+        warn!("Failed to print the statistics: {}", e);
+    }
+
+    if args.format.is_some() || args.output.is_some() {
+        let format = args.format.unwrap_or(OutputFormat::Text);
+        let written = match &args.output {
+            Some(path) => {
+                File::create(path).and_then(|mut f| write_repair_report(&report, format, &mut f))
+            }
+            None => write_repair_report(&report, format, &mut io::stdout().lock()),
+        };
+        if let Err(e) = written {
+            error!("Failed to write the report: {}", e);
+        }
+    }
+    exit_code(&report, args.repair.strict)
+}
+
+fn write_repair_report<W: Write>(
+    report: &RepairReport,
+    format: OutputFormat,
+    writer: &mut W,
+) -> io::Result<()> {
+    match format {
+        OutputFormat::Text => report.write_text(writer),
+        OutputFormat::Json => report.write_json(writer),
+        OutputFormat::Csv => report.write_csv(writer),
+    }
+}
+
+/// Runs the `scan` subcommand, printing the path of every locked file, and returns the process exit code.
+fn run_scan(args: &ScanArgs) -> i32 {
+    let target_args = &args.target;
+    let scanner = Scanner::new(NativeFs::default(), NativeLocks::default())
+        .with_walk_order(target_args.walk_order())
+        .with_one_file_system(target_args.one_file_system)
+        .with_smb_leases(args.smb_leases);
+    let mut report = ScanReport::default();
+    for target in target_args.targets() {
+        let scanned = match &target {
+            Target::File(file_path) => scanner.scan_file(file_path),
+            Target::Directory(directory_path) => {
+                scan_directory(&scanner, directory_path, target_args.recursive, &args.fast)
+            }
+        };
+        match scanned {
+            Ok(scanned) => report.merge(scanned),
+            Err(e) => {
+                error!("Failed to scan ({}): {}", target.path().display(), e);
+                return EXIT_USAGE_ERROR;
+            }
+        }
+    }
+
+    let mut stdout = io::stdout().lock();
+    for file in &report.locked {
+        if let Err(e) = writeln!(stdout, "{}", file.path.display()) {
+            error!("Failed to write the locked files: {}", e);
+            return EXIT_USAGE_ERROR;
+        }
+    }
+    info!(
+        "Done: {} scanned, {} locked, {} errors",
+        report.scanned,
+        report.locked.len(),
+        report.errors.len()
+    );
+
+    if !report.errors.is_empty() {
+        EXIT_FAILURES
+    } else if !report.locked.is_empty() {
+        EXIT_REPAIRED
+    } else {
+        EXIT_NOTHING_TO_DO
+    }
+}
+
+/// Runs the `cleanup` subcommand and returns the process exit code.
+fn run_cleanup(args: &CleanupArgs) -> i32 {
+    let temp_naming = args.temp_naming.naming();
+    if !temp_naming.is_valid() {
+        error!("Invalid temporary file naming: --tmp-prefix and --tmp-suffix must not both be empty or contain a path separator");
+        return EXIT_USAGE_ERROR;
+    }
+    #[cfg(unix)]
+    let identity = match check_privileges(&args.privileges) {
+        Ok(identity) => identity,
+        Err(()) => return EXIT_USAGE_ERROR,
+    };
+    #[cfg(unix)]
+    if drop_privileges(identity.as_ref()).is_err() {
+        return EXIT_USAGE_ERROR;
+    }
+    let maintenance = Maintenance::new(NativeFs::default()).with_temp_naming(temp_naming);
+    let leftovers = match maintenance.find_leftovers(&args.directory, args.recursive) {
+        Ok(leftovers) => leftovers,
+        Err(e) => {
+            error!("Failed to clean up directory: {}", e);
+            return EXIT_USAGE_ERROR;
+        }
+    };
+
+    let mut failed = 0;
+    for leftover in &leftovers {
+        if args.dry_run {
+            let _ = writeln!(io::stdout(), "{}", leftover.display());
+        } else if let Err(e) = maintenance.remove_leftover(leftover) {
+            error!("Failed to remove {}: {}", leftover.display(), e);
+            failed += 1;
+        }
+    }
+    info!(
+        "Done: {} leftover temporary files, {} failed",
+        leftovers.len(),
+        failed
+    );
+    maintenance_exit_code(leftovers.len(), failed)
+}
+
+/// Runs the `undo` subcommand and returns the process exit code.
+fn run_undo(args: &UndoArgs) -> i32 {
+    #[cfg(unix)]
+    let identity = match check_privileges(&args.privileges) {
+        Ok(identity) => identity,
+        Err(()) => return EXIT_USAGE_ERROR,
+    };
+    let mut files = match quarantined_files(&args.audit_log) {
+        Ok(files) => files,
+        Err(e) => {
+            error!(
+                "Failed to read the audit log ({}): {}",
+                args.audit_log.display(),
+                e
+            );
+            return EXIT_USAGE_ERROR;
+        }
+    };
+    // A file quarantined in several runs is restored from its latest quarantine only
+    files.reverse();
+    let mut seen = std::collections::HashSet::new();
+    files.retain(|file| seen.insert(file.path.clone()));
+    #[cfg(unix)]
+    if drop_privileges(identity.as_ref()).is_err() {
+        return EXIT_USAGE_ERROR;
+    }
+
+    let maintenance = Maintenance::new(NativeFs::default());
+    let mut failed = 0;
+    for file in &files {
+        if args.dry_run {
+            let _ = writeln!(
+                io::stdout(),
+                "{} -> {}",
+                file.destination.display(),
+                file.path.display()
+            );
+        } else if let Err(e) = maintenance.restore(file) {
+            error!(
+                "Failed to restore {} to {}: {}",
+                file.destination.display(),
+                file.path.display(),
+                e
+            );
+            failed += 1;
+        }
+    }
+    info!("Done: {} quarantined files, {} failed", files.len(), failed);
+    maintenance_exit_code(files.len(), failed)
+}
+
+/// Derives the process exit code of `cleanup` and `undo` from the number of processed and failed files.
+fn maintenance_exit_code(processed: usize, failed: usize) -> i32 {
+    if failed > 0 {
+        EXIT_FAILURES
+    } else if processed > 0 {
+        EXIT_REPAIRED
+    } else {
+        EXIT_NOTHING_TO_DO
+    }
+}
+
+/// Runs the `report` subcommand and returns the process exit code.
+fn run_report(args: &ReportArgs) -> i32 {
+    let scanner =
+        Scanner::new(NativeFs::default(), NativeLocks::default()).with_smb_leases(args.smb_leases);
+    let report = match (&args.file, &args.directory) {
+        (Some(file_path), _) => scanner.scan_file(file_path),
+        (None, Some(directory_path)) => {
+            scan_directory(&scanner, directory_path, args.recursive, &args.fast)
+        }
+        (None, None) => unreachable!("clap requires a file or a directory"),
+    };
+    let report = match report {
+        Ok(report) => report,
+        Err(e) => {
+            error!("Failed to scan: {}", e);
+            return EXIT_USAGE_ERROR;
+        }
+    };
+
+    let written = match &args.output {
+        Some(path) => {
+            File::create(path).and_then(|mut f| write_report(&report, args.format, &mut f))
+        }
+        None => write_report(&report, args.format, &mut io::stdout().lock()),
+    };
+    match written {
+        Ok(()) => EXIT_NOTHING_TO_DO,
+        Err(e) => {
+            error!("Failed to write the report: {}", e);
+            EXIT_USAGE_ERROR
+        }
+    }
+}
+
+fn scan_directory(
+    scanner: &Scanner<NativeFs, NativeLocks>,
+    directory_path: &Path,
+    recursive: bool,
+    fast: &FastScanArgs,
+) -> io::Result<ScanReport> {
+    match fast.fast {
+        true => scanner.scan_directory_fast(directory_path, recursive, fast.jobs),
+        false => scanner.scan_directory(directory_path, recursive),
+    }
+}
+
+fn write_report<W: Write>(
+    report: &ScanReport,
+    format: OutputFormat,
+    writer: &mut W,
+) -> io::Result<()> {
+    match format {
+        OutputFormat::Text => report.write_text(writer),
+        OutputFormat::Json => report.write_json(writer),
+        OutputFormat::Csv => report.write_csv(writer),
+    }
+}
+
+/// Runs the `ctl` subcommand and returns the process exit code.
+#[cfg(unix)]
+fn run_ctl(args: &CtlArgs) -> i32 {
+    let response = match control::send(&args.socket, &args.request) {
+        Ok(response) => response,
+        Err(e) => {
+            error!(
+                "Failed to reach the control socket ({}): {}",
+                args.socket.display(),
+                e
+            );
+            return EXIT_USAGE_ERROR;
+        }
+    };
+
+    match serde_json::to_string_pretty(&response) {
+        Ok(response) => {
+            let _ = writeln!(io::stdout(), "{}", response);
+        }
+        Err(e) => error!("Failed to format the response: {}", e),
+    }
+    match response["ok"].as_bool() {
+        Some(true) => EXIT_NOTHING_TO_DO,
+        _ => EXIT_FAILURES,
+    }
+}
+
+/// Runs the `completions` subcommand and returns the process exit code.
+fn run_completions(args: &CompletionsArgs) -> i32 {
+    let mut command = Cli::command().name(BIN_NAME);
+    match &args.output_dir {
+        Some(directory) => {
+            match clap_complete::generate_to(args.shell, &mut command, BIN_NAME, directory) {
+                Ok(path) => info!("Wrote completions: {}", path.display()),
+                Err(e) => {
+                    error!("Failed to write the completions: {}", e);
+                    return EXIT_USAGE_ERROR;
+                }
+            }
+        }
+        None => clap_complete::generate(args.shell, &mut command, BIN_NAME, &mut io::stdout()),
+    }
+    EXIT_NOTHING_TO_DO
+}
+
+/// Runs the `man` subcommand and returns the process exit code.
+fn run_man(args: &ManArgs) -> i32 {
+    let command = Cli::command().name(BIN_NAME);
+    let written = match &args.output_dir {
+        Some(directory) => clap_mangen::generate_to(command, directory)
+            .map(|()| info!("Wrote man pages: {}", directory.display())),
+        None => clap_mangen::Man::new(command).render(&mut io::stdout()),
+    };
+    match written {
+        Ok(()) => EXIT_NOTHING_TO_DO,
+        Err(e) => {
+            error!("Failed to write the man page: {}", e);
+            EXIT_USAGE_ERROR
+        }
+    }
+}
+
+/// Builds the ONTAP configuration from the command-line arguments, if requested.
+#[cfg(feature = "ontap")]
+fn ontap_config(args: &RepairArgs) -> Option<OntapConfig> {
+    let mut config = OntapConfig::new(
+        args.ontap_url.clone()?,
+        args.ontap_user.clone()?,
+        args.ontap_password.clone()?,
+        args.ontap_svm.clone()?,
+        args.ontap_volume.clone()?,
+        args.ontap_mount.clone()?,
+    );
+    config.accept_invalid_certs = args.ontap_insecure;
+    Some(config)
+}
+
+/// Builds the webhook notifier from the command-line arguments, if requested.
+#[cfg(feature = "webhook")]
+fn webhook_notifier(args: &RepairArgs) -> io::Result<Option<WebhookNotifier>> {
+    let url = match &args.webhook_url {
+        Some(url) => url,
+        None => return Ok(None),
+    };
+
+    let mut config = WebhookConfig::new(url.clone());
+    config.repaired_threshold = args.webhook_threshold;
+    config.template = match args.webhook_template.as_deref() {
+        Some(template) => match template.strip_prefix('@') {
+            Some(path) => Some(std::fs::read_to_string(path)?),
+            None => Some(template.to_string()),
+        },
+        None => None,
+    };
+    WebhookNotifier::new(config).map(Some)
+}
+
+/// Derives the process exit code from the repair report.
+fn exit_code(report: &RepairReport, strict: bool) -> i32 {
+    if report.failed() > 0
+        || report.timed_out() > 0
+        || report.unverified() > 0
+        || (strict && (report.skipped() > 0 || report.quarantined() > 0))
+    {
+        EXIT_FAILURES
+    } else if report.repaired() > 0 || report.quarantined() > 0 {
+        EXIT_REPAIRED
+    } else {
+        EXIT_NOTHING_TO_DO
+    }
+}
+
+fn main() {
+    // Parse command-line arguments, reporting usage errors with our own exit code.
+    let args = match Cli::try_parse() {
+        Ok(args) => args,
+        Err(e) if e.use_stderr() => {
+            let _ = e.print();
+            process::exit(EXIT_USAGE_ERROR);
+        }
+        Err(e) => e.exit(),
+    };
+
+    let default_log_level = if args.logging.verbose {
+        LevelFilter::DEBUG
+    } else {
+        LevelFilter::INFO
+    };
+
+    // Initialize the logger.
+    let log_config = LogConfig {
+        format: args.logging.log_format,
+        level: default_log_level,
+        target: args.logging.log_target.clone(),
+        rotation: args.logging.log_rotation,
+        max_files: args.logging.log_max_files,
+    };
+    if let Err(e) = logging::init(&log_config) {
+        eprintln!(
+            "Failed to set up logging to {}: {}",
+            args.logging.log_target, e
+        );
+        process::exit(EXIT_USAGE_ERROR);
+    }
+
+    let code = match &args.command {
+        Some(Command::Repair(repair_args)) => run_repair(repair_args),
+        Some(Command::Scan(scan_args)) => run_scan(scan_args),
+        Some(Command::Watch(watch_args)) => watch::run_watch(watch_args),
+        #[cfg(unix)]
+        Some(Command::Daemon(daemon_args)) => {
+            daemon::run_daemon(daemon_args, &args.logging.log_target)
+        }
+        Some(Command::Cleanup(cleanup_args)) => run_cleanup(cleanup_args),
+        Some(Command::Report(report_args)) => run_report(report_args),
+        Some(Command::Undo(undo_args)) => run_undo(undo_args),
+        Some(Command::Monitor(monitor_args)) => contention::run_monitor(monitor_args),
+        Some(Command::Offenders(offenders_args)) => contention::run_offenders(offenders_args),
+        #[cfg(unix)]
+        Some(Command::Ctl(ctl_args)) => run_ctl(ctl_args),
+        #[cfg(feature = "agent")]
+        Some(Command::Agent(agent_args)) => remote::run_agent(agent_args),
+        #[cfg(feature = "agent")]
+        Some(Command::Remote(remote_args)) => remote::run_remote(remote_args),
+        Some(Command::Completions(completions_args)) => run_completions(completions_args),
+        Some(Command::Man(man_args)) => run_man(man_args),
+        None => {
+            warn!("Running without a subcommand is deprecated, use `netfs_unlker repair` instead");
+            run_repair(&args.repair)
+        }
+    };
+    process::exit(code);
+}
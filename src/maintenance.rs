@@ -0,0 +1,154 @@
+//! # Maintenance Module
+//!
+//! This module contains the `Maintenance` operations that tidy up after the repair engine:
+//! removing the temporary copies left next to the originals by interrupted repairs, and moving
+//! quarantined files back to their original location.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::path::Path;
+//! use netfs_unlker::audit::quarantined_files;
+//! use netfs_unlker::backend::NativeFs;
+//! use netfs_unlker::maintenance::Maintenance;
+//!
+//! let maintenance = Maintenance::new(NativeFs::default());
+//! for leftover in maintenance.find_leftovers(Path::new("/mnt/share"), true).unwrap() {
+//!     maintenance.remove_leftover(&leftover).unwrap();
+//! }
+//! for file in quarantined_files(Path::new("/var/log/netfs-unlker/audit.jsonl")).unwrap() {
+//!     maintenance.restore(&file).unwrap();
+//! }
+//! ```
+
+use crate::audit::QuarantinedFile;
+use crate::backend::{FileKind, FileOps};
+use crate::options::TempNaming;
+use crate::walk::{Walk, WalkOrder};
+use std::io::{self, Error};
+use std::path::{Path, PathBuf};
+use tracing::{debug, info, warn};
+
+/// Prefix of the temporary copies written by earlier versions, `.tmp.<name>`.
+const LEGACY_TMP_PREFIX: &str = ".tmp.";
+
+/// Cleanup and undo operations on top of a `FileOps` backend.
+#[derive(Debug)]
+pub struct Maintenance<F: FileOps> {
+    fs: F,
+    temp_naming: TempNaming,
+}
+
+impl<F: FileOps> Maintenance<F> {
+    /// Creates the maintenance operations on top of the given backend.
+    pub fn new(fs: F) -> Self {
+        Maintenance {
+            fs,
+            temp_naming: TempNaming::default(),
+        }
+    }
+
+    /// Looks for temporary copies named with the given naming instead of the default one.
+    /// Copies named like the earlier `.tmp.<name>` are found either way.
+    pub fn with_temp_naming(mut self, temp_naming: TempNaming) -> Self {
+        self.temp_naming = temp_naming;
+        self
+    }
+
+    /// Finds the temporary copies left behind by interrupted repairs in the specified directory.
+    ///
+    /// Only temporary files whose original file still exists next to them are reported, so unrelated
+    /// files that happen to share the naming scheme of an orphaned copy are left alone.
+    /// Subdirectories that cannot be read are logged and skipped.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the specified directory path does not exist or cannot be read.
+    pub fn find_leftovers(
+        &self,
+        directory_path: &Path,
+        recursive: bool,
+    ) -> io::Result<Vec<PathBuf>> {
+        let mut leftovers = Vec::new();
+        for entry in Walk::new(&self.fs, directory_path, recursive, WalkOrder::default())? {
+            match entry {
+                Ok(path) if self.is_leftover(&path) => {
+                    debug!("Found leftover temporary file: ({})", path.display());
+                    leftovers.push(path);
+                }
+                Ok(_) => {}
+                Err(e) => warn!(
+                    "Failed to read directory ({}): {}",
+                    e.path.display(),
+                    e.error
+                ),
+            }
+        }
+        Ok(leftovers)
+    }
+
+    /// Removes a temporary copy found by `find_leftovers`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the file cannot be removed.
+    pub fn remove_leftover(&self, path: &Path) -> io::Result<()> {
+        self.fs.remove_file(path)?;
+        info!("Removed leftover temporary file: ({})", path.display());
+        Ok(())
+    }
+
+    /// Moves a quarantined file back to its original location.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the quarantined file no longer exists, if a file was created at the
+    /// original location in the meantime, or if the file cannot be moved.
+    pub fn restore(&self, file: &QuarantinedFile) -> io::Result<()> {
+        self.fs.metadata(&file.destination)?;
+        if self.fs.metadata(&file.path).is_ok() {
+            return Err(Error::new(
+                io::ErrorKind::AlreadyExists,
+                "the original location is taken by another file",
+            ));
+        }
+
+        match self.fs.rename(&file.destination, &file.path) {
+            Ok(()) => {}
+            // The quarantine directory may live on another filesystem
+            Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+                self.fs.copy(&file.destination, &file.path)?;
+                self.fs.remove_file(&file.destination)?;
+            }
+            Err(e) => return Err(e),
+        }
+        info!(
+            "Restored quarantined file: ({}) -> ({})",
+            file.destination.display(),
+            file.path.display()
+        );
+        Ok(())
+    }
+
+    fn is_leftover(&self, path: &Path) -> bool {
+        let name = match path.file_name() {
+            Some(name) => name,
+            None => return false,
+        };
+        let original_names = [
+            self.temp_naming.original_name(name),
+            TempNaming::new(LEGACY_TMP_PREFIX, "").original_name(name),
+        ];
+
+        let is_file = |path: &Path| {
+            self.fs
+                .metadata(path)
+                .is_ok_and(|m| m.kind == FileKind::File)
+        };
+        original_names
+            .iter()
+            .flatten()
+            .any(|original_name| is_file(&path.with_file_name(original_name)))
+            && is_file(path)
+    }
+}
@@ -0,0 +1,691 @@
+//! # Mock Backend Module
+//!
+//! This module contains `MemoryFs`, an in-memory implementation of the `FileOps` and `LockOps` traits.
+//! It simulates locked files and can inject failures and delays into individual operations, or fail
+//! operations at random, which makes it possible to exercise the repair pipeline without a network mount.
+//!
+//! # Examples
+//!
+//! ```
+//! use std::path::Path;
+//! use netfs_unlker::mock::MemoryFs;
+//! use netfs_unlker::{FileOutcome, RepairOptions, Repairer};
+//!
+//! let fs = MemoryFs::new();
+//! fs.add_locked_file("/mnt/data.db", b"payload");
+//!
+//! let repairer = Repairer::new(&fs, &fs, RepairOptions::default());
+//! let report = repairer.repair_file(Path::new("/mnt/data.db")).unwrap();
+//!
+//! assert!(matches!(report.files[0].outcome, FileOutcome::Repaired));
+//! assert_eq!(fs.contents("/mnt/data.db").unwrap(), b"payload");
+//! ```
+
+use crate::backend::{
+    Acl, DirEntries, FileKind, FileMetadata, FileOps, LockInfo, LockKind, LockOps, LockType,
+};
+use crate::deadline;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Cursor, Read};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// Root of the staging directories created by `MemoryFs`.
+const STAGING_ROOT: &str = "/.staging";
+
+/// Operation of the mock backend that a failure can be injected into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Metadata,
+    ReadDir,
+    Open,
+    Copy,
+    Write,
+    ReadAcl,
+    WriteAcl,
+    Rename,
+    RemoveFile,
+    Sync,
+    CreateStagingDir,
+    RemoveStagingDir,
+    IsLocked,
+    Unlock,
+    LockInfo,
+    IsLeased,
+    BreakLease,
+}
+
+#[derive(Debug)]
+enum Entry {
+    Directory,
+    File {
+        data: Vec<u8>,
+        locked: bool,
+        leased: bool,
+        modified: SystemTime,
+        inode: u64,
+        acl: Option<Acl>,
+    },
+}
+
+#[derive(Debug)]
+struct Failure {
+    operation: Operation,
+    path: Option<PathBuf>,
+    kind: io::ErrorKind,
+    /// Number of calls left to fail, or `None` to fail every call.
+    remaining: Option<u32>,
+}
+
+/// Random failures of operations with a given probability, drawn from a seeded generator so that a
+/// failing run can be replayed.
+///
+/// # Examples
+///
+/// ```
+/// use netfs_unlker::mock::{Chaos, MemoryFs, Operation};
+///
+/// let fs = MemoryFs::new();
+/// fs.set_chaos(Chaos::new(42).fail(Operation::Rename, 0.5).fail_all(0.05));
+/// ```
+#[derive(Debug, Clone)]
+pub struct Chaos {
+    state: u64,
+    probabilities: Vec<(Option<Operation>, f64)>,
+}
+
+impl Chaos {
+    /// Creates a generator seeded with `seed` that fails nothing yet.
+    pub fn new(seed: u64) -> Self {
+        Chaos {
+            state: seed,
+            probabilities: Vec::new(),
+        }
+    }
+
+    /// Makes calls of `operation` fail with the given probability between 0 and 1.
+    pub fn fail(mut self, operation: Operation, probability: f64) -> Self {
+        self.probabilities.push((Some(operation), probability));
+        self
+    }
+
+    /// Makes calls of every operation fail with the given probability between 0 and 1, on top of the
+    /// probabilities of single operations.
+    pub fn fail_all(mut self, probability: f64) -> Self {
+        self.probabilities.push((None, probability));
+        self
+    }
+
+    /// Decides whether the current call of `operation` fails.
+    fn strikes(&mut self, operation: Operation) -> bool {
+        let probabilities: Vec<f64> = self
+            .probabilities
+            .iter()
+            .filter(|(o, _)| o.is_none() || *o == Some(operation))
+            .map(|(_, probability)| *probability)
+            .collect();
+        probabilities
+            .into_iter()
+            .any(|probability| self.next_unit() < probability)
+    }
+
+    /// Returns the next number of the splitmix64 sequence, scaled to `[0, 1)`.
+    fn next_unit(&mut self) -> f64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        (z >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[derive(Debug)]
+struct Delay {
+    operation: Operation,
+    path: Option<PathBuf>,
+    duration: Duration,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    entries: BTreeMap<PathBuf, Entry>,
+    failures: Vec<Failure>,
+    chaos: Option<Chaos>,
+    delays: Vec<Delay>,
+    live_holders: BTreeSet<PathBuf>,
+    mounts: BTreeMap<PathBuf, u64>,
+    clock: u64,
+    inodes: u64,
+    staging_dirs: u64,
+}
+
+impl State {
+    fn tick(&mut self) -> SystemTime {
+        self.clock += 1;
+        SystemTime::UNIX_EPOCH + Duration::from_secs(self.clock)
+    }
+
+    fn allocate_inode(&mut self) -> u64 {
+        self.inodes += 1;
+        self.inodes
+    }
+
+    fn add_parents(&mut self, path: &Path) {
+        for ancestor in path.ancestors().skip(1) {
+            self.entries
+                .entry(ancestor.to_path_buf())
+                .or_insert(Entry::Directory);
+        }
+    }
+
+    fn check(&mut self, operation: Operation, path: &Path) -> io::Result<()> {
+        // A delayed call blocks like a hung filesystem call of a native backend would
+        if let Some(delay) = self.delays.iter().find(|d| {
+            d.operation == operation && (d.path.is_none() || d.path.as_deref() == Some(path))
+        }) {
+            let duration = delay.duration;
+            deadline::run(move || {
+                thread::sleep(duration);
+                Ok(())
+            })?;
+        }
+
+        match self.failures.iter_mut().find(|f| {
+            f.operation == operation
+                && (f.path.is_none() || f.path.as_deref() == Some(path))
+                && f.remaining != Some(0)
+        }) {
+            Some(failure) => {
+                if let Some(remaining) = &mut failure.remaining {
+                    *remaining -= 1;
+                }
+                Err(io::Error::new(
+                    failure.kind,
+                    format!("injected {:?} failure", operation),
+                ))
+            }
+            None if self.chaos.as_mut().is_some_and(|c| c.strikes(operation)) => Err(
+                io::Error::other(format!("injected random {:?} failure", operation)),
+            ),
+            None => Ok(()),
+        }
+    }
+
+    fn file(&self, path: &Path) -> io::Result<(&Vec<u8>, bool)> {
+        match self.entries.get(path) {
+            Some(Entry::File { data, locked, .. }) => Ok((data, *locked)),
+            Some(Entry::Directory) => Err(io::Error::other("is a directory")),
+            None => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+
+    fn is_leased(&self, path: &Path) -> bool {
+        matches!(
+            self.entries.get(path),
+            Some(Entry::File { leased: true, .. })
+        )
+    }
+
+    /// Returns the device of the innermost mount containing `path`; the root filesystem is device 0.
+    fn device(&self, path: &Path) -> u64 {
+        path.ancestors()
+            .find_map(|ancestor| self.mounts.get(ancestor))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    fn require_parent(&self, path: &Path) -> io::Result<()> {
+        match path.parent().and_then(|p| self.entries.get(p)) {
+            Some(Entry::Directory) => Ok(()),
+            _ => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+
+    /// Creates or replaces the unlocked file at `path` with `data` and returns its length.
+    fn write(&mut self, path: &Path, data: Vec<u8>) -> io::Result<u64> {
+        self.require_parent(path)?;
+        let len = data.len() as u64;
+        let modified = self.tick();
+        let inode = self.allocate_inode();
+        self.entries.insert(
+            path.to_path_buf(),
+            Entry::File {
+                data,
+                locked: false,
+                leased: false,
+                modified,
+                inode,
+                acl: None,
+            },
+        );
+        Ok(len)
+    }
+
+    /// Leaves the first half of `data` at `path`, like a write interrupted halfway.
+    fn write_partial(&mut self, path: &Path, data: &[u8]) {
+        let _ = self.write(path, data[..data.len() / 2].to_vec());
+    }
+}
+
+/// In-memory filesystem with simulated locks and injectable failures.
+///
+/// Copies and renames behave like on a real filesystem: a copy creates a new, unlocked file,
+/// and renaming over an existing file replaces it together with its lock. A copy or write that fails
+/// leaves the first half of the file behind, like one interrupted halfway. The staging directories
+/// count as another filesystem, so renaming into or out of them fails with `CrossesDevices`.
+#[derive(Debug, Default)]
+pub struct MemoryFs {
+    state: Mutex<State>,
+}
+
+impl MemoryFs {
+    /// Creates an empty filesystem containing only the root directory.
+    pub fn new() -> Self {
+        let fs = MemoryFs::default();
+        fs.add_dir("/");
+        fs
+    }
+
+    /// Adds a directory, creating missing parent directories.
+    pub fn add_dir(&self, path: impl AsRef<Path>) {
+        let mut state = self.state();
+        state.add_parents(path.as_ref());
+        state
+            .entries
+            .insert(path.as_ref().to_path_buf(), Entry::Directory);
+    }
+
+    /// Adds a directory with another filesystem mounted on it, creating missing parent directories.
+    /// Entries below it report a device of their own in their metadata.
+    pub fn add_mount(&self, path: impl AsRef<Path>) {
+        self.add_dir(path.as_ref());
+        let mut state = self.state();
+        let device = state.mounts.len() as u64 + 1;
+        state.mounts.insert(path.as_ref().to_path_buf(), device);
+    }
+
+    /// Adds an unlocked file, creating missing parent directories.
+    pub fn add_file(&self, path: impl AsRef<Path>, data: &[u8]) {
+        self.insert_file(path.as_ref(), data, false);
+    }
+
+    /// Adds a locked file, creating missing parent directories.
+    pub fn add_locked_file(&self, path: impl AsRef<Path>, data: &[u8]) {
+        self.insert_file(path.as_ref(), data, true);
+    }
+
+    /// Adds a file whose lease probe is refused, as for a lease another SMB client holds, creating
+    /// missing parent directories. Only `is_leased` reports the lease; it is given up when
+    /// `break_lease` is called on the file.
+    pub fn add_leased_file(&self, path: impl AsRef<Path>, data: &[u8]) {
+        self.insert_file(path.as_ref(), data, false);
+        if let Some(Entry::File { leased, .. }) = self.state().entries.get_mut(path.as_ref()) {
+            *leased = true;
+        }
+    }
+
+    /// Sets the access control list of an existing file.
+    pub fn set_acl(&self, path: impl AsRef<Path>, acl: Acl) {
+        if let Some(Entry::File { acl: a, .. }) = self.state().entries.get_mut(path.as_ref()) {
+            *a = Some(acl);
+        }
+    }
+
+    /// Returns the access control list of a file, or `None` if there is no such file or it has none.
+    pub fn acl(&self, path: impl AsRef<Path>) -> Option<Acl> {
+        match self.state().entries.get(path.as_ref()) {
+            Some(Entry::File { acl, .. }) => acl.clone(),
+            _ => None,
+        }
+    }
+
+    /// Marks the process holding the lock on `path` as still alive.
+    pub fn set_holder_alive(&self, path: impl AsRef<Path>) {
+        self.state()
+            .live_holders
+            .insert(path.as_ref().to_path_buf());
+    }
+
+    /// Overwrites the content of an existing file, updating its modification time.
+    pub fn write(&self, path: impl AsRef<Path>, data: &[u8]) {
+        let mut state = self.state();
+        let now = state.tick();
+        if let Some(Entry::File {
+            data: d, modified, ..
+        }) = state.entries.get_mut(path.as_ref())
+        {
+            *d = data.to_vec();
+            *modified = now;
+        }
+    }
+
+    /// Returns the content of a file, or `None` if there is no such file.
+    pub fn contents(&self, path: impl AsRef<Path>) -> Option<Vec<u8>> {
+        self.state()
+            .file(path.as_ref())
+            .ok()
+            .map(|(d, _)| d.clone())
+    }
+
+    /// Returns all paths present in the filesystem, including directories.
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.state().entries.keys().cloned().collect()
+    }
+
+    /// Makes every call of `operation` fail with an error of the given kind.
+    pub fn fail(&self, operation: Operation, kind: io::ErrorKind) {
+        self.state().failures.push(Failure {
+            operation,
+            path: None,
+            kind,
+            remaining: None,
+        });
+    }
+
+    /// Makes calls of `operation` on `path` fail with an error of the given kind.
+    ///
+    /// For operations with two paths (`Copy`, `Rename`) the source path is matched.
+    pub fn fail_path(&self, operation: Operation, path: impl AsRef<Path>, kind: io::ErrorKind) {
+        self.state().failures.push(Failure {
+            operation,
+            path: Some(path.as_ref().to_path_buf()),
+            kind,
+            remaining: None,
+        });
+    }
+
+    /// Makes the next `count` calls of `operation` on `path` fail with an error of the given kind,
+    /// simulating a transient error. Later calls succeed again.
+    ///
+    /// For operations with two paths (`Copy`, `Rename`) the source path is matched.
+    pub fn fail_path_times(
+        &self,
+        operation: Operation,
+        path: impl AsRef<Path>,
+        kind: io::ErrorKind,
+        count: u32,
+    ) {
+        self.state().failures.push(Failure {
+            operation,
+            path: Some(path.as_ref().to_path_buf()),
+            kind,
+            remaining: Some(count),
+        });
+    }
+
+    /// Makes operations fail at random as configured by `chaos`, replacing earlier random failures.
+    /// Failures injected for specific operations take precedence.
+    pub fn set_chaos(&self, chaos: Chaos) {
+        self.state().chaos = Some(chaos);
+    }
+
+    /// Removes all injected failures, including random ones.
+    pub fn clear_failures(&self) {
+        let mut state = self.state();
+        state.failures.clear();
+        state.chaos = None;
+    }
+
+    /// Makes calls of `operation` on `path` block for `duration` before they run.
+    ///
+    /// The delay is bound by the per-file timeout of the repair engine like a call of a native backend.
+    /// For operations with two paths (`Copy`, `Rename`) the source path is matched.
+    pub fn delay_path(&self, operation: Operation, path: impl AsRef<Path>, duration: Duration) {
+        self.state().delays.push(Delay {
+            operation,
+            path: Some(path.as_ref().to_path_buf()),
+            duration,
+        });
+    }
+
+    fn insert_file(&self, path: &Path, data: &[u8], locked: bool) {
+        let mut state = self.state();
+        state.add_parents(path);
+        let modified = state.tick();
+        let inode = state.allocate_inode();
+        state.entries.insert(
+            path.to_path_buf(),
+            Entry::File {
+                data: data.to_vec(),
+                locked,
+                leased: false,
+                modified,
+                inode,
+                acl: None,
+            },
+        );
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl FileOps for MemoryFs {
+    fn metadata(&self, path: &Path) -> io::Result<FileMetadata> {
+        let mut state = self.state();
+        state.check(Operation::Metadata, path)?;
+        let device = Some(state.device(path));
+        match state.entries.get(path) {
+            Some(Entry::Directory) => Ok(FileMetadata {
+                kind: FileKind::Directory,
+                len: 0,
+                modified: None,
+                inode: None,
+                device,
+            }),
+            Some(Entry::File {
+                data,
+                modified,
+                inode,
+                ..
+            }) => Ok(FileMetadata {
+                kind: FileKind::File,
+                len: data.len() as u64,
+                modified: Some(*modified),
+                inode: Some(*inode),
+                device,
+            }),
+            None => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+
+    fn read_dir(&self, path: &Path) -> io::Result<DirEntries<'_>> {
+        let mut state = self.state();
+        state.check(Operation::ReadDir, path)?;
+        match state.entries.get(path) {
+            Some(Entry::Directory) => {
+                let paths: Vec<PathBuf> = state
+                    .entries
+                    .keys()
+                    .filter(|p| p.parent() == Some(path))
+                    .cloned()
+                    .collect();
+                Ok(Box::new(paths.into_iter().map(Ok)))
+            }
+            Some(Entry::File { .. }) => Err(io::Error::other("not a directory")),
+            None => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+
+    fn open(&self, path: &Path) -> io::Result<Box<dyn Read + '_>> {
+        let mut state = self.state();
+        state.check(Operation::Open, path)?;
+        let (data, _) = state.file(path)?;
+        Ok(Box::new(Cursor::new(data.clone())))
+    }
+
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<u64> {
+        let mut state = self.state();
+        if let Err(e) = state.check(Operation::Copy, from) {
+            if let Ok((data, ..)) = state.file(from) {
+                let data = data.clone();
+                state.write_partial(to, &data);
+            }
+            return Err(e);
+        }
+        let data = state.file(from)?.0.clone();
+        state.write(to, data)
+    }
+
+    fn write_like(&self, to: &Path, contents: &[u8], like: &Path) -> io::Result<u64> {
+        let mut state = self.state();
+        if let Err(e) = state.check(Operation::Write, like) {
+            state.write_partial(to, contents);
+            return Err(e);
+        }
+        state.write(to, contents.to_vec())
+    }
+
+    fn read_acl(&self, path: &Path) -> io::Result<Option<Acl>> {
+        let mut state = self.state();
+        state.check(Operation::ReadAcl, path)?;
+        match state.entries.get(path) {
+            Some(Entry::File { acl, .. }) => Ok(acl.clone()),
+            Some(Entry::Directory) => Err(io::Error::other("is a directory")),
+            None => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+
+    fn write_acl(&self, path: &Path, acl: &Acl) -> io::Result<()> {
+        let mut state = self.state();
+        state.check(Operation::WriteAcl, path)?;
+        match state.entries.get_mut(path) {
+            Some(Entry::File { acl: a, .. }) => {
+                *a = Some(acl.clone());
+                Ok(())
+            }
+            Some(Entry::Directory) => Err(io::Error::other("is a directory")),
+            None => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        let mut state = self.state();
+        state.check(Operation::Rename, from)?;
+        state.require_parent(to)?;
+        // The staging directories live on a filesystem of their own
+        if from.starts_with(STAGING_ROOT) != to.starts_with(STAGING_ROOT) {
+            return Err(io::ErrorKind::CrossesDevices.into());
+        }
+        let entry = state
+            .entries
+            .remove(from)
+            .ok_or_else(|| io::Error::from(io::ErrorKind::NotFound))?;
+        state.entries.insert(to.to_path_buf(), entry);
+        Ok(())
+    }
+
+    fn remove_file(&self, path: &Path) -> io::Result<()> {
+        let mut state = self.state();
+        state.check(Operation::RemoveFile, path)?;
+        state.file(path)?;
+        state.entries.remove(path);
+        Ok(())
+    }
+
+    fn sync_file(&self, path: &Path) -> io::Result<()> {
+        let mut state = self.state();
+        state.check(Operation::Sync, path)?;
+        state.file(path).map(|_| ())
+    }
+
+    fn sync_dir(&self, path: &Path) -> io::Result<()> {
+        let mut state = self.state();
+        state.check(Operation::Sync, path)?;
+        match state.entries.get(path) {
+            Some(Entry::Directory) => Ok(()),
+            Some(Entry::File { .. }) => Err(io::Error::other("not a directory")),
+            None => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+
+    fn create_staging_dir(&self) -> io::Result<PathBuf> {
+        let mut state = self.state();
+        let path = Path::new(STAGING_ROOT).join(state.staging_dirs.to_string());
+        state.check(Operation::CreateStagingDir, &path)?;
+        state.staging_dirs += 1;
+        state.add_parents(&path);
+        state.entries.insert(path.clone(), Entry::Directory);
+        Ok(path)
+    }
+
+    fn remove_staging_dir(&self, path: &Path) -> io::Result<()> {
+        let mut state = self.state();
+        state.check(Operation::RemoveStagingDir, path)?;
+        state.entries.retain(|p, _| !p.starts_with(path));
+        if state
+            .entries
+            .keys()
+            .all(|p| !p.starts_with(STAGING_ROOT) || p == Path::new(STAGING_ROOT))
+        {
+            state.entries.remove(Path::new(STAGING_ROOT));
+        }
+        Ok(())
+    }
+}
+
+impl LockOps for MemoryFs {
+    fn is_locked(&self, path: &Path) -> io::Result<bool> {
+        let mut state = self.state();
+        state.check(Operation::IsLocked, path)?;
+        let (_, locked) = state.file(path)?;
+        Ok(locked)
+    }
+
+    fn unlock(&self, path: &Path) -> io::Result<()> {
+        let mut state = self.state();
+        state.check(Operation::Unlock, path)?;
+        match state.entries.get_mut(path) {
+            Some(Entry::File { locked, .. }) => {
+                *locked = false;
+                Ok(())
+            }
+            _ => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+
+    fn lock_info(&self, path: &Path) -> io::Result<Option<LockInfo>> {
+        let mut state = self.state();
+        state.check(Operation::LockInfo, path)?;
+        let (_, locked) = state.file(path)?;
+        if !locked {
+            return Ok(None);
+        }
+        Ok(Some(LockInfo {
+            kind: LockKind::Record,
+            lock_type: LockType::Exclusive,
+            start: 0,
+            len: None,
+            pid: None,
+        }))
+    }
+
+    fn is_leased(&self, path: &Path) -> io::Result<bool> {
+        let mut state = self.state();
+        state.check(Operation::IsLeased, path)?;
+        state.file(path)?;
+        Ok(state.is_leased(path))
+    }
+
+    fn break_lease(&self, path: &Path) -> io::Result<()> {
+        let mut state = self.state();
+        state.check(Operation::BreakLease, path)?;
+        match state.entries.get_mut(path) {
+            Some(Entry::File { leased, .. }) => {
+                *leased = false;
+                Ok(())
+            }
+            _ => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+
+    fn is_holder_alive(&self, path: &Path, _lock: &LockInfo) -> io::Result<bool> {
+        let state = self.state();
+        let (_, locked) = state.file(path)?;
+        Ok(locked && state.live_holders.contains(path))
+    }
+}
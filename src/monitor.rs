@@ -0,0 +1,295 @@
+//! # Monitor Module
+//!
+//! This module contains the lock contention history: the locks found by periodic scans are appended to
+//! a JSON lines file by `LockHistory`, one `LockSample` per line, and `top_offenders` ranks the files
+//! found locked most often over a period, to point at the applications that keep leaving locks behind.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::path::Path;
+//! use std::time::{Duration, SystemTime};
+//! use netfs_unlker::backend::{NativeFs, NativeLocks};
+//! use netfs_unlker::monitor::{read_history, top_offenders, LockHistory, LockSample};
+//! use netfs_unlker::scan::Scanner;
+//!
+//! let history = LockHistory::open(Path::new("/var/lib/netfs-unlker/locks.jsonl")).unwrap();
+//! let scanner = Scanner::new(NativeFs::default(), NativeLocks::default());
+//! let report = scanner.scan_directory(Path::new("/mnt/share"), true).unwrap();
+//! history.record(&LockSample::from_report(&report, SystemTime::now())).unwrap();
+//!
+//! let week_ago = SystemTime::now() - Duration::from_secs(7 * 24 * 60 * 60);
+//! let samples = read_history(Path::new("/var/lib/netfs-unlker/locks.jsonl"), week_ago).unwrap();
+//! let offenders = top_offenders(&samples, 10);
+//! ```
+
+use crate::backend::{LockKind, LockType};
+use crate::format::{
+    deserialize_time, lock_kind_name, serialize_path, serialize_secs, serialize_time,
+};
+use crate::scan::ScanReport;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+/// A lock found by a sample.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SampledLock {
+    /// Path of the locked file.
+    #[serde(serialize_with = "serialize_path")]
+    pub path: PathBuf,
+    /// Mechanism behind the lock.
+    pub kind: LockKind,
+    /// Type of the lock.
+    pub lock_type: LockType,
+    /// PID of the lock holder, if known.
+    pub pid: Option<u32>,
+}
+
+/// The locks found by one scan of the monitored paths.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockSample {
+    /// Time the scan finished.
+    #[serde(
+        serialize_with = "serialize_time",
+        deserialize_with = "deserialize_time"
+    )]
+    pub timestamp: SystemTime,
+    /// Number of files that were probed.
+    pub scanned: usize,
+    /// Number of paths that could not be probed.
+    pub errors: usize,
+    /// Locks that were found.
+    pub locked: Vec<SampledLock>,
+}
+
+impl LockSample {
+    /// Takes the sample of a scan that finished at `timestamp`.
+    pub fn from_report(report: &ScanReport, timestamp: SystemTime) -> Self {
+        LockSample {
+            timestamp,
+            scanned: report.scanned,
+            errors: report.errors.len(),
+            locked: report
+                .locked
+                .iter()
+                .map(|file| SampledLock {
+                    path: file.path.clone(),
+                    kind: file.lock.kind,
+                    lock_type: file.lock.lock_type,
+                    pid: file.lock.pid,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Append-only history of lock samples, one JSON object per line.
+#[derive(Debug)]
+pub struct LockHistory {
+    file: Mutex<File>,
+}
+
+impl LockHistory {
+    /// Opens the history at `path` for appending, creating it if it does not exist.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the history cannot be opened.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(LockHistory {
+            file: Mutex::new(file),
+        })
+    }
+
+    /// Appends a sample to the history.
+    pub fn record(&self, sample: &LockSample) -> io::Result<()> {
+        let line = serde_json::to_string(sample)?;
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        writeln!(file, "{}", line)?;
+        file.sync_data()
+    }
+}
+
+/// Reads the samples of a history written by `LockHistory` taken at or after `since`, oldest first.
+///
+/// Lines that cannot be parsed, e.g. the last line of a monitor killed while writing it, are skipped.
+///
+/// # Errors
+///
+/// Returns an `Err` if the history cannot be read.
+pub fn read_history(path: &Path, since: SystemTime) -> io::Result<Vec<LockSample>> {
+    let mut samples = Vec::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        match serde_json::from_str::<LockSample>(&line?) {
+            Ok(sample) if sample.timestamp >= since => samples.push(sample),
+            _ => continue,
+        }
+    }
+    samples.sort_by_key(|sample| sample.timestamp);
+    Ok(samples)
+}
+
+/// A file found locked by the samples of a period.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Offender {
+    /// Path of the file.
+    #[serde(serialize_with = "serialize_path")]
+    pub path: PathBuf,
+    /// Number of samples that found the file locked.
+    pub samples: usize,
+    /// Share of all samples of the period that found the file locked, from 0 to 1.
+    pub share: f64,
+    /// Time of the first sample that found the file locked.
+    #[serde(serialize_with = "serialize_time")]
+    pub first_seen: SystemTime,
+    /// Time of the last sample that found the file locked.
+    #[serde(serialize_with = "serialize_time")]
+    pub last_seen: SystemTime,
+    /// Longest time between the first and the last of consecutive samples that found the file locked,
+    /// a lower bound of the longest time a lock was held.
+    #[serde(rename = "longest_held_secs", serialize_with = "serialize_secs")]
+    pub longest_held: Duration,
+    /// Mechanisms behind the locks.
+    pub kinds: Vec<LockKind>,
+    /// PIDs of the lock holders that were reported, in ascending order.
+    pub pids: Vec<u32>,
+}
+
+/// Ranks the files found locked by `samples`, which must be ordered by time, and returns the `limit`
+/// found locked most often. Ties go to the file held the longest.
+pub fn top_offenders(samples: &[LockSample], limit: usize) -> Vec<Offender> {
+    struct Tally {
+        offender: Offender,
+        /// Time of the first sample of the current streak and the index of its last sample.
+        streak: (SystemTime, usize),
+    }
+
+    let mut tallies: HashMap<&Path, Tally> = HashMap::new();
+    for (index, sample) in samples.iter().enumerate() {
+        for lock in &sample.locked {
+            let tally = tallies.entry(&lock.path).or_insert_with(|| Tally {
+                offender: Offender {
+                    path: lock.path.clone(),
+                    samples: 0,
+                    share: 0.0,
+                    first_seen: sample.timestamp,
+                    last_seen: sample.timestamp,
+                    longest_held: Duration::ZERO,
+                    kinds: Vec::new(),
+                    pids: Vec::new(),
+                },
+                streak: (sample.timestamp, index),
+            });
+            let offender = &mut tally.offender;
+            // A file may be reported more than once by a sample, e.g. for several byte ranges
+            if offender.samples > 0 && tally.streak.1 == index {
+                add_unique(&mut offender.kinds, lock.kind);
+                add_unique(&mut offender.pids, lock.pid);
+                continue;
+            }
+            if offender.samples > 0 && tally.streak.1 + 1 != index {
+                tally.streak.0 = sample.timestamp;
+            }
+            tally.streak.1 = index;
+            offender.samples += 1;
+            offender.last_seen = sample.timestamp;
+            let held = sample
+                .timestamp
+                .duration_since(tally.streak.0)
+                .unwrap_or_default();
+            offender.longest_held = offender.longest_held.max(held);
+            add_unique(&mut offender.kinds, lock.kind);
+            add_unique(&mut offender.pids, lock.pid);
+        }
+    }
+
+    let mut offenders: Vec<Offender> = tallies
+        .into_values()
+        .map(|tally| {
+            let mut offender = tally.offender;
+            offender.share = offender.samples as f64 / samples.len() as f64;
+            offender.pids.sort_unstable();
+            offender
+        })
+        .collect();
+    offenders.sort_by(|a, b| {
+        b.samples
+            .cmp(&a.samples)
+            .then(b.longest_held.cmp(&a.longest_held))
+            .then_with(|| a.path.cmp(&b.path))
+    });
+    offenders.truncate(limit);
+    offenders
+}
+
+fn add_unique<T: PartialEq>(values: &mut Vec<T>, value: impl Into<Option<T>>) {
+    if let Some(value) = value.into() {
+        if !values.contains(&value) {
+            values.push(value);
+        }
+    }
+}
+
+/// Top offenders of a period of the history.
+#[derive(Debug, Clone, Serialize)]
+pub struct OffenderReport {
+    /// Start of the period.
+    #[serde(serialize_with = "serialize_time")]
+    pub since: SystemTime,
+    /// Number of samples taken in the period.
+    pub samples: usize,
+    /// Files found locked most often, most often first.
+    pub offenders: Vec<Offender>,
+}
+
+impl OffenderReport {
+    /// Ranks the files found locked by the samples taken since `since`; see `top_offenders`.
+    pub fn new(samples: &[LockSample], since: SystemTime, limit: usize) -> Self {
+        OffenderReport {
+            since,
+            samples: samples.len(),
+            offenders: top_offenders(samples, limit),
+        }
+    }
+
+    /// Writes the report as a human-readable table.
+    pub fn write_text<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        for offender in &self.offenders {
+            let pids: Vec<String> = offender.pids.iter().map(u32::to_string).collect();
+            let kinds: Vec<&str> = offender.kinds.iter().map(|k| lock_kind_name(*k)).collect();
+            writeln!(
+                writer,
+                "{:>6} {:>5.1}% {:>12} {:<9} {:<12} {}",
+                offender.samples,
+                offender.share * 100.0,
+                humantime::format_duration(Duration::from_secs(offender.longest_held.as_secs()))
+                    .to_string(),
+                kinds.join(","),
+                if pids.is_empty() {
+                    "-".to_string()
+                } else {
+                    pids.join(",")
+                },
+                offender.path.display()
+            )?;
+        }
+        writeln!(
+            writer,
+            "{} samples since {}",
+            self.samples,
+            humantime::format_rfc3339_seconds(self.since)
+        )
+    }
+
+    /// Writes the report as pretty-printed JSON.
+    pub fn write_json<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        serde_json::to_writer_pretty(&mut *writer, self)?;
+        writeln!(writer)
+    }
+}
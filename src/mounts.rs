@@ -0,0 +1,79 @@
+//! A module for inspecting mounted filesystems.
+//!
+//! This module provides functions to find the mount entry a path lives on, as listed in `/proc/self/mounts`.
+
+use std::ffi::OsString;
+use std::fs;
+use std::io::Result;
+use std::os::unix::ffi::OsStringExt;
+use std::path::{Path, PathBuf};
+
+/// Location of the mount table.
+const MOUNTS_PATH: &str = "/proc/self/mounts";
+
+/// An entry of the mount table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MountEntry {
+    /// Mounted device or remote export, e.g. `filer:/vol/data`.
+    pub source: String,
+    /// Directory the filesystem is mounted on.
+    pub mount_point: PathBuf,
+    /// Filesystem type, e.g. `nfs4`.
+    pub fs_type: String,
+    /// Mount options, e.g. `rw`, `mand`.
+    pub options: Vec<String>,
+}
+
+/// Returns the mount entry the path lives on, or `None` if the mount table is not available.
+///
+/// # Arguments
+///
+/// * `path` - The path to look up; symbolic links are resolved first.
+///
+/// # Returns
+///
+/// Returns the entry with the longest mount point that contains the path.
+pub fn find_mount(path: &Path) -> Result<Option<MountEntry>> {
+    let path = fs::canonicalize(path)?;
+    let table = match fs::read_to_string(MOUNTS_PATH) {
+        Ok(table) => table,
+        Err(_) => return Ok(None), // No mount table on this platform
+    };
+
+    Ok(table
+        .lines()
+        .filter_map(parse_line)
+        .filter(|entry| path.starts_with(&entry.mount_point))
+        .max_by_key(|entry| entry.mount_point.as_os_str().len()))
+}
+
+fn parse_line(line: &str) -> Option<MountEntry> {
+    let mut fields = line.split_whitespace();
+    let source = String::from_utf8_lossy(&unescape(fields.next()?)).into_owned();
+    let mount_point = PathBuf::from(OsString::from_vec(unescape(fields.next()?)));
+    let fs_type = String::from_utf8_lossy(&unescape(fields.next()?)).into_owned();
+    let options = fields.next()?.split(',').map(str::to_string).collect();
+
+    Some(MountEntry {
+        source,
+        mount_point,
+        fs_type,
+        options,
+    })
+}
+
+/// Decodes the octal escapes (`\040` for a space) used in the mount table.
+fn unescape(field: &str) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(field.len());
+    let mut rest = field.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        if b == b'\\' && tail.len() >= 3 && tail[..3].iter().all(|c| (b'0'..=b'7').contains(c)) {
+            bytes.push((tail[0] - b'0') * 64 + (tail[1] - b'0') * 8 + (tail[2] - b'0'));
+            rest = &tail[3..];
+        } else {
+            bytes.push(b);
+            rest = tail;
+        }
+    }
+    bytes
+}
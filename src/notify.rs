@@ -0,0 +1,149 @@
+//! # Notify Module
+//!
+//! This module contains the `WebhookNotifier`, which POSTs the summary of a repair run to a webhook
+//! (for example a Slack incoming webhook) when the run repaired more files than a threshold or ran into failures.
+//! It is available with the `webhook` cargo feature.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::path::Path;
+//! use netfs_unlker::notify::{WebhookConfig, WebhookNotifier};
+//! use netfs_unlker::repair_files_in_directory;
+//!
+//! let mut config = WebhookConfig::new("https://hooks.slack.com/services/T000/B000/XXXX");
+//! config.template = Some(r#"{"text": "{{target}}: {{repaired}} repaired, {{failed}} failed"}"#.to_string());
+//! let notifier = WebhookNotifier::new(config).unwrap();
+//!
+//! let report = repair_files_in_directory(Path::new("/mnt/share"), true).unwrap();
+//! let summary = report.summary();
+//! if notifier.should_notify(&summary) {
+//!     notifier.notify("/mnt/share", &summary).unwrap();
+//! }
+//! ```
+
+use crate::report::ReportSummary;
+use reqwest::blocking::Client;
+use serde::Serialize;
+use std::io;
+use std::time::Duration;
+use tracing::debug;
+
+/// Default timeout of the webhook request.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Webhook settings and the conditions that trigger a notification.
+#[derive(Debug, Clone)]
+pub struct WebhookConfig {
+    /// URL the summary is POSTed to.
+    pub url: String,
+    /// Payload template, or `None` to send the summary as JSON.
+    ///
+    /// `{{target}}`, `{{total}}`, `{{repaired}}`, `{{unverified}}`, `{{not_locked}}`, `{{skipped}}`,
+    /// `{{quarantined}}`, `{{failed}}` and `{{timed_out}}` are replaced with the values of the run, and `{{summary}}`
+    /// with the whole summary as a JSON object. `{{target}}` is escaped for use inside a JSON string.
+    pub template: Option<String>,
+    /// Notify when more than this many files were repaired.
+    pub repaired_threshold: usize,
+    /// Notify when files failed, timed out or could not be verified.
+    pub on_failure: bool,
+    /// Timeout of the webhook request.
+    pub timeout: Duration,
+}
+
+impl WebhookConfig {
+    /// Creates a configuration that notifies about every repaired file and every failure.
+    pub fn new(url: impl Into<String>) -> Self {
+        WebhookConfig {
+            url: url.into(),
+            template: None,
+            repaired_threshold: 0,
+            on_failure: true,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+}
+
+/// Default payload: the target and the summary of the run.
+#[derive(Serialize)]
+struct Payload<'a> {
+    target: &'a str,
+    #[serde(flatten)]
+    summary: &'a ReportSummary,
+}
+
+/// Sends run summaries to a webhook.
+#[derive(Debug)]
+pub struct WebhookNotifier {
+    config: WebhookConfig,
+    client: Client,
+}
+
+impl WebhookNotifier {
+    /// Creates a notifier for the given webhook.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the HTTP client cannot be created.
+    pub fn new(config: WebhookConfig) -> io::Result<Self> {
+        let client = Client::builder()
+            .timeout(config.timeout)
+            .build()
+            .map_err(io::Error::other)?;
+        Ok(WebhookNotifier { config, client })
+    }
+
+    /// Checks whether a run with the given summary has to be reported.
+    pub fn should_notify(&self, summary: &ReportSummary) -> bool {
+        summary.repaired > self.config.repaired_threshold
+            || (self.config.on_failure
+                && (summary.failed > 0 || summary.timed_out > 0 || summary.unverified > 0))
+    }
+
+    /// POSTs the summary of the run on `target` to the webhook.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the request fails or the webhook responds with an error status.
+    pub fn notify(&self, target: &str, summary: &ReportSummary) -> io::Result<()> {
+        let body = self.payload(target, summary)?;
+        debug!("Send webhook notification: {}", self.config.url);
+
+        self.client
+            .post(&self.config.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body)
+            .send()
+            .and_then(|r| r.error_for_status())
+            .map(|_| ())
+            .map_err(io::Error::other)
+    }
+
+    /// Renders the request body for the run.
+    fn payload(&self, target: &str, summary: &ReportSummary) -> io::Result<String> {
+        let template = match &self.config.template {
+            Some(template) => template,
+            None => return Ok(serde_json::to_string(&Payload { target, summary })?),
+        };
+
+        // The target is escaped as the content of a JSON string, without the quotes
+        let escaped_target = serde_json::to_string(target)?;
+        let escaped_target = &escaped_target[1..escaped_target.len() - 1];
+
+        let values = [
+            ("target", escaped_target.to_string()),
+            ("total", summary.total.to_string()),
+            ("repaired", summary.repaired.to_string()),
+            ("unverified", summary.unverified.to_string()),
+            ("not_locked", summary.not_locked.to_string()),
+            ("skipped", summary.skipped.to_string()),
+            ("quarantined", summary.quarantined.to_string()),
+            ("failed", summary.failed.to_string()),
+            ("timed_out", summary.timed_out.to_string()),
+            ("summary", serde_json::to_string(summary)?),
+        ];
+        Ok(values.iter().fold(template.clone(), |body, (name, value)| {
+            body.replace(&format!("{{{{{}}}}}", name), value)
+        }))
+    }
+}
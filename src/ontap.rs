@@ -0,0 +1,263 @@
+//! # ONTAP Module
+//!
+//! This module contains a `LockBreaker` that releases NFS/CIFS locks server-side through the
+//! NetApp ONTAP REST API (`/api/protocols/locks`), so locked files do not have to be replaced.
+//! It is available with the `ontap` cargo feature.
+//!
+//! The same client also classifies lock owners as a `LockStatusQuery`: a lock held by an NFS client
+//! that is no longer connected to the SVM (`/api/protocols/nfs/connected-clients`), typically an
+//! NFSv3 client that rebooted or went away without releasing its NLM locks, is stale.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::path::{Path, PathBuf};
+//! use netfs_unlker::backend::{NativeFs, NativeLocks};
+//! use netfs_unlker::ontap::{OntapConfig, OntapLockBreaker};
+//! use netfs_unlker::{RepairOptions, Repairer};
+//!
+//! let config = OntapConfig::new(
+//!     "https://cluster.example.com",
+//!     "admin",
+//!     "secret",
+//!     "svm1",
+//!     "vol1",
+//!     PathBuf::from("/mnt/vol1"),
+//! );
+//! let repairer = Repairer::new(NativeFs::default(), NativeLocks::default(), RepairOptions::default())
+//!     .with_lock_breaker(OntapLockBreaker::new(config).unwrap());
+//! let report = repairer.repair_file(Path::new("/mnt/vol1/data.db"));
+//! ```
+
+use crate::backend::{HolderStatus, LockBreaker, LockStatusQuery};
+use reqwest::blocking::Client;
+use serde::de::IgnoredAny;
+use serde::Deserialize;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::time::Duration;
+use tracing::debug;
+
+/// Default timeout of a single REST request.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Connection settings for the ONTAP cluster and the mapping of the local mount to the volume.
+#[derive(Debug, Clone)]
+pub struct OntapConfig {
+    /// Base URL of the cluster management interface, e.g. `https://cluster.example.com`.
+    pub base_url: String,
+    /// User name used for HTTP basic authentication.
+    pub username: String,
+    /// Password used for HTTP basic authentication.
+    pub password: String,
+    /// Name of the SVM serving the volume.
+    pub svm: String,
+    /// Name of the volume.
+    pub volume: String,
+    /// Local path where the volume is mounted; paths below it are mapped to volume paths.
+    pub mount_point: PathBuf,
+    /// Accept invalid TLS certificates (self-signed cluster certificates).
+    pub accept_invalid_certs: bool,
+    /// Timeout of a single REST request.
+    pub timeout: Duration,
+}
+
+impl OntapConfig {
+    /// Creates a configuration with certificate validation enabled and the default timeout.
+    pub fn new(
+        base_url: impl Into<String>,
+        username: impl Into<String>,
+        password: impl Into<String>,
+        svm: impl Into<String>,
+        volume: impl Into<String>,
+        mount_point: PathBuf,
+    ) -> Self {
+        OntapConfig {
+            base_url: base_url.into(),
+            username: username.into(),
+            password: password.into(),
+            svm: svm.into(),
+            volume: volume.into(),
+            mount_point,
+            accept_invalid_certs: false,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+}
+
+/// A lock record as returned by `GET /api/protocols/locks`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct OntapLock {
+    /// Identifier of the lock, used to break it.
+    pub uuid: String,
+    /// Path of the locked file within the volume.
+    pub path: Option<String>,
+    /// Protocol that holds the lock (`nfsv3`, `nfsv4`, `cifs`, ...).
+    pub protocol: Option<String>,
+    /// Lock type (`byte_lock`, `share_level`, `delegation`, ...).
+    #[serde(rename = "type")]
+    pub lock_type: Option<String>,
+    /// IP address of the client owning the lock.
+    pub client_address: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Records<T> {
+    #[serde(default = "Vec::new")]
+    records: Vec<T>,
+}
+
+/// `LockBreaker` and `LockStatusQuery` backed by the ONTAP REST API.
+#[derive(Debug, Clone)]
+pub struct OntapLockBreaker {
+    config: OntapConfig,
+    client: Client,
+}
+
+impl OntapLockBreaker {
+    /// Creates a lock breaker for the given cluster.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the HTTP client cannot be created.
+    pub fn new(config: OntapConfig) -> io::Result<Self> {
+        let client = Client::builder()
+            .danger_accept_invalid_certs(config.accept_invalid_certs)
+            .timeout(config.timeout)
+            .build()
+            .map_err(io::Error::other)?;
+        Ok(OntapLockBreaker { config, client })
+    }
+
+    /// Lists the locks held on the file at the local `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the path is outside of the mount point or the API request fails.
+    pub fn list_locks(&self, path: &Path) -> io::Result<Vec<OntapLock>> {
+        let volume_path = self.volume_path(path)?;
+        debug!("Query ONTAP locks: {}:{}", self.config.volume, volume_path);
+
+        let response = self
+            .client
+            .get(format!("{}/api/protocols/locks", self.base_url()))
+            .basic_auth(&self.config.username, Some(&self.config.password))
+            .query(&[
+                ("svm.name", self.config.svm.as_str()),
+                ("volume.name", self.config.volume.as_str()),
+                ("path", volume_path.as_str()),
+                ("fields", "uuid,path,protocol,type,client_address"),
+            ])
+            .send()
+            .and_then(|r| r.error_for_status())
+            .map_err(io::Error::other)?;
+
+        let records: Records<OntapLock> = response.json().map_err(io::Error::other)?;
+        Ok(records.records)
+    }
+
+    /// Breaks the lock with the given identifier.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the API request fails.
+    pub fn break_lock(&self, uuid: &str) -> io::Result<()> {
+        debug!("Break ONTAP lock: {}", uuid);
+        self.client
+            .delete(format!("{}/api/protocols/locks/{}", self.base_url(), uuid))
+            .basic_auth(&self.config.username, Some(&self.config.password))
+            .send()
+            .and_then(|r| r.error_for_status())
+            .map(|_| ())
+            .map_err(io::Error::other)
+    }
+
+    /// Checks whether the NFS client with the IP address `address` is still connected to the SVM.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the API request fails.
+    pub fn is_client_connected(&self, address: &str) -> io::Result<bool> {
+        debug!("Query ONTAP connected clients: {}", address);
+        let response = self
+            .client
+            .get(format!(
+                "{}/api/protocols/nfs/connected-clients",
+                self.base_url()
+            ))
+            .basic_auth(&self.config.username, Some(&self.config.password))
+            .query(&[
+                ("svm.name", self.config.svm.as_str()),
+                ("client_ip", address),
+                ("fields", "client_ip"),
+            ])
+            .send()
+            .and_then(|r| r.error_for_status())
+            .map_err(io::Error::other)?;
+
+        // Only the presence of a record matters
+        let records: Records<IgnoredAny> = response.json().map_err(io::Error::other)?;
+        Ok(!records.records.is_empty())
+    }
+
+    /// Maps a local path below the mount point to a path within the volume, e.g. `/dir/file`.
+    fn volume_path(&self, path: &Path) -> io::Result<String> {
+        let relative = path.strip_prefix(&self.config.mount_point).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "path is outside of the ONTAP mount point",
+            )
+        })?;
+
+        Ok(relative
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(name) => Some(format!("/{}", name.to_string_lossy())),
+                _ => None,
+            })
+            .collect())
+    }
+
+    fn base_url(&self) -> &str {
+        self.config.base_url.trim_end_matches('/')
+    }
+}
+
+impl LockBreaker for OntapLockBreaker {
+    fn break_locks(&self, path: &Path) -> io::Result<usize> {
+        let locks = self.list_locks(path)?;
+        for lock in &locks {
+            self.break_lock(&lock.uuid)?;
+        }
+        Ok(locks.len())
+    }
+}
+
+impl LockStatusQuery for OntapLockBreaker {
+    /// Classifies the locks on the file: live if any owning NFS client is still connected, stale if
+    /// none is, and unknown if the file has no locks on the filer or a lock is held over another
+    /// protocol or without a client address.
+    fn holder_status(&self, path: &Path) -> io::Result<HolderStatus> {
+        let locks = self.list_locks(path)?;
+        if locks.is_empty() {
+            return Ok(HolderStatus::Unknown);
+        }
+
+        let mut status = HolderStatus::Stale;
+        for lock in &locks {
+            let nfs = lock
+                .protocol
+                .as_deref()
+                .is_some_and(|protocol| protocol.starts_with("nfs"));
+            match lock.client_address.as_deref() {
+                Some(address) if nfs => {
+                    if self.is_client_connected(address)? {
+                        return Ok(HolderStatus::Live);
+                    }
+                }
+                _ => status = HolderStatus::Unknown,
+            }
+        }
+        Ok(status)
+    }
+}
@@ -0,0 +1,289 @@
+//! # Repair Options Module
+//!
+//! This module contains the settings that tune how the repair process behaves.
+
+use crate::backend::FileMetadata;
+use crate::strategy::CopyStrategy;
+use crate::walk::WalkOrder;
+use std::cmp::Ordering;
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Default time to wait for a file held by a mandatory lock to become readable.
+pub const DEFAULT_MANDATORY_LOCK_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Default number of times a stage is retried after failing with a stale file handle.
+pub const DEFAULT_STALE_HANDLE_RETRIES: u32 = 3;
+
+/// Default number of failed repair attempts after which a file is quarantined.
+pub const DEFAULT_QUARANTINE_AFTER: u32 = 3;
+
+/// Default prefix of the temporary copy written next to the original file before it is renamed over it.
+pub const DEFAULT_TMP_PREFIX: &str = ".netfs-unlker.";
+
+/// Default suffix of the temporary copy written next to the original file.
+pub const DEFAULT_TMP_SUFFIX: &str = ".tmp";
+
+/// Options controlling the repair process.
+///
+/// The `Default` implementation matches the behavior of `repair_file` and `repair_files_in_directory`.
+#[derive(Debug, Clone)]
+pub struct RepairOptions {
+    /// Compare SHA-256 checksums of the staged copy and the repaired file after the rename.
+    /// The size and lock state are always verified.
+    pub verify_checksum: bool,
+    /// Try to release only the locked byte ranges before falling back to replacing the whole file.
+    /// The native backends cannot release the locks of other processes or clients, so with them the
+    /// file is always replaced; only backends that can release foreign locks benefit.
+    pub release_ranges: bool,
+    /// Probe files without a record lock for SMB leases held by other clients, and break the lease
+    /// of such files instead of replacing them. Off by default: CIFS mounts without read-caching
+    /// oplocks, e.g. `cache=none` mounts, refuse the probe for every file.
+    pub smb_leases: bool,
+    /// How long to retry non-blocking reads of a file held by a mandatory lock before giving up.
+    pub mandatory_lock_timeout: Duration,
+    /// Also operate on targets on local (non-network) filesystems, which are skipped by default.
+    pub allow_local: bool,
+    /// Maximum number of bytes copied per second, or `None` for no limit.
+    pub max_bytes_per_sec: Option<u64>,
+    /// Maximum number of files processed per second during a directory sweep, or `None` for no limit.
+    pub max_files_per_sec: Option<f64>,
+    /// Only process files of at least this many bytes during a directory sweep.
+    pub min_size: Option<u64>,
+    /// Only process files of at most this many bytes during a directory sweep.
+    pub max_size: Option<u64>,
+    /// Only process files modified within this duration before the sweep.
+    pub newer_than: Option<Duration>,
+    /// Only process files last modified longer than this duration before the sweep.
+    pub older_than: Option<Duration>,
+    /// Directory irreparable files are moved to, or `None` to leave them in place.
+    /// The directory has to exist.
+    pub quarantine_dir: Option<PathBuf>,
+    /// Number of failed repair attempts after which a file is quarantined.
+    /// Only used together with `quarantine_dir`.
+    pub quarantine_after: u32,
+    /// Time a single file may take before it is given up on and reported as timed out,
+    /// or `None` for no limit. A filesystem call that is still blocked when the time is up is
+    /// abandoned on a worker thread, so a dead export cannot stall the sweep; it is not cancelled
+    /// and keeps running until the filesystem responds. Temporary files are still removed afterwards
+    /// within a short grace period.
+    pub file_timeout: Option<Duration>,
+    /// Number of times a pipeline stage is retried after failing with a stale NFS file handle (`ESTALE`).
+    /// The path is re-resolved before every retry.
+    pub stale_handle_retries: u32,
+    /// Repair files whose lock holder is still alive, which are skipped by default.
+    pub force: bool,
+    /// Naming of the temporary copies written during the repair.
+    pub temp_naming: TempNaming,
+    /// Stop a directory sweep at the first subdirectory that cannot be read, instead of reporting
+    /// it as failed and moving on.
+    pub stop_on_error: bool,
+    /// How the staged copy is moved next to the original before the final rename.
+    pub copy_strategy: CopyStrategy,
+    /// Flush the copy next to the original and its directory to stable storage before the rename,
+    /// and the directory again after it. Turning this off is faster, but a crash of the client or
+    /// the filer right after the repair can leave an empty or partially written file behind.
+    pub fsync: bool,
+    /// Order in which a directory sweep repairs the files it found.
+    pub sort_order: SortOrder,
+    /// Order in which a directory sweep walks the tree; with `SortOrder::None` this is also the
+    /// order of the repairs.
+    pub walk_order: WalkOrder,
+    /// Do not descend into subdirectories on another filesystem than the swept directory during a
+    /// recursive sweep; they are reported as skipped instead.
+    pub one_file_system: bool,
+    /// Carry the ACL of the original over to the repaired file, and check it during verification.
+    /// Backends and filesystems without ACL support are repaired as if this was off.
+    pub preserve_acls: bool,
+    /// Only repair files whose name matches one of these patterns during a directory sweep, or every
+    /// file if empty. Patterns support `*` and `?`; a policy file can override them for its subtree.
+    pub include: Vec<String>,
+    /// Pass over files whose name matches one of these patterns during a directory sweep.
+    pub exclude: Vec<String>,
+    /// Directory a copy of every file is saved to right before it is replaced, or `None` for no
+    /// backups. The directory has to exist.
+    pub backup_dir: Option<PathBuf>,
+}
+
+impl Default for RepairOptions {
+    fn default() -> Self {
+        RepairOptions {
+            verify_checksum: false,
+            release_ranges: false,
+            smb_leases: false,
+            mandatory_lock_timeout: DEFAULT_MANDATORY_LOCK_TIMEOUT,
+            allow_local: false,
+            max_bytes_per_sec: None,
+            max_files_per_sec: None,
+            min_size: None,
+            max_size: None,
+            newer_than: None,
+            older_than: None,
+            quarantine_dir: None,
+            quarantine_after: DEFAULT_QUARANTINE_AFTER,
+            file_timeout: None,
+            stale_handle_retries: DEFAULT_STALE_HANDLE_RETRIES,
+            force: false,
+            temp_naming: TempNaming::default(),
+            stop_on_error: false,
+            copy_strategy: CopyStrategy::default(),
+            fsync: true,
+            sort_order: SortOrder::None,
+            walk_order: WalkOrder::default(),
+            one_file_system: false,
+            preserve_acls: true,
+            include: Vec::new(),
+            exclude: Vec::new(),
+            backup_dir: None,
+        }
+    }
+}
+
+impl RepairOptions {
+    /// Checks whether a file passes the size and age filters, taking `now` as the time of the sweep.
+    ///
+    /// A file without a modification time does not pass an age filter.
+    pub fn matches_filters(&self, metadata: &FileMetadata, now: SystemTime) -> bool {
+        if self.min_size.is_some_and(|min| metadata.len < min)
+            || self.max_size.is_some_and(|max| metadata.len > max)
+        {
+            return false;
+        }
+
+        if self.newer_than.is_none() && self.older_than.is_none() {
+            return true;
+        }
+
+        let age = match metadata.modified {
+            // A modification time in the future counts as just modified
+            Some(modified) => now.duration_since(modified).unwrap_or_default(),
+            None => return false,
+        };
+        self.newer_than.is_none_or(|newer| age <= newer)
+            && self.older_than.is_none_or(|older| age >= older)
+    }
+}
+
+/// Naming of the temporary copy of a file, `<prefix><name><suffix>`, written in the staging directory
+/// and next to the original file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TempNaming {
+    /// Prefix put in front of the name of the original file.
+    pub prefix: String,
+    /// Suffix appended to the name of the original file.
+    pub suffix: String,
+}
+
+impl Default for TempNaming {
+    fn default() -> Self {
+        TempNaming::new(DEFAULT_TMP_PREFIX, DEFAULT_TMP_SUFFIX)
+    }
+}
+
+impl TempNaming {
+    /// Creates a naming with the given prefix and suffix.
+    pub fn new(prefix: impl Into<String>, suffix: impl Into<String>) -> Self {
+        TempNaming {
+            prefix: prefix.into(),
+            suffix: suffix.into(),
+        }
+    }
+
+    /// Checks whether the naming can be used: the prefix and suffix must not both be empty, so the
+    /// temporary copy never has the name of its original, and must not contain a path separator.
+    pub fn is_valid(&self) -> bool {
+        let has_separator = |part: &str| part.contains(std::path::is_separator);
+        let is_empty = self.prefix.is_empty() && self.suffix.is_empty();
+        !is_empty && !has_separator(&self.prefix) && !has_separator(&self.suffix)
+    }
+
+    /// Returns the name of the temporary copy of the file named `name`.
+    ///
+    /// The name is kept as is, so files whose names are not valid UTF-8 get a temporary copy as well.
+    pub fn temp_name(&self, name: &OsStr) -> OsString {
+        let mut temp_name = OsString::from(&self.prefix);
+        temp_name.push(name);
+        temp_name.push(&self.suffix);
+        temp_name
+    }
+
+    /// Returns the name of the original file of the temporary copy named `temp_name`, or `None` if
+    /// the name does not follow this naming.
+    pub fn original_name<'a>(&self, temp_name: &'a OsStr) -> Option<&'a OsStr> {
+        let bytes = temp_name.as_encoded_bytes();
+        let name = bytes
+            .strip_prefix(self.prefix.as_bytes())?
+            .strip_suffix(self.suffix.as_bytes())
+            .filter(|name| !name.is_empty() && name.len() < bytes.len())?;
+        // SAFETY: the name is split off the encoded bytes of an `OsStr` at the boundaries of the
+        // UTF-8 prefix and suffix, which keeps it a valid encoding.
+        Some(unsafe { OsStr::from_encoded_bytes_unchecked(name) })
+    }
+}
+
+/// File or directory to repair in a run over several targets, see `Repairer::repair_targets`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    /// A single file, repaired without the filters and the file rate limit of a sweep.
+    File(PathBuf),
+    /// A directory whose files are swept.
+    Directory(PathBuf),
+}
+
+impl Target {
+    /// Returns the path of the target.
+    pub fn path(&self) -> &Path {
+        match self {
+            Target::File(path) | Target::Directory(path) => path,
+        }
+    }
+}
+
+/// File found by a directory sweep, as passed to the sweep ordering.
+#[derive(Debug, Clone)]
+pub struct SweepEntry {
+    /// Path of the file.
+    pub path: PathBuf,
+    /// Metadata of the file, or `None` if it could not be read.
+    pub metadata: Option<FileMetadata>,
+}
+
+/// Order in which a directory sweep repairs the files it found.
+///
+/// With any order other than `None`, the sweep lists all files before repairing the first one.
+/// Files whose metadata cannot be read come last; ties keep the order in which the files were found.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SortOrder {
+    /// Repair the files in the order they are found.
+    #[default]
+    None,
+    /// Smallest files first.
+    SizeAsc,
+    /// Largest files first.
+    SizeDesc,
+    /// Least recently modified files first.
+    MtimeAsc,
+    /// Most recently modified files first.
+    MtimeDesc,
+}
+
+impl SortOrder {
+    /// Compares two sweep entries according to this order.
+    pub fn compare(self, a: &SweepEntry, b: &SweepEntry) -> Ordering {
+        if self == SortOrder::None {
+            return Ordering::Equal;
+        }
+        let (a, b) = match (&a.metadata, &b.metadata) {
+            (Some(a), Some(b)) => (a, b),
+            (a, b) => return a.is_none().cmp(&b.is_none()),
+        };
+        match self {
+            SortOrder::None => Ordering::Equal,
+            SortOrder::SizeAsc => a.len.cmp(&b.len),
+            SortOrder::SizeDesc => b.len.cmp(&a.len),
+            SortOrder::MtimeAsc => a.modified.cmp(&b.modified),
+            SortOrder::MtimeDesc => b.modified.cmp(&a.modified),
+        }
+    }
+}
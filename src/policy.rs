@@ -0,0 +1,334 @@
+//! # Policy Module
+//!
+//! This module contains the per-directory policies of a sweep. A `.netfs-unlker.toml` file in a directory
+//! overrides the file name patterns, the force setting and the backups of the sweep for that directory and
+//! everything below it, so the teams owning different subtrees can set their own rules under one sweep.
+//! A policy file deeper in the tree overrides the settings it sets again; the others are inherited.
+//!
+//! Policy files are written in a subset of TOML: `key = value` pairs with strings, booleans and arrays
+//! of strings, and `#` comments.
+//!
+//! ```toml
+//! # Only the databases, never the scratch files, even if their holder is alive
+//! include = ["*.db", "*.sqlite"]
+//! exclude = ["scratch-*"]
+//! force = true
+//! backup_dir = "/backups/team-a"
+//! ```
+
+use crate::options::RepairOptions;
+use std::ffi::OsStr;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Name of the policy file looked for in every directory of a sweep.
+pub const POLICY_FILE_NAME: &str = ".netfs-unlker.toml";
+
+/// Settings of a policy file; settings that are not set are inherited.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Policy {
+    /// Patterns of the file names to repair. Other files are passed over. Overrides
+    /// `RepairOptions::include`.
+    pub include: Option<Vec<String>>,
+    /// Patterns of the file names never to repair. Overrides `RepairOptions::exclude`.
+    pub exclude: Option<Vec<String>>,
+    /// Repair files whose lock holder is still alive. Overrides `RepairOptions::force`.
+    pub force: Option<bool>,
+    /// Whether to back up files before replacing them; `false` turns backups off, `true` turns them
+    /// back on below a directory that turned them off.
+    pub backup: Option<bool>,
+    /// Directory the backups are saved to, relative to the directory of the policy file unless
+    /// absolute. Overrides `RepairOptions::backup_dir`.
+    pub backup_dir: Option<PathBuf>,
+}
+
+impl Policy {
+    /// Parses the content of the policy file in `directory`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` of kind `InvalidData` naming the line if the content is not valid TOML of the
+    /// supported subset, sets an unknown key, or a value of the wrong type.
+    pub fn parse(content: &str, directory: &Path) -> io::Result<Self> {
+        let mut policy = Policy::default();
+        let mut parser = Parser::new(content);
+        while let Some((line, key)) = parser.next_key()? {
+            let value = parser.value()?;
+            let invalid =
+                |expected: &str| invalid_data(line, format!("{} must be {}", key, expected));
+            match key.as_str() {
+                "include" => {
+                    policy.include = Some(
+                        value
+                            .strings()
+                            .ok_or_else(|| invalid("an array of strings"))?,
+                    )
+                }
+                "exclude" => {
+                    policy.exclude = Some(
+                        value
+                            .strings()
+                            .ok_or_else(|| invalid("an array of strings"))?,
+                    )
+                }
+                "force" => {
+                    policy.force = Some(value.boolean().ok_or_else(|| invalid("a boolean"))?)
+                }
+                "backup" => {
+                    policy.backup = Some(value.boolean().ok_or_else(|| invalid("a boolean"))?)
+                }
+                "backup_dir" => {
+                    let dir = value.string().ok_or_else(|| invalid("a string"))?;
+                    policy.backup_dir = Some(directory.join(dir));
+                }
+                _ => return Err(invalid_data(line, format!("unknown key {}", key))),
+            }
+        }
+        Ok(policy)
+    }
+
+    /// Returns the policy of a subdirectory with the policy file `child`: the settings of `child`, and
+    /// those of this policy that `child` does not set.
+    pub fn merge(&self, child: &Policy) -> Policy {
+        Policy {
+            include: child.include.clone().or_else(|| self.include.clone()),
+            exclude: child.exclude.clone().or_else(|| self.exclude.clone()),
+            force: child.force.or(self.force),
+            backup: child.backup.or(self.backup),
+            backup_dir: child.backup_dir.clone().or_else(|| self.backup_dir.clone()),
+        }
+    }
+
+    /// Checks whether a file named `file_name` is to be repaired under this policy, falling back to the
+    /// patterns of `options`. Without include patterns, every file not excluded is.
+    pub fn selects(&self, file_name: &OsStr, options: &RepairOptions) -> bool {
+        let Some(file_name) = file_name.to_str() else {
+            // Patterns are text; a name that is not cannot match them
+            return self.include.as_ref().unwrap_or(&options.include).is_empty();
+        };
+        let include = self.include.as_ref().unwrap_or(&options.include);
+        let exclude = self.exclude.as_ref().unwrap_or(&options.exclude);
+        (include.is_empty() || include.iter().any(|pattern| glob_match(pattern, file_name)))
+            && !exclude.iter().any(|pattern| glob_match(pattern, file_name))
+    }
+
+    /// Checks whether files are repaired even if their lock holder is alive, falling back to `options`.
+    pub fn force(&self, options: &RepairOptions) -> bool {
+        self.force.unwrap_or(options.force)
+    }
+
+    /// Returns the directory backups are saved to, falling back to `options`, or `None` if files are
+    /// not backed up.
+    pub fn backup_dir<'a>(&'a self, options: &'a RepairOptions) -> Option<&'a Path> {
+        match self.backup {
+            Some(false) => None,
+            _ => self.backup_dir.as_deref().or(options.backup_dir.as_deref()),
+        }
+    }
+}
+
+/// Matches `name` against a shell pattern, where `*` matches any run of characters and `?` any single
+/// character.
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let (pattern, name): (Vec<char>, Vec<char>) =
+        (pattern.chars().collect(), name.chars().collect());
+    let (mut p, mut n) = (0, 0);
+    // Position after the last `*` and the name position it matched up to, to backtrack to
+    let mut star: Option<(usize, usize)> = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p + 1, n));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match star {
+                Some((after_star, matched)) => {
+                    p = after_star;
+                    n = matched + 1;
+                    star = Some((after_star, matched + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+fn invalid_data(line: usize, message: String) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("line {}: {}", line, message),
+    )
+}
+
+/// Value of a key in a policy file.
+enum Value {
+    String(String),
+    Boolean(bool),
+    Array(Vec<String>),
+}
+
+impl Value {
+    fn string(self) -> Option<String> {
+        match self {
+            Value::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    fn boolean(self) -> Option<bool> {
+        match self {
+            Value::Boolean(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    fn strings(self) -> Option<Vec<String>> {
+        match self {
+            Value::Array(strings) => Some(strings),
+            _ => None,
+        }
+    }
+}
+
+/// Reader of the TOML subset of policy files.
+struct Parser<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    line: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn new(content: &'a str) -> Self {
+        Parser {
+            chars: content.chars().peekable(),
+            line: 1,
+        }
+    }
+
+    /// Reads the next key and the `=` after it, returning its line, or `None` at the end.
+    fn next_key(&mut self) -> io::Result<Option<(usize, String)>> {
+        self.skip_whitespace(true);
+        let Some(&c) = self.chars.peek() else {
+            return Ok(None);
+        };
+        let key = match c {
+            '"' | '\'' => self.string()?,
+            '[' => return Err(self.error("tables are not supported")),
+            _ => {
+                let mut key = String::new();
+                while let Some(&c) = self
+                    .chars
+                    .peek()
+                    .filter(|c| c.is_ascii_alphanumeric() || **c == '_' || **c == '-')
+                {
+                    key.push(c);
+                    self.chars.next();
+                }
+                if key.is_empty() {
+                    return Err(self.error(format!("unexpected character {:?}", c)));
+                }
+                key
+            }
+        };
+        self.skip_whitespace(false);
+        match self.chars.next() {
+            Some('=') => Ok(Some((self.line, key))),
+            _ => Err(self.error(format!("expected = after {}", key))),
+        }
+    }
+
+    /// Reads a value and checks that only a comment follows it on its line.
+    fn value(&mut self) -> io::Result<Value> {
+        self.skip_whitespace(false);
+        let value = match self.chars.peek() {
+            Some('"' | '\'') => Value::String(self.string()?),
+            Some('[') => {
+                self.chars.next();
+                let mut strings = Vec::new();
+                loop {
+                    self.skip_whitespace(true);
+                    match self.chars.peek() {
+                        Some(']') => break,
+                        Some('"' | '\'') => strings.push(self.string()?),
+                        _ => return Err(self.error("expected a string or ]")),
+                    }
+                    self.skip_whitespace(true);
+                    match self.chars.next() {
+                        Some(',') => {}
+                        Some(']') => return self.end_of_line(Value::Array(strings)),
+                        _ => return Err(self.error("expected , or ]")),
+                    }
+                }
+                self.chars.next();
+                Value::Array(strings)
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(&c) = self.chars.peek().filter(|c| c.is_ascii_alphanumeric()) {
+                    word.push(c);
+                    self.chars.next();
+                }
+                match word.as_str() {
+                    "true" => Value::Boolean(true),
+                    "false" => Value::Boolean(false),
+                    _ => return Err(self.error("expected a string, a boolean or an array")),
+                }
+            }
+        };
+        self.end_of_line(value)
+    }
+
+    fn end_of_line(&mut self, value: Value) -> io::Result<Value> {
+        self.skip_whitespace(false);
+        match self.chars.peek() {
+            None | Some('\n') => Ok(value),
+            Some(_) => Err(self.error("expected the end of the line")),
+        }
+    }
+
+    /// Reads a basic (`"`) or literal (`'`) string on a single line.
+    fn string(&mut self) -> io::Result<String> {
+        let quote = self.chars.next();
+        let mut s = String::new();
+        loop {
+            match self.chars.next() {
+                None | Some('\n') => return Err(self.error("unterminated string")),
+                Some(c) if Some(c) == quote => return Ok(s),
+                Some('\\') if quote == Some('"') => match self.chars.next() {
+                    Some('"') => s.push('"'),
+                    Some('\\') => s.push('\\'),
+                    Some('n') => s.push('\n'),
+                    Some('t') => s.push('\t'),
+                    _ => return Err(self.error("unsupported escape sequence")),
+                },
+                Some(c) => s.push(c),
+            }
+        }
+    }
+
+    /// Skips spaces, tabs and comments, and line breaks too if `newlines` is set.
+    fn skip_whitespace(&mut self, newlines: bool) {
+        while let Some(&c) = self.chars.peek() {
+            match c {
+                ' ' | '\t' | '\r' => {}
+                '\n' if newlines => self.line += 1,
+                '#' => {
+                    while self.chars.peek().is_some_and(|&c| c != '\n') {
+                        self.chars.next();
+                    }
+                    continue;
+                }
+                _ => return,
+            }
+            self.chars.next();
+        }
+    }
+
+    fn error(&self, message: impl Into<String>) -> io::Error {
+        invalid_data(self.line, message.into())
+    }
+}
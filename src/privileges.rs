@@ -0,0 +1,119 @@
+//! # Privileges Module
+//!
+//! This module contains the minimum-privilege handling of the `repair` and `watch` subcommands: runs
+//! as root are refused unless `--allow-root` is given, and `--run-as <USER>` switches to an
+//! unprivileged user once the resources that need root (log file, run lock, audit log, control
+//! socket) are open. Switching the user also clears all capabilities of the process.
+
+use std::ffi::{CStr, CString};
+use std::io::{self, Error};
+use std::mem::MaybeUninit;
+use std::ptr;
+
+/// Buffer size for `getpwnam_r` when the system does not suggest one.
+const PASSWD_BUFFER_SIZE: usize = 16 * 1024;
+
+/// User the process switches to.
+#[derive(Debug, Clone)]
+pub struct Identity {
+    name: CString,
+    uid: libc::uid_t,
+    gid: libc::gid_t,
+}
+
+impl Identity {
+    /// Looks up a user by name, or by numeric user ID.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` of kind `NotFound` if there is no such user.
+    pub fn lookup(user: &str) -> io::Result<Self> {
+        let name = CString::new(user).map_err(|_| Error::from(io::ErrorKind::InvalidInput))?;
+        let mut buffer = vec![0u8; passwd_buffer_size()];
+        let mut passwd = MaybeUninit::<libc::passwd>::uninit();
+        let mut result = ptr::null_mut();
+
+        let ret = match user.parse::<libc::uid_t>() {
+            Ok(uid) => unsafe {
+                libc::getpwuid_r(
+                    uid,
+                    passwd.as_mut_ptr(),
+                    buffer.as_mut_ptr().cast(),
+                    buffer.len(),
+                    &mut result,
+                )
+            },
+            Err(_) => unsafe {
+                libc::getpwnam_r(
+                    name.as_ptr(),
+                    passwd.as_mut_ptr(),
+                    buffer.as_mut_ptr().cast(),
+                    buffer.len(),
+                    &mut result,
+                )
+            },
+        };
+        if ret != 0 {
+            return Err(Error::from_raw_os_error(ret));
+        }
+        if result.is_null() {
+            return Err(Error::new(io::ErrorKind::NotFound, "no such user"));
+        }
+
+        let passwd = unsafe { passwd.assume_init() };
+        Ok(Identity {
+            name: unsafe { CStr::from_ptr(passwd.pw_name) }.to_owned(),
+            uid: passwd.pw_uid,
+            gid: passwd.pw_gid,
+        })
+    }
+
+    /// Checks whether this is the root user.
+    pub fn is_root(&self) -> bool {
+        self.uid == 0
+    }
+
+    /// Returns the name of the user.
+    pub fn name(&self) -> &str {
+        self.name.to_str().unwrap_or("?")
+    }
+
+    /// Switches the process to this user, with its primary group and supplementary groups.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the process lacks the privileges to switch, or if root privileges could
+    /// still be regained afterwards.
+    pub fn switch(&self) -> io::Result<()> {
+        // The groups go first, as changing them needs the privileges the user switch gives up
+        if unsafe { libc::initgroups(self.name.as_ptr(), self.gid as _) } != 0 {
+            return Err(Error::last_os_error());
+        }
+        if unsafe { libc::setgid(self.gid) } != 0 {
+            return Err(Error::last_os_error());
+        }
+        if unsafe { libc::setuid(self.uid) } != 0 {
+            return Err(Error::last_os_error());
+        }
+
+        if self.uid != 0 && unsafe { libc::setuid(0) } == 0 {
+            return Err(Error::new(
+                io::ErrorKind::PermissionDenied,
+                "root privileges can be regained after switching the user",
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Checks whether the process runs with root privileges.
+pub fn is_root() -> bool {
+    unsafe { libc::geteuid() == 0 }
+}
+
+fn passwd_buffer_size() -> usize {
+    match unsafe { libc::sysconf(libc::_SC_GETPW_R_SIZE_MAX) } {
+        size if size > 0 => size as usize,
+        _ => PASSWD_BUFFER_SIZE,
+    }
+}
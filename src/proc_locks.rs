@@ -0,0 +1,102 @@
+//! A module for inspecting the kernel lock table and the processes holding locks.
+//!
+//! This module provides functions to list the locks held on a file, as listed in `/proc/locks`,
+//! and to check whether the process holding a lock is still running.
+
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+/// Location of the lock table.
+const LOCKS_PATH: &str = "/proc/locks";
+
+/// An entry of the lock table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockEntry {
+    /// PID of the process holding the lock.
+    pub pid: u32,
+    /// Major number of the device the locked file lives on.
+    pub major: u32,
+    /// Minor number of the device the locked file lives on.
+    pub minor: u32,
+    /// Inode number of the locked file.
+    pub inode: u64,
+}
+
+/// Returns the granted locks held on the file at `path`; waiting lock requests are left out.
+///
+/// # Returns
+///
+/// Returns an empty list if the lock table is not available on this platform.
+pub fn locks_on(path: &Path) -> Result<Vec<LockEntry>> {
+    let metadata = fs::metadata(path)?;
+    let (major, minor) = (libc::major(metadata.dev()), libc::minor(metadata.dev()));
+    let table = match fs::read_to_string(LOCKS_PATH) {
+        Ok(table) => table,
+        Err(_) => return Ok(Vec::new()), // No lock table on this platform
+    };
+
+    Ok(table
+        .lines()
+        .filter_map(parse_line)
+        .filter(|entry| {
+            entry.major == major && entry.minor == minor && entry.inode == metadata.ino()
+        })
+        .collect())
+}
+
+/// Checks whether a process with the given PID is running on this host.
+pub fn process_exists(pid: u32) -> bool {
+    let pid = match libc::pid_t::try_from(pid) {
+        Ok(pid) if pid > 0 => pid,
+        _ => return false,
+    };
+
+    // Signal 0 only checks whether the process can be signalled; EPERM means it exists
+    match unsafe { libc::kill(pid, 0) } {
+        0 => true,
+        _ => Error::last_os_error().raw_os_error() == Some(libc::EPERM),
+    }
+}
+
+/// Parses a line such as `1: POSIX  ADVISORY  WRITE 1234 00:2d:1054 0 EOF`.
+fn parse_line(line: &str) -> Option<LockEntry> {
+    let mut fields = line.split_whitespace().skip(1); // Lock number
+    if fields.next()? == "->" {
+        return None; // Blocked request waiting for the lock
+    }
+
+    let pid = fields.nth(2)?.parse().ok()?;
+    let mut id = fields.next()?.split(':');
+    let major = u32::from_str_radix(id.next()?, 16).ok()?;
+    let minor = u32::from_str_radix(id.next()?, 16).ok()?;
+    let inode = id.next()?.parse().ok()?;
+
+    Some(LockEntry {
+        pid,
+        major,
+        minor,
+        inode,
+    })
+}
+
+/// Checks whether the holder of a lock on the file at `path` is still alive on this host.
+///
+/// The holder counts as alive if `pid` (as reported by `F_GETLK`) names a running process, or if the
+/// lock table lists a lock on the file held by a running process.
+///
+/// # Errors
+///
+/// Returns an `Err` if the file cannot be inspected.
+pub fn is_holder_alive(path: &Path, pid: Option<u32>) -> Result<bool> {
+    if pid.is_some_and(process_exists) {
+        return Ok(true);
+    }
+
+    match locks_on(path) {
+        Ok(locks) => Ok(locks.iter().any(|lock| process_exists(lock.pid))),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(e),
+    }
+}
@@ -0,0 +1,63 @@
+//! # Progress Module
+//!
+//! This module contains the `ProgressObserver` trait through which the repair engine reports live
+//! progress, for example to draw a progress bar or to stream it to a web console. The engine emits
+//! a `ProgressEvent` when it starts a file, enters a pipeline stage, copies data, and finishes a file.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::path::Path;
+//! use netfs_unlker::backend::{NativeFs, NativeLocks};
+//! use netfs_unlker::progress::ProgressEvent;
+//! use netfs_unlker::{RepairOptions, Repairer};
+//!
+//! let repairer = Repairer::new(NativeFs::default(), NativeLocks::default(), RepairOptions::default())
+//!     .with_progress_observer(|event: &ProgressEvent<'_>| {
+//!         if let ProgressEvent::FileFinished { path, outcome, .. } = event {
+//!             println!("{}: {}", path.display(), outcome.name());
+//!         }
+//!     });
+//! let report = repairer.repair_directory(Path::new("/mnt/share"), true);
+//! ```
+
+use crate::report::FileOutcome;
+use std::path::Path;
+use std::time::Duration;
+
+/// Progress of the repair engine.
+#[derive(Debug)]
+pub enum ProgressEvent<'a> {
+    /// The engine started processing the file at `path`.
+    FileStarted { path: &'a Path },
+    /// The repair of the file entered the pipeline stage `stage`, such as `copy_to_staging` or `rename`.
+    StageChanged { path: &'a Path, stage: &'static str },
+    /// `bytes` more bytes of the file were copied.
+    BytesCopied { path: &'a Path, bytes: u64 },
+    /// The engine finished the file with an outcome that is not a failure.
+    FileFinished {
+        path: &'a Path,
+        outcome: &'a FileOutcome,
+        duration: Duration,
+    },
+    /// The repair of the file failed or timed out.
+    FileFailed {
+        path: &'a Path,
+        outcome: &'a FileOutcome,
+        duration: Duration,
+    },
+}
+
+/// Receiver of the progress events of the repair engine.
+///
+/// The events are delivered on the thread running the repair, so an observer should return quickly.
+pub trait ProgressObserver {
+    /// Called for every progress event.
+    fn on_event(&self, event: &ProgressEvent<'_>);
+}
+
+impl<F: Fn(&ProgressEvent<'_>)> ProgressObserver for F {
+    fn on_event(&self, event: &ProgressEvent<'_>) {
+        self(event)
+    }
+}
@@ -0,0 +1,87 @@
+//! # Progress Bar Module
+//!
+//! This module contains the progress line the `repair` subcommand draws on stderr, built on the
+//! progress events of the repair engine. The line shows the number of processed and failed files,
+//! the bytes copied so far, and the stage of the file being repaired.
+
+use bytesize::ByteSize;
+use netfs_unlker::progress::{ProgressEvent, ProgressObserver};
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+/// Minimum time between two redraws of the line.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Progress line on stderr; the line is cleared when dropped.
+#[derive(Debug, Default)]
+pub struct ProgressBar {
+    state: RefCell<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    processed: usize,
+    failed: usize,
+    bytes_copied: u64,
+    current: Option<(PathBuf, &'static str)>,
+    drawn: Option<Instant>,
+}
+
+impl ProgressObserver for ProgressBar {
+    fn on_event(&self, event: &ProgressEvent<'_>) {
+        let mut state = self.state.borrow_mut();
+        match event {
+            ProgressEvent::FileStarted { path } => {
+                state.current = Some((path.to_path_buf(), "start"));
+            }
+            ProgressEvent::StageChanged { path, stage } => {
+                state.current = Some((path.to_path_buf(), stage));
+            }
+            ProgressEvent::BytesCopied { bytes, .. } => state.bytes_copied += bytes,
+            ProgressEvent::FileFinished { .. } => {
+                state.processed += 1;
+                state.current = None;
+            }
+            ProgressEvent::FileFailed { .. } => {
+                state.processed += 1;
+                state.failed += 1;
+                state.current = None;
+            }
+        }
+
+        // Sweeps over many small files would otherwise spend their time drawing
+        if state
+            .drawn
+            .is_none_or(|drawn| drawn.elapsed() >= REDRAW_INTERVAL)
+        {
+            state.drawn = Some(Instant::now());
+            let _ = state.draw(&mut io::stderr().lock());
+        }
+    }
+}
+
+impl Drop for ProgressBar {
+    fn drop(&mut self) {
+        if self.state.get_mut().drawn.is_some() {
+            let _ = write!(io::stderr(), "\r\x1b[2K");
+        }
+    }
+}
+
+impl State {
+    fn draw<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write!(
+            writer,
+            "\r\x1b[2K{} files, {} failed, {} copied",
+            self.processed,
+            self.failed,
+            ByteSize(self.bytes_copied)
+        )?;
+        if let Some((path, stage)) = &self.current {
+            write!(writer, " | {} {}", stage, path.display())?;
+        }
+        writer.flush()
+    }
+}
@@ -0,0 +1,238 @@
+//! # Remote Module
+//!
+//! This module contains the `agent` subcommand, which serves scans and repairs below its targets over
+//! the REST API of `netfs_unlker::agent`, and the `remote` subcommand, which drives such an agent.
+
+use crate::cli::{AgentArgs, RemoteArgs, RemoteRequest, RepairArgs};
+use crate::{
+    acquire_run_guards, open_audit_log, shutdown, Engine, EXIT_FAILURES, EXIT_NOTHING_TO_DO,
+    EXIT_REPAIRED, EXIT_USAGE_ERROR,
+};
+#[cfg(unix)]
+use crate::{check_privileges, drop_privileges};
+use netfs_unlker::agent::{AgentClient, AgentEvent, AgentHandler, AgentServer, ProgressStream};
+use netfs_unlker::audit::JsonLinesAuditLog;
+use netfs_unlker::backend::{NativeFs, NativeLocks};
+use netfs_unlker::scan::{ScanReport, Scanner};
+use netfs_unlker::RepairReport;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use tracing::{error, info, warn};
+
+/// Runs scans and repairs requested through the agent with the options of the command line.
+///
+/// Scans run concurrently, repairs one at a time, so two requests cannot race for the same file.
+/// The audit log is opened once and shared by the repairs, so its hash chain continues across them.
+struct EngineHandler<'a> {
+    args: &'a RepairArgs,
+    audit_log: Option<Arc<JsonLinesAuditLog>>,
+    repairing: Mutex<()>,
+}
+
+impl AgentHandler for EngineHandler<'_> {
+    fn scan(&self, path: &Path, recursive: bool) -> io::Result<ScanReport> {
+        let scanner = Scanner::new(NativeFs::default(), NativeLocks::default())
+            .with_walk_order(self.args.target.walk_order())
+            .with_one_file_system(self.args.target.one_file_system);
+        match path.is_dir() {
+            true => scanner.scan_directory(path, recursive),
+            false => scanner.scan_file(path),
+        }
+    }
+
+    fn repair(
+        &self,
+        path: &Path,
+        recursive: bool,
+        progress: ProgressStream,
+    ) -> io::Result<RepairReport> {
+        // A repairer per request, so the progress goes to the client that asked for it
+        let engine = Engine::with_audit_log(self.args, self.audit_log.clone())
+            .ok_or_else(|| io::Error::other("the repair engine cannot be set up"))?;
+        let repairer = engine.repairer.with_progress_observer(progress);
+        let _repairing = self.repairing.lock().unwrap_or_else(|e| e.into_inner());
+        let report = match path.is_dir() {
+            true => repairer.repair_directory(path, recursive),
+            false => repairer.repair_file(path),
+        }?;
+        info!(
+            "Remote repair done: {} repaired, {} failed ({})",
+            report.repaired(),
+            report.failed(),
+            path.display()
+        );
+        Ok(report)
+    }
+}
+
+/// Runs the `agent` subcommand until a shutdown is requested and returns the process exit code.
+pub fn run_agent(args: &AgentArgs) -> i32 {
+    let Some(token) = read_token(&args.token_file) else {
+        return EXIT_USAGE_ERROR;
+    };
+    #[cfg(unix)]
+    let identity = match check_privileges(&args.repair.privileges) {
+        Ok(identity) => identity,
+        Err(()) => return EXIT_USAGE_ERROR,
+    };
+    let guards = match acquire_run_guards(&args.repair) {
+        Ok(guards) => guards,
+        Err(()) => return EXIT_USAGE_ERROR,
+    };
+    let Ok(audit_log) = open_audit_log(&args.repair) else {
+        return EXIT_USAGE_ERROR;
+    };
+    // Validates the repair options before serving the first request
+    if Engine::with_audit_log(&args.repair, audit_log.clone()).is_none() {
+        return EXIT_USAGE_ERROR;
+    }
+
+    // Requested paths are canonicalized before they are checked, so the roots are too
+    let mut roots = Vec::new();
+    for target in args.repair.target.targets() {
+        match fs::canonicalize(target.path()) {
+            Ok(root) => roots.push(root),
+            Err(e) => {
+                error!(
+                    "Failed to resolve the agent root ({}): {}",
+                    target.path().display(),
+                    e
+                );
+                return EXIT_USAGE_ERROR;
+            }
+        }
+    }
+    let server = match AgentServer::bind(&args.listen, token) {
+        Ok(server) => server.with_roots(roots),
+        Err(e) => {
+            error!("Failed to listen on {}: {}", args.listen, e);
+            return EXIT_USAGE_ERROR;
+        }
+    };
+    #[cfg(unix)]
+    if drop_privileges(identity.as_ref()).is_err() {
+        return EXIT_USAGE_ERROR;
+    }
+
+    shutdown::install();
+    let handler = EngineHandler {
+        args: &args.repair,
+        audit_log,
+        repairing: Mutex::new(()),
+    };
+    if let Err(e) = server.serve(handler, || !shutdown::requested()) {
+        error!("The agent failed: {}", e);
+        return EXIT_USAGE_ERROR;
+    }
+    info!("Shutting down");
+    drop(guards);
+    EXIT_NOTHING_TO_DO
+}
+
+/// Runs the `remote` subcommand and returns the process exit code.
+pub fn run_remote(args: &RemoteArgs) -> i32 {
+    let Some(token) = read_token(&args.token_file) else {
+        return EXIT_USAGE_ERROR;
+    };
+    let client = match AgentClient::new(&args.url, token) {
+        Ok(client) => client,
+        Err(e) => {
+            error!("Failed to set up the agent client: {}", e);
+            return EXIT_USAGE_ERROR;
+        }
+    };
+
+    match &args.request {
+        RemoteRequest::Health => match client.health() {
+            Ok(version) => {
+                let _ = writeln!(io::stdout(), "{}", version);
+                EXIT_NOTHING_TO_DO
+            }
+            Err(e) => failed_request(&args.url, e),
+        },
+        RemoteRequest::Scan { path, recursive } => {
+            let report = match client.scan(path, *recursive) {
+                Ok(report) => report,
+                Err(e) => return failed_request(&args.url, e),
+            };
+            print_json(&report);
+            let count = |field: &str| report[field].as_array().map_or(0, Vec::len);
+            if count("errors") > 0 {
+                EXIT_FAILURES
+            } else if count("locked") > 0 {
+                EXIT_REPAIRED
+            } else {
+                EXIT_NOTHING_TO_DO
+            }
+        }
+        RemoteRequest::Repair { path, recursive } => {
+            let summary = client.repair(path, *recursive, |event| match event {
+                AgentEvent::FileFinished { path, outcome, .. } => {
+                    info!("{}: ({})", outcome, path)
+                }
+                AgentEvent::FileFailed {
+                    path,
+                    outcome,
+                    detail,
+                    ..
+                } => warn!(
+                    "{}: ({}): {}",
+                    outcome,
+                    path,
+                    detail.as_deref().unwrap_or_default()
+                ),
+                _ => {}
+            });
+            let summary = match summary {
+                Ok(summary) => summary,
+                Err(e) => return failed_request(&args.url, e),
+            };
+            print_json(&summary);
+            if summary.failed > 0 || summary.timed_out > 0 || summary.unverified > 0 {
+                EXIT_FAILURES
+            } else if summary.repaired > 0 || summary.quarantined > 0 {
+                EXIT_REPAIRED
+            } else {
+                EXIT_NOTHING_TO_DO
+            }
+        }
+    }
+}
+
+/// Reads the agent token from `path`, ignoring surrounding whitespace.
+///
+/// # Returns
+///
+/// Returns `None` if the file cannot be read or is empty; the error is logged.
+fn read_token(path: &Path) -> Option<String> {
+    match fs::read_to_string(path) {
+        Ok(token) if !token.trim().is_empty() => Some(token.trim().to_string()),
+        Ok(_) => {
+            error!("The token file is empty ({})", path.display());
+            None
+        }
+        Err(e) => {
+            error!("Failed to read the token file ({}): {}", path.display(), e);
+            None
+        }
+    }
+}
+
+fn failed_request(url: &str, e: io::Error) -> i32 {
+    error!("Request to the agent failed ({}): {}", url, e);
+    match e.kind() {
+        io::ErrorKind::PermissionDenied | io::ErrorKind::InvalidInput => EXIT_USAGE_ERROR,
+        _ => EXIT_FAILURES,
+    }
+}
+
+fn print_json(value: &impl serde::Serialize) {
+    match serde_json::to_string_pretty(value) {
+        Ok(json) => {
+            let _ = writeln!(io::stdout(), "{}", json);
+        }
+        Err(e) => error!("Failed to format the response: {}", e),
+    }
+}
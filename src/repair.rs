@@ -0,0 +1,1657 @@
+//! # Repair Engine Module
+//!
+//! This module contains the `Repairer`, which drives the repair pipeline for single files and directories.
+//! The engine is generic over the `FileOps` and `LockOps` traits, so it can run against a real
+//! network mount as well as against an alternative backend.
+//!
+//! Every processed file gets a `file` tracing span with the `path`, `size`, `lock_holder` and
+//! `holder_status` fields, and every pipeline stage a nested `stage` span, so the events of one file
+//! can be correlated even when runs are interleaved.
+
+use crate::audit::{AuditOutcome, AuditRecord, AuditSink, OriginalFile};
+use crate::backend::{
+    Acl, FileKind, FileMetadata, FileOps, FilesystemKind, HolderStatus, LockBreaker, LockInfo,
+    LockOps, LockStatusQuery, LockingMode,
+};
+use crate::cache::ProbeCache;
+use crate::deadline;
+use crate::error::RepairError;
+use crate::hooks::Hooks;
+use crate::options::{RepairOptions, SortOrder, SweepEntry, Target};
+use crate::policy::{Policy, POLICY_FILE_NAME};
+use crate::progress::{ProgressEvent, ProgressObserver};
+use crate::report::{FileOutcome, FileReport, RepairReport, SkipReason, VerificationFailure};
+use crate::strategy::CopyStrategy;
+use crate::throttle::Throttle;
+use crate::walk::{Walk, WalkError};
+use sha2::{Digest, Sha256};
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
+use std::io::{self, Error, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime};
+use tracing::field::{Empty, Value};
+use tracing::span::EnteredSpan;
+use tracing::{debug, error, info, info_span, warn, Span};
+
+const DEVIDER: &str = "#############################\n";
+
+/// Size and modification time of a file, used to detect concurrent writes.
+#[derive(Debug, PartialEq, Eq)]
+struct FileSnapshot {
+    len: u64,
+    modified: Option<SystemTime>,
+}
+
+impl From<FileMetadata> for FileSnapshot {
+    fn from(metadata: FileMetadata) -> Self {
+        FileSnapshot {
+            len: metadata.len,
+            modified: metadata.modified,
+        }
+    }
+}
+
+/// Custom order of the files of a directory sweep, see `Repairer::with_comparator`.
+type Comparator = dyn Fn(&SweepEntry, &SweepEntry) -> Ordering;
+
+/// Size, checksum and ACL of the staged copy, the expected end state of the repaired file.
+struct StagedCopy {
+    len: u64,
+    /// Checksum of the staged copy, computed when an audit sink is set or checksums are verified.
+    checksum: Option<String>,
+    /// ACL carried over from the original, if it has one and ACLs are preserved.
+    acl: Option<Acl>,
+}
+
+/// Staging directory that is removed when dropped.
+struct StagingDir<'a, F: FileOps> {
+    fs: &'a F,
+    path: PathBuf,
+}
+
+impl<'a, F: FileOps> StagingDir<'a, F> {
+    fn new(fs: &'a F) -> io::Result<Self> {
+        let path = fs.create_staging_dir()?;
+        Ok(StagingDir { fs, path })
+    }
+}
+
+impl<F: FileOps> Drop for StagingDir<'_, F> {
+    fn drop(&mut self) {
+        let _grace = deadline::grace();
+        if let Err(e) = self.fs.remove_staging_dir(&self.path) {
+            warn!(
+                "Failed to remove staging directory ({}): {}",
+                self.path.display(),
+                e
+            );
+        }
+    }
+}
+
+/// Temporary copy next to the original that is removed when dropped, unless it was renamed over the
+/// original.
+///
+/// It is set up before the copy is written, so that a partial copy is removed as well; a copy that
+/// was never created is not reported.
+struct TempFile<'a, F: FileOps> {
+    fs: &'a F,
+    path: &'a Path,
+    renamed: bool,
+}
+
+impl<'a, F: FileOps> TempFile<'a, F> {
+    fn new(fs: &'a F, path: &'a Path) -> Self {
+        TempFile {
+            fs,
+            path,
+            renamed: false,
+        }
+    }
+
+    /// Marks the copy as renamed over the original, so there is nothing left to remove.
+    fn renamed(mut self) {
+        self.renamed = true;
+    }
+}
+
+impl<F: FileOps> Drop for TempFile<'_, F> {
+    fn drop(&mut self) {
+        if self.renamed {
+            return;
+        }
+        // The copy is removed even if the file timed out
+        let _grace = deadline::grace();
+        match self.fs.remove_file(self.path) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => warn!(
+                "Failed to remove temporary file ({}): {}",
+                self.path.display(),
+                e
+            ),
+        }
+    }
+}
+
+/// Current pipeline stage of a file, tracked as a `stage` span nested in the `file` span and
+/// reported to the progress observer.
+struct Stage<'a> {
+    file: Span,
+    current: Option<EnteredSpan>,
+    path: &'a Path,
+    observer: Option<&'a dyn ProgressObserver>,
+}
+
+impl<'a> Stage<'a> {
+    /// Starts tracking the stages of the file at `path`, whose span is the current span.
+    fn new(path: &'a Path, observer: Option<&'a dyn ProgressObserver>) -> Self {
+        Stage {
+            file: Span::current(),
+            current: None,
+            path,
+            observer,
+        }
+    }
+
+    /// Leaves the previous stage and enters the stage `name`.
+    fn enter(&mut self, name: &'static str) {
+        self.current.take();
+        self.current = Some(info_span!("stage", stage = name).entered());
+        debug!("Enter stage");
+        if let Some(observer) = self.observer {
+            observer.on_event(&ProgressEvent::StageChanged {
+                path: self.path,
+                stage: name,
+            });
+        }
+    }
+
+    /// Reports `bytes` copied in the current stage and returns them.
+    fn copied(&self, bytes: u64) -> u64 {
+        if let Some(observer) = self.observer {
+            observer.on_event(&ProgressEvent::BytesCopied {
+                path: self.path,
+                bytes,
+            });
+        }
+        bytes
+    }
+
+    /// Records a field of the enclosing `file` span.
+    fn record(&self, field: &'static str, value: impl Value) {
+        self.file.record(field, value);
+    }
+}
+
+/// What is known about a locked file whose repair was attempted, collected for the audit trail.
+#[derive(Default)]
+struct Attempt {
+    locked: bool,
+    hooked: bool,
+    lock: Option<LockInfo>,
+    original: Option<FileMetadata>,
+    checksum: Option<String>,
+    bytes_copied: u64,
+}
+
+/// Repair engine for locked files.
+///
+/// The `Repairer` copies a locked file to a local staging directory, copies it back next to the
+/// original and atomically renames it over the original, verifying the end state afterwards.
+/// If a `LockBreaker` is configured, the locks are first broken server-side, and with
+/// `RepairOptions::release_ranges` the locked byte ranges are released individually, as far as the
+/// `LockOps` backend can release foreign locks; the copy-based repair is only used when that is not
+/// possible. With `RepairOptions::smb_leases`,
+/// files that are only leased by another SMB client have the lease broken and are never replaced.
+/// The copies and the directory sweep are throttled according to `RepairOptions::max_bytes_per_sec`
+/// and `RepairOptions::max_files_per_sec`.
+/// If an `AuditSink` is configured, every attempted repair of a locked file is recorded in it,
+/// and configured `Hooks` run before and after the repair of every locked file.
+/// A `ProgressObserver` receives live progress events.
+/// With `RepairOptions::file_timeout`, a file whose filesystem calls do not finish in time is
+/// reported as `FileOutcome::TimedOut` and the sweep moves on.
+/// Stages failing with a stale NFS file handle are retried after re-resolving the path.
+/// Files whose lock holder is still alive are skipped unless `RepairOptions::force` is set.
+/// A directory sweep repairs the files in the order given by `RepairOptions::sort_order`, or by
+/// the comparator set with `with_comparator`. With a `ProbeCache`, a sweep does not probe the files
+/// found unlocked by an earlier sweep that are unchanged since.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::Path;
+/// use netfs_unlker::backend::{NativeFs, NativeLocks};
+/// use netfs_unlker::{RepairOptions, Repairer};
+///
+/// let repairer = Repairer::new(NativeFs::default(), NativeLocks::default(), RepairOptions::default());
+/// let report = repairer.repair_directory(Path::new("/path/to/directory"), true);
+/// ```
+pub struct Repairer<F: FileOps, L: LockOps> {
+    fs: F,
+    locks: L,
+    options: RepairOptions,
+    lock_breaker: Option<Box<dyn LockBreaker>>,
+    lock_status: Option<Box<dyn LockStatusQuery>>,
+    audit_sink: Option<Box<dyn AuditSink>>,
+    hooks: Option<Box<dyn Hooks>>,
+    progress_observer: Option<Box<dyn ProgressObserver>>,
+    comparator: Option<Box<Comparator>>,
+    probe_cache: Option<ProbeCache>,
+    byte_throttle: Option<Throttle>,
+    file_throttle: Option<Throttle>,
+}
+
+impl<F: FileOps, L: LockOps> Repairer<F, L> {
+    /// Creates a new repair engine on top of the given backends.
+    pub fn new(fs: F, locks: L, options: RepairOptions) -> Self {
+        let byte_throttle = options
+            .max_bytes_per_sec
+            .filter(|&rate| rate > 0)
+            .map(|rate| Throttle::new(rate as f64));
+        let file_throttle = options
+            .max_files_per_sec
+            .filter(|&rate| rate > 0.0)
+            .map(Throttle::new);
+
+        Repairer {
+            fs,
+            locks,
+            options,
+            lock_breaker: None,
+            lock_status: None,
+            audit_sink: None,
+            hooks: None,
+            progress_observer: None,
+            comparator: None,
+            probe_cache: None,
+            byte_throttle,
+            file_throttle,
+        }
+    }
+
+    /// Sets a server-side lock breaker that is tried before the copy-based repair.
+    pub fn with_lock_breaker(mut self, lock_breaker: impl LockBreaker + 'static) -> Self {
+        self.lock_breaker = Some(Box::new(lock_breaker));
+        self
+    }
+
+    /// Sets a server-side classification of lock owners; only locks it classifies as stale are then
+    /// repaired, unless `RepairOptions::force` is set.
+    pub fn with_lock_status(mut self, lock_status: impl LockStatusQuery + 'static) -> Self {
+        self.lock_status = Some(Box::new(lock_status));
+        self
+    }
+
+    /// Sets a sink that receives an audit record for every attempted repair of a locked file.
+    pub fn with_audit_sink(mut self, audit_sink: impl AuditSink + 'static) -> Self {
+        self.audit_sink = Some(Box::new(audit_sink));
+        self
+    }
+
+    /// Sets hooks that run before and after the repair of every locked file.
+    pub fn with_hooks(mut self, hooks: impl Hooks + 'static) -> Self {
+        self.hooks = Some(Box::new(hooks));
+        self
+    }
+
+    /// Sets an observer that receives the progress events of the engine.
+    pub fn with_progress_observer(mut self, observer: impl ProgressObserver + 'static) -> Self {
+        self.progress_observer = Some(Box::new(observer));
+        self
+    }
+
+    /// Sets a cache of the files found unlocked, which directory sweeps consult before probing a
+    /// file and write back when they finish.
+    pub fn with_probe_cache(mut self, probe_cache: ProbeCache) -> Self {
+        self.probe_cache = Some(probe_cache);
+        self
+    }
+
+    /// Sets the order in which a directory sweep repairs the files it found, replacing
+    /// `RepairOptions::sort_order`.
+    pub fn with_comparator(
+        mut self,
+        comparator: impl Fn(&SweepEntry, &SweepEntry) -> Ordering + 'static,
+    ) -> Self {
+        self.comparator = Some(Box::new(comparator));
+        self
+    }
+
+    /// Returns the options the engine was created with.
+    pub fn options(&self) -> &RepairOptions {
+        &self.options
+    }
+
+    /// Repairs all files in the specified directory.
+    ///
+    /// A failure to repair a single file is recorded in the report and does not stop the run.
+    /// A directory on a local filesystem is skipped unless `RepairOptions::allow_local` is set.
+    /// Files that do not match the size and age filters of the options are left out of the report.
+    /// A subdirectory that cannot be read is reported as failed, unless `RepairOptions::stop_on_error` is set.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the specified directory path does not exist or cannot be read, or if a
+    /// subdirectory cannot be read and `RepairOptions::stop_on_error` is set.
+    pub fn repair_directory(
+        &self,
+        directory_path: &Path,
+        recursive: bool,
+    ) -> io::Result<RepairReport> {
+        let started = Instant::now();
+        let mut report = RepairReport::new();
+        if self.is_local_target(directory_path) {
+            report.push(
+                directory_path.to_path_buf(),
+                FileOutcome::Skipped(SkipReason::LocalFilesystem),
+            );
+            return Ok(report);
+        }
+
+        self.sweep(&[directory_path], recursive, &mut None, &mut report)?;
+        report.elapsed = started.elapsed();
+        Ok(report)
+    }
+
+    /// Repairs several files and directories in one run, with a single report.
+    ///
+    /// The files are repaired first, in the given order, then the directories are swept as one:
+    /// the sort order applies across all of them. A file reached through several targets is only
+    /// repaired once, provided it is spelled the same way. Targets on a local filesystem are skipped
+    /// unless `RepairOptions::allow_local` is set.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` before repairing anything if a target does not exist, or an `Err` as
+    /// `repair_directory` does if a directory cannot be read.
+    pub fn repair_targets(&self, targets: &[Target], recursive: bool) -> io::Result<RepairReport> {
+        let started = Instant::now();
+        for target in targets {
+            let _deadline = deadline::start(self.options.file_timeout);
+            if let Err(e) = self.fs.metadata(target.path()) {
+                error!("Such target not found: ({})", target.path().display());
+                return Err(e);
+            }
+        }
+
+        let mut report = RepairReport::new();
+        let mut seen = (targets.len() > 1).then(HashSet::new);
+        let mut directories = Vec::new();
+        for target in targets {
+            let local = {
+                let _deadline = deadline::start(self.options.file_timeout);
+                self.is_local_target(target.path())
+            };
+            if local {
+                report.push(
+                    target.path().to_path_buf(),
+                    FileOutcome::Skipped(SkipReason::LocalFilesystem),
+                );
+                continue;
+            }
+
+            match target {
+                Target::File(path) if is_first_visit(&mut seen, path) => {
+                    debug!("{}", DEVIDER);
+                    report
+                        .files
+                        .push(self.repair_path(path, &Policy::default()));
+                }
+                Target::File(_) => {}
+                Target::Directory(path) => directories.push(path.as_path()),
+            }
+        }
+
+        if !directories.is_empty() {
+            self.sweep(&directories, recursive, &mut seen, &mut report)?;
+        }
+        report.elapsed = started.elapsed();
+        Ok(report)
+    }
+
+    /// Sweeps the given directories into `report`, sorting the files of all of them together.
+    ///
+    /// With `seen`, files already in the set are passed over and the others are added to it.
+    /// Every file is repaired under the policy of its directory; the files covered by a policy file
+    /// that cannot be read are passed over, and the policy file is reported as failed.
+    fn sweep(
+        &self,
+        directories: &[&Path],
+        recursive: bool,
+        seen: &mut Option<HashSet<PathBuf>>,
+        report: &mut RepairReport,
+    ) -> io::Result<()> {
+        let listed: Vec<_> = directories
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect();
+        let span = info_span!("sweep", directory = %listed.join(", "), recursive);
+        let _entered = span.enter();
+
+        let now = SystemTime::now();
+        let sorted = self.comparator.is_some() || self.options.sort_order != SortOrder::None;
+        let mut found = Vec::new();
+        let mut policies = HashMap::new();
+        for directory_path in directories {
+            let walk = Walk::new(&self.fs, directory_path, recursive, self.options.walk_order)?
+                .one_file_system(directory_path, self.options.one_file_system);
+            for entry in walk {
+                let path = match entry {
+                    Ok(path) => path,
+                    Err(WalkError { path, error })
+                        if error.kind() == io::ErrorKind::CrossesDevices =>
+                    {
+                        info!("Not crossing into another filesystem: ({})", path.display());
+                        report.push(path, FileOutcome::Skipped(SkipReason::OtherFilesystem));
+                        if let Some(file) = report.files.last() {
+                            self.emit_finished(file);
+                        }
+                        continue;
+                    }
+                    Err(WalkError { path, error }) => {
+                        warn!("Failed to read directory ({}): {}", path.display(), error);
+                        if self.options.stop_on_error {
+                            return Err(error);
+                        }
+                        report.push(path, FileOutcome::Failed(RepairError::unreadable(error)));
+                        if let Some(file) = report.files.last() {
+                            self.emit_finished(file);
+                        }
+                        continue;
+                    }
+                };
+                if !is_first_visit(seen, &path) {
+                    debug!("File was already swept: ({})", path.display());
+                    continue;
+                }
+                let Some(file_name) = path.file_name() else {
+                    continue;
+                };
+                if file_name == POLICY_FILE_NAME {
+                    continue;
+                }
+                let Some(policy) = path.parent().and_then(|parent| {
+                    self.policy_of(parent, directory_path, &mut policies, report)
+                }) else {
+                    debug!(
+                        "File is covered by an invalid policy file: ({})",
+                        path.display()
+                    );
+                    continue;
+                };
+                if !policy.selects(file_name, &self.options) {
+                    debug!("File does not match the patterns: ({})", path.display());
+                    continue;
+                }
+                let metadata = {
+                    let _deadline = deadline::start(self.options.file_timeout);
+                    self.fs.metadata(&path).ok()
+                };
+                if !self.is_selected(metadata.as_ref(), now) {
+                    debug!("File does not match the filters: ({})", path.display());
+                    continue;
+                }
+                match sorted {
+                    true => found.push((SweepEntry { path, metadata }, policy)),
+                    false => report
+                        .files
+                        .push(self.sweep_file(&path, metadata.as_ref(), &policy)),
+                }
+            }
+        }
+
+        if sorted {
+            match &self.comparator {
+                Some(comparator) => found.sort_by(|(a, _), (b, _)| comparator(a, b)),
+                None => found.sort_by(|(a, _), (b, _)| self.options.sort_order.compare(a, b)),
+            }
+            for (entry, policy) in found {
+                report
+                    .files
+                    .push(self.sweep_file(&entry.path, entry.metadata.as_ref(), &policy));
+            }
+        }
+
+        if let Some(cache) = &self.probe_cache {
+            for directory_path in directories {
+                cache.prune(directory_path, recursive);
+            }
+            if let Err(e) = cache.save() {
+                warn!(
+                    "Failed to save the probe cache ({}): {}",
+                    cache.path().display(),
+                    e
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the policy of `directory` within the swept directory `root`: the policy of its parent
+    /// merged with its own policy file, if any. Policies are looked up once per sweep in `policies`.
+    ///
+    /// # Returns
+    ///
+    /// Returns `None` if a policy file of the directory or one of its parents up to `root` cannot be
+    /// read or parsed; that file is reported as failed the first time.
+    fn policy_of(
+        &self,
+        directory: &Path,
+        root: &Path,
+        policies: &mut HashMap<PathBuf, Option<Arc<Policy>>>,
+        report: &mut RepairReport,
+    ) -> Option<Arc<Policy>> {
+        if let Some(policy) = policies.get(directory) {
+            return policy.clone();
+        }
+        let inherited = match directory.parent() {
+            Some(parent) if directory != root && directory.starts_with(root) => {
+                self.policy_of(parent, root, policies, report)
+            }
+            _ => Some(Arc::new(Policy::default())),
+        };
+        let policy = inherited.and_then(|inherited| match self.load_policy(directory) {
+            Ok(None) => Some(inherited),
+            Ok(Some(policy)) => {
+                debug!("Apply policy file: ({})", directory.display());
+                Some(Arc::new(inherited.merge(&policy)))
+            }
+            Err(e) => {
+                let path = directory.join(POLICY_FILE_NAME);
+                error!(
+                    "Invalid policy file, passing over its directory ({}): {}",
+                    path.display(),
+                    e
+                );
+                report.push(path, FileOutcome::Failed(RepairError::InvalidPolicy(e)));
+                if let Some(file) = report.files.last() {
+                    self.emit_finished(file);
+                }
+                None
+            }
+        });
+        policies.insert(directory.to_path_buf(), policy.clone());
+        policy
+    }
+
+    /// Reads and parses the policy file of `directory`, returning `None` if there is none.
+    fn load_policy(&self, directory: &Path) -> io::Result<Option<Policy>> {
+        let path = directory.join(POLICY_FILE_NAME);
+        let _deadline = deadline::start(self.options.file_timeout);
+        let mut content = String::new();
+        match self.fs.open(&path) {
+            Ok(mut file) => file.read_to_string(&mut content)?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        Policy::parse(&content, directory).map(Some)
+    }
+
+    /// Repairs a file found by a sweep, unless the probe cache knows it as unlocked and unchanged,
+    /// and updates the cache with the outcome.
+    fn sweep_file(
+        &self,
+        path: &Path,
+        metadata: Option<&FileMetadata>,
+        policy: &Policy,
+    ) -> FileReport {
+        let (Some(cache), Some(metadata)) = (&self.probe_cache, metadata) else {
+            return self.sweep_path(path, policy);
+        };
+        if cache.is_unchanged(path, metadata) {
+            debug!(
+                "File is unchanged since it was found unlocked: ({})",
+                path.display()
+            );
+            let file = FileReport {
+                path: path.to_path_buf(),
+                outcome: FileOutcome::NotLocked,
+                size: Some(metadata.len),
+                locked: false,
+                lock: None,
+                bytes_copied: 0,
+                duration: Duration::ZERO,
+            };
+            self.emit_finished(&file);
+            return file;
+        }
+
+        let file = self.sweep_path(path, policy);
+        match file.outcome {
+            FileOutcome::NotLocked => cache.record_unlocked(path, metadata),
+            _ => cache.forget(path),
+        }
+        file
+    }
+
+    /// Repairs a single file.
+    ///
+    /// The outcome, including a failed repair, is returned as a single-entry `RepairReport`.
+    /// A file on a local filesystem is skipped unless `RepairOptions::allow_local` is set.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the file does not exist.
+    pub fn repair_file(&self, file_path: &Path) -> io::Result<RepairReport> {
+        debug!("{}", DEVIDER);
+        let started = Instant::now();
+        let deadline = deadline::start(self.options.file_timeout);
+        if let Err(e) = self.fs.metadata(file_path) {
+            error!("Such file not found: ({})", file_path.display());
+            return Err(e);
+        }
+
+        let local = self.is_local_target(file_path);
+        drop(deadline);
+
+        let mut report = RepairReport::new();
+        match local {
+            true => report.push(
+                file_path.to_path_buf(),
+                FileOutcome::Skipped(SkipReason::LocalFilesystem),
+            ),
+            false => report
+                .files
+                .push(self.repair_path(file_path, &Policy::default())),
+        }
+        report.elapsed = started.elapsed();
+        Ok(report)
+    }
+
+    /// Repairs a single path under `policy` within its own `file` span and converts the result into a
+    /// `FileReport`.
+    ///
+    /// The per-file timeout covers all attempts on the path as well as the quarantine; the hooks
+    /// and the audit record are still handled for a file that timed out.
+    fn repair_path(&self, file_path: &Path, policy: &Policy) -> FileReport {
+        let started = Instant::now();
+        let span = info_span!(
+            "file",
+            path = %file_path.display(),
+            size = Empty,
+            lock_holder = Empty,
+            holder_status = Empty
+        );
+        let _entered = span.enter();
+        let _deadline = deadline::start(self.options.file_timeout);
+        self.emit(&ProgressEvent::FileStarted { path: file_path });
+
+        let mut attempt = Attempt::default();
+        let mut outcome = self.attempt_repair(file_path, &mut attempt, policy);
+
+        if let Some(quarantine_dir) = &self.options.quarantine_dir {
+            let mut failures = 1;
+            while is_quarantinable(&outcome) && failures < self.options.quarantine_after {
+                warn!(
+                    "Retrying failed repair, attempt {} of {}: ({})",
+                    failures + 1,
+                    self.options.quarantine_after,
+                    file_path.display()
+                );
+                outcome = self.attempt_repair(file_path, &mut attempt, policy);
+                failures += 1;
+            }
+
+            if let FileOutcome::Failed(error) = outcome {
+                outcome = match is_quarantinable_error(&error) {
+                    true => self.quarantine(file_path, quarantine_dir, error),
+                    false => FileOutcome::Failed(error),
+                };
+            }
+        }
+
+        let outcome = match attempt.hooked {
+            true => self.run_post_hook(file_path, outcome),
+            false => outcome,
+        };
+
+        let mut file = FileReport {
+            path: file_path.to_path_buf(),
+            outcome,
+            size: attempt.original.as_ref().map(|original| original.len),
+            locked: attempt.locked,
+            lock: attempt.lock.clone(),
+            bytes_copied: attempt.bytes_copied,
+            duration: Duration::ZERO,
+        };
+        if attempt.locked {
+            self.audit(file_path, attempt, &file.outcome);
+        }
+        file.duration = started.elapsed();
+        self.emit_finished(&file);
+        file
+    }
+
+    /// Passes a progress event to the observer, if one is set.
+    fn emit(&self, event: &ProgressEvent<'_>) {
+        if let Some(observer) = &self.progress_observer {
+            observer.on_event(event);
+        }
+    }
+
+    /// Reports a finished file as `FileFailed` if it failed or timed out, and as `FileFinished` otherwise.
+    fn emit_finished(&self, file: &FileReport) {
+        let (path, outcome, duration) = (file.path.as_path(), &file.outcome, file.duration);
+        self.emit(&match outcome {
+            FileOutcome::Failed(_) | FileOutcome::TimedOut => ProgressEvent::FileFailed {
+                path,
+                outcome,
+                duration,
+            },
+            _ => ProgressEvent::FileFinished {
+                path,
+                outcome,
+                duration,
+            },
+        });
+    }
+
+    /// Makes a single repair attempt and converts the result into a `FileOutcome`.
+    fn attempt_repair(
+        &self,
+        file_path: &Path,
+        attempt: &mut Attempt,
+        policy: &Policy,
+    ) -> FileOutcome {
+        match self.unlock_file(file_path, attempt, policy) {
+            Ok(outcome) => outcome,
+            Err(_) if deadline::expired() => {
+                error!(
+                    "Timed out repairing file, moving on: ({})",
+                    file_path.display()
+                );
+                FileOutcome::TimedOut
+            }
+            Err(e) => {
+                error!("Failed to repair file ({}): {}", file_path.display(), e);
+                FileOutcome::Failed(e)
+            }
+        }
+    }
+
+    /// Moves an irreparable file to the quarantine directory. A quarantine directory on another
+    /// filesystem gets a copy of the file, and the original is removed afterwards.
+    ///
+    /// Returns `FileOutcome::Failed` with the original error if the file cannot be quarantined at all;
+    /// the file is then left in place.
+    fn quarantine(
+        &self,
+        file_path: &Path,
+        quarantine_dir: &Path,
+        error: RepairError,
+    ) -> FileOutcome {
+        let quarantined =
+            self.unused_destination(file_path, quarantine_dir)
+                .and_then(|destination| {
+                    match self.fs.rename(file_path, &destination) {
+                        Ok(()) => {}
+                        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+                            self.fs.copy(file_path, &destination)?;
+                            if let Err(e) = self.fs.remove_file(file_path) {
+                                let _ = self.fs.remove_file(&destination);
+                                return Err(e);
+                            }
+                        }
+                        Err(e) => return Err(e),
+                    }
+                    Ok(destination)
+                });
+
+        match quarantined {
+            Ok(destination) => {
+                warn!(
+                    "Quarantined irreparable file: ({}) -> ({})",
+                    file_path.display(),
+                    destination.display()
+                );
+                FileOutcome::Quarantined { destination, error }
+            }
+            Err(e) => {
+                error!("Failed to quarantine file ({}): {}", file_path.display(), e);
+                FileOutcome::Failed(error)
+            }
+        }
+    }
+
+    /// Picks a path in the quarantine or backup directory `dir` that is not taken yet, based on the
+    /// file name.
+    fn unused_destination(&self, file_path: &Path, dir: &Path) -> io::Result<PathBuf> {
+        let file_name = file_path
+            .file_name()
+            .ok_or_else(|| Error::from(io::ErrorKind::InvalidInput))?;
+
+        let mut destination = dir.join(file_name);
+        let mut suffix = 0;
+        while self.fs.metadata(&destination).is_ok() {
+            suffix += 1;
+            let mut name = file_name.to_os_string();
+            name.push(format!(".{}", suffix));
+            destination = dir.join(name);
+        }
+        Ok(destination)
+    }
+
+    /// Saves the staged copy at `tmp_file_path`, whose content is that of the original at
+    /// `file_path`, to the backup directory. The original itself may still be locked.
+    fn back_up(
+        &self,
+        tmp_file_path: &Path,
+        file_path: &Path,
+        backup_dir: &Path,
+        attempt: &mut Attempt,
+    ) -> Result<(), RepairError> {
+        let destination = self.unused_destination(file_path, backup_dir)?;
+        debug!(
+            "Back up: netapp ({}) -> ({})",
+            tmp_file_path.display(),
+            destination.display()
+        );
+        attempt.bytes_copied +=
+            self.retry_stale(tmp_file_path, || self.copy(tmp_file_path, &destination))?;
+        info!(
+            "Backed up file: ({}) -> ({})",
+            file_path.display(),
+            destination.display()
+        );
+        Ok(())
+    }
+
+    /// Runs the post-repair hook, turning the outcome into a failure if the hook fails.
+    fn run_post_hook(&self, file_path: &Path, outcome: FileOutcome) -> FileOutcome {
+        let hooks = match &self.hooks {
+            Some(hooks) => hooks,
+            None => return outcome,
+        };
+
+        debug!("Run post-repair hook: ({})", file_path.display());
+        match hooks.post_repair(file_path, &outcome) {
+            Ok(()) => outcome,
+            Err(e) => {
+                error!("Post-repair hook failed ({}): {}", file_path.display(), e);
+                FileOutcome::Failed(RepairError::PostHook(e))
+            }
+        }
+    }
+
+    /// Writes the audit record of a repair attempt to the configured `AuditSink`.
+    fn audit(&self, file_path: &Path, attempt: Attempt, outcome: &FileOutcome) {
+        let audit_sink = match &self.audit_sink {
+            Some(audit_sink) => audit_sink,
+            None => return,
+        };
+
+        let quarantined_to = match outcome {
+            FileOutcome::Quarantined { destination, .. } => Some(destination.clone()),
+            _ => None,
+        };
+        let (outcome, detail) = match outcome {
+            FileOutcome::Repaired => (AuditOutcome::Repaired, None),
+            FileOutcome::RepairedButUnverified(failure) => {
+                (AuditOutcome::Unverified, Some(failure.to_string()))
+            }
+            FileOutcome::Failed(e) => (AuditOutcome::Failed, Some(e.to_string())),
+            FileOutcome::Quarantined { destination, error } => (
+                AuditOutcome::Quarantined,
+                Some(format!("{} (moved to {})", error, destination.display())),
+            ),
+            FileOutcome::TimedOut => (AuditOutcome::TimedOut, None),
+            // Not reached for locked files, which are the only ones audited
+            FileOutcome::NotLocked | FileOutcome::Skipped(_) => return,
+        };
+
+        let record = AuditRecord {
+            timestamp: SystemTime::now(),
+            path: file_path.to_path_buf(),
+            original: attempt.original.map(|original| OriginalFile {
+                inode: original.inode,
+                size: original.len,
+                mtime: original.modified,
+                checksum: attempt.checksum,
+            }),
+            new_inode: self.fs.metadata(file_path).ok().and_then(|m| m.inode),
+            lock: attempt.lock,
+            quarantined_to,
+            outcome,
+            detail,
+        };
+
+        if let Err(e) = audit_sink.record(&record) {
+            error!(
+                "Failed to write the audit record ({}): {}",
+                file_path.display(),
+                e
+            );
+        }
+    }
+
+    /// Handles the actual unlocking and repairing process for a locked file.
+    ///
+    /// Detailed logs are written to track the progress and any errors encountered during the process.
+    /// It involves copying the file to a temporary location, unlocking it, and then replacing the original file.
+    /// The size and modification time of the original are recorded before staging and re-checked right
+    /// before the final rename, so writes made by the owning application in between are never discarded.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if any step in the repair process fails, including access errors and a failed
+    /// backup, or `RepairError::ConcurrentModification` if the original file changed during the repair.
+    fn unlock_file(
+        &self,
+        file_path: &Path,
+        attempt: &mut Attempt,
+        policy: &Policy,
+    ) -> Result<FileOutcome, RepairError> {
+        debug!("Start unlocking file: ({})", file_path.display());
+
+        let mut stage = Stage::new(file_path, self.progress_observer.as_deref());
+        stage.enter("probe");
+        attempt.original = self.fs.metadata(file_path).ok();
+        if attempt
+            .original
+            .as_ref()
+            .is_none_or(|metadata| metadata.kind != FileKind::File)
+        {
+            warn!("This is not a file name: ({})", file_path.display());
+            return Ok(FileOutcome::Skipped(SkipReason::NotAFile));
+        }
+
+        let locked = self.retry_stale(file_path, || self.locks.is_locked(file_path))?;
+        let leased = !locked
+            && self.options.smb_leases
+            && self.retry_stale(file_path, || self.locks.is_leased(file_path))?;
+        if !locked && !leased {
+            info!("File is not locked: ({})", file_path.display());
+            return Ok(FileOutcome::NotLocked);
+        }
+        let lock = match leased {
+            true => Some(LockInfo::smb_lease()),
+            false => self.locks.lock_info(file_path).ok().flatten(),
+        };
+        if let Some(pid) = lock.as_ref().and_then(|lock| lock.pid) {
+            stage.record("lock_holder", pid);
+        }
+        if lock
+            .as_ref()
+            .is_some_and(|lock| self.is_holder_alive(file_path, lock))
+        {
+            if !policy.force(&self.options) {
+                warn!(
+                    "Lock holder is still alive, skipping, use --force to override: ({})",
+                    file_path.display()
+                );
+                return Ok(FileOutcome::Skipped(SkipReason::LockHolderAlive));
+            }
+            warn!(
+                "Lock holder is still alive, repairing anyway: ({})",
+                file_path.display()
+            );
+        }
+        if let Some(reason) = self.classify_holder(file_path, &stage) {
+            if !policy.force(&self.options) {
+                warn!(
+                    "Lock is not stale, skipping, use --force to override: ({})",
+                    file_path.display()
+                );
+                return Ok(FileOutcome::Skipped(reason));
+            }
+            warn!(
+                "Lock is not stale, repairing anyway: ({})",
+                file_path.display()
+            );
+        }
+        attempt.locked = true;
+        attempt.original = self.fs.metadata(file_path).ok();
+        attempt.lock = lock;
+
+        // Retried attempts run the pre-repair hook only once
+        if let Some(hooks) = self.hooks.as_ref().filter(|_| !attempt.hooked) {
+            stage.enter("pre_hook");
+            debug!("Run pre-repair hook: ({})", file_path.display());
+            hooks.pre_repair(file_path).map_err(RepairError::PreHook)?;
+            attempt.hooked = true;
+        }
+
+        if self.break_locks_server_side(file_path, &mut stage) {
+            info!(
+                "Successfully unlocked on the server: ({})",
+                file_path.display()
+            );
+            return Ok(FileOutcome::Repaired);
+        }
+
+        // A leased file is never replaced: the lease probe cannot tell a lease held by another
+        // client from a file another client merely has open
+        if leased {
+            self.break_smb_lease(file_path, &mut stage)?;
+            info!(
+                "Successfully broke the SMB lease: ({})",
+                file_path.display()
+            );
+            return Ok(FileOutcome::Repaired);
+        }
+
+        if self.options.release_ranges && self.release_locked_ranges(file_path, &mut stage) {
+            info!(
+                "Successfully released locked ranges: ({})",
+                file_path.display()
+            );
+            return Ok(FileOutcome::Repaired);
+        }
+
+        let tmp_file_name = match file_path
+            .file_name()
+            .map(|name| self.options.temp_naming.temp_name(name))
+        {
+            Some(name) => name,
+            None => {
+                warn!("Wrong format of file name ({})", file_path.display());
+                return Ok(FileOutcome::Skipped(SkipReason::InvalidFileName));
+            }
+        };
+
+        let directory = file_path
+            .parent()
+            .ok_or_else(|| Error::from(io::ErrorKind::InvalidInput))?;
+        let netapp_tmp_file_path = directory.join(&tmp_file_name);
+        let snapshot =
+            FileSnapshot::from(self.retry_stale(file_path, || self.fs.metadata(file_path))?);
+        stage.record("size", snapshot.len);
+
+        let mandatory = matches!(
+            self.locks.locking_mode(file_path),
+            Ok(LockingMode::Mandatory)
+        );
+        let acl = self.read_acl(file_path)?;
+        // From here on, a failure before the rename leaves the temporary copy behind unless removed
+        let tmp_file = TempFile::new(&self.fs, &netapp_tmp_file_path);
+        let in_memory = match self.options.copy_strategy.is_in_memory(snapshot.len) && !mandatory {
+            true => self.copy_in_memory(file_path, &netapp_tmp_file_path, &mut stage, attempt)?,
+            false => None,
+        };
+        let mut staged = match in_memory {
+            Some(staged) => staged,
+            None => self.copy_through_staging(
+                file_path,
+                &tmp_file_name,
+                &netapp_tmp_file_path,
+                mandatory,
+                &mut stage,
+                attempt,
+            )?,
+        };
+        if self.audit_sink.is_some() {
+            attempt.checksum = staged.checksum.clone();
+        }
+        if let Some(acl) = acl {
+            stage.enter("acl");
+            debug!("Apply ACL: ({})", netapp_tmp_file_path.display());
+            if let Err(e) = self.retry_stale(&netapp_tmp_file_path, || {
+                self.fs.write_acl(&netapp_tmp_file_path, &acl)
+            }) {
+                warn!(
+                    "Failed to carry the ACL over, keeping the original ({}): {}",
+                    file_path.display(),
+                    e
+                );
+                return Err(e);
+            }
+            staged.acl = Some(acl);
+        }
+        // Flushed before the rename, so that it cannot expose a partially written file after a crash
+        if self.options.fsync {
+            self.retry_stale(&netapp_tmp_file_path, || {
+                self.fs.sync_file(&netapp_tmp_file_path)
+            })?;
+            self.retry_stale(directory, || self.fs.sync_dir(directory))?;
+        }
+
+        stage.enter("check");
+        if FileSnapshot::from(self.retry_stale(file_path, || self.fs.metadata(file_path))?)
+            != snapshot
+        {
+            warn!(
+                "File was modified during the repair, keeping the original: ({})",
+                file_path.display()
+            );
+            return Err(RepairError::ConcurrentModification);
+        }
+
+        if let Some(backup_dir) = policy.backup_dir(&self.options) {
+            stage.enter("backup");
+            if let Err(e) = self.back_up(&netapp_tmp_file_path, file_path, backup_dir, attempt) {
+                warn!(
+                    "Failed to back up the file, keeping the original ({}): {}",
+                    file_path.display(),
+                    e
+                );
+                return Err(e);
+            }
+        }
+
+        stage.enter("rename");
+        debug!(
+            "Atomic file rename: netapp({}) -> netapp ({})",
+            netapp_tmp_file_path.display(),
+            file_path.display()
+        );
+        self.retry_stale(file_path, || {
+            self.fs.rename(&netapp_tmp_file_path, file_path)
+        })?;
+        tmp_file.renamed();
+        // The original is already replaced, so a failure only leaves the rename possibly volatile
+        if self.options.fsync {
+            if let Err(e) = self.retry_stale(directory, || self.fs.sync_dir(directory)) {
+                warn!(
+                    "Failed to flush the directory after the rename ({}): {}",
+                    directory.display(),
+                    e
+                );
+            }
+        }
+
+        stage.enter("verify");
+        if let Some(failure) = self.verify_repaired_file(file_path, &staged) {
+            warn!(
+                "Repaired file failed verification ({}): {}",
+                file_path.display(),
+                failure
+            );
+            return Ok(FileOutcome::RepairedButUnverified(failure));
+        }
+
+        info!("Successfully unlocked: ({})", file_path.display());
+
+        Ok(FileOutcome::Repaired)
+    }
+
+    /// Reads the file at `file_path` into memory and writes it to `tmp_file_path` next to the
+    /// original, without a staging directory.
+    ///
+    /// # Returns
+    ///
+    /// Returns the measured copy, or `None` if the backend cannot write files, in which case the
+    /// copy is to be staged on disk.
+    fn copy_in_memory(
+        &self,
+        file_path: &Path,
+        tmp_file_path: &Path,
+        stage: &mut Stage,
+        attempt: &mut Attempt,
+    ) -> Result<Option<StagedCopy>, RepairError> {
+        stage.enter("copy_in_memory");
+        debug!(
+            "Copy through memory: netapp ({}) -> netapp ({})",
+            file_path.display(),
+            tmp_file_path.display()
+        );
+        let contents = self.retry_stale(file_path, || {
+            let mut contents = Vec::new();
+            self.fs.open(file_path)?.read_to_end(&mut contents)?;
+            Ok(contents)
+        })?;
+        if let Some(throttle) = &self.byte_throttle {
+            throttle.acquire(contents.len() as u64);
+        }
+        attempt.bytes_copied += stage.copied(contents.len() as u64);
+
+        let written = match self.retry_stale(tmp_file_path, || {
+            self.fs.write_like(tmp_file_path, &contents, file_path)
+        }) {
+            Ok(written) => written,
+            Err(RepairError::Io(e)) if e.kind() == io::ErrorKind::Unsupported => {
+                debug!(
+                    "Backend cannot write files, staging instead: ({})",
+                    file_path.display()
+                );
+                return Ok(None);
+            }
+            Err(e) => return Err(e),
+        };
+        if let Some(throttle) = &self.byte_throttle {
+            throttle.acquire(written);
+        }
+        attempt.bytes_copied += stage.copied(written);
+
+        Ok(Some(StagedCopy {
+            len: contents.len() as u64,
+            checksum: match self.audit_sink.is_some() || self.options.verify_checksum {
+                true => Some(hex_digest(&Sha256::digest(&contents))),
+                false => None,
+            },
+            acl: None,
+        }))
+    }
+
+    /// Copies the file at `file_path` to a local staging directory and puts the staged copy at
+    /// `tmp_file_path` next to the original, using the configured `CopyStrategy`.
+    ///
+    /// # Returns
+    ///
+    /// Returns the staged copy, measured before it was put in place.
+    fn copy_through_staging(
+        &self,
+        file_path: &Path,
+        tmp_file_name: &OsStr,
+        tmp_file_path: &Path,
+        mandatory: bool,
+        stage: &mut Stage,
+        attempt: &mut Attempt,
+    ) -> Result<StagedCopy, RepairError> {
+        stage.enter("copy_to_staging");
+        let dir = StagingDir::new(&self.fs)?;
+        let local_tmp_file_path = dir.path.join(tmp_file_name);
+
+        debug!(
+            "Copy from netapp: netapp ({}) -> local ({})",
+            file_path.display(),
+            local_tmp_file_path.display()
+        );
+
+        if mandatory {
+            debug!(
+                "Mandatory locking in effect, reading without blocking: ({})",
+                file_path.display()
+            );
+            let copied = self.retry_stale(file_path, || {
+                self.fs.copy_nonblocking(
+                    file_path,
+                    &local_tmp_file_path,
+                    self.options.mandatory_lock_timeout,
+                )
+            })?;
+            if let Some(throttle) = &self.byte_throttle {
+                throttle.acquire(copied);
+            }
+            attempt.bytes_copied += stage.copied(copied);
+        } else {
+            let copied =
+                self.retry_stale(file_path, || self.copy(file_path, &local_tmp_file_path))?;
+            attempt.bytes_copied += stage.copied(copied);
+        }
+
+        // The staged file may be moved away, so it is measured up front for the verification
+        let staged = StagedCopy {
+            len: self.fs.metadata(&local_tmp_file_path)?.len,
+            checksum: match self.audit_sink.is_some() || self.options.verify_checksum {
+                true => Some(self.checksum(&local_tmp_file_path)?),
+                false => None,
+            },
+            acl: None,
+        };
+
+        stage.enter("unlock");
+        debug!("Unlock file: ({})", local_tmp_file_path.display());
+        self.locks.unlock(&local_tmp_file_path)?;
+
+        stage.enter("copy_back");
+        let copied = self.place_staged(&local_tmp_file_path, tmp_file_path)?;
+        attempt.bytes_copied += stage.copied(copied);
+        Ok(staged)
+    }
+
+    /// Puts the staged file at `tmp_file_path`, next to the original, using the configured
+    /// `CopyStrategy`, and returns the number of bytes copied.
+    fn place_staged(
+        &self,
+        staged_file_path: &Path,
+        tmp_file_path: &Path,
+    ) -> Result<u64, RepairError> {
+        let moved = match self.options.copy_strategy {
+            CopyStrategy::SameDirectory | CopyStrategy::Auto { .. } => false,
+            CopyStrategy::Move => {
+                debug!(
+                    "Move to tmp path: local ({}) -> netapp ({})",
+                    staged_file_path.display(),
+                    tmp_file_path.display()
+                );
+                match self.fs.rename(staged_file_path, tmp_file_path) {
+                    Ok(()) => true,
+                    Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+                        debug!(
+                            "Staging directory is on another filesystem, copying instead: ({})",
+                            tmp_file_path.display()
+                        );
+                        false
+                    }
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        };
+
+        let copied = match moved {
+            true => 0,
+            false => {
+                debug!(
+                    "Copy to back tmp path: local ({}) -> netapp ({})",
+                    staged_file_path.display(),
+                    tmp_file_path.display()
+                );
+                self.retry_stale(tmp_file_path, || self.copy(staged_file_path, tmp_file_path))?
+            }
+        };
+        Ok(copied)
+    }
+
+    /// Runs an operation of a pipeline stage on `path`, retrying it when it fails with a stale NFS
+    /// file handle.
+    ///
+    /// Before every retry the path is re-resolved from scratch, so the client looks up the parent
+    /// directory and the file again instead of reusing the handles invalidated by a rename.
+    ///
+    /// # Errors
+    ///
+    /// Returns `RepairError::StaleHandle` if the handle is still stale after
+    /// `RepairOptions::stale_handle_retries` retries, and `RepairError::Io` for any other failure.
+    fn retry_stale<T>(
+        &self,
+        path: &Path,
+        mut operation: impl FnMut() -> io::Result<T>,
+    ) -> Result<T, RepairError> {
+        let mut retries = 0;
+        loop {
+            match operation() {
+                Err(e) if e.kind() == io::ErrorKind::StaleNetworkFileHandle => {
+                    if retries == self.options.stale_handle_retries {
+                        return Err(RepairError::StaleHandle(e));
+                    }
+                    retries += 1;
+                    warn!(
+                        "Stale file handle, re-resolving and retrying, retry {} of {}: ({})",
+                        retries,
+                        self.options.stale_handle_retries,
+                        path.display()
+                    );
+                    self.re_resolve(path);
+                }
+                result => return Ok(result?),
+            }
+        }
+    }
+
+    /// Looks up `path` again starting from the parent directory, refreshing the cached handles.
+    fn re_resolve(&self, path: &Path) {
+        if let Some(parent) = path.parent() {
+            let _ = self.fs.metadata(parent);
+        }
+        let _ = self.fs.metadata(path);
+    }
+
+    /// Copies `from` into `to`, throttled if a byte rate limit is configured.
+    fn copy(&self, from: &Path, to: &Path) -> io::Result<u64> {
+        match &self.byte_throttle {
+            Some(throttle) => self.fs.copy_throttled(from, to, throttle),
+            None => self.fs.copy(from, to),
+        }
+    }
+
+    /// Checks whether the holder of a lock is still alive; a holder that cannot be checked counts as gone.
+    fn is_holder_alive(&self, file_path: &Path, lock: &LockInfo) -> bool {
+        self.locks
+            .is_holder_alive(file_path, lock)
+            .unwrap_or_else(|e| {
+                warn!(
+                    "Failed to check whether the lock holder is alive ({}): {}",
+                    file_path.display(),
+                    e
+                );
+                false
+            })
+    }
+
+    /// Classifies the owner of the locks on a file with the configured `LockStatusQuery`.
+    ///
+    /// Returns the reason to skip the file unless the lock is stale, or `None` if the lock is stale or
+    /// no classification is configured. A classification that fails counts as unknown.
+    fn classify_holder(&self, file_path: &Path, stage: &Stage) -> Option<SkipReason> {
+        let lock_status = self.lock_status.as_ref()?;
+        let status = lock_status.holder_status(file_path).unwrap_or_else(|e| {
+            warn!(
+                "Failed to query the lock owner status ({}): {}",
+                file_path.display(),
+                e
+            );
+            HolderStatus::Unknown
+        });
+        stage.record("holder_status", status.name());
+
+        match status {
+            HolderStatus::Stale => None,
+            HolderStatus::Live => Some(SkipReason::LockHolderAlive),
+            HolderStatus::Unknown => Some(SkipReason::LockNotStale),
+        }
+    }
+
+    /// Tries to break the locks of a file with the configured `LockBreaker`.
+    ///
+    /// Returns `true` if the locks were broken and the file is no longer locked,
+    /// `false` if the copy-based repair has to be used instead.
+    fn break_locks_server_side(&self, file_path: &Path, stage: &mut Stage) -> bool {
+        let lock_breaker = match &self.lock_breaker {
+            Some(lock_breaker) => lock_breaker,
+            None => return false,
+        };
+        stage.enter("break");
+
+        debug!("Break locks on the server: ({})", file_path.display());
+        match lock_breaker.break_locks(file_path) {
+            Ok(0) => {
+                debug!(
+                    "No server-side locks found, falling back to copy: ({})",
+                    file_path.display()
+                );
+                false
+            }
+            Ok(_) => !self.locks.is_locked(file_path).unwrap_or(true),
+            Err(e) => {
+                warn!(
+                    "Server-side lock break unavailable, falling back to copy ({}): {}",
+                    file_path.display(),
+                    e
+                );
+                false
+            }
+        }
+    }
+
+    /// Asks the server to break the SMB leases other clients hold on a file.
+    ///
+    /// # Errors
+    ///
+    /// Returns `RepairError::LeaseBreak` if the lease could not be broken; the file is left untouched.
+    fn break_smb_lease(&self, file_path: &Path, stage: &mut Stage) -> Result<(), RepairError> {
+        stage.enter("break_lease");
+        debug!("Break SMB lease: ({})", file_path.display());
+        self.locks
+            .break_lease(file_path)
+            .map_err(RepairError::LeaseBreak)
+    }
+
+    /// Tries to release the locked byte ranges of a file one by one.
+    ///
+    /// Returns `true` if the file is no longer locked afterwards,
+    /// `false` if the copy-based repair has to be used instead.
+    fn release_locked_ranges(&self, file_path: &Path, stage: &mut Stage) -> bool {
+        stage.enter("release_ranges");
+        let released = (|| -> io::Result<bool> {
+            for range in self.locks.locked_ranges(file_path)? {
+                debug!(
+                    "Release locked range {}+{:?}: ({})",
+                    range.start,
+                    range.len,
+                    file_path.display()
+                );
+                self.locks.unlock_range(file_path, range.start, range.len)?;
+            }
+            Ok(!self.locks.is_locked(file_path)?)
+        })();
+
+        match released {
+            Ok(true) => true,
+            Ok(false) => {
+                debug!(
+                    "File is still locked after releasing ranges, falling back to copy: ({})",
+                    file_path.display()
+                );
+                false
+            }
+            Err(e) => {
+                warn!(
+                    "Failed to release locked ranges, falling back to copy ({}): {}",
+                    file_path.display(),
+                    e
+                );
+                false
+            }
+        }
+    }
+
+    /// Verifies the end state of a repaired file against the staged copy.
+    ///
+    /// The final file is probed for locks, then its size (and, if enabled, its checksum)
+    /// is compared with the staged copy.
+    ///
+    /// Returns `None` if the repaired file passed all checks.
+    fn verify_repaired_file(
+        &self,
+        file_path: &Path,
+        staged: &StagedCopy,
+    ) -> Option<VerificationFailure> {
+        debug!("Verify repaired file: ({})", file_path.display());
+
+        let checked = (|| -> Result<Option<VerificationFailure>, RepairError> {
+            if self.retry_stale(file_path, || self.locks.is_locked(file_path))? {
+                return Ok(Some(VerificationFailure::StillLocked));
+            }
+
+            let expected = staged.len;
+            let actual = self
+                .retry_stale(file_path, || self.fs.metadata(file_path))?
+                .len;
+            if expected != actual {
+                return Ok(Some(VerificationFailure::SizeMismatch { expected, actual }));
+            }
+
+            if let Some(checksum) = staged
+                .checksum
+                .as_ref()
+                .filter(|_| self.options.verify_checksum)
+            {
+                if *checksum != self.checksum(file_path)? {
+                    return Ok(Some(VerificationFailure::ChecksumMismatch));
+                }
+            }
+
+            if let Some(acl) = &staged.acl {
+                if self
+                    .retry_stale(file_path, || self.fs.read_acl(file_path))?
+                    .as_ref()
+                    != Some(acl)
+                {
+                    return Ok(Some(VerificationFailure::AclMismatch));
+                }
+            }
+
+            Ok(None)
+        })();
+
+        checked.unwrap_or_else(|e| {
+            error!(
+                "Failed to read back repaired file ({}): {}",
+                file_path.display(),
+                e
+            );
+            Some(VerificationFailure::Unreadable)
+        })
+    }
+
+    /// Reads the ACL of the original to carry it over to the repaired file, unless
+    /// `RepairOptions::preserve_acls` is off. Backends without ACL support report none.
+    fn read_acl(&self, file_path: &Path) -> Result<Option<Acl>, RepairError> {
+        if !self.options.preserve_acls {
+            return Ok(None);
+        }
+        match self.retry_stale(file_path, || self.fs.read_acl(file_path)) {
+            Err(RepairError::Io(e)) if e.kind() == io::ErrorKind::Unsupported => Ok(None),
+            acl => acl,
+        }
+    }
+
+    /// Computes the hex-encoded SHA-256 checksum of a file.
+    fn checksum(&self, path: &Path) -> io::Result<String> {
+        let mut hasher = Sha256::new();
+        io::copy(&mut self.fs.open(path)?, &mut hasher)?;
+        Ok(hex_digest(&hasher.finalize()))
+    }
+
+    /// Checks whether a target has to be skipped because it lives on a local filesystem.
+    ///
+    /// Targets whose filesystem cannot be classified are not skipped.
+    fn is_local_target(&self, path: &Path) -> bool {
+        if self.options.allow_local {
+            return false;
+        }
+
+        match self.fs.filesystem_kind(path) {
+            Ok(FilesystemKind::Local) => {
+                warn!(
+                    "Skipping target on a local filesystem, use --allow-local to override: ({})",
+                    path.display()
+                );
+                true
+            }
+            Ok(_) => false,
+            Err(e) => {
+                warn!(
+                    "Failed to detect the filesystem type ({}): {}",
+                    path.display(),
+                    e
+                );
+                false
+            }
+        }
+    }
+
+    /// Checks whether a sweep entry passes the size and age filters; only regular files are filtered.
+    fn is_selected(&self, metadata: Option<&FileMetadata>, now: SystemTime) -> bool {
+        match metadata {
+            Some(metadata) if metadata.kind == FileKind::File => {
+                self.options.matches_filters(metadata, now)
+            }
+            _ => true,
+        }
+    }
+
+    /// Repairs a file found by a directory sweep under `policy`, paced by the file rate limit.
+    fn sweep_path(&self, path: &Path, policy: &Policy) -> FileReport {
+        if let Some(throttle) = &self.file_throttle {
+            throttle.acquire(1);
+        }
+        self.repair_path(path, policy)
+    }
+}
+
+/// Records a visit of `path`, returning `false` if it was visited before; without a set every
+/// visit is the first.
+fn is_first_visit(seen: &mut Option<HashSet<PathBuf>>, path: &Path) -> bool {
+    seen.as_mut()
+        .is_none_or(|seen| seen.insert(path.to_path_buf()))
+}
+
+/// Formats a digest as lowercase hex.
+fn hex_digest(digest: &[u8]) -> String {
+    digest.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Checks whether an outcome is a failure that counts towards quarantining the file.
+fn is_quarantinable(outcome: &FileOutcome) -> bool {
+    matches!(outcome, FileOutcome::Failed(error) if is_quarantinable_error(error))
+}
+
+/// Only I/O failures count; a file that is being written to or whose hook failed is left in place.
+fn is_quarantinable_error(error: &RepairError) -> bool {
+    matches!(error, RepairError::Io(_))
+}
@@ -0,0 +1,464 @@
+//! # Repair Report Module
+//!
+//! This module contains the types describing the result of a repair run.
+//! Every processed path gets a `FileReport` entry, and the `RepairReport` aggregates them so callers
+//! (for example the CLI) can decide what happened without parsing the logs.
+
+use crate::backend::{LockInfo, LockKind, LockType};
+use crate::error::RepairError;
+use crate::format::{
+    lock_kind_name, lock_type_name, serialize_path, serialize_secs, write_csv_row, CSV_COLUMNS,
+};
+use bytesize::ByteSize;
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::fmt;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// Reason why a path was not processed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    /// The path does not point to a regular file.
+    NotAFile,
+    /// The file name cannot be used to build a temporary file name.
+    InvalidFileName,
+    /// The target lives on a local filesystem, and local filesystems are not allowed.
+    LocalFilesystem,
+    /// The process holding the lock is still alive, and forcing the repair is not allowed.
+    LockHolderAlive,
+    /// The server could not confirm that the owner of the lock is gone, and forcing the repair is
+    /// not allowed.
+    LockNotStale,
+    /// The directory lives on another filesystem than the swept directory, and the sweep is kept on
+    /// one filesystem.
+    OtherFilesystem,
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SkipReason::NotAFile => write!(f, "not a regular file"),
+            SkipReason::InvalidFileName => write!(f, "invalid file name"),
+            SkipReason::LocalFilesystem => write!(f, "local filesystem"),
+            SkipReason::LockHolderAlive => write!(f, "lock holder is alive"),
+            SkipReason::LockNotStale => write!(f, "lock is not known to be stale"),
+            SkipReason::OtherFilesystem => write!(f, "on another filesystem"),
+        }
+    }
+}
+
+/// Reason why the end state of a repaired file could not be verified.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VerificationFailure {
+    /// The repaired file is still reported as locked.
+    StillLocked,
+    /// The repaired file size differs from the staged copy.
+    SizeMismatch { expected: u64, actual: u64 },
+    /// The repaired file content differs from the staged copy.
+    ChecksumMismatch,
+    /// The repaired file could not be reopened or read.
+    Unreadable,
+    /// The ACL of the repaired file differs from the ACL of the original.
+    AclMismatch,
+}
+
+impl fmt::Display for VerificationFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            VerificationFailure::StillLocked => write!(f, "file is still locked"),
+            VerificationFailure::SizeMismatch { expected, actual } => {
+                write!(
+                    f,
+                    "size mismatch: expected {} bytes, got {}",
+                    expected, actual
+                )
+            }
+            VerificationFailure::ChecksumMismatch => write!(f, "checksum mismatch"),
+            VerificationFailure::Unreadable => write!(f, "file cannot be read back"),
+            VerificationFailure::AclMismatch => write!(f, "ACL mismatch"),
+        }
+    }
+}
+
+/// Outcome of processing a single path.
+#[derive(Debug)]
+pub enum FileOutcome {
+    /// The file was not locked, nothing had to be done.
+    NotLocked,
+    /// The file was locked and has been successfully replaced by an unlocked copy.
+    Repaired,
+    /// The file was replaced, but the verification of the end state failed.
+    RepairedButUnverified(VerificationFailure),
+    /// The path was not processed.
+    Skipped(SkipReason),
+    /// The repair process failed.
+    Failed(RepairError),
+    /// The repair failed repeatedly and the file was moved to the quarantine directory.
+    /// If the quarantine directory is on another filesystem, the file is copied there and the
+    /// original is removed afterwards.
+    Quarantined {
+        /// Path of the file in the quarantine directory.
+        destination: PathBuf,
+        /// Error of the last failed repair attempt.
+        error: RepairError,
+    },
+    /// Processing the file took longer than `RepairOptions::file_timeout` and was given up on.
+    TimedOut,
+}
+
+impl FileOutcome {
+    /// Short name of the outcome, as used in the statistics.
+    pub fn name(&self) -> &'static str {
+        match self {
+            FileOutcome::NotLocked => "not_locked",
+            FileOutcome::Repaired => "repaired",
+            FileOutcome::RepairedButUnverified(_) => "unverified",
+            FileOutcome::Skipped(_) => "skipped",
+            FileOutcome::Failed(_) => "failed",
+            FileOutcome::Quarantined { .. } => "quarantined",
+            FileOutcome::TimedOut => "timed_out",
+        }
+    }
+
+    /// Describes why the file was not repaired cleanly, or `None` if there is nothing to report.
+    pub fn detail(&self) -> Option<String> {
+        match self {
+            FileOutcome::NotLocked | FileOutcome::Repaired => None,
+            FileOutcome::RepairedButUnverified(failure) => Some(failure.to_string()),
+            FileOutcome::Skipped(reason) => Some(reason.to_string()),
+            FileOutcome::Failed(error) => Some(error.to_string()),
+            FileOutcome::Quarantined { error, .. } => Some(error.to_string()),
+            FileOutcome::TimedOut => Some("per-file timeout expired".to_string()),
+        }
+    }
+}
+
+/// Report entry for a single path.
+#[derive(Debug)]
+pub struct FileReport {
+    /// Path of the processed file.
+    pub path: PathBuf,
+    /// What happened to the file.
+    pub outcome: FileOutcome,
+    /// Size of the file in bytes before the repair, if it was read.
+    pub size: Option<u64>,
+    /// Whether the file was found locked.
+    pub locked: bool,
+    /// The lock found on the file, if known.
+    pub lock: Option<LockInfo>,
+    /// Number of bytes copied while repairing the file, over all copies.
+    pub bytes_copied: u64,
+    /// Time spent on the file.
+    pub duration: Duration,
+}
+
+/// Number of files per outcome of a repair run.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReportSummary {
+    /// Number of processed paths.
+    pub total: usize,
+    /// Number of files that were successfully repaired.
+    pub repaired: usize,
+    /// Number of files that were replaced but failed verification.
+    pub unverified: usize,
+    /// Number of files that were not locked.
+    pub not_locked: usize,
+    /// Number of paths that were skipped.
+    pub skipped: usize,
+    /// Number of files that were moved to the quarantine directory.
+    pub quarantined: usize,
+    /// Number of files whose repair failed.
+    pub failed: usize,
+    /// Number of files that were given up on after the per-file timeout.
+    pub timed_out: usize,
+}
+
+/// Row of the per-file CSV and JSON reports.
+#[derive(Serialize)]
+struct FileRow {
+    #[serde(serialize_with = "serialize_path")]
+    path: PathBuf,
+    size: Option<u64>,
+    lock_kind: Option<LockKind>,
+    lock_type: Option<LockType>,
+    pid: Option<u32>,
+    action: &'static str,
+    #[serde(rename = "duration_secs", serialize_with = "serialize_secs")]
+    duration: Duration,
+    error: Option<String>,
+}
+
+impl From<&FileReport> for FileRow {
+    fn from(file: &FileReport) -> Self {
+        FileRow {
+            path: file.path.clone(),
+            size: file.size,
+            lock_kind: file.lock.as_ref().map(|lock| lock.kind),
+            lock_type: file.lock.as_ref().map(|lock| lock.lock_type),
+            pid: file.lock.as_ref().and_then(|lock| lock.pid),
+            action: file.outcome.name(),
+            duration: file.duration,
+            error: file.outcome.detail(),
+        }
+    }
+}
+
+/// Per-file JSON report.
+#[derive(Serialize)]
+struct JsonReport {
+    summary: ReportSummary,
+    #[serde(rename = "elapsed_secs", serialize_with = "serialize_secs")]
+    elapsed: Duration,
+    files: Vec<FileRow>,
+}
+
+/// A file listed among the slowest files of a run.
+#[derive(Debug, Clone, Serialize)]
+pub struct SlowFile {
+    /// Path of the file.
+    #[serde(serialize_with = "serialize_path")]
+    pub path: PathBuf,
+    /// Name of the outcome, see `FileOutcome::name`.
+    pub outcome: &'static str,
+    /// Time spent on the file.
+    #[serde(rename = "duration_secs", serialize_with = "serialize_secs")]
+    pub duration: Duration,
+}
+
+/// Statistics of a repair run, printed at the end of the run.
+#[derive(Debug, Clone, Serialize)]
+pub struct RunStatistics {
+    /// Number of files per outcome; `total` is the number of files scanned.
+    #[serde(flatten)]
+    pub summary: ReportSummary,
+    /// Number of files that were found locked.
+    pub locked: usize,
+    /// Number of bytes copied by all repairs.
+    pub bytes_copied: u64,
+    /// Wall-clock time of the run.
+    #[serde(rename = "elapsed_secs", serialize_with = "serialize_secs")]
+    pub elapsed: Duration,
+    /// The files that took longest, slowest first.
+    pub slowest: Vec<SlowFile>,
+}
+
+impl RunStatistics {
+    /// Writes the statistics as a human-readable table.
+    pub fn write_text<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let summary = &self.summary;
+        let rows = [
+            ("Files scanned", summary.total.to_string()),
+            ("Locked", self.locked.to_string()),
+            ("Repaired", summary.repaired.to_string()),
+            ("Unverified", summary.unverified.to_string()),
+            ("Not locked", summary.not_locked.to_string()),
+            ("Skipped", summary.skipped.to_string()),
+            ("Quarantined", summary.quarantined.to_string()),
+            ("Failed", summary.failed.to_string()),
+            ("Timed out", summary.timed_out.to_string()),
+            (
+                "Bytes copied",
+                format!(
+                    "{} ({} bytes)",
+                    ByteSize(self.bytes_copied),
+                    self.bytes_copied
+                ),
+            ),
+            ("Wall-clock time", format_duration(self.elapsed)),
+        ];
+        for (name, value) in rows {
+            writeln!(writer, "{:<16} {}", name, value)?;
+        }
+
+        if !self.slowest.is_empty() {
+            writeln!(writer, "Slowest files:")?;
+            for file in &self.slowest {
+                writeln!(
+                    writer,
+                    "  {:>10} {:<11} {}",
+                    format_duration(file.duration),
+                    file.outcome,
+                    file.path.display()
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the statistics as a JSON document.
+    pub fn write_json<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        serde_json::to_writer_pretty(&mut *writer, self)?;
+        writeln!(writer)
+    }
+}
+
+/// Formats a duration in seconds with millisecond precision, e.g. `1.250s`.
+fn format_duration(duration: Duration) -> String {
+    format!("{:.3}s", duration.as_secs_f64())
+}
+
+/// Aggregated result of a repair run.
+#[derive(Debug, Default)]
+pub struct RepairReport {
+    /// Entries in the order the paths were processed.
+    pub files: Vec<FileReport>,
+    /// Wall-clock time of the run.
+    pub elapsed: Duration,
+}
+
+impl RepairReport {
+    /// Creates an empty report.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the outcome for a path that was not worked on, such as a skipped path.
+    pub fn push(&mut self, path: PathBuf, outcome: FileOutcome) {
+        self.files.push(FileReport {
+            path,
+            outcome,
+            size: None,
+            locked: false,
+            lock: None,
+            bytes_copied: 0,
+            duration: Duration::ZERO,
+        });
+    }
+
+    /// Appends all entries of another report to this one, adding up the wall-clock times.
+    pub fn merge(&mut self, other: RepairReport) {
+        self.files.extend(other.files);
+        self.elapsed += other.elapsed;
+    }
+
+    /// Number of files that were not locked.
+    pub fn not_locked(&self) -> usize {
+        self.count(|o| matches!(o, FileOutcome::NotLocked))
+    }
+
+    /// Number of files that were successfully repaired.
+    pub fn repaired(&self) -> usize {
+        self.count(|o| matches!(o, FileOutcome::Repaired))
+    }
+
+    /// Number of files that were replaced but failed verification.
+    pub fn unverified(&self) -> usize {
+        self.count(|o| matches!(o, FileOutcome::RepairedButUnverified(_)))
+    }
+
+    /// Number of paths that were skipped.
+    pub fn skipped(&self) -> usize {
+        self.count(|o| matches!(o, FileOutcome::Skipped(_)))
+    }
+
+    /// Number of files whose repair failed.
+    pub fn failed(&self) -> usize {
+        self.count(|o| matches!(o, FileOutcome::Failed(_)))
+    }
+
+    /// Number of files that were moved to the quarantine directory.
+    pub fn quarantined(&self) -> usize {
+        self.count(|o| matches!(o, FileOutcome::Quarantined { .. }))
+    }
+
+    /// Number of files that were given up on after the per-file timeout.
+    pub fn timed_out(&self) -> usize {
+        self.count(|o| matches!(o, FileOutcome::TimedOut))
+    }
+
+    /// Returns the number of files per outcome.
+    pub fn summary(&self) -> ReportSummary {
+        ReportSummary {
+            total: self.files.len(),
+            repaired: self.repaired(),
+            unverified: self.unverified(),
+            not_locked: self.not_locked(),
+            skipped: self.skipped(),
+            quarantined: self.quarantined(),
+            failed: self.failed(),
+            timed_out: self.timed_out(),
+        }
+    }
+
+    /// Writes the report as a human-readable list, one line per file.
+    pub fn write_text<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        for file in &self.files {
+            write!(
+                writer,
+                "{:<11} {:>9} {}",
+                file.outcome.name(),
+                format_duration(file.duration),
+                file.path.display()
+            )?;
+            match file.outcome.detail() {
+                Some(detail) => writeln!(writer, ": {}", detail)?,
+                None => writeln!(writer)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the report as a JSON document with the summary and one entry per file.
+    pub fn write_json<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let report = JsonReport {
+            summary: self.summary(),
+            elapsed: self.elapsed,
+            files: self.files.iter().map(FileRow::from).collect(),
+        };
+        serde_json::to_writer_pretty(&mut *writer, &report)?;
+        writeln!(writer)
+    }
+
+    /// Writes the report as CSV, one row per file with the outcome as the action.
+    ///
+    /// The columns are the same as in `ScanReport::write_csv`.
+    pub fn write_csv<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write_csv_row(writer, &CSV_COLUMNS)?;
+        for file in &self.files {
+            let row = FileRow::from(file);
+            write_csv_row(
+                writer,
+                &[
+                    &row.path.to_string_lossy(),
+                    &row.size.map(|s| s.to_string()).unwrap_or_default(),
+                    row.lock_type.map(lock_type_name).unwrap_or_default(),
+                    &row.pid.map(|p| p.to_string()).unwrap_or_default(),
+                    row.action,
+                    &format!("{:.3}", row.duration.as_secs_f64()),
+                    &row.error.unwrap_or_default(),
+                    row.lock_kind.map(lock_kind_name).unwrap_or_default(),
+                ],
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Returns the statistics of the run, listing the `slowest` files that took longest.
+    pub fn statistics(&self, slowest: usize) -> RunStatistics {
+        let mut by_duration: Vec<&FileReport> = self.files.iter().collect();
+        by_duration.sort_by_key(|f| Reverse(f.duration));
+
+        RunStatistics {
+            summary: self.summary(),
+            locked: self.files.iter().filter(|f| f.locked).count(),
+            bytes_copied: self.files.iter().map(|f| f.bytes_copied).sum(),
+            elapsed: self.elapsed,
+            slowest: by_duration
+                .into_iter()
+                .filter(|f| !f.duration.is_zero())
+                .take(slowest)
+                .map(|f| SlowFile {
+                    path: f.path.clone(),
+                    outcome: f.outcome.name(),
+                    duration: f.duration,
+                })
+                .collect(),
+        }
+    }
+
+    fn count(&self, predicate: impl Fn(&FileOutcome) -> bool) -> usize {
+        self.files.iter().filter(|f| predicate(&f.outcome)).count()
+    }
+}
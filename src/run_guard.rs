@@ -0,0 +1,98 @@
+//! # Run Guard Module
+//!
+//! This module contains the `RunGuard`, an advisory lock that keeps two runs from sweeping the same
+//! target at the same time. Overlapping runs, for example from a cron job whose previous invocation
+//! has not finished, would otherwise race on the same files and leave duplicate temporary copies.
+//!
+//! The lock file lives outside the target, so the sweep never finds it: by default in the temporary
+//! directory of the host, named after the target. The lock only protects against runs on the same host.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::path::Path;
+//! use netfs_unlker::run_guard::RunGuard;
+//!
+//! let target = Path::new("/mnt/share");
+//! let _guard = RunGuard::acquire(&RunGuard::default_path(target), false).unwrap();
+//! // Repair the target; the lock is released when the guard is dropped.
+//! ```
+
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::io::{self, Error, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// Advisory run lock on a target, released when dropped.
+///
+/// The lock file itself is left in place, as removing it would race with a run about to lock it.
+#[derive(Debug)]
+pub struct RunGuard {
+    file: File,
+    path: PathBuf,
+}
+
+impl RunGuard {
+    /// Returns the default lock file path for `target`, in the temporary directory of the host.
+    ///
+    /// The name is derived from the canonical path of the target, so different spellings of the same
+    /// target share one lock.
+    pub fn default_path(target: &Path) -> PathBuf {
+        let target = fs::canonicalize(target).unwrap_or_else(|_| target.to_path_buf());
+        let digest = Sha256::digest(target.as_os_str().as_encoded_bytes());
+        let name: String = digest[..8].iter().map(|b| format!("{:02x}", b)).collect();
+        env::temp_dir().join(format!("netfs-unlker-{}.lock", name))
+    }
+
+    /// Takes the run lock at `path`, creating the lock file if needed.
+    ///
+    /// With `wait`, blocks until a run holding the lock releases it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` of kind `WouldBlock` if another run holds the lock and `wait` is not set,
+    /// or any other `Err` if the lock file cannot be opened or locked.
+    pub fn acquire(path: &Path, wait: bool) -> io::Result<Self> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+
+        match wait {
+            true => file.lock()?,
+            false => match file.try_lock() {
+                Ok(()) => {}
+                Err(TryLockError::WouldBlock) => {
+                    return Err(Error::new(
+                        io::ErrorKind::WouldBlock,
+                        "another run holds the run lock",
+                    ))
+                }
+                Err(TryLockError::Error(e)) => return Err(e),
+            },
+        }
+
+        // The PID only helps finding the other run; the lock is what counts
+        file.set_len(0)?;
+        writeln!(file, "{}", process::id())?;
+        Ok(RunGuard {
+            file,
+            path: path.to_path_buf(),
+        })
+    }
+
+    /// Returns the path of the lock file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for RunGuard {
+    fn drop(&mut self) {
+        let _ = self.file.unlock();
+    }
+}
@@ -0,0 +1,427 @@
+//! # Scan Module
+//!
+//! This module contains the lock inventory: it walks files and records every lock found,
+//! with its type, byte range and holder PID where known, without modifying anything.
+//! It is independent of the repair path and is used to produce reports before and after maintenance windows.
+
+use crate::backend::{FileKind, FileOps, LockInfo, LockOps, LockingMode};
+use crate::format::{lock_kind_name, lock_type_name, serialize_path, write_csv_row, CSV_COLUMNS};
+use crate::walk::{Walk, WalkError, WalkOrder};
+use serde::Serialize;
+use std::io::{self, Write};
+use std::num::NonZeroUsize;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use tracing::{debug, warn};
+
+/// A locked file found by the scan.
+#[derive(Debug, Clone, Serialize)]
+pub struct LockedFile {
+    /// Path of the locked file.
+    #[serde(serialize_with = "serialize_path")]
+    pub path: PathBuf,
+    /// Size of the file in bytes.
+    pub size: u64,
+    /// The lock held on the file.
+    pub lock: LockInfo,
+    /// Whether the lock is advisory or enforced by the kernel.
+    pub mode: LockingMode,
+}
+
+/// A path that could not be scanned.
+#[derive(Debug, Clone, Serialize)]
+pub struct ScanError {
+    /// Path that could not be scanned.
+    #[serde(serialize_with = "serialize_path")]
+    pub path: PathBuf,
+    /// Description of the error.
+    pub error: String,
+}
+
+/// A directory the scan did not descend into.
+#[derive(Debug, Clone, Serialize)]
+pub struct SkippedPath {
+    /// Path of the directory.
+    #[serde(serialize_with = "serialize_path")]
+    pub path: PathBuf,
+    /// Why it was skipped.
+    pub reason: String,
+}
+
+/// Inventory of the locks found under a set of paths.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ScanReport {
+    /// Number of regular files that were probed.
+    pub scanned: usize,
+    /// Files that are locked.
+    pub locked: Vec<LockedFile>,
+    /// Paths that could not be probed.
+    pub errors: Vec<ScanError>,
+    /// Directories on another filesystem that a scan kept on one filesystem did not descend into.
+    pub skipped: Vec<SkippedPath>,
+}
+
+impl ScanReport {
+    /// Appends the results of another scan, e.g. of the next target of a run.
+    pub fn merge(&mut self, other: ScanReport) {
+        self.scanned += other.scanned;
+        self.locked.extend(other.locked);
+        self.errors.extend(other.errors);
+        self.skipped.extend(other.skipped);
+    }
+
+    /// Writes the report as a human-readable table.
+    pub fn write_text<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        for file in &self.locked {
+            writeln!(
+                writer,
+                "{:<9} {:<9} {:<9} {:<24} {:>8} {} ({} bytes)",
+                lock_kind_name(file.lock.kind),
+                lock_type_name(file.lock.lock_type),
+                locking_mode_name(file.mode),
+                lock_range(&file.lock),
+                file.lock
+                    .pid
+                    .map_or_else(|| "-".to_string(), |p| p.to_string()),
+                file.path.display(),
+                file.size
+            )?;
+        }
+        for error in &self.errors {
+            writeln!(
+                writer,
+                "error     {}: {}",
+                error.path.display(),
+                error.error
+            )?;
+        }
+        for skipped in &self.skipped {
+            writeln!(
+                writer,
+                "skipped   {}: {}",
+                skipped.path.display(),
+                skipped.reason
+            )?;
+        }
+        write!(
+            writer,
+            "{} files scanned, {} locked, {} errors",
+            self.scanned,
+            self.locked.len(),
+            self.errors.len()
+        )?;
+        match self.skipped.len() {
+            0 => writeln!(writer),
+            skipped => writeln!(writer, ", {} skipped", skipped),
+        }
+    }
+
+    /// Writes the report as a JSON document.
+    pub fn write_json<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        serde_json::to_writer_pretty(&mut *writer, self)?;
+        writeln!(writer)
+    }
+
+    /// Writes the report as CSV, one row per locked file (action `locked`), per path that could not
+    /// be scanned (action `error`) and per directory that was not descended into (action `skipped`).
+    ///
+    /// The columns are the same as in `RepairReport::write_csv`.
+    pub fn write_csv<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        write_csv_row(writer, &CSV_COLUMNS)?;
+        for file in &self.locked {
+            write_csv_row(
+                writer,
+                &[
+                    &file.path.to_string_lossy(),
+                    &file.size.to_string(),
+                    lock_type_name(file.lock.lock_type),
+                    &file.lock.pid.map(|p| p.to_string()).unwrap_or_default(),
+                    "locked",
+                    "",
+                    "",
+                    lock_kind_name(file.lock.kind),
+                ],
+            )?;
+        }
+        for error in &self.errors {
+            write_csv_row(
+                writer,
+                &[
+                    &error.path.to_string_lossy(),
+                    "",
+                    "",
+                    "",
+                    "error",
+                    "",
+                    &error.error,
+                    "",
+                ],
+            )?;
+        }
+        for skipped in &self.skipped {
+            write_csv_row(
+                writer,
+                &[
+                    &skipped.path.to_string_lossy(),
+                    "",
+                    "",
+                    "",
+                    "skipped",
+                    "",
+                    &skipped.reason,
+                    "",
+                ],
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Lock inventory scanner.
+///
+/// # Examples
+///
+/// ```
+/// use std::path::Path;
+/// use netfs_unlker::backend::{NativeFs, NativeLocks};
+/// use netfs_unlker::scan::Scanner;
+///
+/// let scanner = Scanner::new(NativeFs::default(), NativeLocks::default());
+/// let report = scanner.scan_directory(Path::new("/path/to/directory"), true);
+/// ```
+#[derive(Debug)]
+pub struct Scanner<F: FileOps, L: LockOps> {
+    fs: F,
+    locks: L,
+    walk_order: WalkOrder,
+    one_file_system: bool,
+    smb_leases: bool,
+}
+
+impl<F: FileOps, L: LockOps> Scanner<F, L> {
+    /// Creates a new scanner on top of the given backends.
+    pub fn new(fs: F, locks: L) -> Self {
+        Scanner {
+            fs,
+            locks,
+            walk_order: WalkOrder::default(),
+            one_file_system: false,
+            smb_leases: false,
+        }
+    }
+
+    /// Sets the order in which a directory scan walks the tree.
+    pub fn with_walk_order(mut self, walk_order: WalkOrder) -> Self {
+        self.walk_order = walk_order;
+        self
+    }
+
+    /// Keeps a recursive directory scan on the filesystem of the scanned directory, recording the
+    /// subdirectories on other filesystems in `ScanReport::skipped`.
+    pub fn with_one_file_system(mut self, one_file_system: bool) -> Self {
+        self.one_file_system = one_file_system;
+        self
+    }
+
+    /// Also reports files without a record lock whose SMB lease probe (`LockOps::is_leased`) is
+    /// refused, as locked by an SMB lease. Off by default, as mounts without read-caching oplocks
+    /// refuse the probe for every file.
+    pub fn with_smb_leases(mut self, smb_leases: bool) -> Self {
+        self.smb_leases = smb_leases;
+        self
+    }
+
+    /// Scans all files in the specified directory.
+    ///
+    /// Subdirectories that cannot be read are recorded in `ScanReport::errors`, and subdirectories on
+    /// another filesystem of a scan kept on one filesystem in `ScanReport::skipped`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the specified directory path does not exist or cannot be read.
+    pub fn scan_directory(&self, directory_path: &Path, recursive: bool) -> io::Result<ScanReport> {
+        let mut report = ScanReport::default();
+        let walk = Walk::new(&self.fs, directory_path, recursive, self.walk_order)?
+            .one_file_system(directory_path, self.one_file_system);
+        for entry in walk {
+            match entry {
+                Ok(path) => self.scan_path(path, &mut report),
+                Err(e) => Self::record_walk_error(&mut report, e),
+            }
+        }
+        Ok(report)
+    }
+
+    /// Scans a single file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the file does not exist.
+    pub fn scan_file(&self, file_path: &Path) -> io::Result<ScanReport> {
+        self.fs.metadata(file_path)?;
+        let mut report = ScanReport::default();
+        self.scan_path(file_path.to_path_buf(), &mut report);
+        Ok(report)
+    }
+
+    fn scan_path(&self, path: PathBuf, report: &mut ScanReport) {
+        let size = match self.fs.metadata(&path) {
+            Ok(m) if m.kind == FileKind::File => m.len,
+            Ok(_) => return,
+            Err(e) => return Self::record_error(report, path, e),
+        };
+
+        report.scanned += 1;
+        let lock = self.locks.lock_info(&path);
+        match lock.map(|lock| lock.or_else(|| self.lease_of(&path))) {
+            Ok(Some(lock)) => {
+                debug!("Found locked file: ({})", path.display());
+                let mode = self
+                    .locks
+                    .locking_mode(&path)
+                    .unwrap_or(LockingMode::Advisory);
+                report.locked.push(LockedFile {
+                    path,
+                    size,
+                    lock,
+                    mode,
+                });
+            }
+            Ok(None) => {}
+            Err(e) => Self::record_error(report, path, e),
+        }
+    }
+
+    /// Returns the SMB lease found on a file without a record lock, if leases are looked for.
+    fn lease_of(&self, path: &Path) -> Option<LockInfo> {
+        match self.smb_leases && self.locks.is_leased(path).unwrap_or(false) {
+            true => Some(LockInfo::smb_lease()),
+            false => None,
+        }
+    }
+
+    fn record_walk_error(report: &mut ScanReport, e: WalkError) {
+        if e.error.kind() == io::ErrorKind::CrossesDevices {
+            debug!(
+                "Not crossing into another filesystem: ({})",
+                e.path.display()
+            );
+            report.skipped.push(SkippedPath {
+                path: e.path,
+                reason: e.error.to_string(),
+            });
+        } else {
+            Self::record_error(report, e.path, e.error);
+        }
+    }
+
+    fn record_error(report: &mut ScanReport, path: PathBuf, e: io::Error) {
+        warn!("Failed to scan ({}): {}", path.display(), e);
+        report.errors.push(ScanError {
+            path,
+            error: e.to_string(),
+        });
+    }
+}
+
+impl<F: FileOps + Sync, L: LockOps + Sync> Scanner<F, L> {
+    /// Scans all files in the specified directory like `scan_directory`, probing up to `jobs` files at
+    /// a time while the tree is walked. Each file is probed with `LockOps::probe`, a single open on the
+    /// native backends, which makes inventories of high-latency mounts several times faster.
+    ///
+    /// Locked files and errors are reported in path order rather than in walk order.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the specified directory path does not exist or cannot be read.
+    pub fn scan_directory_fast(
+        &self,
+        directory_path: &Path,
+        recursive: bool,
+        jobs: NonZeroUsize,
+    ) -> io::Result<ScanReport> {
+        let walk = Walk::new(&self.fs, directory_path, recursive, self.walk_order)?
+            .one_file_system(directory_path, self.one_file_system);
+        let (sender, receiver) = mpsc::sync_channel::<PathBuf>(jobs.get() * 4);
+        let receiver = Mutex::new(receiver);
+
+        let mut report = thread::scope(|scope| {
+            let workers: Vec<_> = (0..jobs.get())
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut report = ScanReport::default();
+                        let next = || receiver.lock().unwrap_or_else(|e| e.into_inner()).recv();
+                        while let Ok(path) = next() {
+                            self.probe_path(path, &mut report);
+                        }
+                        report
+                    })
+                })
+                .collect();
+
+            let mut report = ScanReport::default();
+            for entry in walk {
+                match entry {
+                    Ok(path) => {
+                        if sender.send(path).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => Self::record_walk_error(&mut report, e),
+                }
+            }
+            drop(sender);
+            for worker in workers {
+                report.merge(
+                    worker
+                        .join()
+                        .unwrap_or_else(|e| std::panic::resume_unwind(e)),
+                );
+            }
+            report
+        });
+
+        report.locked.sort_by(|a, b| a.path.cmp(&b.path));
+        report.errors.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(report)
+    }
+
+    fn probe_path(&self, path: PathBuf, report: &mut ScanReport) {
+        let probe = match self.locks.probe(&path) {
+            Ok(probe) => probe,
+            Err(e) if e.kind() == io::ErrorKind::Unsupported => {
+                return self.scan_path(path, report)
+            }
+            Err(e) => return Self::record_error(report, path, e),
+        };
+        if probe.kind != FileKind::File {
+            return;
+        }
+
+        report.scanned += 1;
+        if let Some(lock) = probe.lock.or_else(|| self.lease_of(&path)) {
+            debug!("Found locked file: ({})", path.display());
+            report.locked.push(LockedFile {
+                path,
+                size: probe.len,
+                lock,
+                mode: probe.mode,
+            });
+        }
+    }
+}
+
+fn locking_mode_name(mode: LockingMode) -> &'static str {
+    match mode {
+        LockingMode::Advisory => "advisory",
+        LockingMode::Mandatory => "mandatory",
+    }
+}
+
+fn lock_range(lock: &LockInfo) -> String {
+    match lock.len {
+        Some(len) => format!("{}..{}", lock.start, lock.start + len),
+        None => format!("{}..EOF", lock.start),
+    }
+}
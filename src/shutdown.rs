@@ -0,0 +1,80 @@
+//! # Shutdown Module
+//!
+//! This module tracks shutdown requests (`SIGTERM` and `SIGINT`) of the long-running `watch` and `daemon`
+//! subcommands, so the current sweep can finish before the process exits, and the reload requests
+//! (`SIGHUP`) of `daemon`.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Longest time `wait` sleeps before checking for a shutdown request.
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+static RELOAD: AtomicBool = AtomicBool::new(false);
+
+/// Installs the signal handlers recording shutdown requests.
+///
+/// On Windows, Ctrl-C keeps terminating the process immediately.
+pub fn install() {
+    #[cfg(unix)]
+    unsafe {
+        let handler = handle as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        libc::signal(libc::SIGTERM, handler);
+        libc::signal(libc::SIGINT, handler);
+    }
+}
+
+/// Installs the signal handler recording reload requests, which replaces the default action of
+/// `SIGHUP`, terminating the process.
+#[cfg(unix)]
+pub fn install_reload() {
+    unsafe {
+        let handler = handle_reload as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        libc::signal(libc::SIGHUP, handler);
+    }
+}
+
+#[cfg(unix)]
+extern "C" fn handle(_signal: libc::c_int) {
+    REQUESTED.store(true, Ordering::SeqCst);
+}
+
+#[cfg(unix)]
+extern "C" fn handle_reload(_signal: libc::c_int) {
+    RELOAD.store(true, Ordering::SeqCst);
+}
+
+/// Checks whether a shutdown was requested.
+pub fn requested() -> bool {
+    REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Checks whether a reload was requested since the last call, and clears the request.
+#[cfg(unix)]
+pub fn take_reload() -> bool {
+    RELOAD.swap(false, Ordering::SeqCst)
+}
+
+/// Sleeps for `duration` or until a shutdown or reload is requested.
+///
+/// # Returns
+///
+/// Returns `false` if the wait was cut short by a shutdown request.
+pub fn wait(duration: Duration) -> bool {
+    wait_with(duration, thread::sleep)
+}
+
+/// Like `wait`, but blocks in `idle`, which is called repeatedly with the time to block for.
+pub fn wait_with(duration: Duration, mut idle: impl FnMut(Duration)) -> bool {
+    let deadline = Instant::now() + duration;
+    while !requested() && !RELOAD.load(Ordering::SeqCst) {
+        let now = Instant::now();
+        if now >= deadline {
+            return true;
+        }
+        idle((deadline - now).min(POLL_INTERVAL));
+    }
+    !requested()
+}
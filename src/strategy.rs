@@ -0,0 +1,41 @@
+//! # Copy Strategy Module
+//!
+//! This module contains the strategies for putting the staged copy of a locked file in place of the
+//! original. Every strategy ends with the same step: the final temporary file lives in the directory
+//! of the original, is flushed to stable storage together with that directory (unless
+//! `RepairOptions::fsync` is off), and is then renamed over the original. The rename never crosses
+//! filesystems, so it cannot fail with `EXDEV` and stays atomic; a crash leaves either the original
+//! or the complete copy behind.
+//!
+//! `Auto` skips the staging directory for small files: they are read into memory and written
+//! straight to the temporary file next to the original.
+
+/// Default size up to which `CopyStrategy::Auto` repairs a file in memory.
+pub const DEFAULT_IN_MEMORY_THRESHOLD: u64 = 1024 * 1024;
+
+/// How the staged copy of a file is moved next to the original before the final rename.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CopyStrategy {
+    /// Copy the staged file to the temporary file next to the original. The staged file stays in the
+    /// staging directory.
+    #[default]
+    SameDirectory,
+    /// Rename the staged file to the temporary file next to the original, which saves the copy when
+    /// the staging directory lives on the same filesystem. When it does not (`EXDEV`), the staged file
+    /// is copied like `SameDirectory` does.
+    Move,
+    /// Read files of up to `in_memory_threshold` bytes into memory and write them to the temporary
+    /// file next to the original, without a staging directory; copy larger files like
+    /// `SameDirectory` does. Files held by a mandatory lock are always staged, as reading them may block.
+    Auto {
+        /// Largest file size, in bytes, repaired in memory.
+        in_memory_threshold: u64,
+    },
+}
+
+impl CopyStrategy {
+    /// Checks whether a file of `len` bytes is repaired in memory.
+    pub fn is_in_memory(&self, len: u64) -> bool {
+        matches!(self, CopyStrategy::Auto { in_memory_threshold } if len <= *in_memory_threshold)
+    }
+}
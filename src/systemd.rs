@@ -0,0 +1,168 @@
+//! # Systemd Module
+//!
+//! This module implements the `sd_notify(3)` protocol used by services with `Type=notify`: the service
+//! reports its readiness, shutdown and status, and sends keep-alive pings when the unit has `WatchdogSec=` set.
+//! It is available with the `systemd` cargo feature.
+
+use std::env;
+use std::io;
+use std::os::unix::net::UnixDatagram;
+use std::process;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use tracing::warn;
+
+/// Environment variable holding the path of the notification socket.
+const NOTIFY_SOCKET_ENV: &str = "NOTIFY_SOCKET";
+/// Environment variable holding the watchdog timeout in microseconds.
+const WATCHDOG_USEC_ENV: &str = "WATCHDOG_USEC";
+/// Environment variable holding the PID the watchdog applies to.
+const WATCHDOG_PID_ENV: &str = "WATCHDOG_PID";
+
+/// Connection to the service manager's notification socket.
+pub struct Notifier {
+    socket: UnixDatagram,
+    address: String,
+    watchdog_timeout: Option<Duration>,
+}
+
+impl Notifier {
+    /// Connects to the notification socket passed by systemd.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(None)` if the process was not started by systemd with `Type=notify`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the socket cannot be created.
+    pub fn from_env() -> io::Result<Option<Self>> {
+        let address = match env::var(NOTIFY_SOCKET_ENV) {
+            Ok(address) if !address.is_empty() => address,
+            _ => return Ok(None),
+        };
+
+        let watchdog_pid = env::var(WATCHDOG_PID_ENV)
+            .ok()
+            .and_then(|pid| pid.parse::<u32>().ok());
+        let watchdog_timeout = env::var(WATCHDOG_USEC_ENV)
+            .ok()
+            .and_then(|usec| usec.parse::<u64>().ok())
+            .filter(|&usec| usec > 0)
+            .filter(|_| watchdog_pid.is_none_or(|pid| pid == process::id()))
+            .map(Duration::from_micros);
+
+        Ok(Some(Notifier {
+            socket: UnixDatagram::unbound()?,
+            address,
+            watchdog_timeout,
+        }))
+    }
+
+    /// Interval of the watchdog pings, half of the unit's `WatchdogSec=`, or `None` if the watchdog is disabled.
+    pub fn watchdog_interval(&self) -> Option<Duration> {
+        self.watchdog_timeout.map(|timeout| timeout / 2)
+    }
+
+    /// Starts a thread pinging the watchdog every `watchdog_interval` until the returned `Watchdog` is dropped.
+    ///
+    /// # Returns
+    ///
+    /// Returns `Ok(None)` if the watchdog is disabled.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if the socket cannot be duplicated for the thread.
+    pub fn start_watchdog(&self) -> io::Result<Option<Watchdog>> {
+        let interval = match self.watchdog_interval() {
+            Some(interval) => interval,
+            None => return Ok(None),
+        };
+
+        let notifier = Notifier {
+            socket: self.socket.try_clone()?,
+            address: self.address.clone(),
+            watchdog_timeout: self.watchdog_timeout,
+        };
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                if let Err(e) = notifier.watchdog() {
+                    warn!("Failed to ping the systemd watchdog: {}", e);
+                }
+            }
+        });
+
+        Ok(Some(Watchdog {
+            stop: Some(stop),
+            thread: Some(thread),
+        }))
+    }
+
+    /// Reports that the service finished starting up.
+    pub fn ready(&self) -> io::Result<()> {
+        self.send("READY=1")
+    }
+
+    /// Reports that the service is shutting down.
+    pub fn stopping(&self) -> io::Result<()> {
+        self.send("STOPPING=1")
+    }
+
+    /// Sends a keep-alive ping to the watchdog.
+    pub fn watchdog(&self) -> io::Result<()> {
+        self.send("WATCHDOG=1")
+    }
+
+    /// Updates the status line shown by `systemctl status`.
+    pub fn status(&self, status: &str) -> io::Result<()> {
+        self.send(&format!("STATUS={}", status.replace('\n', " ")))
+    }
+
+    fn send(&self, message: &str) -> io::Result<()> {
+        match self.address.strip_prefix('@') {
+            Some(name) => self.send_abstract(name, message),
+            None => self
+                .socket
+                .send_to(message.as_bytes(), &self.address)
+                .map(|_| ()),
+        }
+    }
+
+    /// Sends to a socket in the abstract namespace, which is written with a leading `@`.
+    #[cfg(target_os = "linux")]
+    fn send_abstract(&self, name: &str, message: &str) -> io::Result<()> {
+        use std::os::linux::net::SocketAddrExt;
+        use std::os::unix::net::SocketAddr;
+
+        let address = SocketAddr::from_abstract_name(name)?;
+        self.socket
+            .send_to_addr(message.as_bytes(), &address)
+            .map(|_| ())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn send_abstract(&self, _name: &str, _message: &str) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "abstract notification sockets are only supported on Linux",
+        ))
+    }
+}
+
+/// Thread pinging the systemd watchdog; stopped when dropped.
+pub struct Watchdog {
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        // Closing the channel wakes the thread up
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
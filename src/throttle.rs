@@ -0,0 +1,86 @@
+//! # Throttle Module
+//!
+//! This module contains the `Throttle` rate limiter used to cap the bytes copied and the files processed
+//! per second, so a sweep does not saturate a shared filer.
+
+use std::io::{self, Read};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Rate limiter that paces callers to a fixed number of units (bytes, files) per second.
+///
+/// Every call to `acquire` reserves time for the requested units; a caller that runs ahead of
+/// the rate is put to sleep until its reservation starts. Unused time is not saved up, so the
+/// rate cannot be exceeded after an idle period. Clones share the same reservations.
+///
+/// # Examples
+///
+/// ```
+/// use netfs_unlker::throttle::Throttle;
+///
+/// let throttle = Throttle::new(1000.0);
+/// throttle.acquire(10); // Returns immediately
+/// throttle.acquire(10); // Sleeps for about 10 ms
+/// ```
+#[derive(Debug, Clone)]
+pub struct Throttle {
+    rate: f64,
+    next: Arc<Mutex<Instant>>,
+}
+
+impl Throttle {
+    /// Creates a throttle allowing `rate` units per second.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `rate` is not a positive number.
+    pub fn new(rate: f64) -> Self {
+        assert!(rate > 0.0, "throttle rate must be positive");
+        Throttle {
+            rate,
+            next: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    /// Returns the number of units allowed per second.
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    /// Reserves `amount` units, sleeping while earlier reservations have not elapsed yet.
+    pub fn acquire(&self, amount: u64) {
+        let delay = {
+            let mut next = self.next.lock().unwrap_or_else(|e| e.into_inner());
+            let now = Instant::now();
+            let start = (*next).max(now);
+            *next = start + Duration::from_secs_f64(amount as f64 / self.rate);
+            start - now
+        };
+
+        if !delay.is_zero() {
+            thread::sleep(delay);
+        }
+    }
+}
+
+/// Reader that paces the bytes read from the inner reader with a `Throttle`.
+pub struct ThrottledReader<'a, R> {
+    inner: R,
+    throttle: &'a Throttle,
+}
+
+impl<'a, R: Read> ThrottledReader<'a, R> {
+    /// Wraps `inner`, charging every byte read to `throttle`.
+    pub fn new(inner: R, throttle: &'a Throttle) -> Self {
+        ThrottledReader { inner, throttle }
+    }
+}
+
+impl<R: Read> Read for ThrottledReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.throttle.acquire(read as u64);
+        Ok(read)
+    }
+}
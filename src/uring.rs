@@ -0,0 +1,393 @@
+//! A module for copying files through io_uring on Linux.
+//!
+//! This module provides a copier that keeps several reads and writes in flight at once through an
+//! io_uring instance, reading into and writing from buffers registered with the kernel. It talks to
+//! the kernel through the raw `io_uring_setup`, `io_uring_enter` and `io_uring_register` system calls.
+//!
+//! Kernels without io_uring, or where it is disabled (`kernel.io_uring_disabled`, seccomp filters of
+//! container runtimes, a `RLIMIT_MEMLOCK` too low for the buffers), make `copy` fail with an error of
+//! kind `Unsupported`, and the caller falls back to the standard copier.
+
+use std::fs::File;
+use std::io::{Error, ErrorKind, Result};
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::{AsRawFd, FromRawFd, OwnedFd, RawFd};
+use std::ptr;
+use std::slice;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use tracing::debug;
+
+/// Number of buffers, and so of reads or writes in flight.
+const QUEUE_DEPTH: u32 = 8;
+/// Size of each buffer.
+const BUFFER_SIZE: usize = 1024 * 1024;
+
+/// `IORING_OP_READ_FIXED`: read into a registered buffer.
+const OP_READ_FIXED: u8 = 4;
+/// `IORING_OP_WRITE_FIXED`: write from a registered buffer.
+const OP_WRITE_FIXED: u8 = 5;
+/// `IORING_ENTER_GETEVENTS`: wait for completions.
+const ENTER_GETEVENTS: u32 = 1;
+/// `IORING_REGISTER_BUFFERS`.
+const REGISTER_BUFFERS: u32 = 0;
+/// mmap offsets of the submission ring, the completion ring and the submission entries.
+const OFF_SQ_RING: i64 = 0;
+const OFF_CQ_RING: i64 = 0x8000000;
+const OFF_SQES: i64 = 0x10000000;
+
+// The structures below mirror the kernel ABI
+const _: () = assert!(std::mem::size_of::<Params>() == 120);
+const _: () = assert!(std::mem::size_of::<Sqe>() == 64);
+const _: () = assert!(std::mem::size_of::<Cqe>() == 16);
+
+/// Cleared once io_uring turned out to be unavailable, so later copies skip the setup.
+static AVAILABLE: AtomicBool = AtomicBool::new(true);
+
+#[repr(C)]
+#[derive(Default)]
+struct SqringOffsets {
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    flags: u32,
+    dropped: u32,
+    array: u32,
+    resv1: u32,
+    user_addr: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct CqringOffsets {
+    head: u32,
+    tail: u32,
+    ring_mask: u32,
+    ring_entries: u32,
+    overflow: u32,
+    cqes: u32,
+    flags: u32,
+    resv1: u32,
+    user_addr: u64,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct Params {
+    sq_entries: u32,
+    cq_entries: u32,
+    flags: u32,
+    sq_thread_cpu: u32,
+    sq_thread_idle: u32,
+    features: u32,
+    wq_fd: u32,
+    resv: [u32; 3],
+    sq_off: SqringOffsets,
+    cq_off: CqringOffsets,
+}
+
+#[repr(C)]
+#[derive(Default)]
+struct Sqe {
+    opcode: u8,
+    flags: u8,
+    ioprio: u16,
+    fd: i32,
+    off: u64,
+    addr: u64,
+    len: u32,
+    rw_flags: u32,
+    user_data: u64,
+    buf_index: u16,
+    personality: u16,
+    splice_fd_in: i32,
+    addr3: u64,
+    pad: u64,
+}
+
+#[repr(C)]
+struct Cqe {
+    user_data: u64,
+    res: i32,
+    flags: u32,
+}
+
+/// Memory mapping of a part of the ring, unmapped when dropped.
+struct Mapping {
+    ptr: *mut u8,
+    len: usize,
+}
+
+impl Mapping {
+    fn new(fd: RawFd, len: usize, offset: i64) -> Result<Self> {
+        let ptr = unsafe {
+            libc::mmap(
+                ptr::null_mut(),
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED | libc::MAP_POPULATE,
+                fd,
+                offset,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(Error::last_os_error());
+        }
+        Ok(Mapping {
+            ptr: ptr as *mut u8,
+            len,
+        })
+    }
+
+    /// Returns the ring field at byte `offset` of the mapping.
+    fn at<T>(&self, offset: u32) -> *mut T {
+        // SAFETY: the offsets are reported by the kernel and lie within the mapping
+        unsafe { self.ptr.add(offset as usize) as *mut T }
+    }
+}
+
+impl Drop for Mapping {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.ptr as *mut libc::c_void, self.len) };
+    }
+}
+
+/// io_uring instance with its submission and completion rings.
+struct Ring {
+    // The mappings are declared before the descriptor so they are unmapped before it is closed
+    sq: Mapping,
+    cq: Mapping,
+    sqes: Mapping,
+    params: Params,
+    fd: OwnedFd,
+}
+
+impl Ring {
+    fn new(entries: u32) -> Result<Self> {
+        let mut params = Params::default();
+        let ret = unsafe {
+            libc::syscall(
+                libc::SYS_io_uring_setup,
+                entries,
+                &mut params as *mut Params,
+            )
+        };
+        if ret < 0 {
+            return Err(Error::last_os_error());
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(ret as RawFd) };
+
+        let sq_len = params.sq_off.array as usize + params.sq_entries as usize * 4;
+        let cq_len =
+            params.cq_off.cqes as usize + params.cq_entries as usize * std::mem::size_of::<Cqe>();
+        let sqes_len = params.sq_entries as usize * std::mem::size_of::<Sqe>();
+        Ok(Ring {
+            sq: Mapping::new(fd.as_raw_fd(), sq_len, OFF_SQ_RING)?,
+            cq: Mapping::new(fd.as_raw_fd(), cq_len, OFF_CQ_RING)?,
+            sqes: Mapping::new(fd.as_raw_fd(), sqes_len, OFF_SQES)?,
+            params,
+            fd,
+        })
+    }
+
+    /// Registers `buffers` with the kernel; entry `i` is then addressed by `buf_index` `i`.
+    fn register_buffers(&self, buffers: &[libc::iovec]) -> Result<()> {
+        let ret = unsafe {
+            libc::syscall(
+                libc::SYS_io_uring_register,
+                self.fd.as_raw_fd(),
+                REGISTER_BUFFERS,
+                buffers.as_ptr(),
+                buffers.len() as u32,
+            )
+        };
+        match ret {
+            0 => Ok(()),
+            _ => Err(Error::last_os_error()),
+        }
+    }
+
+    fn sq_field(&self, offset: u32) -> &AtomicU32 {
+        // SAFETY: ring indices are aligned 32-bit fields shared with the kernel
+        unsafe { &*self.sq.at::<AtomicU32>(offset) }
+    }
+
+    fn cq_field(&self, offset: u32) -> &AtomicU32 {
+        // SAFETY: as above, for the completion ring
+        unsafe { &*self.cq.at::<AtomicU32>(offset) }
+    }
+
+    /// Queues `sqe`; the caller never queues more entries than the ring holds before waiting.
+    fn push(&self, sqe: Sqe) {
+        let off = &self.params.sq_off;
+        let tail = self.sq_field(off.tail).load(Ordering::Relaxed);
+        let mask = self.sq_field(off.ring_mask).load(Ordering::Relaxed);
+        let index = tail & mask;
+        // SAFETY: `index` is masked to the ring size, so both writes stay within their mappings
+        unsafe {
+            ptr::write(self.sqes.at::<Sqe>(0).add(index as usize), sqe);
+            ptr::write(self.sq.at::<u32>(off.array).add(index as usize), index);
+        }
+        self.sq_field(off.tail)
+            .store(tail.wrapping_add(1), Ordering::Release);
+    }
+
+    /// Pops the next completion as `(user_data, res)`, if any.
+    fn pop(&self) -> Option<(u64, i32)> {
+        let off = &self.params.cq_off;
+        let head = self.cq_field(off.head).load(Ordering::Relaxed);
+        if head == self.cq_field(off.tail).load(Ordering::Acquire) {
+            return None;
+        }
+        let mask = self.cq_field(off.ring_mask).load(Ordering::Relaxed);
+        // SAFETY: the entry at a masked index between head and tail was filled in by the kernel
+        let cqe = unsafe { ptr::read(self.cq.at::<Cqe>(off.cqes).add((head & mask) as usize)) };
+        self.cq_field(off.head)
+            .store(head.wrapping_add(1), Ordering::Release);
+        Some((cqe.user_data, cqe.res))
+    }
+
+    /// Submits the `count` queued entries and stores the result of each in `results[user_data]`.
+    fn submit_and_wait(&self, count: usize, results: &mut [i32]) -> Result<()> {
+        let mut unsubmitted = count as u32;
+        let mut completed = 0;
+        while completed < count {
+            let ret = unsafe {
+                libc::syscall(
+                    libc::SYS_io_uring_enter,
+                    self.fd.as_raw_fd(),
+                    unsubmitted,
+                    1u32,
+                    ENTER_GETEVENTS,
+                    ptr::null::<libc::sigset_t>(),
+                    0usize,
+                )
+            };
+            if ret < 0 {
+                let e = Error::last_os_error();
+                match e.kind() {
+                    ErrorKind::Interrupted => continue,
+                    _ => return Err(e),
+                }
+            }
+            unsubmitted -= ret as u32;
+            while let Some((user_data, res)) = self.pop() {
+                results[user_data as usize] = res;
+                completed += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Copies the content of `from` into `to` through io_uring, returning the number of bytes copied.
+///
+/// # Errors
+///
+/// Returns an `Err` of kind `Unsupported` if io_uring is not available, in which case nothing was
+/// written, or any other `Err` if reading or writing fails.
+pub fn copy(from: &File, to: &File) -> Result<u64> {
+    if !AVAILABLE.load(Ordering::Relaxed) {
+        return Err(ErrorKind::Unsupported.into());
+    }
+    // The kernel writes into the buffer behind our back, so it is only ever accessed through `base`
+    let mut buffer = vec![0u8; QUEUE_DEPTH as usize * BUFFER_SIZE];
+    let base = buffer.as_mut_ptr();
+    let ring = match setup(base) {
+        Ok(ring) => ring,
+        Err(e) => {
+            debug!(
+                "io_uring is not available, using the standard copier: {}",
+                e
+            );
+            AVAILABLE.store(false, Ordering::Relaxed);
+            return Err(Error::new(ErrorKind::Unsupported, e));
+        }
+    };
+
+    let mut offset = 0u64;
+    let mut results = [0i32; QUEUE_DEPTH as usize];
+    loop {
+        for i in 0..QUEUE_DEPTH as usize {
+            ring.push(fixed(
+                OP_READ_FIXED,
+                from,
+                base,
+                i,
+                offset + (i * BUFFER_SIZE) as u64,
+                BUFFER_SIZE,
+            ));
+        }
+        ring.submit_and_wait(QUEUE_DEPTH as usize, &mut results)?;
+
+        // Only the reads up to the first short one are contiguous; a short read of 0 bytes is the end
+        let mut lens = Vec::with_capacity(QUEUE_DEPTH as usize);
+        for &res in &results {
+            if res < 0 {
+                return Err(Error::from_raw_os_error(-res));
+            }
+            lens.push(res as usize);
+            if res as usize != BUFFER_SIZE {
+                break;
+            }
+        }
+        let eof = lens.last() == Some(&0);
+        lens.retain(|&len| len > 0);
+
+        let mut written = offset;
+        for (i, &len) in lens.iter().enumerate() {
+            ring.push(fixed(OP_WRITE_FIXED, to, base, i, written, len));
+            written += len as u64;
+        }
+        ring.submit_and_wait(lens.len(), &mut results)?;
+        for (i, &len) in lens.iter().enumerate() {
+            let res = results[i];
+            if res < 0 {
+                return Err(Error::from_raw_os_error(-res));
+            }
+            // Finish a short write synchronously
+            if (res as usize) < len {
+                // SAFETY: the write completed, so the kernel no longer accesses the buffer
+                let rest = unsafe {
+                    slice::from_raw_parts(
+                        base.add(i * BUFFER_SIZE + res as usize),
+                        len - res as usize,
+                    )
+                };
+                to.write_all_at(rest, offset + res as u64)?;
+            }
+            offset += len as u64;
+        }
+
+        if eof {
+            return Ok(offset);
+        }
+    }
+}
+
+/// Sets up a ring with the memory at `base` registered as `QUEUE_DEPTH` buffers of `BUFFER_SIZE` bytes.
+fn setup(base: *mut u8) -> Result<Ring> {
+    let ring = Ring::new(QUEUE_DEPTH)?;
+    let buffers: Vec<libc::iovec> = (0..QUEUE_DEPTH as usize)
+        .map(|i| libc::iovec {
+            iov_base: base.wrapping_add(i * BUFFER_SIZE) as *mut libc::c_void,
+            iov_len: BUFFER_SIZE,
+        })
+        .collect();
+    ring.register_buffers(&buffers)?;
+    Ok(ring)
+}
+
+/// Builds a read or write of `len` bytes at `offset` of `file`, through registered buffer `index`.
+fn fixed(opcode: u8, file: &File, base: *mut u8, index: usize, offset: u64, len: usize) -> Sqe {
+    Sqe {
+        opcode,
+        fd: file.as_raw_fd(),
+        off: offset,
+        addr: base.wrapping_add(index * BUFFER_SIZE) as u64,
+        len: len as u32,
+        user_data: index as u64,
+        buf_index: index as u16,
+        ..Sqe::default()
+    }
+}
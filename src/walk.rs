@@ -0,0 +1,287 @@
+//! # Directory Walk Module
+//!
+//! This module contains the directory traversal shared by the repair and the scan paths, and the
+//! `LockedFileIter` built on it for callers that drive their own processing loop.
+//!
+//! The traversal is streaming: entries are read from the directory as the iterator advances, so a
+//! directory with millions of files is never held in memory at once. Only the subdirectories still
+//! to be visited are queued. The tree is walked breadth-first by default; a `WalkOrder` selects a
+//! depth-first walk, and directory listings sorted by name for a reproducible order.
+//!
+//! A walk can be kept on the filesystem of its root, like `du -x` or `rsync --one-file-system`:
+//! subdirectories on another device are then reported instead of descended into.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use std::path::Path;
+//! use netfs_unlker::backend::{NativeFs, NativeLocks};
+//! use netfs_unlker::walk::LockedFileIter;
+//!
+//! let (fs, locks) = (NativeFs::default(), NativeLocks::default());
+//! for entry in LockedFileIter::new(&fs, &locks, Path::new("/mnt/share"), true).unwrap() {
+//!     match entry {
+//!         Ok(path) => println!("{}", path.display()),
+//!         Err(e) => eprintln!("{}: {}", e.path.display(), e.error),
+//!     }
+//! }
+//! ```
+
+use crate::backend::{DirEntries, FileKind, FileOps, LockOps};
+use std::collections::VecDeque;
+use std::io::{self, Error};
+use std::path::{Path, PathBuf};
+use tracing::error;
+
+/// Entry that could not be read during a walk: a subdirectory that cannot be listed, or a file whose
+/// lock state cannot be probed.
+#[derive(Debug)]
+pub struct WalkError {
+    /// Path of the entry.
+    pub path: PathBuf,
+    /// Error returned when reading it.
+    pub error: io::Error,
+}
+
+/// Order in which a walk descends into subdirectories.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Traversal {
+    /// Visit all entries of a directory before those of its subdirectories. Only one directory is
+    /// open at a time.
+    #[default]
+    BreadthFirst,
+    /// Descend into a subdirectory as soon as it is found, finishing its subtree first. One
+    /// directory per level of the tree is open at a time.
+    DepthFirst,
+}
+
+/// Order in which a walk visits the entries of a tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct WalkOrder {
+    /// Order in which subdirectories are descended into.
+    pub traversal: Traversal,
+    /// List the entries of every directory sorted by name, instead of in the order of the
+    /// filesystem, so that repeated walks over an unchanged tree visit it in the same order.
+    /// The names of the directory being listed are then held in memory.
+    pub sorted: bool,
+}
+
+/// Walk over the directory `root`, yielding every entry that is not descended into.
+///
+/// With `recursive`, subdirectories are traversed instead of being yielded, in the order given by
+/// the `WalkOrder`. Symbolic links to directories are yielded rather than followed, so a walk never
+/// leaves the tree below `root` and cannot loop. A subdirectory that cannot be read is yielded as a `WalkError`, and the walk goes
+/// on with the next one.
+///
+/// A walk kept on one filesystem yields a subdirectory on another device as a `WalkError` of kind
+/// `CrossesDevices`, without descending into it.
+pub(crate) struct Walk<'a, F: FileOps> {
+    fs: &'a F,
+    recursive: bool,
+    order: WalkOrder,
+    // Device of the root when the walk is kept on its filesystem
+    device: Option<u64>,
+    pending: VecDeque<PathBuf>,
+    // Directories being listed, innermost last; breadth-first walks list one at a time
+    open: Vec<(PathBuf, DirEntries<'a>)>,
+}
+
+impl<'a, F: FileOps> Walk<'a, F> {
+    /// Starts a walk over `root`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if `root` is not a directory or cannot be read.
+    pub(crate) fn new(
+        fs: &'a F,
+        root: &Path,
+        recursive: bool,
+        order: WalkOrder,
+    ) -> io::Result<Self> {
+        if !is_directory(fs, root) {
+            error!("Such directory not found: ({})", root.display());
+            return Err(Error::from(io::ErrorKind::NotFound));
+        }
+
+        let entries = list(fs, root, order.sorted)?;
+        Ok(Walk {
+            fs,
+            recursive,
+            order,
+            device: None,
+            pending: VecDeque::new(),
+            open: vec![(root.to_path_buf(), entries)],
+        })
+    }
+
+    /// Keeps the walk on the filesystem of `root` with `enabled`. Platforms that do not report the
+    /// device of an entry are never considered to cross filesystems.
+    pub(crate) fn one_file_system(mut self, root: &Path, enabled: bool) -> Self {
+        self.device = match enabled {
+            true => self.fs.metadata(root).ok().and_then(|m| m.device),
+            false => None,
+        };
+        self
+    }
+
+    /// Checks whether the directory `path` lives on another filesystem than the root of a walk
+    /// kept on one filesystem.
+    fn crosses_filesystem(&self, path: &Path) -> bool {
+        let Some(root) = self.device else {
+            return false;
+        };
+        self.fs
+            .metadata(path)
+            .is_ok_and(|m| m.device.is_some_and(|device| device != root))
+    }
+}
+
+impl<F: FileOps> Iterator for Walk<'_, F> {
+    type Item = Result<PathBuf, WalkError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let next = match self.open.last_mut() {
+                Some((_, entries)) => entries.next(),
+                None => {
+                    let directory = self.pending.pop_front()?;
+                    match list(self.fs, &directory, self.order.sorted) {
+                        Ok(entries) => self.open.push((directory, entries)),
+                        Err(error) => {
+                            return Some(Err(WalkError {
+                                path: directory,
+                                error,
+                            }))
+                        }
+                    }
+                    continue;
+                }
+            };
+
+            match next {
+                Some(Ok(path)) if self.recursive && is_real_directory(self.fs, &path) => {
+                    if self.crosses_filesystem(&path) {
+                        let error =
+                            Error::new(io::ErrorKind::CrossesDevices, "on another filesystem");
+                        return Some(Err(WalkError { path, error }));
+                    }
+                    match self.order.traversal {
+                        Traversal::BreadthFirst => self.pending.push_back(path),
+                        Traversal::DepthFirst => match list(self.fs, &path, self.order.sorted) {
+                            Ok(entries) => self.open.push((path, entries)),
+                            Err(error) => return Some(Err(WalkError { path, error })),
+                        },
+                    }
+                }
+                Some(Ok(path)) => return Some(Ok(path)),
+                // The rest of a directory that fails mid-listing is given up
+                Some(Err(error)) => {
+                    let (path, _) = self.open.pop()?;
+                    return Some(Err(WalkError { path, error }));
+                }
+                None => {
+                    self.open.pop();
+                }
+            }
+        }
+    }
+}
+
+/// Streaming iterator over the locked files in a directory, yielded as they are found.
+///
+/// Entries that are not regular files are passed over. A subdirectory that cannot be read, or a file
+/// whose metadata or lock state cannot be probed, is yielded as a `WalkError`; the walk goes on with
+/// the next entry.
+pub struct LockedFileIter<'a, F: FileOps, L: LockOps> {
+    walk: Walk<'a, F>,
+    locks: &'a L,
+}
+
+impl<'a, F: FileOps, L: LockOps> LockedFileIter<'a, F, L> {
+    /// Starts looking for locked files in the directory `root`, descending into subdirectories with
+    /// `recursive`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if `root` is not a directory or cannot be read.
+    pub fn new(fs: &'a F, locks: &'a L, root: &Path, recursive: bool) -> io::Result<Self> {
+        Self::with_order(fs, locks, root, recursive, WalkOrder::default())
+    }
+
+    /// Starts looking for locked files in the directory `root`, visiting the tree in the given order.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `Err` if `root` is not a directory or cannot be read.
+    pub fn with_order(
+        fs: &'a F,
+        locks: &'a L,
+        root: &Path,
+        recursive: bool,
+        order: WalkOrder,
+    ) -> io::Result<Self> {
+        Ok(LockedFileIter {
+            walk: Walk::new(fs, root, recursive, order)?,
+            locks,
+        })
+    }
+}
+
+impl<F: FileOps, L: LockOps> Iterator for LockedFileIter<'_, F, L> {
+    type Item = Result<PathBuf, WalkError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let path = match self.walk.next()? {
+                Ok(path) => path,
+                Err(e) => return Some(Err(e)),
+            };
+            let probed = self.walk.fs.metadata(&path).and_then(|m| match m.kind {
+                FileKind::File => self.locks.is_locked(&path),
+                _ => Ok(false),
+            });
+            match probed {
+                Ok(true) => return Some(Ok(path)),
+                Ok(false) => {}
+                Err(error) => return Some(Err(WalkError { path, error })),
+            }
+        }
+    }
+}
+
+/// Lists the directory `path`, sorted by name with `sorted`.
+///
+/// A sorted listing that fails midway keeps the entries read before the failure, followed by the error.
+fn list<'a, F: FileOps>(fs: &'a F, path: &Path, sorted: bool) -> io::Result<DirEntries<'a>> {
+    let entries = fs.read_dir(path)?;
+    if !sorted {
+        return Ok(entries);
+    }
+
+    let mut listed = Vec::new();
+    let mut failure = None;
+    for entry in entries {
+        match entry {
+            Ok(path) => listed.push(path),
+            Err(e) => {
+                failure = Some(e);
+                break;
+            }
+        }
+    }
+    listed.sort();
+    Ok(Box::new(listed.into_iter().map(Ok).chain(failure.map(Err))))
+}
+
+fn is_directory<F: FileOps>(fs: &F, path: &Path) -> bool {
+    fs.metadata(path)
+        .map(|m| m.kind == FileKind::Directory)
+        .unwrap_or(false)
+}
+
+/// Checks whether `path` is a directory and not a symbolic link to one.
+fn is_real_directory<F: FileOps>(fs: &F, path: &Path) -> bool {
+    fs.symlink_metadata(path)
+        .map(|m| m.kind == FileKind::Directory)
+        .unwrap_or(false)
+}
@@ -0,0 +1,272 @@
+//! # Watch Module
+//!
+//! This module contains the `watch` subcommand: it sweeps the target at a fixed interval until it is
+//! stopped, serves the control socket and reports its lifecycle to systemd. The same loop runs the
+//! `daemon` subcommand, which reloads its arguments while running.
+
+use crate::cli::WatchArgs;
+#[cfg(unix)]
+use crate::control::{ControlServer, RepairJob};
+#[cfg(unix)]
+use crate::daemon::Service;
+use crate::shutdown;
+#[cfg(all(unix, feature = "systemd"))]
+use crate::systemd;
+use crate::{acquire_run_guards, Engine, EXIT_NOTHING_TO_DO, EXIT_USAGE_ERROR};
+#[cfg(unix)]
+use crate::{check_privileges, drop_privileges, logging, reacquire_run_guards};
+#[cfg(unix)]
+use netfs_unlker::run_guard::RunGuard;
+#[cfg(all(unix, feature = "systemd"))]
+use std::io;
+#[cfg(unix)]
+use std::mem;
+use std::time::{Duration, Instant};
+use tracing::info;
+#[cfg(unix)]
+use tracing::{debug, error, warn};
+
+/// Runs sweeps every `--interval` until a shutdown is requested and returns the process exit code.
+///
+/// A sweep that fails is logged and retried at the next interval. Under systemd with the `systemd`
+/// feature, readiness is reported before the first sweep and the watchdog is pinged throughout.
+pub fn run_watch(args: &WatchArgs) -> i32 {
+    run(
+        args,
+        #[cfg(unix)]
+        None,
+    )
+}
+
+/// Runs the watch loop with the arguments `initial`, as a daemon if `service` is given.
+///
+/// A daemon reports its startup to `service` before the first sweep. On `SIGHUP` it reopens the log
+/// file and reloads its arguments, which take effect from the next sweep; the control socket and the
+/// user of `--run-as` stay those it was started with.
+pub fn run(initial: &WatchArgs, #[cfg(unix)] mut service: Option<&mut Service>) -> i32 {
+    #[cfg(unix)]
+    let identity = match check_privileges(&initial.repair.privileges) {
+        Ok(identity) => identity,
+        Err(()) => return EXIT_USAGE_ERROR,
+    };
+    #[cfg_attr(not(unix), allow(unused_mut))]
+    let mut guards = match acquire_run_guards(&initial.repair) {
+        Ok(guard) => guard,
+        Err(()) => return EXIT_USAGE_ERROR,
+    };
+    #[cfg_attr(not(unix), allow(unused_mut))]
+    let mut engine = match Engine::from_args(&initial.repair) {
+        Some(engine) => engine,
+        None => return EXIT_USAGE_ERROR,
+    };
+
+    shutdown::install();
+    info!(
+        "Watching with an interval of {}",
+        humantime::format_duration(initial.interval)
+    );
+
+    #[cfg(all(unix, feature = "systemd"))]
+    let systemd = match systemd::Notifier::from_env() {
+        Ok(systemd) => systemd,
+        Err(e) => {
+            error!(
+                "Failed to connect to the systemd notification socket: {}",
+                e
+            );
+            return EXIT_USAGE_ERROR;
+        }
+    };
+    #[cfg(all(unix, feature = "systemd"))]
+    let _watchdog = match systemd.as_ref().map(systemd::Notifier::start_watchdog) {
+        Some(Err(e)) => {
+            error!("Failed to start the systemd watchdog: {}", e);
+            return EXIT_USAGE_ERROR;
+        }
+        Some(Ok(watchdog)) => watchdog,
+        None => None,
+    };
+
+    #[cfg(unix)]
+    let control = match initial.control_socket.as_deref().map(ControlServer::bind) {
+        Some(Err(e)) => {
+            error!("Failed to open the control socket: {}", e);
+            return EXIT_USAGE_ERROR;
+        }
+        Some(Ok(control)) => Some(control),
+        None => None,
+    };
+    #[cfg(unix)]
+    if drop_privileges(identity.as_ref()).is_err() {
+        return EXIT_USAGE_ERROR;
+    }
+
+    #[cfg(all(unix, feature = "systemd"))]
+    if let Some(systemd) = &systemd {
+        notify_systemd(systemd.ready());
+    }
+
+    #[cfg(unix)]
+    if let Some(service) = service.as_deref_mut() {
+        service.ready();
+    }
+
+    #[cfg_attr(not(unix), allow(unused_mut))]
+    let mut reloaded: Option<WatchArgs> = None;
+    let mut last_sweep: Option<Instant> = None;
+    loop {
+        #[cfg(unix)]
+        if let Some(service) = service.as_deref() {
+            if shutdown::take_reload() {
+                if let Some(args) = reload(service, &mut engine, &mut guards) {
+                    reloaded = Some(args);
+                }
+            }
+        }
+        let args = reloaded.as_ref().unwrap_or(initial);
+
+        let next_sweep = last_sweep.map_or_else(Instant::now, |last| last + args.interval);
+        let wait = next_sweep.saturating_duration_since(Instant::now());
+        if !wait.is_zero() {
+            if !wait_for_next_sweep(
+                &engine,
+                wait,
+                #[cfg(unix)]
+                control.as_ref(),
+            ) {
+                break;
+            }
+            continue;
+        }
+
+        #[cfg(unix)]
+        if control.as_ref().is_some_and(ControlServer::paused) {
+            debug!("Scheduled sweep skipped, sweeps are paused");
+            last_sweep = Some(Instant::now());
+            continue;
+        }
+
+        #[cfg(unix)]
+        if let Some(control) = &control {
+            control.update_status(|status| status.busy = true);
+        }
+        let report = engine.sweep(&args.repair.target);
+        last_sweep = Some(Instant::now());
+        #[cfg(unix)]
+        if let Some(control) = &control {
+            control.update_status(|status| {
+                status.busy = false;
+                if let Some(report) = &report {
+                    status.record_sweep(report);
+                }
+            });
+        }
+
+        if let Some(report) = report {
+            engine.summarize(&report, &args.repair.target);
+            #[cfg(all(unix, feature = "systemd"))]
+            if let Some(systemd) = &systemd {
+                notify_systemd(systemd.status(&format!(
+                    "Last sweep: {} repaired, {} failed",
+                    report.repaired(),
+                    report.failed()
+                )));
+            }
+        }
+
+        if shutdown::requested() {
+            break;
+        }
+    }
+
+    info!("Shutting down");
+    drop(guards);
+    #[cfg(all(unix, feature = "systemd"))]
+    if let Some(systemd) = &systemd {
+        notify_systemd(systemd.stopping());
+    }
+    EXIT_NOTHING_TO_DO
+}
+
+/// Waits for up to `duration` for the next scheduled sweep, running the repairs requested through the
+/// control socket meanwhile. A reload request cuts the wait short.
+///
+/// # Returns
+///
+/// Returns `false` if a shutdown was requested.
+fn wait_for_next_sweep(
+    engine: &Engine,
+    duration: Duration,
+    #[cfg(unix)] control: Option<&ControlServer>,
+) -> bool {
+    #[cfg(unix)]
+    if let Some(control) = control {
+        return shutdown::wait_with(duration, |timeout| {
+            if let Some(job) = control.next_job(timeout) {
+                run_job(engine, control, job);
+            }
+        });
+    }
+    #[cfg(not(unix))]
+    let _ = engine;
+    shutdown::wait(duration)
+}
+
+/// Reopens the log file and reloads the arguments of a daemon, replacing the engine and the run locks.
+///
+/// # Returns
+///
+/// Returns the new arguments, or `None` if they are invalid or a run lock cannot be taken, in which
+/// case the previous ones stay in effect; the error is logged.
+#[cfg(unix)]
+fn reload(service: &Service, engine: &mut Engine, guards: &mut Vec<RunGuard>) -> Option<WatchArgs> {
+    info!("Reloading");
+    if let Err(e) = logging::reopen() {
+        warn!("Failed to reopen the log file: {}", e);
+    }
+
+    let args = service.reload_config()?;
+    let Some(reloaded) = Engine::from_args(&args.repair) else {
+        warn!("Keeping the previous configuration");
+        return None;
+    };
+    match reacquire_run_guards(&args.repair, mem::take(guards)) {
+        Ok(reacquired) => *guards = reacquired,
+        Err(held) => {
+            *guards = held;
+            warn!("Keeping the previous configuration");
+            return None;
+        }
+    }
+
+    *engine = reloaded;
+    info!(
+        "Reloaded, watching with an interval of {}",
+        humantime::format_duration(args.interval)
+    );
+    Some(args)
+}
+
+/// Runs a repair requested through the control socket and replies with its result.
+#[cfg(unix)]
+fn run_job(engine: &Engine, control: &ControlServer, job: RepairJob) {
+    info!(
+        "Repair requested through the control socket: {}",
+        job.path.display()
+    );
+    control.update_status(|status| status.busy = true);
+    let result = match job.path.is_dir() {
+        true => engine.repairer.repair_directory(&job.path, job.recursive),
+        false => engine.repairer.repair_file(&job.path),
+    };
+    control.update_status(|status| status.busy = false);
+    job.finish(result);
+}
+
+/// Logs a failed notification of systemd; the service keeps running.
+#[cfg(all(unix, feature = "systemd"))]
+fn notify_systemd(result: io::Result<()>) {
+    if let Err(e) = result {
+        warn!("Failed to notify systemd: {}", e);
+    }
+}
@@ -0,0 +1,184 @@
+//! A module for handling file locks on Windows.
+//!
+//! This module provides functions to probe and release byte-range locks taken with `LockFileEx`,
+//! and to atomically replace files, mirroring the `fcntl` module on Unix-like systems.
+
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{Error, Result};
+use std::os::windows::ffi::OsStrExt;
+use std::os::windows::io::AsRawHandle;
+use std::path::{Component, Path, Prefix};
+use windows_sys::Win32::Foundation::{ERROR_LOCK_VIOLATION, ERROR_NOT_LOCKED};
+use windows_sys::Win32::Storage::FileSystem::{
+    GetDriveTypeW, LockFileEx, MoveFileExW, UnlockFileEx, LOCKFILE_EXCLUSIVE_LOCK,
+    LOCKFILE_FAIL_IMMEDIATELY, LOCK_FILE_FLAGS, MOVEFILE_REPLACE_EXISTING, MOVEFILE_WRITE_THROUGH,
+};
+use windows_sys::Win32::System::IO::OVERLAPPED;
+
+/// Unlocks a file that was previously locked.
+///
+/// # Arguments
+///
+/// * `file` - A reference to the `File` that needs to be unlocked.
+///
+/// # Returns
+///
+/// This function returns a `Result` which is `Ok` if the file was successfully unlocked, or an `Err`
+/// if an error occurred during unlocking.
+pub fn unlock(file: &File) -> Result<()> {
+    let size = file.metadata()?.len();
+    unlock_range(file, 0, size)
+}
+
+/// Unlocks a byte range of a file.
+///
+/// # Arguments
+///
+/// * `file` - A reference to the `File` that needs to be unlocked.
+/// * `start` - Offset of the first byte of the range.
+/// * `len` - Length of the range.
+///
+/// # Returns
+///
+/// This function returns a `Result` which is `Ok` if the range was successfully unlocked, or an `Err`
+/// if an error occurred during unlocking.
+pub fn unlock_range(file: &File, start: u64, len: u64) -> Result<()> {
+    match unlock_range_internal(file, start, len) {
+        // Windows reports an error when the range was not locked by this handle
+        Err(e) if e.raw_os_error() == Some(ERROR_NOT_LOCKED as i32) => Ok(()),
+        other => other,
+    }
+}
+
+/// Checks if a file is locked.
+///
+/// The check tries to take an exclusive, non-blocking lock on the whole file and releases it again.
+///
+/// # Arguments
+///
+/// * `file` - A reference to the `File` to be checked.
+///
+/// # Returns
+///
+/// Returns `true` if the file is locked, `false` otherwise.
+pub fn is_file_locked(file: &File) -> bool {
+    file.metadata()
+        .and_then(|m| is_file_locked_internal(file, m.len(), LOCKFILE_EXCLUSIVE_LOCK))
+        .unwrap_or(false)
+}
+
+/// Checks if a file is locked so that even a shared lock cannot be taken.
+///
+/// # Arguments
+///
+/// * `file` - A reference to the `File` to be checked.
+///
+/// # Returns
+///
+/// Returns `true` if the file is exclusively locked, `false` otherwise.
+pub fn is_file_share_locked(file: &File) -> bool {
+    file.metadata()
+        .and_then(|m| is_file_locked_internal(file, m.len(), 0))
+        .unwrap_or(false)
+}
+
+/// Atomically renames `from` to `to`, replacing `to` if it exists.
+///
+/// # Returns
+///
+/// Returns a `Result` which is `Ok` if the file was moved, or an `Err` if an error occurred.
+pub fn move_file_replace(from: &Path, to: &Path) -> Result<()> {
+    let from = wide(from.as_os_str());
+    let to = wide(to.as_os_str());
+
+    let ret = unsafe {
+        MoveFileExW(
+            from.as_ptr(),
+            to.as_ptr(),
+            MOVEFILE_REPLACE_EXISTING | MOVEFILE_WRITE_THROUGH,
+        )
+    };
+    match ret {
+        0 => Err(Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+/// Checks if a path lives on a network share.
+///
+/// UNC paths are always remote; paths on drive letters are remote if the drive is a mapped network drive.
+///
+/// # Arguments
+///
+/// * `path` - An absolute path, as returned by `std::fs::canonicalize`.
+///
+/// # Returns
+///
+/// Returns `true` if the path is on a network share, `false` otherwise.
+pub fn is_remote_path(path: &Path) -> bool {
+    match path.components().next() {
+        Some(Component::Prefix(prefix)) => match prefix.kind() {
+            Prefix::UNC(..) | Prefix::VerbatimUNC(..) => true,
+            Prefix::Disk(letter) | Prefix::VerbatimDisk(letter) => {
+                let root = wide(OsStr::new(&format!("{}:\\", letter as char)));
+                unsafe { GetDriveTypeW(root.as_ptr()) == DRIVE_REMOTE }
+            }
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// `GetDriveTypeW` result for a remote (network) drive.
+const DRIVE_REMOTE: u32 = 4;
+
+fn is_file_locked_internal(file: &File, size: u64, mode: LOCK_FILE_FLAGS) -> Result<bool> {
+    let mut overlapped: OVERLAPPED = unsafe { std::mem::zeroed() };
+    let (low, high) = split(lock_len(size));
+
+    let ret = unsafe {
+        LockFileEx(
+            file.as_raw_handle() as _,
+            mode | LOCKFILE_FAIL_IMMEDIATELY,
+            0,
+            low,
+            high,
+            &mut overlapped,
+        )
+    };
+    match ret {
+        0 => match Error::last_os_error().raw_os_error() {
+            Some(code) if code == ERROR_LOCK_VIOLATION as i32 => Ok(true), // Held by someone else
+            _ => Ok(false),
+        },
+        _ => unlock_range_internal(file, 0, size).map(|_| false), // We got the lock, so nobody else holds one
+    }
+}
+
+fn unlock_range_internal(file: &File, start: u64, len: u64) -> Result<()> {
+    let mut overlapped: OVERLAPPED = unsafe { std::mem::zeroed() };
+    let (offset_low, offset_high) = split(start);
+    overlapped.Anonymous.Anonymous.Offset = offset_low;
+    overlapped.Anonymous.Anonymous.OffsetHigh = offset_high;
+    let (low, high) = split(lock_len(len));
+
+    let ret = unsafe { UnlockFileEx(file.as_raw_handle() as _, 0, low, high, &mut overlapped) };
+    match ret {
+        0 => Err(Error::last_os_error()),
+        _ => Ok(()),
+    }
+}
+
+/// Length of the range to lock; an empty file still gets a one-byte range so the probe is meaningful.
+fn lock_len(size: u64) -> u64 {
+    size.max(1)
+}
+
+fn split(value: u64) -> (u32, u32) {
+    (value as u32, (value >> 32) as u32)
+}
+
+fn wide(value: &OsStr) -> Vec<u16> {
+    value.encode_wide().chain(Some(0)).collect()
+}
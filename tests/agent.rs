@@ -0,0 +1,329 @@
+#![cfg(feature = "agent")]
+
+use netfs_unlker::agent::{AgentClient, AgentEvent, AgentHandler, AgentServer, ProgressStream};
+use netfs_unlker::backend::{FileOps, LockOps, NativeFs, NativeLocks};
+use netfs_unlker::mock::{MemoryFs, Operation};
+use netfs_unlker::scan::{ScanReport, Scanner};
+use netfs_unlker::{RepairOptions, RepairReport, Repairer};
+use std::io::{self, BufRead, BufReader, ErrorKind, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+const TOKEN: &str = "s3cret";
+
+/// Serves scans and repairs of an in-memory filesystem.
+struct MemoryHandler {
+    fs: MemoryFs,
+}
+
+impl AgentHandler for MemoryHandler {
+    fn scan(&self, path: &Path, recursive: bool) -> io::Result<ScanReport> {
+        Scanner::new(&self.fs, &self.fs).scan_directory(path, recursive)
+    }
+
+    fn repair(
+        &self,
+        path: &Path,
+        recursive: bool,
+        progress: ProgressStream,
+    ) -> io::Result<RepairReport> {
+        Repairer::new(&self.fs, &self.fs, RepairOptions::default())
+            .with_progress_observer(progress)
+            .repair_directory(path, recursive)
+    }
+
+    fn resolve(&self, path: &Path) -> io::Result<PathBuf> {
+        // The in-memory filesystem has no symbolic links
+        self.fs.metadata(path).map(|_| path.to_path_buf())
+    }
+}
+
+/// Serves scans and repairs of the local filesystem, resolving paths with the default
+/// `AgentHandler::resolve`.
+struct NativeHandler;
+
+impl AgentHandler for NativeHandler {
+    fn scan(&self, path: &Path, recursive: bool) -> io::Result<ScanReport> {
+        Scanner::new(NativeFs::default(), NativeLocks::default()).scan_directory(path, recursive)
+    }
+
+    fn repair(
+        &self,
+        path: &Path,
+        recursive: bool,
+        progress: ProgressStream,
+    ) -> io::Result<RepairReport> {
+        let options = RepairOptions {
+            allow_local: true,
+            ..RepairOptions::default()
+        };
+        Repairer::new(NativeFs::default(), NativeLocks::default(), options)
+            .with_progress_observer(progress)
+            .repair_directory(path, recursive)
+    }
+}
+
+/// Runs an agent serving `roots` with `handler` while `test` drives it with a client using `token`.
+fn serve_agent<H: AgentHandler>(
+    handler: H,
+    roots: Vec<PathBuf>,
+    token: &str,
+    test: impl FnOnce(&AgentClient, &H),
+) {
+    let server = AgentServer::bind("127.0.0.1:0", TOKEN)
+        .unwrap()
+        .with_roots(roots);
+    serve(server, handler, token, test);
+}
+
+/// Runs `server` with `handler` while `test` drives it with a client using `token`.
+fn serve<H: AgentHandler>(
+    server: AgentServer,
+    handler: H,
+    token: &str,
+    test: impl FnOnce(&AgentClient, &H),
+) {
+    let client =
+        AgentClient::new(format!("http://{}", server.local_addr().unwrap()), token).unwrap();
+    let running = AtomicBool::new(true);
+    thread::scope(|scope| {
+        scope.spawn(|| server.serve(&handler, || running.load(Ordering::SeqCst)));
+        test(&client, &handler);
+        running.store(false, Ordering::SeqCst);
+    });
+}
+
+/// Runs an agent serving `/mnt/share` of `fs` while `test` drives it with a client using `token`.
+fn with_agent(fs: MemoryFs, token: &str, test: impl FnOnce(&AgentClient, &MemoryFs)) {
+    let handler = MemoryHandler { fs };
+    serve_agent(
+        handler,
+        vec![PathBuf::from("/mnt/share")],
+        token,
+        |client, handler| test(client, &handler.fs),
+    );
+}
+
+#[test]
+fn repair_streams_progress() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/data.db", b"important");
+    fs.add_file("/mnt/share/idle.db", b"idle");
+
+    with_agent(fs, TOKEN, |client, fs| {
+        let mut events = Vec::new();
+        let summary = client
+            .repair(Path::new("/mnt/share"), true, |event| {
+                events.push(event.clone())
+            })
+            .unwrap();
+
+        assert_eq!(summary.repaired, 1);
+        assert_eq!(summary.not_locked, 1);
+        assert!(events.contains(&AgentEvent::FileStarted {
+            path: "/mnt/share/data.db".to_string()
+        }));
+        assert!(events.iter().any(|event| matches!(
+            event,
+            AgentEvent::StageChanged { stage, .. } if stage == "rename"
+        )));
+        assert!(events.iter().any(|event| matches!(
+            event,
+            AgentEvent::FileFinished { path, outcome, .. }
+                if path == "/mnt/share/data.db" && outcome == "repaired"
+        )));
+        assert!(matches!(events.last(), Some(AgentEvent::Done { .. })));
+        assert_eq!(fs.contents("/mnt/share/data.db").unwrap(), b"important");
+    });
+}
+
+#[test]
+fn scan_returns_the_inventory() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/a/data.db", b"data");
+    fs.add_file("/mnt/share/idle.db", b"idle");
+
+    with_agent(fs, TOKEN, |client, _| {
+        let report = client.scan(Path::new("/mnt/share"), true).unwrap();
+        assert_eq!(report["scanned"], 2);
+        assert_eq!(report["locked"][0]["path"], "/mnt/share/a/data.db");
+        assert_eq!(client.health().unwrap(), env!("CARGO_PKG_VERSION"));
+    });
+}
+
+#[test]
+fn refuses_a_wrong_token() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/data.db", b"data");
+
+    with_agent(fs, "guess", |client, fs| {
+        let e = client.health().unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::PermissionDenied);
+        let e = client
+            .repair(Path::new("/mnt/share"), true, |_| {})
+            .unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::PermissionDenied);
+        assert!(fs.is_locked(Path::new("/mnt/share/data.db")).unwrap());
+    });
+}
+
+#[test]
+fn refuses_paths_outside_of_the_roots() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/other/data.db", b"data");
+
+    with_agent(fs, TOKEN, |client, _| {
+        for path in ["/mnt/other", "/mnt/share/../other", "mnt/share"] {
+            let e = client.scan(Path::new(path), true).unwrap_err();
+            assert_eq!(e.kind(), ErrorKind::PermissionDenied, "{}", path);
+        }
+    });
+}
+
+#[test]
+#[cfg(unix)]
+fn refuses_symbolic_links_out_of_the_roots() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().join("share");
+    let outside = dir.path().join("outside");
+    std::fs::create_dir_all(root.join("inner")).unwrap();
+    std::fs::create_dir(&outside).unwrap();
+    std::fs::write(outside.join("secret.db"), b"secret").unwrap();
+    std::os::unix::fs::symlink(&outside, root.join("escape")).unwrap();
+    std::os::unix::fs::symlink(root.join("inner"), root.join("alias")).unwrap();
+    let root = std::fs::canonicalize(&root).unwrap();
+
+    serve_agent(NativeHandler, vec![root.clone()], TOKEN, |client, _| {
+        for path in [root.join("escape"), root.join("escape/secret.db")] {
+            let e = client.scan(&path, true).unwrap_err();
+            assert_eq!(e.kind(), ErrorKind::PermissionDenied, "{}", path.display());
+        }
+        let e = client
+            .repair(&root.join("escape"), true, |_| {})
+            .unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::PermissionDenied);
+
+        // Links that stay below the root are served
+        client.scan(&root.join("alias"), true).unwrap();
+    });
+}
+
+#[test]
+#[cfg(unix)]
+fn recursive_requests_do_not_follow_links_out_of_the_roots() {
+    let dir = tempfile::tempdir().unwrap();
+    let root = dir.path().join("share");
+    let outside = dir.path().join("outside");
+    std::fs::create_dir(&root).unwrap();
+    std::fs::create_dir(&outside).unwrap();
+    std::fs::write(outside.join("secret.db"), b"secret").unwrap();
+    std::os::unix::fs::symlink(&outside, root.join("escape")).unwrap();
+    std::os::unix::fs::symlink(&root, root.join("loop")).unwrap();
+    let root = std::fs::canonicalize(&root).unwrap();
+
+    serve_agent(NativeHandler, vec![root.clone()], TOKEN, |client, _| {
+        let report = client.scan(&root, true).unwrap();
+        assert_eq!(report["scanned"], 0);
+
+        let summary = client.repair(&root, true, |_| {}).unwrap();
+        assert_eq!(summary.not_locked, 0);
+        assert_eq!(summary.failed, 0);
+    });
+}
+
+#[test]
+fn refuses_missing_paths() {
+    with_agent(MemoryFs::new(), TOKEN, |client, _| {
+        let mut events = Vec::new();
+        let result = client.repair(Path::new("/mnt/share/missing"), false, |event| {
+            events.push(event.clone())
+        });
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::NotFound);
+        assert!(events.is_empty());
+    });
+}
+
+#[test]
+fn reports_a_failed_repair() {
+    let fs = MemoryFs::new();
+    fs.add_dir("/mnt/share");
+    fs.fail(Operation::ReadDir, ErrorKind::PermissionDenied);
+
+    with_agent(fs, TOKEN, |client, _| {
+        let mut events = Vec::new();
+        let result = client.repair(Path::new("/mnt/share"), false, |event| {
+            events.push(event.clone())
+        });
+        assert!(result.is_err());
+        assert!(matches!(events.as_slice(), [AgentEvent::Error { .. }]));
+    });
+}
+
+#[test]
+fn refuses_an_empty_token() {
+    let e = AgentServer::bind("127.0.0.1:0", "").err().unwrap();
+    assert_eq!(e.kind(), ErrorKind::InvalidInput);
+}
+
+/// Sends `request` to the agent at `address` on a raw connection and returns its status line.
+fn raw_status(address: SocketAddr, request: &[u8]) -> String {
+    let mut stream = TcpStream::connect(address).unwrap();
+    // The agent may answer before the whole request is sent
+    let _ = stream.write_all(request);
+    let mut status = String::new();
+    BufReader::new(stream).read_line(&mut status).unwrap();
+    status.trim_end().to_string()
+}
+
+#[test]
+fn refuses_oversized_headers() {
+    let server = AgentServer::bind("127.0.0.1:0", TOKEN).unwrap();
+    let address = server.local_addr().unwrap();
+    serve(
+        server,
+        MemoryHandler {
+            fs: MemoryFs::new(),
+        },
+        TOKEN,
+        |_, _| {
+            let mut request = b"GET /v1/health HTTP/1.1\r\nX-Padding: ".to_vec();
+            request.extend(vec![b'a'; 64 * 1024]);
+            request.extend(b"\r\n\r\n");
+            assert!(raw_status(address, &request).starts_with("HTTP/1.1 431"));
+
+            let mut request = b"GET /".to_vec();
+            request.extend(vec![b'a'; 64 * 1024]);
+            assert!(raw_status(address, &request).starts_with("HTTP/1.1 431"));
+        },
+    );
+}
+
+#[test]
+fn refuses_connections_over_the_limit() {
+    let server = AgentServer::bind("127.0.0.1:0", TOKEN)
+        .unwrap()
+        .with_max_connections(1);
+    let address = server.local_addr().unwrap();
+    serve(
+        server,
+        MemoryHandler {
+            fs: MemoryFs::new(),
+        },
+        TOKEN,
+        |client, _| {
+            let idle = TcpStream::connect(address).unwrap();
+            // Give the agent time to accept the idle connection
+            thread::sleep(Duration::from_millis(500));
+            assert!(
+                raw_status(address, b"GET /v1/health HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 503")
+            );
+
+            drop(idle);
+            thread::sleep(Duration::from_millis(500));
+            assert_eq!(client.health().unwrap(), env!("CARGO_PKG_VERSION"));
+        },
+    );
+}
@@ -0,0 +1,114 @@
+use netfs_unlker::audit::{
+    verify_audit_log, AuditOutcome, AuditRecord, AuditSink, JsonLinesAuditLog,
+};
+use netfs_unlker::mock::MemoryFs;
+use netfs_unlker::{RepairOptions, Repairer};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Default)]
+struct Records(Arc<Mutex<Vec<AuditRecord>>>);
+
+impl AuditSink for Records {
+    fn record(&self, record: &AuditRecord) -> io::Result<()> {
+        self.0.lock().unwrap().push(record.clone());
+        Ok(())
+    }
+}
+
+#[test]
+fn records_repaired_files_only() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/a", b"locked");
+    fs.add_file("/mnt/share/b", b"unlocked");
+    let records = Records::default();
+
+    Repairer::new(&fs, &fs, RepairOptions::default())
+        .with_audit_sink(records.clone())
+        .repair_directory(Path::new("/mnt/share"), false)
+        .unwrap();
+
+    let records = records.0.lock().unwrap();
+    assert_eq!(records.len(), 1);
+    let record = &records[0];
+    let original = record.original.as_ref().unwrap();
+    assert_eq!(record.path, Path::new("/mnt/share/a"));
+    assert_eq!(record.outcome, AuditOutcome::Repaired);
+    assert_eq!(original.size, 6);
+    assert!(original.checksum.is_some());
+    assert_ne!(record.new_inode, original.inode);
+    assert!(record.lock.is_some());
+}
+
+#[test]
+fn audit_log_chain_detects_tampering() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("audit.jsonl");
+
+    for name in ["/mnt/share/a", "/mnt/share/b"] {
+        let fs = MemoryFs::new();
+        fs.add_locked_file(name, b"locked");
+        Repairer::new(&fs, &fs, RepairOptions::default())
+            .with_audit_sink(JsonLinesAuditLog::open(&path).unwrap())
+            .repair_file(Path::new(name))
+            .unwrap();
+    }
+    assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 2);
+    assert_eq!(verify_audit_log(&path).unwrap(), None);
+
+    let tampered = fs::read_to_string(&path)
+        .unwrap()
+        .replacen("/mnt/share/a", "/mnt/share/c", 1);
+    fs::write(&path, tampered).unwrap();
+    assert_eq!(verify_audit_log(&path).unwrap(), Some(2));
+}
+
+#[test]
+fn repairers_sharing_an_audit_log_continue_its_chain() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("audit.jsonl");
+    let audit_log = Arc::new(JsonLinesAuditLog::open(&path).unwrap());
+
+    for name in ["/mnt/share/a", "/mnt/share/b", "/mnt/share/c"] {
+        let fs = MemoryFs::new();
+        fs.add_locked_file(name, b"locked");
+        Repairer::new(&fs, &fs, RepairOptions::default())
+            .with_audit_sink(audit_log.clone())
+            .repair_file(Path::new(name))
+            .unwrap();
+    }
+    assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 3);
+    assert_eq!(verify_audit_log(&path).unwrap(), None);
+}
+
+#[test]
+fn audit_logs_opened_twice_keep_a_single_chain() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("audit.jsonl");
+    // Stands in for two processes writing to the same log
+    let audit_logs = [
+        Arc::new(JsonLinesAuditLog::open(&path).unwrap()),
+        Arc::new(JsonLinesAuditLog::open(&path).unwrap()),
+    ];
+
+    for (i, name) in [
+        "/mnt/share/a",
+        "/mnt/share/b",
+        "/mnt/share/c",
+        "/mnt/share/d",
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let fs = MemoryFs::new();
+        fs.add_locked_file(name, b"locked");
+        Repairer::new(&fs, &fs, RepairOptions::default())
+            .with_audit_sink(audit_logs[i % 2].clone())
+            .repair_file(Path::new(name))
+            .unwrap();
+    }
+    assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 4);
+    assert_eq!(verify_audit_log(&path).unwrap(), None);
+}
@@ -0,0 +1,89 @@
+use netfs_unlker::cache::ProbeCache;
+use netfs_unlker::mock::{MemoryFs, Operation};
+use netfs_unlker::{FileOutcome, RepairOptions, RepairReport, Repairer};
+use std::io::ErrorKind;
+use std::path::Path;
+use std::time::Duration;
+
+fn sweep(fs: &MemoryFs, cache: ProbeCache) -> RepairReport {
+    Repairer::new(fs, fs, RepairOptions::default())
+        .with_probe_cache(cache)
+        .repair_directory(Path::new("/mnt/share"), true)
+        .unwrap()
+}
+
+fn outcome<'a>(report: &'a RepairReport, path: &str) -> &'a FileOutcome {
+    &report
+        .files
+        .iter()
+        .find(|file| file.path == Path::new(path))
+        .unwrap()
+        .outcome
+}
+
+#[test]
+fn unchanged_unlocked_files_are_not_probed_again() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache_path = dir.path().join("probes.json");
+    let fs = MemoryFs::new();
+    fs.add_file("/mnt/share/idle", b"idle");
+    fs.add_file("/mnt/share/nested/busy", b"busy");
+
+    let report = sweep(&fs, ProbeCache::open(&cache_path).unwrap());
+    assert_eq!(report.not_locked(), 2);
+    assert!(cache_path.exists());
+
+    // Probing fails from now on, so only files that are probed again fail
+    fs.fail(Operation::IsLocked, ErrorKind::Other);
+    fs.write("/mnt/share/nested/busy", b"busier");
+    let report = sweep(&fs, ProbeCache::open(&cache_path).unwrap());
+    assert!(matches!(
+        outcome(&report, "/mnt/share/idle"),
+        FileOutcome::NotLocked
+    ));
+    assert!(matches!(
+        outcome(&report, "/mnt/share/nested/busy"),
+        FileOutcome::Failed(_)
+    ));
+}
+
+#[test]
+fn replaced_file_is_probed_and_repaired() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache_path = dir.path().join("probes.json");
+    let fs = MemoryFs::new();
+    fs.add_file("/mnt/share/data.db", b"data");
+    sweep(&fs, ProbeCache::open(&cache_path).unwrap());
+
+    fs.add_locked_file("/mnt/share/data.db", b"data");
+    let report = sweep(&fs, ProbeCache::open(&cache_path).unwrap());
+    assert_eq!(report.repaired(), 1);
+}
+
+#[test]
+fn expired_entries_are_probed_again() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache_path = dir.path().join("probes.json");
+    let fs = MemoryFs::new();
+    fs.add_file("/mnt/share/data.db", b"data");
+    sweep(&fs, ProbeCache::open(&cache_path).unwrap());
+
+    fs.fail(Operation::IsLocked, ErrorKind::Other);
+    let cache = ProbeCache::open(&cache_path)
+        .unwrap()
+        .with_max_age(Duration::ZERO);
+    assert_eq!(sweep(&fs, cache).failed(), 1);
+}
+
+#[test]
+fn damaged_cache_is_reported() {
+    let dir = tempfile::tempdir().unwrap();
+    let cache_path = dir.path().join("probes.json");
+    std::fs::write(&cache_path, b"{\"version\": 1, \"files\": {").unwrap();
+
+    let err = ProbeCache::open(&cache_path).unwrap_err();
+    assert!(matches!(
+        err.kind(),
+        ErrorKind::InvalidData | ErrorKind::UnexpectedEof
+    ));
+}
@@ -0,0 +1,224 @@
+//! Repairs under randomly failing operations, checking that no failure path loses or truncates the
+//! original data or leaves a temporary copy behind. A failing seed is printed so the run can be
+//! replayed.
+
+use netfs_unlker::backend::{Acl, AclKind};
+use netfs_unlker::mock::{Chaos, MemoryFs, Operation};
+use netfs_unlker::strategy::CopyStrategy;
+use netfs_unlker::{RepairOptions, Repairer};
+use std::path::Path;
+
+/// Number of seeds run per configuration.
+const SEEDS: u64 = 200;
+
+/// Files of the share, locked and unlocked, of different sizes including an empty one.
+fn share() -> (MemoryFs, Vec<(String, Vec<u8>)>) {
+    let fs = MemoryFs::new();
+    let files: Vec<(String, Vec<u8>)> = (0..8)
+        .map(|i| {
+            let path = format!("/mnt/share/{}/file{}.db", i % 3, i);
+            let data = (0..i * 97).map(|b| (b % 251) as u8).collect();
+            (path, data)
+        })
+        .collect();
+    for (i, (path, data)) in files.iter().enumerate() {
+        match i % 4 {
+            3 => fs.add_file(path, data),
+            _ => fs.add_locked_file(path, data),
+        }
+        if i % 2 == 0 {
+            fs.set_acl(
+                path,
+                Acl {
+                    kind: AclKind::Posix,
+                    data: vec![i as u8; 28],
+                },
+            );
+        }
+    }
+    (fs, files)
+}
+
+fn options(copy_strategy: CopyStrategy) -> RepairOptions {
+    RepairOptions {
+        verify_checksum: true,
+        copy_strategy,
+        ..RepairOptions::default()
+    }
+}
+
+/// Sweeps the share once under `chaos`, then again without, and checks every file after both.
+fn assert_originals_survive(chaos: impl Fn(u64) -> Chaos, copy_strategy: CopyStrategy) {
+    let mut failed = 0;
+    for seed in 0..SEEDS {
+        let (fs, files) = share();
+        fs.set_chaos(chaos(seed));
+        let repairer = Repairer::new(&fs, &fs, options(copy_strategy));
+        if let Ok(report) = repairer.repair_directory(Path::new("/mnt/share"), true) {
+            failed += report.failed() + report.unverified();
+        }
+        assert_intact(&fs, &files, seed);
+
+        fs.clear_failures();
+        let report = repairer
+            .repair_directory(Path::new("/mnt/share"), true)
+            .unwrap();
+        assert_eq!(report.failed(), 0, "seed {}: {:?}", seed, report.files);
+        assert_intact(&fs, &files, seed);
+    }
+    assert!(failed > 0, "no repair failed, the chaos is too tame");
+}
+
+/// Sweeps the share once under `chaos`, which must not fail removals, and checks that no failed
+/// attempt left its temporary copy next to the original.
+fn assert_no_temp_files_survive(chaos: impl Fn(u64) -> Chaos, copy_strategy: CopyStrategy) {
+    let mut failed = 0;
+    for seed in 0..SEEDS {
+        let (fs, files) = share();
+        fs.set_chaos(chaos(seed));
+        let repairer = Repairer::new(&fs, &fs, options(copy_strategy));
+        if let Ok(report) = repairer.repair_directory(Path::new("/mnt/share"), true) {
+            failed += report.failed() + report.unverified();
+        }
+        let temp_files: Vec<_> = fs
+            .paths()
+            .into_iter()
+            .filter(|path| path.to_string_lossy().contains(".netfs-unlker."))
+            .collect();
+        assert!(temp_files.is_empty(), "seed {}: {:?}", seed, temp_files);
+        assert_intact(&fs, &files, seed);
+    }
+    assert!(failed > 0, "no repair failed, the chaos is too tame");
+}
+
+fn assert_intact(fs: &MemoryFs, files: &[(String, Vec<u8>)], seed: u64) {
+    for (path, data) in files {
+        match fs.contents(path) {
+            Some(contents) => assert!(
+                contents == *data,
+                "seed {}: {} has {} bytes instead of {}",
+                seed,
+                path,
+                contents.len(),
+                data.len()
+            ),
+            None => panic!("seed {}: {} is lost", seed, path),
+        }
+    }
+}
+
+#[test]
+fn failing_copy_keeps_originals() {
+    assert_originals_survive(
+        |seed| Chaos::new(seed).fail(Operation::Copy, 0.3),
+        CopyStrategy::SameDirectory,
+    );
+}
+
+#[test]
+fn failing_copy_back_keeps_originals() {
+    // The first copy stages the file, the second one places it next to the original
+    assert_originals_survive(
+        |seed| {
+            Chaos::new(seed)
+                .fail(Operation::Copy, 0.3)
+                .fail(Operation::WriteAcl, 0.3)
+        },
+        CopyStrategy::Move,
+    );
+}
+
+#[test]
+fn failing_rename_keeps_originals() {
+    assert_originals_survive(
+        |seed| Chaos::new(seed).fail(Operation::Rename, 0.3),
+        CopyStrategy::SameDirectory,
+    );
+}
+
+#[test]
+fn failing_sync_keeps_originals() {
+    assert_originals_survive(
+        |seed| Chaos::new(seed).fail(Operation::Sync, 0.3),
+        CopyStrategy::SameDirectory,
+    );
+}
+
+#[test]
+fn failed_attempts_leave_no_temp_files() {
+    for copy_strategy in [
+        CopyStrategy::SameDirectory,
+        CopyStrategy::Move,
+        CopyStrategy::Auto {
+            in_memory_threshold: 400,
+        },
+    ] {
+        assert_no_temp_files_survive(
+            |seed| {
+                Chaos::new(seed)
+                    .fail(Operation::Copy, 0.2)
+                    .fail(Operation::Write, 0.2)
+                    .fail(Operation::Sync, 0.2)
+                    .fail(Operation::Rename, 0.2)
+                    .fail(Operation::WriteAcl, 0.2)
+            },
+            copy_strategy,
+        );
+    }
+}
+
+#[test]
+fn failing_verification_keeps_originals() {
+    assert_originals_survive(
+        |seed| {
+            Chaos::new(seed)
+                .fail(Operation::Open, 0.3)
+                .fail(Operation::ReadAcl, 0.3)
+                .fail(Operation::IsLocked, 0.1)
+        },
+        CopyStrategy::SameDirectory,
+    );
+}
+
+#[test]
+fn failing_in_memory_repairs_keep_originals() {
+    assert_originals_survive(
+        |seed| {
+            Chaos::new(seed)
+                .fail(Operation::Write, 0.3)
+                .fail(Operation::Open, 0.2)
+        },
+        CopyStrategy::Auto {
+            in_memory_threshold: 400,
+        },
+    );
+}
+
+#[test]
+fn failing_anything_keeps_originals() {
+    for copy_strategy in [
+        CopyStrategy::SameDirectory,
+        CopyStrategy::Move,
+        CopyStrategy::Auto {
+            in_memory_threshold: 400,
+        },
+    ] {
+        assert_originals_survive(|seed| Chaos::new(seed).fail_all(0.05), copy_strategy);
+    }
+}
+
+#[test]
+fn same_seed_fails_the_same_calls() {
+    let outcomes = |seed| {
+        let (fs, _) = share();
+        fs.set_chaos(Chaos::new(seed).fail_all(0.1));
+        Repairer::new(&fs, &fs, options(CopyStrategy::default()))
+            .repair_directory(Path::new("/mnt/share"), true)
+            .map(|report| {
+                let outcomes: Vec<_> = report.files.iter().map(|file| &file.outcome).collect();
+                format!("{:?}", outcomes)
+            })
+            .map_err(|e| e.to_string())
+    };
+    assert_eq!(outcomes(7), outcomes(7));
+}
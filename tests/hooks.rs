@@ -0,0 +1,96 @@
+use netfs_unlker::backend::LockOps;
+use netfs_unlker::hooks::Hooks;
+use netfs_unlker::mock::{MemoryFs, Operation};
+use netfs_unlker::{FileOutcome, RepairError, RepairOptions, Repairer};
+use std::io::{self, ErrorKind};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Default)]
+struct Recorder {
+    calls: Arc<Mutex<Vec<String>>>,
+    fail_pre: bool,
+}
+
+impl Hooks for Recorder {
+    fn pre_repair(&self, path: &Path) -> io::Result<()> {
+        self.calls
+            .lock()
+            .unwrap()
+            .push(format!("pre {}", path.display()));
+        match self.fail_pre {
+            true => Err(io::Error::other("service did not stop")),
+            false => Ok(()),
+        }
+    }
+
+    fn post_repair(&self, path: &Path, outcome: &FileOutcome) -> io::Result<()> {
+        let outcome = match outcome {
+            FileOutcome::Failed(_) => "failed",
+            _ => "ok",
+        };
+        self.calls
+            .lock()
+            .unwrap()
+            .push(format!("post {} {}", path.display(), outcome));
+        Ok(())
+    }
+}
+
+#[test]
+fn hooks_run_around_locked_files_only() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/a", b"a");
+    fs.add_file("/mnt/share/b", b"b");
+    let hooks = Recorder::default();
+
+    Repairer::new(&fs, &fs, RepairOptions::default())
+        .with_hooks(hooks.clone())
+        .repair_directory(Path::new("/mnt/share"), false)
+        .unwrap();
+
+    assert_eq!(
+        *hooks.calls.lock().unwrap(),
+        ["pre /mnt/share/a", "post /mnt/share/a ok"]
+    );
+}
+
+#[test]
+fn failing_pre_hook_aborts_the_repair() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/a", b"a");
+    let hooks = Recorder {
+        fail_pre: true,
+        ..Recorder::default()
+    };
+
+    let report = Repairer::new(&fs, &fs, RepairOptions::default())
+        .with_hooks(hooks.clone())
+        .repair_file(Path::new("/mnt/share/a"))
+        .unwrap();
+
+    assert!(matches!(
+        report.files[0].outcome,
+        FileOutcome::Failed(RepairError::PreHook(_))
+    ));
+    assert!(fs.is_locked(Path::new("/mnt/share/a")).unwrap());
+    assert_eq!(*hooks.calls.lock().unwrap(), ["pre /mnt/share/a"]);
+}
+
+#[test]
+fn post_hook_runs_after_failed_repair() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/a", b"a");
+    fs.fail(Operation::Rename, ErrorKind::Other);
+    let hooks = Recorder::default();
+
+    Repairer::new(&fs, &fs, RepairOptions::default())
+        .with_hooks(hooks.clone())
+        .repair_file(Path::new("/mnt/share/a"))
+        .unwrap();
+
+    assert_eq!(
+        *hooks.calls.lock().unwrap(),
+        ["pre /mnt/share/a", "post /mnt/share/a failed"]
+    );
+}
@@ -0,0 +1,436 @@
+#![cfg(unix)]
+
+use netfs_unlker::backend::{LockOps, LockType, NativeFs, NativeLocks};
+use netfs_unlker::locks::{self, FileLockGuard, TryLock};
+use netfs_unlker::scan::Scanner;
+use netfs_unlker::{FileOutcome, RepairOptions, Repairer};
+use std::env;
+use std::fs::{self, File, Permissions};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::time::{Duration, Instant};
+
+/// Environment variables handing the lock to take over to the `lock_holder` helper process.
+const HOLDER_PATH: &str = "NETFS_UNLKER_TEST_LOCK_PATH";
+const HOLDER_RANGE: &str = "NETFS_UNLKER_TEST_LOCK_RANGE";
+const HOLDER_TYPE: &str = "NETFS_UNLKER_TEST_LOCK_TYPE";
+
+/// Lock holder run in a child process, as record locks never conflict within one process.
+///
+/// Takes the lock described by the environment, reports it on stdout and holds it until stdin is
+/// closed. Does nothing when run as a regular test.
+#[test]
+fn lock_holder() {
+    let Ok(path) = env::var(HOLDER_PATH) else {
+        return;
+    };
+    let (start, len) = match env::var(HOLDER_RANGE) {
+        Ok(range) => {
+            let (start, len) = range.split_once(':').unwrap();
+            (start.parse().unwrap(), len.parse().unwrap())
+        }
+        Err(_) => (0, 0),
+    };
+    let l_type = match env::var(HOLDER_TYPE).as_deref() {
+        Ok("shared") => libc::F_RDLCK,
+        _ => libc::F_WRLCK,
+    };
+
+    // Shared locks only need the file to be readable, so read-only files can be locked too
+    let file = File::options()
+        .read(true)
+        .write(l_type == libc::F_WRLCK)
+        .open(path)
+        .unwrap();
+    let fl = libc::flock {
+        l_whence: 0,
+        l_start: start,
+        l_len: len,
+        l_type: l_type as i16,
+        l_pid: 0,
+    };
+    assert_eq!(
+        unsafe { libc::fcntl(file.as_raw_fd(), libc::F_SETLK, &fl) },
+        0
+    );
+
+    println!("lock held");
+    std::io::stdout().flush().unwrap();
+    let mut line = String::new();
+    let _ = std::io::stdin().read_line(&mut line);
+}
+
+/// Child process holding a lock on a file; the lock is released when it is dropped.
+struct Holder {
+    child: Child,
+    // Kept open so that the harness of the child can still report its result
+    _stdout: BufReader<ChildStdout>,
+}
+
+impl Holder {
+    fn spawn(path: &Path, range: Option<(i64, i64)>, lock_type: LockType) -> Self {
+        let mut command = Command::new(env::current_exe().unwrap());
+        command
+            .args(["--exact", "lock_holder", "--nocapture", "--test-threads=1"])
+            .env(HOLDER_PATH, path)
+            .env(
+                HOLDER_TYPE,
+                match lock_type {
+                    LockType::Shared => "shared",
+                    LockType::Exclusive => "exclusive",
+                },
+            )
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped());
+        if let Some((start, len)) = range {
+            command.env(HOLDER_RANGE, format!("{}:{}", start, len));
+        }
+
+        let mut child = command.spawn().unwrap();
+        let mut stdout = BufReader::new(child.stdout.take().unwrap());
+        let mut line = String::new();
+        while stdout.read_line(&mut line).unwrap() > 0 {
+            // The harness prints the test name on the same line
+            if line.trim_end().ends_with("lock held") {
+                return Holder {
+                    child,
+                    _stdout: stdout,
+                };
+            }
+            line.clear();
+        }
+        let status = child.wait().unwrap();
+        panic!("lock holder exited before taking the lock: {}", status);
+    }
+
+    fn pid(&self) -> u32 {
+        self.child.id()
+    }
+
+    fn release(mut self) {
+        drop(self.child.stdin.take());
+        self.child.wait().unwrap();
+    }
+}
+
+impl Drop for Holder {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn data_file(dir: &Path) -> (std::path::PathBuf, File) {
+    let path = dir.join("data.db");
+    fs::write(&path, vec![0u8; 4096]).unwrap();
+    let file = File::options().read(true).write(true).open(&path).unwrap();
+    (path, file)
+}
+
+#[test]
+fn lock_held_by_another_process_is_reported_with_its_holder() {
+    let dir = tempfile::tempdir().unwrap();
+    let (path, file) = data_file(dir.path());
+    assert_eq!(locks::get_lock_info(&file).unwrap(), None);
+    assert!(!locks::is_file_locked(&file));
+
+    let holder = Holder::spawn(&path, None, LockType::Exclusive);
+    assert!(locks::is_file_locked(&file));
+
+    let info = locks::get_lock_info(&file).unwrap().unwrap();
+    assert_eq!(info.lock_type, LockType::Exclusive);
+    assert_eq!((info.start, info.len), (0, None));
+    assert_eq!(info.pid, Some(holder.pid()));
+
+    match locks::try_lock_exclusive(&file).unwrap() {
+        TryLock::Conflict(Some(lock)) => assert_eq!(lock.pid, Some(holder.pid())),
+        outcome => panic!("unexpected outcome: {:?}", outcome),
+    }
+    assert!(matches!(
+        locks::try_lock_shared(&file).unwrap(),
+        TryLock::Conflict(_)
+    ));
+
+    holder.release();
+    assert_eq!(locks::try_lock_exclusive(&file).unwrap(), TryLock::Acquired);
+    locks::unlock(&file).unwrap();
+}
+
+#[test]
+fn shared_locks_do_not_conflict() {
+    let dir = tempfile::tempdir().unwrap();
+    let (path, file) = data_file(dir.path());
+
+    let holder = Holder::spawn(&path, None, LockType::Shared);
+    assert_eq!(locks::try_lock_shared(&file).unwrap(), TryLock::Acquired);
+    assert!(matches!(
+        locks::try_lock_exclusive(&file).unwrap(),
+        TryLock::Conflict(_)
+    ));
+
+    locks::unlock(&file).unwrap();
+    holder.release();
+}
+
+#[test]
+fn lock_with_timeout_gives_up_or_waits_for_the_holder() {
+    let dir = tempfile::tempdir().unwrap();
+    let (path, file) = data_file(dir.path());
+
+    let holder = Holder::spawn(&path, None, LockType::Exclusive);
+    let started = Instant::now();
+    let outcome =
+        locks::lock_with_timeout(&file, LockType::Exclusive, Duration::from_millis(200)).unwrap();
+    assert!(matches!(outcome, TryLock::Conflict(_)));
+    assert!(started.elapsed() >= Duration::from_millis(200));
+
+    // Release the holder while a longer attempt is still retrying
+    let waiter = std::thread::spawn(move || {
+        let outcome =
+            locks::lock_with_timeout(&file, LockType::Exclusive, Duration::from_secs(10)).unwrap();
+        (outcome, file)
+    });
+    std::thread::sleep(Duration::from_millis(100));
+    holder.release();
+
+    let (outcome, file) = waiter.join().unwrap();
+    assert_eq!(outcome, TryLock::Acquired);
+    locks::unlock(&file).unwrap();
+}
+
+#[test]
+fn locked_ranges_are_listed_in_order() {
+    let dir = tempfile::tempdir().unwrap();
+    let (path, file) = data_file(dir.path());
+
+    let tail = Holder::spawn(&path, Some((2048, 0)), LockType::Exclusive);
+    let head = Holder::spawn(&path, Some((100, 50)), LockType::Shared);
+
+    let ranges = locks::get_locked_ranges(&file).unwrap();
+    let summary: Vec<_> = ranges
+        .iter()
+        .map(|lock| (lock.start, lock.len, lock.lock_type, lock.pid))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            (100, Some(50), LockType::Shared, Some(head.pid())),
+            (2048, None, LockType::Exclusive, Some(tail.pid())),
+        ]
+    );
+
+    head.release();
+    tail.release();
+    assert!(locks::get_locked_ranges(&file).unwrap().is_empty());
+}
+
+#[test]
+fn unlocking_a_range_does_not_release_the_locks_of_other_processes() {
+    let dir = tempfile::tempdir().unwrap();
+    let (path, file) = data_file(dir.path());
+    let holder = Holder::spawn(&path, Some((0, 1024)), LockType::Exclusive);
+
+    locks::unlock_range(&file, 0, Some(1024)).unwrap();
+    let ranges = locks::get_locked_ranges(&file).unwrap();
+    assert_eq!(ranges.len(), 1);
+    assert_eq!(ranges[0].pid, Some(holder.pid()));
+
+    assert_eq!(
+        locks::unlock_range(&file, u64::MAX, None)
+            .unwrap_err()
+            .kind(),
+        ErrorKind::InvalidInput
+    );
+    assert_eq!(
+        locks::unlock_range(&file, 0, Some(u64::MAX))
+            .unwrap_err()
+            .kind(),
+        ErrorKind::InvalidInput
+    );
+    holder.release();
+}
+
+#[test]
+fn releasing_ranges_falls_back_to_replacing_the_file() {
+    let dir = tempfile::tempdir().unwrap();
+    let (path, file) = data_file(dir.path());
+    let holder = Holder::spawn(&path, Some((0, 1024)), LockType::Exclusive);
+    let inode = fs::metadata(&path).unwrap().ino();
+
+    let report = Repairer::new(
+        NativeFs::default(),
+        NativeLocks::default(),
+        RepairOptions {
+            release_ranges: true,
+            allow_local: true,
+            force: true,
+            ..RepairOptions::default()
+        },
+    )
+    .repair_file(&path)
+    .unwrap();
+
+    assert!(
+        matches!(report.files[0].outcome, FileOutcome::Repaired),
+        "{:?}",
+        report.files[0].outcome
+    );
+    // The lock of the holder stays on the original, which was replaced by a copy
+    assert_ne!(fs::metadata(&path).unwrap().ino(), inode);
+    assert_eq!(locks::get_locked_ranges(&file).unwrap().len(), 1);
+    holder.release();
+}
+
+#[test]
+fn unlocked_range_can_be_locked_by_another_process() {
+    let dir = tempfile::tempdir().unwrap();
+    let (path, file) = data_file(dir.path());
+    assert_eq!(locks::try_lock_exclusive(&file).unwrap(), TryLock::Acquired);
+
+    // The holder fails to start unless the range is free again
+    locks::unlock_range(&file, 0, Some(1024)).unwrap();
+    let holder = Holder::spawn(&path, Some((0, 1024)), LockType::Exclusive);
+
+    let other = File::options().read(true).write(true).open(&path).unwrap();
+    let ranges = locks::get_locked_ranges(&other).unwrap();
+    assert_eq!(ranges.len(), 1);
+    assert_eq!(ranges[0].pid, Some(holder.pid()));
+
+    holder.release();
+}
+
+#[test]
+fn guard_is_refused_while_another_process_holds_the_lock() {
+    let dir = tempfile::tempdir().unwrap();
+    let (path, file) = data_file(dir.path());
+
+    let holder = Holder::spawn(&path, None, LockType::Shared);
+    let err = FileLockGuard::try_lock(&file, LockType::Exclusive).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::WouldBlock);
+    let guard = FileLockGuard::try_lock(&file, LockType::Shared).unwrap();
+    assert_eq!(guard.lock_type(), LockType::Shared);
+    drop(guard);
+
+    holder.release();
+    let guard =
+        FileLockGuard::lock_with_timeout(&file, LockType::Exclusive, Duration::from_secs(1))
+            .unwrap();
+    assert_eq!(guard.lock_type(), LockType::Exclusive);
+}
+
+/// Open file description locks conflict between descriptors of one process.
+#[cfg(target_os = "linux")]
+#[test]
+fn guard_is_released_when_dropped() {
+    let dir = tempfile::tempdir().unwrap();
+    let (path, file) = data_file(dir.path());
+    let other = File::options().read(true).write(true).open(&path).unwrap();
+
+    let lock = |file| -> std::io::Result<()> {
+        let _guard = FileLockGuard::try_lock(file, LockType::Exclusive)?;
+        assert_eq!(
+            FileLockGuard::try_lock(&other, LockType::Shared)
+                .unwrap_err()
+                .kind(),
+            ErrorKind::WouldBlock
+        );
+        Err(ErrorKind::Other.into())
+    };
+    // The early return of the closure releases the lock
+    assert!(lock(&file).is_err());
+    let _guard = FileLockGuard::try_lock(&other, LockType::Exclusive).unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn downgraded_guard_admits_shared_locks() {
+    let dir = tempfile::tempdir().unwrap();
+    let (path, file) = data_file(dir.path());
+    let other = File::options().read(true).write(true).open(&path).unwrap();
+
+    let mut guard = FileLockGuard::try_lock(&file, LockType::Exclusive).unwrap();
+    assert!(FileLockGuard::try_lock(&other, LockType::Shared).is_err());
+
+    guard.downgrade().unwrap();
+    assert_eq!(guard.lock_type(), LockType::Shared);
+    let shared = FileLockGuard::try_lock(&other, LockType::Shared).unwrap();
+    drop(shared);
+    assert!(FileLockGuard::try_lock(&other, LockType::Exclusive).is_err());
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn forgotten_guard_keeps_the_lock_until_the_file_is_closed() {
+    let dir = tempfile::tempdir().unwrap();
+    let (path, file) = data_file(dir.path());
+    let other = File::options().read(true).write(true).open(&path).unwrap();
+
+    FileLockGuard::try_lock(&file, LockType::Exclusive)
+        .unwrap()
+        .forget();
+    assert!(FileLockGuard::try_lock(&other, LockType::Shared).is_err());
+
+    drop(file);
+    let _guard = FileLockGuard::try_lock(&other, LockType::Exclusive).unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn fast_scan_probes_each_file_once_for_its_size_and_lock() {
+    let dir = tempfile::tempdir().unwrap();
+    let (path, _file) = data_file(dir.path());
+    fs::write(dir.path().join("idle"), b"idle").unwrap();
+    std::os::unix::fs::symlink(&path, dir.path().join("link")).unwrap();
+    let holder = Holder::spawn(&path, None, LockType::Exclusive);
+
+    let probe = NativeLocks::default().probe(&path).unwrap();
+    assert_eq!(probe.len, 4096);
+    assert_eq!(probe.lock.unwrap().pid, Some(holder.pid()));
+
+    let report = Scanner::new(NativeFs::default(), NativeLocks::default())
+        .scan_directory_fast(dir.path(), false, 2.try_into().unwrap())
+        .unwrap();
+    assert_eq!(report.scanned, 2);
+    assert!(report.errors.is_empty());
+    assert_eq!(report.locked.len(), 1);
+    assert_eq!(
+        (report.locked[0].path.as_path(), report.locked[0].size),
+        (path.as_path(), 4096)
+    );
+}
+
+#[test]
+fn read_only_file_is_repaired_and_flushed() {
+    let dir = tempfile::tempdir().unwrap();
+    let (path, file) = data_file(dir.path());
+    drop(file);
+    fs::set_permissions(&path, Permissions::from_mode(0o444)).unwrap();
+    let holder = Holder::spawn(&path, None, LockType::Shared);
+
+    let report = Repairer::new(
+        NativeFs::default(),
+        NativeLocks::default(),
+        RepairOptions {
+            allow_local: true,
+            force: true,
+            ..RepairOptions::default()
+        },
+    )
+    .repair_file(&path)
+    .unwrap();
+
+    assert!(
+        matches!(report.files[0].outcome, FileOutcome::Repaired),
+        "{:?}",
+        report.files[0].outcome
+    );
+    assert_eq!(fs::read(&path).unwrap(), vec![0u8; 4096]);
+    assert_eq!(
+        fs::metadata(&path).unwrap().permissions().mode() & 0o777,
+        0o444
+    );
+    holder.release();
+}
@@ -0,0 +1,122 @@
+use netfs_unlker::audit::{quarantined_files, JsonLinesAuditLog};
+use netfs_unlker::maintenance::Maintenance;
+use netfs_unlker::mock::{MemoryFs, Operation};
+use netfs_unlker::options::TempNaming;
+use netfs_unlker::{RepairOptions, Repairer};
+use std::io::ErrorKind;
+use std::path::Path;
+
+#[test]
+fn finds_leftovers_next_to_their_original() {
+    let fs = MemoryFs::new();
+    fs.add_file("/mnt/share/a", b"a");
+    fs.add_file("/mnt/share/.tmp.a", b"a");
+    fs.add_file("/mnt/share/.tmp.orphan", b"unrelated");
+    fs.add_file("/mnt/share/sub/b", b"b");
+    fs.add_file("/mnt/share/sub/.tmp.b", b"b");
+
+    let maintenance = Maintenance::new(&fs);
+    let mut leftovers = maintenance
+        .find_leftovers(Path::new("/mnt/share"), true)
+        .unwrap();
+    leftovers.sort();
+    assert_eq!(
+        leftovers,
+        [
+            Path::new("/mnt/share/.tmp.a"),
+            Path::new("/mnt/share/sub/.tmp.b")
+        ]
+    );
+
+    maintenance.remove_leftover(&leftovers[0]).unwrap();
+    assert!(fs.contents("/mnt/share/.tmp.a").is_none());
+    assert_eq!(fs.contents("/mnt/share/a").unwrap(), b"a");
+}
+
+#[test]
+fn finds_leftovers_of_the_configured_and_legacy_naming() {
+    let fs = MemoryFs::new();
+    fs.add_file("/mnt/share/a", b"a");
+    fs.add_file("/mnt/share/.netfs-unlker.a.tmp", b"a");
+    fs.add_file("/mnt/share/~a.part", b"a");
+    fs.add_file("/mnt/share/b", b"b");
+    fs.add_file("/mnt/share/.tmp.b", b"b");
+
+    let mut leftovers = Maintenance::new(&fs)
+        .find_leftovers(Path::new("/mnt/share"), false)
+        .unwrap();
+    leftovers.sort();
+    assert_eq!(
+        leftovers,
+        [
+            Path::new("/mnt/share/.netfs-unlker.a.tmp"),
+            Path::new("/mnt/share/.tmp.b")
+        ]
+    );
+
+    let mut leftovers = Maintenance::new(&fs)
+        .with_temp_naming(TempNaming::new("~", ".part"))
+        .find_leftovers(Path::new("/mnt/share"), false)
+        .unwrap();
+    leftovers.sort();
+    assert_eq!(
+        leftovers,
+        [
+            Path::new("/mnt/share/.tmp.b"),
+            Path::new("/mnt/share/~a.part")
+        ]
+    );
+}
+
+#[test]
+fn restores_quarantined_files_from_audit_log() {
+    let dir = tempfile::tempdir().unwrap();
+    let log = dir.path().join("audit.jsonl");
+
+    let fs = MemoryFs::new();
+    fs.add_dir("/mnt/quarantine");
+    fs.add_locked_file("/mnt/share/a", b"a");
+    fs.fail_path(Operation::Copy, "/mnt/share/a", ErrorKind::PermissionDenied);
+    Repairer::new(
+        &fs,
+        &fs,
+        RepairOptions {
+            quarantine_dir: Some("/mnt/quarantine".into()),
+            ..RepairOptions::default()
+        },
+    )
+    .with_audit_sink(JsonLinesAuditLog::open(&log).unwrap())
+    .repair_file(Path::new("/mnt/share/a"))
+    .unwrap();
+
+    let files = quarantined_files(&log).unwrap();
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0].destination, Path::new("/mnt/quarantine/a"));
+
+    let maintenance = Maintenance::new(&fs);
+    maintenance.restore(&files[0]).unwrap();
+    assert_eq!(fs.contents("/mnt/share/a").unwrap(), b"a");
+    assert!(fs.contents("/mnt/quarantine/a").is_none());
+    assert_eq!(
+        maintenance.restore(&files[0]).unwrap_err().kind(),
+        ErrorKind::NotFound
+    );
+}
+
+#[test]
+fn unreadable_subdirectory_does_not_stop_the_cleanup() {
+    let fs = MemoryFs::new();
+    fs.add_file("/mnt/share/a", b"a");
+    fs.add_file("/mnt/share/.tmp.a", b"a");
+    fs.add_file("/mnt/share/private/b", b"b");
+    fs.fail_path(
+        Operation::ReadDir,
+        "/mnt/share/private",
+        ErrorKind::PermissionDenied,
+    );
+
+    let leftovers = Maintenance::new(&fs)
+        .find_leftovers(Path::new("/mnt/share"), true)
+        .unwrap();
+    assert_eq!(leftovers, [Path::new("/mnt/share/.tmp.a")]);
+}
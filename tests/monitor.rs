@@ -0,0 +1,172 @@
+use netfs_unlker::backend::{LockKind, LockOps, LockType};
+use netfs_unlker::mock::MemoryFs;
+use netfs_unlker::monitor::{
+    read_history, top_offenders, LockHistory, LockSample, OffenderReport, SampledLock,
+};
+use netfs_unlker::scan::Scanner;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+const MINUTE: Duration = Duration::from_secs(60);
+
+/// Builds a sample taken `minutes` after the epoch that found `paths` locked.
+fn sample(minutes: u32, paths: &[&str]) -> LockSample {
+    LockSample {
+        timestamp: SystemTime::UNIX_EPOCH + MINUTE * minutes,
+        scanned: 10,
+        errors: 0,
+        locked: paths
+            .iter()
+            .map(|path| SampledLock {
+                path: PathBuf::from(path),
+                kind: LockKind::Record,
+                lock_type: LockType::Exclusive,
+                pid: None,
+            })
+            .collect(),
+    }
+}
+
+#[test]
+fn history_keeps_the_samples_of_scans() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/data.db", b"data");
+    fs.add_file("/mnt/share/idle.db", b"idle");
+    let scanner = Scanner::new(&fs, &fs);
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("locks.jsonl");
+    let history = LockHistory::open(&path).unwrap();
+
+    let start = SystemTime::now();
+    let report = scanner
+        .scan_directory(Path::new("/mnt/share"), true)
+        .unwrap();
+    let first = LockSample::from_report(&report, start);
+    history.record(&first).unwrap();
+    fs.unlock(Path::new("/mnt/share/data.db")).unwrap();
+    let report = scanner
+        .scan_directory(Path::new("/mnt/share"), true)
+        .unwrap();
+    let second = LockSample::from_report(&report, start + MINUTE);
+    history.record(&second).unwrap();
+
+    assert_eq!(first.scanned, 2);
+    assert_eq!(first.locked[0].path, Path::new("/mnt/share/data.db"));
+    assert!(second.locked.is_empty());
+    assert_eq!(read_history(&path, start).unwrap(), [first, second.clone()]);
+    assert_eq!(read_history(&path, start + MINUTE).unwrap(), [second]);
+}
+
+#[test]
+fn history_passes_over_damaged_lines() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("locks.jsonl");
+    let history = LockHistory::open(&path).unwrap();
+    history.record(&sample(0, &["/mnt/share/a"])).unwrap();
+    // A monitor killed while writing leaves a truncated line behind
+    let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+    writeln!(file, "{{\"timestamp\":").unwrap();
+    history.record(&sample(1, &["/mnt/share/b"])).unwrap();
+
+    let samples = read_history(&path, SystemTime::UNIX_EPOCH).unwrap();
+    assert_eq!(
+        samples,
+        [sample(0, &["/mnt/share/a"]), sample(1, &["/mnt/share/b"])]
+    );
+}
+
+#[test]
+fn ranks_the_files_locked_most_often() {
+    let samples = [
+        sample(0, &["/mnt/share/a", "/mnt/share/b"]),
+        sample(5, &["/mnt/share/a", "/mnt/share/b"]),
+        sample(10, &["/mnt/share/b", "/mnt/share/c"]),
+        sample(15, &["/mnt/share/a", "/mnt/share/c"]),
+        sample(20, &["/mnt/share/a"]),
+    ];
+
+    let offenders = top_offenders(&samples, 10);
+
+    let ranked: Vec<_> = offenders
+        .iter()
+        .map(|offender| (offender.path.to_str().unwrap(), offender.samples))
+        .collect();
+    assert_eq!(
+        ranked,
+        [
+            ("/mnt/share/a", 4),
+            ("/mnt/share/b", 3),
+            ("/mnt/share/c", 2)
+        ]
+    );
+    let a = &offenders[0];
+    assert_eq!(a.share, 0.8);
+    assert_eq!(a.first_seen, SystemTime::UNIX_EPOCH);
+    assert_eq!(a.last_seen, SystemTime::UNIX_EPOCH + MINUTE * 20);
+    // Locked from 0 to 5 and from 15 to 20
+    assert_eq!(a.longest_held, MINUTE * 5);
+    assert_eq!(offenders[1].longest_held, MINUTE * 10);
+    assert_eq!(top_offenders(&samples, 1).len(), 1);
+}
+
+#[test]
+fn ties_go_to_the_file_held_longest() {
+    let samples = [
+        sample(0, &["/mnt/share/a", "/mnt/share/b"]),
+        sample(1, &["/mnt/share/b"]),
+        sample(2, &["/mnt/share/a"]),
+    ];
+
+    let offenders = top_offenders(&samples, 10);
+
+    assert_eq!(offenders[0].path, Path::new("/mnt/share/b"));
+    assert_eq!(offenders[1].path, Path::new("/mnt/share/a"));
+    assert_eq!(offenders[1].longest_held, Duration::ZERO);
+}
+
+#[test]
+fn counts_a_file_locked_several_times_once_per_sample() {
+    let mut first = sample(0, &["/mnt/share/a"]);
+    first.locked.push(SampledLock {
+        path: PathBuf::from("/mnt/share/a"),
+        kind: LockKind::SmbLease,
+        lock_type: LockType::Shared,
+        pid: Some(42),
+    });
+    let samples = [first, sample(1, &[])];
+
+    let offenders = top_offenders(&samples, 10);
+
+    assert_eq!(offenders.len(), 1);
+    assert_eq!(offenders[0].samples, 1);
+    assert_eq!(offenders[0].share, 0.5);
+    assert_eq!(offenders[0].kinds, [LockKind::Record, LockKind::SmbLease]);
+    assert_eq!(offenders[0].pids, [42]);
+}
+
+#[test]
+fn writes_the_offender_report() {
+    let samples = [sample(0, &["/mnt/share/a"]), sample(60, &["/mnt/share/a"])];
+    let report = OffenderReport::new(&samples, SystemTime::UNIX_EPOCH, 10);
+
+    let mut text = Vec::new();
+    report.write_text(&mut text).unwrap();
+    let text = String::from_utf8(text).unwrap();
+    assert!(text.contains("1h"), "{}", text);
+    assert!(text.contains("/mnt/share/a"), "{}", text);
+    assert!(
+        text.ends_with("2 samples since 1970-01-01T00:00:00Z\n"),
+        "{}",
+        text
+    );
+
+    let mut json = Vec::new();
+    report.write_json(&mut json).unwrap();
+    let json: serde_json::Value = serde_json::from_slice(&json).unwrap();
+    assert_eq!(json["samples"], 2);
+    assert_eq!(json["offenders"][0]["path"], "/mnt/share/a");
+    assert_eq!(json["offenders"][0]["longest_held_secs"], 3600.0);
+    assert_eq!(json["offenders"][0]["kinds"][0], "record");
+}
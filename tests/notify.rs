@@ -0,0 +1,76 @@
+#![cfg(feature = "webhook")]
+
+use netfs_unlker::notify::{WebhookConfig, WebhookNotifier};
+use netfs_unlker::ReportSummary;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::thread;
+
+/// Accepts a single HTTP request, answers it with `204 No Content` and returns its body.
+fn serve_once(listener: TcpListener) -> thread::JoinHandle<String> {
+    thread::spawn(move || {
+        let (stream, _) = listener.accept().unwrap();
+        let mut reader = BufReader::new(stream);
+        let mut length = 0;
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            if line == "\r\n" {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    length = value.trim().parse().unwrap();
+                }
+            }
+        }
+
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).unwrap();
+        reader
+            .get_mut()
+            .write_all(b"HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        String::from_utf8(body).unwrap()
+    })
+}
+
+#[test]
+fn posts_templated_summary() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let mut config = WebhookConfig::new(format!("http://{}/", listener.local_addr().unwrap()));
+    config.template =
+        Some(r#"{"text": "{{target}}: {{repaired}} repaired, {{failed}} failed"}"#.to_string());
+    let server = serve_once(listener);
+
+    let summary = ReportSummary {
+        total: 3,
+        repaired: 2,
+        failed: 1,
+        ..Default::default()
+    };
+    let notifier = WebhookNotifier::new(config).unwrap();
+    assert!(notifier.should_notify(&summary));
+    notifier.notify("/mnt/\"share\"", &summary).unwrap();
+
+    let body: serde_json::Value = serde_json::from_str(&server.join().unwrap()).unwrap();
+    assert_eq!(body["text"], "/mnt/\"share\": 2 repaired, 1 failed");
+}
+
+#[test]
+fn skips_runs_below_threshold() {
+    let mut config = WebhookConfig::new("http://127.0.0.1:9/");
+    config.repaired_threshold = 5;
+    let notifier = WebhookNotifier::new(config).unwrap();
+
+    let quiet = ReportSummary {
+        total: 10,
+        repaired: 5,
+        ..Default::default()
+    };
+    assert!(!notifier.should_notify(&quiet));
+    assert!(notifier.should_notify(&ReportSummary {
+        unverified: 1,
+        ..quiet
+    }));
+}
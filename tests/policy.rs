@@ -0,0 +1,243 @@
+use netfs_unlker::mock::MemoryFs;
+use netfs_unlker::policy::{glob_match, Policy, POLICY_FILE_NAME};
+use netfs_unlker::{FileOutcome, RepairError, RepairOptions, RepairReport, Repairer, SkipReason};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+fn add_policy(fs: &MemoryFs, directory: &str, content: &str) {
+    fs.add_file(
+        Path::new(directory).join(POLICY_FILE_NAME),
+        content.as_bytes(),
+    );
+}
+
+fn outcome<'a>(report: &'a RepairReport, path: &str) -> Option<&'a FileOutcome> {
+    report
+        .files
+        .iter()
+        .find(|file| file.path == Path::new(path))
+        .map(|file| &file.outcome)
+}
+
+#[test]
+fn parses_policy_file() {
+    let policy = Policy::parse(
+        r#"
+        # Databases only
+        include = ["*.db", '*.sqlite'] # trailing comment
+        exclude = [
+            "scratch-*",
+        ]
+        force = true
+        backup = false
+        backup_dir = "backups"
+        "#,
+        Path::new("/mnt/share/team"),
+    )
+    .unwrap();
+
+    assert_eq!(
+        policy,
+        Policy {
+            include: Some(vec!["*.db".into(), "*.sqlite".into()]),
+            exclude: Some(vec!["scratch-*".into()]),
+            force: Some(true),
+            backup: Some(false),
+            backup_dir: Some(PathBuf::from("/mnt/share/team/backups")),
+        }
+    );
+}
+
+#[test]
+fn rejects_invalid_policy_file() {
+    for content in [
+        "unknown = true",
+        "force = \"yes\"",
+        "include = \"*.db\"",
+        "[table]",
+        "force = true true",
+        "exclude = [\"a\"",
+    ] {
+        let e = Policy::parse(content, Path::new("/")).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData, "{}", content);
+    }
+
+    let e = Policy::parse("force = true\n\nbackup = 1", Path::new("/")).unwrap_err();
+    assert!(e.to_string().starts_with("line 3:"), "{}", e);
+}
+
+#[test]
+fn matches_shell_patterns() {
+    assert!(glob_match("*.db", "data.db"));
+    assert!(glob_match("*.db", ".db"));
+    assert!(!glob_match("*.db", "data.db.bak"));
+    assert!(glob_match("data-?.*", "data-1.csv"));
+    assert!(!glob_match("data-?.*", "data-10"));
+    assert!(glob_match("*a*b*", "xxaxxbxx"));
+    assert!(glob_match("*", ""));
+}
+
+#[test]
+fn policy_file_overrides_patterns_for_its_subtree() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/data.db", b"data");
+    fs.add_locked_file("/mnt/share/notes.txt", b"notes");
+    fs.add_locked_file("/mnt/share/team/data.db", b"data");
+    fs.add_locked_file("/mnt/share/team/notes.txt", b"notes");
+    fs.add_locked_file("/mnt/share/team/deep/notes.txt", b"notes");
+    add_policy(&fs, "/mnt/share/team", "include = [\"*.txt\"]");
+
+    let options = RepairOptions {
+        include: vec!["*.db".into()],
+        ..RepairOptions::default()
+    };
+    let report = Repairer::new(&fs, &fs, options)
+        .repair_directory(Path::new("/mnt/share"), true)
+        .unwrap();
+
+    let mut repaired: Vec<_> = report
+        .files
+        .iter()
+        .map(|file| file.path.to_str().unwrap())
+        .collect();
+    repaired.sort();
+    assert_eq!(
+        repaired,
+        [
+            "/mnt/share/data.db",
+            "/mnt/share/team/deep/notes.txt",
+            "/mnt/share/team/notes.txt"
+        ]
+    );
+    assert_eq!(report.repaired(), 3);
+}
+
+#[test]
+fn nested_policy_file_inherits_unset_settings() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/a/b/keep.db", b"keep");
+    fs.add_locked_file("/mnt/share/a/b/tmp.db", b"tmp");
+    fs.set_holder_alive("/mnt/share/a/b/keep.db");
+    fs.set_holder_alive("/mnt/share/a/b/tmp.db");
+    add_policy(&fs, "/mnt/share/a", "force = true");
+    add_policy(&fs, "/mnt/share/a/b", "exclude = [\"tmp*\"]");
+
+    let report = Repairer::new(&fs, &fs, RepairOptions::default())
+        .repair_directory(Path::new("/mnt/share"), true)
+        .unwrap();
+
+    assert_eq!(report.files.len(), 1);
+    assert!(matches!(
+        outcome(&report, "/mnt/share/a/b/keep.db"),
+        Some(FileOutcome::Repaired)
+    ));
+}
+
+#[test]
+fn policy_file_can_turn_force_off() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/live/data.db", b"data");
+    fs.set_holder_alive("/mnt/share/live/data.db");
+    add_policy(&fs, "/mnt/share/live", "force = false");
+
+    let options = RepairOptions {
+        force: true,
+        ..RepairOptions::default()
+    };
+    let report = Repairer::new(&fs, &fs, options)
+        .repair_directory(Path::new("/mnt/share"), true)
+        .unwrap();
+
+    assert!(matches!(
+        outcome(&report, "/mnt/share/live/data.db"),
+        Some(FileOutcome::Skipped(SkipReason::LockHolderAlive))
+    ));
+}
+
+#[test]
+fn backs_up_files_before_replacing_them() {
+    let fs = MemoryFs::new();
+    fs.add_dir("/backups/team");
+    fs.add_locked_file("/mnt/share/data.db", b"data");
+    fs.add_locked_file("/mnt/share/team/data.db", b"team data");
+    fs.add_locked_file("/mnt/share/scratch/data.db", b"scratch");
+    add_policy(&fs, "/mnt/share/team", "backup_dir = \"/backups/team\"");
+    add_policy(&fs, "/mnt/share/scratch", "backup = false");
+
+    let options = RepairOptions {
+        backup_dir: Some(PathBuf::from("/backups")),
+        ..RepairOptions::default()
+    };
+    let report = Repairer::new(&fs, &fs, options)
+        .repair_directory(Path::new("/mnt/share"), true)
+        .unwrap();
+
+    assert_eq!(report.repaired(), 3);
+    let mut backups: Vec<_> = fs
+        .paths()
+        .into_iter()
+        .filter(|path| path.starts_with("/backups") && fs.contents(path).is_some())
+        .collect();
+    backups.sort();
+    assert_eq!(
+        backups,
+        [
+            Path::new("/backups/data.db"),
+            Path::new("/backups/team/data.db")
+        ]
+    );
+    assert_eq!(fs.contents("/backups/data.db").unwrap(), b"data");
+    assert_eq!(fs.contents("/backups/team/data.db").unwrap(), b"team data");
+    assert_eq!(
+        fs.contents("/mnt/share/team/data.db").unwrap(),
+        b"team data"
+    );
+}
+
+#[test]
+fn failed_backup_keeps_original() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/data.db", b"data");
+
+    // The backup directory does not exist
+    let options = RepairOptions {
+        backup_dir: Some(PathBuf::from("/missing")),
+        ..RepairOptions::default()
+    };
+    let report = Repairer::new(&fs, &fs, options)
+        .repair_directory(Path::new("/mnt/share"), true)
+        .unwrap();
+
+    assert!(matches!(
+        outcome(&report, "/mnt/share/data.db"),
+        Some(FileOutcome::Failed(RepairError::Io(_)))
+    ));
+    assert_eq!(fs.contents("/mnt/share/data.db").unwrap(), b"data");
+    assert!(fs
+        .paths()
+        .iter()
+        .all(|path| !path.to_string_lossy().contains(".netfs-unlker.")));
+}
+
+#[test]
+fn invalid_policy_file_passes_over_its_subtree() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/data.db", b"data");
+    fs.add_locked_file("/mnt/share/team/data.db", b"data");
+    fs.add_locked_file("/mnt/share/team/deep/data.db", b"data");
+    add_policy(&fs, "/mnt/share/team", "force = maybe");
+
+    let report = Repairer::new(&fs, &fs, RepairOptions::default())
+        .repair_directory(Path::new("/mnt/share"), true)
+        .unwrap();
+
+    assert_eq!(report.files.len(), 2);
+    assert!(matches!(
+        outcome(&report, "/mnt/share/data.db"),
+        Some(FileOutcome::Repaired)
+    ));
+    assert!(matches!(
+        outcome(&report, "/mnt/share/team/.netfs-unlker.toml"),
+        Some(FileOutcome::Failed(RepairError::InvalidPolicy(_)))
+    ));
+}
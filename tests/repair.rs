@@ -0,0 +1,996 @@
+use netfs_unlker::backend::{
+    Acl, AclKind, FileOps, HolderStatus, LockKind, LockOps, LockStatusQuery,
+};
+use netfs_unlker::mock::{MemoryFs, Operation};
+use netfs_unlker::options::{SortOrder, Target};
+use netfs_unlker::progress::ProgressEvent;
+use netfs_unlker::scan::Scanner;
+use netfs_unlker::strategy::CopyStrategy;
+use netfs_unlker::{FileOutcome, RepairError, RepairOptions, RepairReport, Repairer, SkipReason};
+use std::cell::RefCell;
+use std::ffi::OsStr;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+fn repairer(fs: &MemoryFs) -> Repairer<&MemoryFs, &MemoryFs> {
+    Repairer::new(
+        fs,
+        fs,
+        RepairOptions {
+            verify_checksum: true,
+            ..RepairOptions::default()
+        },
+    )
+}
+
+fn leftovers(fs: &MemoryFs) -> Vec<String> {
+    fs.paths()
+        .into_iter()
+        .map(|p| p.to_string_lossy().into_owned())
+        .filter(|p| p.contains(".netfs-unlker.") || p.starts_with("/.staging"))
+        .collect()
+}
+
+#[test]
+fn repairs_locked_file_and_keeps_content() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/data.db", b"important");
+
+    let report = repairer(&fs)
+        .repair_file(Path::new("/mnt/share/data.db"))
+        .unwrap();
+
+    assert!(matches!(report.files[0].outcome, FileOutcome::Repaired));
+    assert_eq!(fs.contents("/mnt/share/data.db").unwrap(), b"important");
+    assert!(!fs.is_locked(Path::new("/mnt/share/data.db")).unwrap());
+    assert!(leftovers(&fs).is_empty());
+}
+
+#[test]
+fn leaves_unlocked_file_alone() {
+    let fs = MemoryFs::new();
+    fs.add_file("/mnt/share/data.db", b"important");
+
+    let report = repairer(&fs)
+        .repair_file(Path::new("/mnt/share/data.db"))
+        .unwrap();
+
+    assert!(matches!(report.files[0].outcome, FileOutcome::NotLocked));
+    assert_eq!(report.repaired(), 0);
+}
+
+#[test]
+fn missing_file_is_an_error() {
+    let fs = MemoryFs::new();
+
+    assert!(repairer(&fs)
+        .repair_file(Path::new("/mnt/share/missing"))
+        .is_err());
+}
+
+#[test]
+fn directory_sweep_respects_recursion() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/a", b"a");
+    fs.add_file("/mnt/share/b", b"b");
+    fs.add_locked_file("/mnt/share/nested/c", b"c");
+
+    let report = repairer(&fs)
+        .repair_directory(Path::new("/mnt/share"), false)
+        .unwrap();
+    assert_eq!(report.repaired(), 1);
+    assert_eq!(report.not_locked(), 1);
+    assert_eq!(report.skipped(), 1);
+    assert!(fs.is_locked(Path::new("/mnt/share/nested/c")).unwrap());
+
+    let report = repairer(&fs)
+        .repair_directory(Path::new("/mnt/share"), true)
+        .unwrap();
+    assert_eq!(report.repaired(), 1);
+    assert_eq!(report.not_locked(), 2);
+    assert!(!fs.is_locked(Path::new("/mnt/share/nested/c")).unwrap());
+}
+
+#[test]
+fn directory_entry_is_skipped_without_recursion() {
+    let fs = MemoryFs::new();
+    fs.add_dir("/mnt/share/nested");
+
+    let report = repairer(&fs)
+        .repair_directory(Path::new("/mnt/share"), false)
+        .unwrap();
+
+    assert!(matches!(
+        report.files[0].outcome,
+        FileOutcome::Skipped(SkipReason::NotAFile)
+    ));
+}
+
+#[test]
+fn failure_is_recorded_and_sweep_continues() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/a", b"a");
+    fs.add_locked_file("/mnt/share/b", b"b");
+    fs.fail_path(Operation::Copy, "/mnt/share/a", ErrorKind::PermissionDenied);
+
+    let report = repairer(&fs)
+        .repair_directory(Path::new("/mnt/share"), false)
+        .unwrap();
+
+    assert_eq!(report.failed(), 1);
+    assert_eq!(report.repaired(), 1);
+    assert!(matches!(
+        &report.files[0].outcome,
+        FileOutcome::Failed(RepairError::Io(e)) if e.kind() == ErrorKind::PermissionDenied
+    ));
+    assert_eq!(fs.contents("/mnt/share/a").unwrap(), b"a");
+    assert!(leftovers(&fs).is_empty());
+}
+
+#[test]
+fn failed_rename_keeps_original() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/a", b"original");
+    fs.fail(Operation::Rename, ErrorKind::Other);
+
+    let report = repairer(&fs)
+        .repair_file(Path::new("/mnt/share/a"))
+        .unwrap();
+
+    assert_eq!(report.failed(), 1);
+    assert_eq!(fs.contents("/mnt/share/a").unwrap(), b"original");
+    assert!(fs.is_locked(Path::new("/mnt/share/a")).unwrap());
+}
+
+#[test]
+fn unreadable_result_is_reported_as_unverified() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/a", b"original");
+    fs.fail_path(Operation::Open, "/mnt/share/a", ErrorKind::Other);
+
+    let report = repairer(&fs)
+        .repair_file(Path::new("/mnt/share/a"))
+        .unwrap();
+
+    assert_eq!(report.unverified(), 1);
+    assert_eq!(fs.contents("/mnt/share/a").unwrap(), b"original");
+}
+
+#[test]
+fn directory_sweep_applies_size_filters() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/small", b"a");
+    fs.add_locked_file("/mnt/share/large", b"a large file");
+
+    let report = Repairer::new(
+        &fs,
+        &fs,
+        RepairOptions {
+            min_size: Some(2),
+            ..RepairOptions::default()
+        },
+    )
+    .repair_directory(Path::new("/mnt/share"), false)
+    .unwrap();
+
+    assert_eq!(report.files.len(), 1);
+    assert_eq!(report.files[0].path, Path::new("/mnt/share/large"));
+    assert!(fs.is_locked(Path::new("/mnt/share/small")).unwrap());
+}
+
+#[test]
+fn repeatedly_failing_file_is_quarantined() {
+    let fs = MemoryFs::new();
+    fs.add_dir("/mnt/quarantine");
+    fs.add_file("/mnt/quarantine/a", b"older");
+    fs.add_locked_file("/mnt/share/a", b"a");
+    fs.add_locked_file("/mnt/share/b", b"b");
+    fs.fail_path(Operation::Copy, "/mnt/share/a", ErrorKind::PermissionDenied);
+
+    let report = Repairer::new(
+        &fs,
+        &fs,
+        RepairOptions {
+            quarantine_dir: Some("/mnt/quarantine".into()),
+            ..RepairOptions::default()
+        },
+    )
+    .repair_directory(Path::new("/mnt/share"), false)
+    .unwrap();
+
+    assert_eq!(report.quarantined(), 1);
+    assert_eq!(report.repaired(), 1);
+    assert!(matches!(
+        &report.files[0].outcome,
+        FileOutcome::Quarantined { destination, .. } if destination == Path::new("/mnt/quarantine/a.1")
+    ));
+    assert!(fs.contents("/mnt/share/a").is_none());
+    assert_eq!(fs.contents("/mnt/quarantine/a.1").unwrap(), b"a");
+    assert_eq!(fs.contents("/mnt/quarantine/a").unwrap(), b"older");
+}
+
+#[test]
+fn quarantine_copies_across_filesystems_and_removes_the_original() {
+    let fs = MemoryFs::new();
+    fs.add_dir("/mnt/quarantine");
+    fs.add_locked_file("/mnt/share/a", b"a");
+    fs.fail_path_times(
+        Operation::Copy,
+        "/mnt/share/a",
+        ErrorKind::PermissionDenied,
+        1,
+    );
+    fs.fail_path(Operation::Rename, "/mnt/share/a", ErrorKind::CrossesDevices);
+
+    let report = Repairer::new(
+        &fs,
+        &fs,
+        RepairOptions {
+            quarantine_dir: Some("/mnt/quarantine".into()),
+            quarantine_after: 1,
+            ..RepairOptions::default()
+        },
+    )
+    .repair_file(Path::new("/mnt/share/a"))
+    .unwrap();
+
+    assert_eq!(report.quarantined(), 1);
+    assert!(fs.contents("/mnt/share/a").is_none());
+    assert_eq!(fs.contents("/mnt/quarantine/a").unwrap(), b"a");
+}
+
+#[test]
+fn failed_quarantine_move_leaves_the_file_in_place() {
+    let fs = MemoryFs::new();
+    fs.add_dir("/mnt/quarantine");
+    fs.add_locked_file("/mnt/share/a", b"a");
+    fs.fail_path(Operation::Copy, "/mnt/share/a", ErrorKind::PermissionDenied);
+    fs.fail_path(
+        Operation::Rename,
+        "/mnt/share/a",
+        ErrorKind::PermissionDenied,
+    );
+
+    let report = Repairer::new(
+        &fs,
+        &fs,
+        RepairOptions {
+            quarantine_dir: Some("/mnt/quarantine".into()),
+            quarantine_after: 1,
+            ..RepairOptions::default()
+        },
+    )
+    .repair_file(Path::new("/mnt/share/a"))
+    .unwrap();
+
+    assert_eq!(report.quarantined(), 0);
+    assert!(matches!(
+        report.files[0].outcome,
+        FileOutcome::Failed(RepairError::Io(_))
+    ));
+    assert_eq!(fs.contents("/mnt/share/a").unwrap(), b"a");
+    assert!(fs.contents("/mnt/quarantine/a").is_none());
+}
+
+#[test]
+fn hung_file_times_out_and_sweep_continues() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/a", b"a");
+    fs.add_locked_file("/mnt/share/b", b"b");
+    fs.delay_path(Operation::Copy, "/mnt/share/a", Duration::from_secs(10));
+
+    let started = Instant::now();
+    let report = Repairer::new(
+        &fs,
+        &fs,
+        RepairOptions {
+            file_timeout: Some(Duration::from_millis(100)),
+            ..RepairOptions::default()
+        },
+    )
+    .repair_directory(Path::new("/mnt/share"), false)
+    .unwrap();
+
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert_eq!(report.timed_out(), 1);
+    assert_eq!(report.repaired(), 1);
+    assert!(matches!(report.files[0].outcome, FileOutcome::TimedOut));
+    assert_eq!(fs.contents("/mnt/share/a").unwrap(), b"a");
+    assert!(leftovers(&fs).is_empty());
+}
+
+#[test]
+fn stale_handle_is_retried_after_re_resolving() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/data.db", b"data");
+    fs.fail_path_times(
+        Operation::Rename,
+        "/mnt/share/.netfs-unlker.data.db.tmp",
+        ErrorKind::StaleNetworkFileHandle,
+        2,
+    );
+
+    let report = repairer(&fs)
+        .repair_file(Path::new("/mnt/share/data.db"))
+        .unwrap();
+
+    assert!(matches!(report.files[0].outcome, FileOutcome::Repaired));
+    assert_eq!(fs.contents("/mnt/share/data.db").unwrap(), b"data");
+}
+
+#[test]
+fn persistently_stale_handle_is_reported() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/data.db", b"data");
+    fs.fail_path(
+        Operation::Copy,
+        "/mnt/share/data.db",
+        ErrorKind::StaleNetworkFileHandle,
+    );
+
+    let report = repairer(&fs)
+        .repair_file(Path::new("/mnt/share/data.db"))
+        .unwrap();
+
+    assert!(matches!(
+        report.files[0].outcome,
+        FileOutcome::Failed(RepairError::StaleHandle(_))
+    ));
+    assert_eq!(fs.contents("/mnt/share/data.db").unwrap(), b"data");
+    assert!(leftovers(&fs).is_empty());
+}
+
+#[test]
+fn file_with_live_lock_holder_is_skipped_unless_forced() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/data.db", b"data");
+    fs.set_holder_alive("/mnt/share/data.db");
+
+    let report = repairer(&fs)
+        .repair_file(Path::new("/mnt/share/data.db"))
+        .unwrap();
+    assert!(matches!(
+        report.files[0].outcome,
+        FileOutcome::Skipped(SkipReason::LockHolderAlive)
+    ));
+    assert!(fs.is_locked(Path::new("/mnt/share/data.db")).unwrap());
+
+    let report = Repairer::new(
+        &fs,
+        &fs,
+        RepairOptions {
+            force: true,
+            ..RepairOptions::default()
+        },
+    )
+    .repair_file(Path::new("/mnt/share/data.db"))
+    .unwrap();
+    assert!(matches!(report.files[0].outcome, FileOutcome::Repaired));
+    assert!(!fs.is_locked(Path::new("/mnt/share/data.db")).unwrap());
+}
+
+struct FixedStatus(HolderStatus);
+
+impl LockStatusQuery for FixedStatus {
+    fn holder_status(&self, _path: &Path) -> std::io::Result<HolderStatus> {
+        Ok(self.0)
+    }
+}
+
+#[test]
+fn only_stale_locks_are_repaired_with_a_lock_status_query() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/stale", b"stale");
+    fs.add_locked_file("/mnt/share/live", b"live");
+    fs.add_locked_file("/mnt/share/unknown", b"unknown");
+
+    let repair = |path: &str, status: HolderStatus, force: bool| {
+        let options = RepairOptions {
+            force,
+            ..RepairOptions::default()
+        };
+        Repairer::new(&fs, &fs, options)
+            .with_lock_status(FixedStatus(status))
+            .repair_file(Path::new(path))
+            .unwrap()
+            .files
+            .remove(0)
+            .outcome
+    };
+
+    assert!(matches!(
+        repair("/mnt/share/stale", HolderStatus::Stale, false),
+        FileOutcome::Repaired
+    ));
+    assert!(matches!(
+        repair("/mnt/share/live", HolderStatus::Live, false),
+        FileOutcome::Skipped(SkipReason::LockHolderAlive)
+    ));
+    assert!(matches!(
+        repair("/mnt/share/unknown", HolderStatus::Unknown, false),
+        FileOutcome::Skipped(SkipReason::LockNotStale)
+    ));
+    assert!(fs.is_locked(Path::new("/mnt/share/unknown")).unwrap());
+    assert!(matches!(
+        repair("/mnt/share/unknown", HolderStatus::Unknown, true),
+        FileOutcome::Repaired
+    ));
+}
+
+#[test]
+fn statistics_cover_locked_files_and_copied_bytes() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/a", b"0123456789");
+    fs.add_file("/mnt/share/b", b"b");
+    fs.delay_path(Operation::Copy, "/mnt/share/a", Duration::from_millis(20));
+
+    let report = repairer(&fs)
+        .repair_directory(Path::new("/mnt/share"), false)
+        .unwrap();
+    let statistics = report.statistics(1);
+
+    assert_eq!(statistics.summary.total, 2);
+    assert_eq!(statistics.locked, 1);
+    assert_eq!(statistics.summary.repaired, 1);
+    // Copied to the staging directory and back
+    assert_eq!(statistics.bytes_copied, 20);
+    assert!(statistics.elapsed >= Duration::from_millis(20));
+    assert_eq!(statistics.slowest.len(), 1);
+    assert_eq!(statistics.slowest[0].path, Path::new("/mnt/share/a"));
+    assert_eq!(statistics.slowest[0].outcome, "repaired");
+}
+
+#[test]
+fn repair_report_csv_has_one_row_per_file() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/a", b"0123456789");
+    fs.add_file("/mnt/share/b", b"b");
+    fs.add_locked_file("/mnt/share/c", b"c");
+    fs.fail_path(Operation::Copy, "/mnt/share/c", ErrorKind::PermissionDenied);
+
+    let report = repairer(&fs)
+        .repair_directory(Path::new("/mnt/share"), false)
+        .unwrap();
+    let mut csv = Vec::new();
+    report.write_csv(&mut csv).unwrap();
+    let csv = String::from_utf8(csv).unwrap();
+    let rows: Vec<Vec<&str>> = csv.lines().map(|l| l.split(',').collect()).collect();
+
+    assert_eq!(
+        rows[0],
+        [
+            "path",
+            "size",
+            "lock_type",
+            "pid",
+            "action",
+            "duration",
+            "error",
+            "lock_kind"
+        ]
+    );
+    assert_eq!(rows.len(), 4);
+    let row = |path: &str| rows.iter().find(|row| row[0] == path).unwrap();
+    assert_eq!(
+        row("/mnt/share/a")[1..5],
+        ["10", "exclusive", "", "repaired"]
+    );
+    assert_eq!(row("/mnt/share/a")[6..], ["", "record"]);
+    assert_eq!(row("/mnt/share/b")[1..5], ["1", "", "", "not_locked"]);
+    assert_eq!(row("/mnt/share/c")[4], "failed");
+    assert!(!row("/mnt/share/c")[6].is_empty());
+}
+
+#[test]
+fn unreadable_subdirectory_is_reported_and_sweep_continues() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/a", b"a");
+    fs.add_locked_file("/mnt/share/private/b", b"b");
+    fs.add_locked_file("/mnt/share/public/c", b"c");
+    fs.fail_path(
+        Operation::ReadDir,
+        "/mnt/share/private",
+        ErrorKind::PermissionDenied,
+    );
+
+    let report = repairer(&fs)
+        .repair_directory(Path::new("/mnt/share"), true)
+        .unwrap();
+
+    assert_eq!(report.repaired(), 2);
+    assert_eq!(report.failed(), 1);
+    let unreadable = report
+        .files
+        .iter()
+        .find(|file| file.path == Path::new("/mnt/share/private"))
+        .unwrap();
+    assert!(matches!(
+        unreadable.outcome,
+        FileOutcome::Failed(RepairError::PermissionDenied(_))
+    ));
+
+    let stopped = Repairer::new(
+        &fs,
+        &fs,
+        RepairOptions {
+            stop_on_error: true,
+            ..RepairOptions::default()
+        },
+    )
+    .repair_directory(Path::new("/mnt/share"), true);
+    assert_eq!(stopped.unwrap_err().kind(), ErrorKind::PermissionDenied);
+}
+
+#[cfg(unix)]
+#[test]
+fn repairs_file_with_non_utf8_name() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let fs = MemoryFs::new();
+    let path = Path::new("/mnt/share").join(OsStr::from_bytes(b"data-\xff.db"));
+    fs.add_locked_file(&path, b"data");
+
+    let report = repairer(&fs).repair_file(&path).unwrap();
+
+    assert!(matches!(report.files[0].outcome, FileOutcome::Repaired));
+    assert_eq!(fs.contents(&path).unwrap(), b"data");
+    assert!(!fs.is_locked(&path).unwrap());
+    assert!(leftovers(&fs).is_empty());
+}
+
+#[test]
+fn move_strategy_falls_back_to_copy_across_filesystems() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/data.db", b"data");
+
+    let report = Repairer::new(
+        &fs,
+        &fs,
+        RepairOptions {
+            verify_checksum: true,
+            copy_strategy: CopyStrategy::Move,
+            ..RepairOptions::default()
+        },
+    )
+    .repair_file(Path::new("/mnt/share/data.db"))
+    .unwrap();
+
+    assert!(matches!(report.files[0].outcome, FileOutcome::Repaired));
+    assert_eq!(fs.contents("/mnt/share/data.db").unwrap(), b"data");
+    assert!(leftovers(&fs).is_empty());
+}
+
+fn auto_repairer(fs: &MemoryFs, in_memory_threshold: u64) -> Repairer<&MemoryFs, &MemoryFs> {
+    Repairer::new(
+        fs,
+        fs,
+        RepairOptions {
+            verify_checksum: true,
+            copy_strategy: CopyStrategy::Auto {
+                in_memory_threshold,
+            },
+            ..RepairOptions::default()
+        },
+    )
+}
+
+#[test]
+fn auto_strategy_repairs_small_files_without_staging() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/small.cfg", b"data");
+    fs.add_locked_file("/mnt/share/large.db", &[7; 64]);
+    fs.fail(Operation::CreateStagingDir, ErrorKind::PermissionDenied);
+
+    let report = auto_repairer(&fs, 16)
+        .repair_directory(Path::new("/mnt/share"), false)
+        .unwrap();
+
+    let outcome = |path| {
+        &report
+            .files
+            .iter()
+            .find(|file| file.path == Path::new(path))
+            .unwrap()
+            .outcome
+    };
+    assert!(matches!(
+        outcome("/mnt/share/small.cfg"),
+        FileOutcome::Repaired
+    ));
+    assert!(!fs.is_locked(Path::new("/mnt/share/small.cfg")).unwrap());
+    assert_eq!(fs.contents("/mnt/share/small.cfg").unwrap(), b"data");
+    // Only the large file needs the staging directory
+    assert!(matches!(
+        outcome("/mnt/share/large.db"),
+        FileOutcome::Failed(_)
+    ));
+    assert!(fs.is_locked(Path::new("/mnt/share/large.db")).unwrap());
+    assert!(leftovers(&fs).is_empty());
+}
+
+#[test]
+fn auto_strategy_stages_when_the_backend_cannot_write_files() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/small.cfg", b"data");
+    fs.fail(Operation::Write, ErrorKind::Unsupported);
+
+    let report = auto_repairer(&fs, 16)
+        .repair_file(Path::new("/mnt/share/small.cfg"))
+        .unwrap();
+
+    assert!(matches!(report.files[0].outcome, FileOutcome::Repaired));
+    assert_eq!(fs.contents("/mnt/share/small.cfg").unwrap(), b"data");
+    assert!(leftovers(&fs).is_empty());
+}
+
+fn nfs4_acl() -> Acl {
+    Acl {
+        kind: AclKind::Nfs4,
+        data: b"\x00\x00\x00\x01owner@".to_vec(),
+    }
+}
+
+#[test]
+fn acl_is_carried_over_to_the_repaired_file() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/data.db", b"data");
+    fs.set_acl("/mnt/share/data.db", nfs4_acl());
+
+    let report = repairer(&fs)
+        .repair_file(Path::new("/mnt/share/data.db"))
+        .unwrap();
+
+    assert!(matches!(report.files[0].outcome, FileOutcome::Repaired));
+    assert_eq!(fs.acl("/mnt/share/data.db"), Some(nfs4_acl()));
+}
+
+#[test]
+fn original_is_kept_when_the_acl_cannot_be_carried_over() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/data.db", b"data");
+    fs.set_acl("/mnt/share/data.db", nfs4_acl());
+    fs.fail(Operation::WriteAcl, ErrorKind::PermissionDenied);
+
+    let report = repairer(&fs)
+        .repair_file(Path::new("/mnt/share/data.db"))
+        .unwrap();
+
+    assert!(matches!(report.files[0].outcome, FileOutcome::Failed(_)));
+    assert!(fs.is_locked(Path::new("/mnt/share/data.db")).unwrap());
+    assert!(leftovers(&fs).is_empty());
+}
+
+#[test]
+fn acl_is_dropped_when_skipped() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/data.db", b"data");
+    fs.set_acl("/mnt/share/data.db", nfs4_acl());
+    fs.fail(Operation::ReadAcl, ErrorKind::Other);
+
+    let report = Repairer::new(
+        &fs,
+        &fs,
+        RepairOptions {
+            preserve_acls: false,
+            ..RepairOptions::default()
+        },
+    )
+    .repair_file(Path::new("/mnt/share/data.db"))
+    .unwrap();
+
+    assert!(matches!(report.files[0].outcome, FileOutcome::Repaired));
+    assert_eq!(fs.acl("/mnt/share/data.db"), None);
+}
+
+#[test]
+fn original_is_kept_when_the_directory_cannot_be_synced() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/data.db", b"data");
+    fs.fail_path(Operation::Sync, "/mnt/share", ErrorKind::Other);
+
+    let report = repairer(&fs)
+        .repair_file(Path::new("/mnt/share/data.db"))
+        .unwrap();
+
+    assert!(matches!(report.files[0].outcome, FileOutcome::Failed(_)));
+    assert_eq!(fs.contents("/mnt/share/data.db").unwrap(), b"data");
+    assert!(fs.is_locked(Path::new("/mnt/share/data.db")).unwrap());
+}
+
+#[test]
+fn nothing_is_synced_without_fsync() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/data.db", b"data");
+    fs.fail(Operation::Sync, ErrorKind::Other);
+
+    let report = Repairer::new(
+        &fs,
+        &fs,
+        RepairOptions {
+            fsync: false,
+            ..RepairOptions::default()
+        },
+    )
+    .repair_file(Path::new("/mnt/share/data.db"))
+    .unwrap();
+
+    assert!(matches!(report.files[0].outcome, FileOutcome::Repaired));
+    assert_eq!(fs.contents("/mnt/share/data.db").unwrap(), b"data");
+}
+
+#[test]
+fn sweep_repairs_files_in_the_requested_order() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/a", b"123");
+    fs.add_locked_file("/mnt/share/b", b"1");
+    fs.add_locked_file("/mnt/share/c", b"12");
+    let order = |report: &RepairReport| -> Vec<String> {
+        report
+            .files
+            .iter()
+            .map(|file| file.path.display().to_string())
+            .collect()
+    };
+
+    let report = Repairer::new(
+        &fs,
+        &fs,
+        RepairOptions {
+            sort_order: SortOrder::SizeAsc,
+            ..RepairOptions::default()
+        },
+    )
+    .repair_directory(Path::new("/mnt/share"), false)
+    .unwrap();
+    assert_eq!(
+        order(&report),
+        ["/mnt/share/b", "/mnt/share/c", "/mnt/share/a"]
+    );
+
+    fs.add_locked_file("/mnt/share/a", b"123");
+    fs.add_locked_file("/mnt/share/b", b"1");
+    fs.add_locked_file("/mnt/share/c", b"12");
+    let report = repairer(&fs)
+        .with_comparator(|a, b| b.path.cmp(&a.path))
+        .repair_directory(Path::new("/mnt/share"), false)
+        .unwrap();
+    assert_eq!(
+        order(&report),
+        ["/mnt/share/c", "/mnt/share/b", "/mnt/share/a"]
+    );
+    assert_eq!(report.repaired(), 3);
+}
+
+#[test]
+fn several_targets_are_repaired_into_one_report() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/one/a", b"123");
+    fs.add_locked_file("/mnt/two/b", b"1");
+    fs.add_locked_file("/mnt/three/c", b"12");
+
+    let targets = [
+        Target::Directory("/mnt/one".into()),
+        Target::Directory("/mnt/two".into()),
+        // Also reached through a directory, but repaired once
+        Target::File("/mnt/two/b".into()),
+        Target::File("/mnt/three/c".into()),
+    ];
+    let report = Repairer::new(
+        &fs,
+        &fs,
+        RepairOptions {
+            sort_order: SortOrder::SizeDesc,
+            ..RepairOptions::default()
+        },
+    )
+    .repair_targets(&targets, false)
+    .unwrap();
+
+    let order: Vec<_> = report
+        .files
+        .iter()
+        .map(|file| file.path.display().to_string())
+        .collect();
+    // Files first, then the directories sorted together
+    assert_eq!(order, ["/mnt/two/b", "/mnt/three/c", "/mnt/one/a"]);
+    assert_eq!(report.repaired(), 3);
+}
+
+#[test]
+fn missing_target_fails_the_run_before_any_repair() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/a", b"a");
+
+    let targets = [
+        Target::Directory("/mnt/share".into()),
+        Target::File("/mnt/missing".into()),
+    ];
+    assert!(repairer(&fs).repair_targets(&targets, false).is_err());
+    assert!(fs.is_locked(Path::new("/mnt/share/a")).unwrap());
+}
+
+#[test]
+fn progress_events_follow_the_repair() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/a", b"0123456789");
+    fs.add_locked_file("/mnt/share/b", b"b");
+    fs.fail_path(Operation::Copy, "/mnt/share/b", ErrorKind::PermissionDenied);
+
+    let events = Rc::new(RefCell::new(Vec::new()));
+    let observed = Rc::clone(&events);
+    repairer(&fs)
+        .with_progress_observer(move |event: &ProgressEvent<'_>| {
+            observed.borrow_mut().push(match event {
+                ProgressEvent::FileStarted { path } => format!("start {}", path.display()),
+                ProgressEvent::StageChanged { stage, .. } => format!("stage {}", stage),
+                ProgressEvent::BytesCopied { bytes, .. } => format!("copied {}", bytes),
+                ProgressEvent::FileFinished { path, .. } => format!("finished {}", path.display()),
+                ProgressEvent::FileFailed { path, .. } => format!("failed {}", path.display()),
+            })
+        })
+        .repair_directory(Path::new("/mnt/share"), false)
+        .unwrap();
+
+    let events = events.borrow();
+    assert_eq!(events.first().unwrap(), "start /mnt/share/a");
+    assert!(events.contains(&"stage copy_back".to_string()));
+    assert_eq!(
+        events.iter().filter(|e| *e == "copied 10").count(),
+        2,
+        "{:?}",
+        events
+    );
+    assert!(events.contains(&"finished /mnt/share/a".to_string()));
+    assert_eq!(events.last().unwrap(), "failed /mnt/share/b");
+}
+
+fn lease_breaking_repairer(fs: &MemoryFs) -> Repairer<&MemoryFs, &MemoryFs> {
+    Repairer::new(
+        fs,
+        fs,
+        RepairOptions {
+            smb_leases: true,
+            ..RepairOptions::default()
+        },
+    )
+}
+
+#[test]
+fn refused_lease_probe_is_not_a_lock_by_default() {
+    // A mount without read-caching oplocks refuses the lease probe for every file
+    let fs = MemoryFs::new();
+    fs.add_leased_file("/mnt/share/data.db", b"payload");
+    let inode = fs.metadata(Path::new("/mnt/share/data.db")).unwrap().inode;
+
+    let report = repairer(&fs)
+        .repair_file(Path::new("/mnt/share/data.db"))
+        .unwrap();
+
+    assert!(matches!(report.files[0].outcome, FileOutcome::NotLocked));
+    assert!(!fs.is_locked(Path::new("/mnt/share/data.db")).unwrap());
+    assert_eq!(fs.lock_info(Path::new("/mnt/share/data.db")).unwrap(), None);
+    assert_eq!(
+        fs.metadata(Path::new("/mnt/share/data.db")).unwrap().inode,
+        inode
+    );
+    let scan = Scanner::new(&fs, &fs)
+        .scan_file(Path::new("/mnt/share/data.db"))
+        .unwrap();
+    assert!(scan.locked.is_empty());
+}
+
+#[test]
+fn smb_lease_is_broken_without_replacing_the_file() {
+    let fs = MemoryFs::new();
+    fs.add_leased_file("/mnt/share/data.db", b"payload");
+    let inode = fs.metadata(Path::new("/mnt/share/data.db")).unwrap().inode;
+
+    let report = lease_breaking_repairer(&fs)
+        .repair_file(Path::new("/mnt/share/data.db"))
+        .unwrap();
+
+    assert!(matches!(report.files[0].outcome, FileOutcome::Repaired));
+    assert_eq!(
+        report.files[0].lock.as_ref().unwrap().kind,
+        LockKind::SmbLease
+    );
+    assert_eq!(
+        fs.metadata(Path::new("/mnt/share/data.db")).unwrap().inode,
+        inode
+    );
+    assert!(!fs.is_leased(Path::new("/mnt/share/data.db")).unwrap());
+}
+
+#[test]
+fn failed_lease_break_leaves_the_file_in_place() {
+    let fs = MemoryFs::new();
+    fs.add_leased_file("/mnt/share/data.db", b"payload");
+    fs.fail(Operation::BreakLease, ErrorKind::Unsupported);
+    let inode = fs.metadata(Path::new("/mnt/share/data.db")).unwrap().inode;
+
+    let report = lease_breaking_repairer(&fs)
+        .repair_file(Path::new("/mnt/share/data.db"))
+        .unwrap();
+
+    assert!(matches!(
+        report.files[0].outcome,
+        FileOutcome::Failed(RepairError::LeaseBreak(_))
+    ));
+    assert_eq!(
+        fs.metadata(Path::new("/mnt/share/data.db")).unwrap().inode,
+        inode
+    );
+    assert_eq!(fs.contents("/mnt/share/data.db").unwrap(), b"payload");
+}
+
+/// POSIX ACL in the xattr encoding of the kernel, granting `nobody` read access.
+#[cfg(target_os = "linux")]
+fn posix_acl() -> Vec<u8> {
+    let mut data = 2u32.to_le_bytes().to_vec();
+    for (tag, perm, id) in [
+        (0x01u16, 6u16, u32::MAX), // ACL_USER_OBJ
+        (0x02, 4, 65534),          // ACL_USER
+        (0x04, 4, u32::MAX),       // ACL_GROUP_OBJ
+        (0x10, 4, u32::MAX),       // ACL_MASK
+        (0x20, 4, u32::MAX),       // ACL_OTHER
+    ] {
+        data.extend(tag.to_le_bytes());
+        data.extend(perm.to_le_bytes());
+        data.extend(id.to_le_bytes());
+    }
+    data
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn native_acl_round_trips_through_extended_attributes() {
+    use netfs_unlker::backend::NativeFs;
+
+    let dir = tempfile::tempdir().unwrap();
+    let (original, copy) = (dir.path().join("original"), dir.path().join("copy"));
+    std::fs::write(&original, b"data").unwrap();
+    std::fs::write(&copy, b"data").unwrap();
+
+    let fs = NativeFs::default();
+    assert_eq!(fs.read_acl(&copy).unwrap(), None);
+    let acl = Acl {
+        kind: AclKind::Posix,
+        data: posix_acl(),
+    };
+    match fs.write_acl(&original, &acl) {
+        Ok(()) => {}
+        // The temporary directory may live on a filesystem without ACL support
+        Err(e) if e.raw_os_error() == Some(libc::EOPNOTSUPP) => return,
+        Err(e) => panic!("failed to write the ACL: {}", e),
+    }
+
+    let read = fs.read_acl(&original).unwrap().unwrap();
+    fs.write_acl(&copy, &read).unwrap();
+    assert_eq!(fs.read_acl(&copy).unwrap(), Some(read));
+}
+
+#[test]
+fn timed_out_file_leaves_no_temp_file() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/a", b"a");
+    let options = RepairOptions {
+        file_timeout: Some(Duration::from_millis(100)),
+        ..RepairOptions::default()
+    };
+    let tmp_file_path =
+        Path::new("/mnt/share").join(options.temp_naming.temp_name(OsStr::new("a")));
+    fs.delay_path(Operation::Sync, &tmp_file_path, Duration::from_secs(10));
+    // Bound by the deadline like every call of a native backend
+    fs.delay_path(Operation::RemoveFile, &tmp_file_path, Duration::ZERO);
+
+    let report = Repairer::new(&fs, &fs, options)
+        .repair_file(Path::new("/mnt/share/a"))
+        .unwrap();
+
+    assert!(matches!(report.files[0].outcome, FileOutcome::TimedOut));
+    assert_eq!(fs.contents("/mnt/share/a").unwrap(), b"a");
+    assert!(leftovers(&fs).is_empty());
+}
@@ -0,0 +1,39 @@
+use netfs_unlker::run_guard::RunGuard;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::Path;
+use std::process;
+
+#[test]
+fn second_run_is_refused_while_the_lock_is_held() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("run.lock");
+
+    let guard = RunGuard::acquire(&path, false).unwrap();
+    assert_eq!(guard.path(), path);
+    assert_eq!(
+        fs::read_to_string(&path).unwrap().trim(),
+        process::id().to_string()
+    );
+
+    let err = RunGuard::acquire(&path, false).unwrap_err();
+    assert_eq!(err.kind(), ErrorKind::WouldBlock);
+
+    drop(guard);
+    RunGuard::acquire(&path, false).unwrap();
+}
+
+#[test]
+fn default_path_is_the_same_for_every_spelling_of_the_target() {
+    let dir = tempfile::tempdir().unwrap();
+    let spelled = dir.path().join(".");
+
+    assert_eq!(
+        RunGuard::default_path(dir.path()),
+        RunGuard::default_path(&spelled)
+    );
+    assert_ne!(
+        RunGuard::default_path(dir.path()),
+        RunGuard::default_path(Path::new("/mnt/other"))
+    );
+}
@@ -0,0 +1,30 @@
+#![cfg(all(target_os = "linux", feature = "io-uring"))]
+
+use netfs_unlker::backend::{FileOps, NativeFs};
+use std::fs;
+
+#[test]
+fn copies_files_larger_than_the_buffers() {
+    let dir = tempfile::tempdir().unwrap();
+    let (from, to) = (dir.path().join("from"), dir.path().join("to"));
+    // Not a multiple of the buffer size, and more than fits in one batch of buffers
+    let data: Vec<u8> = (0..9 * 1024 * 1024 + 12345)
+        .map(|i| (i % 251) as u8)
+        .collect();
+    fs::write(&from, &data).unwrap();
+
+    let copied = NativeFs::default().copy(&from, &to).unwrap();
+
+    assert_eq!(copied, data.len() as u64);
+    assert_eq!(fs::read(&to).unwrap(), data);
+}
+
+#[test]
+fn copies_empty_files() {
+    let dir = tempfile::tempdir().unwrap();
+    let (from, to) = (dir.path().join("from"), dir.path().join("to"));
+    fs::write(&from, b"").unwrap();
+
+    assert_eq!(NativeFs::default().copy(&from, &to).unwrap(), 0);
+    assert!(fs::read(&to).unwrap().is_empty());
+}
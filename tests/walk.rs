@@ -0,0 +1,217 @@
+use netfs_unlker::backend::{LockOps, NativeFs, NativeLocks};
+use netfs_unlker::mock::{MemoryFs, Operation};
+use netfs_unlker::scan::Scanner;
+use netfs_unlker::walk::{LockedFileIter, Traversal, WalkOrder};
+use netfs_unlker::{FileOutcome, RepairOptions, Repairer, SkipReason};
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+#[test]
+fn yields_locked_files_only() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/a", b"a");
+    fs.add_file("/mnt/share/b", b"b");
+    fs.add_locked_file("/mnt/share/sub/c", b"c");
+
+    let mut found: Vec<PathBuf> = LockedFileIter::new(&fs, &fs, Path::new("/mnt/share"), true)
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    found.sort();
+
+    assert_eq!(
+        found,
+        [
+            PathBuf::from("/mnt/share/a"),
+            PathBuf::from("/mnt/share/sub/c")
+        ]
+    );
+}
+
+#[test]
+fn unreadable_subdirectory_is_yielded_as_an_error() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/private/a", b"a");
+    fs.add_locked_file("/mnt/share/public/b", b"b");
+    fs.fail_path(
+        Operation::ReadDir,
+        "/mnt/share/private",
+        ErrorKind::PermissionDenied,
+    );
+
+    let entries: Vec<_> = LockedFileIter::new(&fs, &fs, Path::new("/mnt/share"), true)
+        .unwrap()
+        .collect();
+
+    assert_eq!(entries.len(), 2);
+    let error = entries.iter().find_map(|e| e.as_ref().err()).unwrap();
+    assert_eq!(error.path, Path::new("/mnt/share/private"));
+    assert_eq!(error.error.kind(), ErrorKind::PermissionDenied);
+    assert!(entries.iter().any(|e| e
+        .as_ref()
+        .is_ok_and(|p| p == Path::new("/mnt/share/public/b"))));
+}
+
+#[test]
+fn missing_root_is_an_error() {
+    let fs = MemoryFs::new();
+
+    assert!(LockedFileIter::new(&fs, &fs, Path::new("/mnt/missing"), true).is_err());
+}
+
+#[test]
+fn depth_first_walk_finishes_a_subtree_before_its_siblings() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/a/deep/x", b"x");
+    fs.add_locked_file("/mnt/share/b/y", b"y");
+    fs.add_locked_file("/mnt/share/z", b"z");
+
+    let walk = |traversal| -> Vec<PathBuf> {
+        let order = WalkOrder {
+            traversal,
+            sorted: true,
+        };
+        LockedFileIter::with_order(&fs, &fs, Path::new("/mnt/share"), true, order)
+            .unwrap()
+            .map(Result::unwrap)
+            .collect()
+    };
+
+    assert_eq!(
+        walk(Traversal::BreadthFirst),
+        [
+            PathBuf::from("/mnt/share/z"),
+            PathBuf::from("/mnt/share/b/y"),
+            PathBuf::from("/mnt/share/a/deep/x")
+        ]
+    );
+    assert_eq!(
+        walk(Traversal::DepthFirst),
+        [
+            PathBuf::from("/mnt/share/a/deep/x"),
+            PathBuf::from("/mnt/share/b/y"),
+            PathBuf::from("/mnt/share/z")
+        ]
+    );
+}
+
+#[test]
+fn ordered_walk_repairs_in_name_order() {
+    let dir = tempfile::tempdir().unwrap();
+    let names = ["m", "c", "x", "a", "q", "f", "b", "z", "k"];
+    for name in names {
+        fs::write(dir.path().join(name), name).unwrap();
+    }
+
+    let repairer = Repairer::new(
+        NativeFs::default(),
+        NativeLocks::default(),
+        RepairOptions {
+            allow_local: true,
+            walk_order: WalkOrder {
+                traversal: Traversal::DepthFirst,
+                sorted: true,
+            },
+            ..RepairOptions::default()
+        },
+    );
+    let report = repairer.repair_directory(dir.path(), true).unwrap();
+
+    let order: Vec<PathBuf> = report.files.iter().map(|file| file.path.clone()).collect();
+    let mut sorted = names.map(|name| dir.path().join(name)).to_vec();
+    sorted.sort();
+    assert_eq!(order, sorted);
+}
+
+#[test]
+fn sweep_kept_on_one_filesystem_skips_other_mounts() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/a", b"a");
+    fs.add_mount("/mnt/share/backup");
+    fs.add_locked_file("/mnt/share/backup/b", b"b");
+
+    let report = Repairer::new(
+        &fs,
+        &fs,
+        RepairOptions {
+            one_file_system: true,
+            ..RepairOptions::default()
+        },
+    )
+    .repair_directory(Path::new("/mnt/share"), true)
+    .unwrap();
+
+    assert_eq!(report.repaired(), 1);
+    assert_eq!(report.files.len(), 2);
+    let crossing = &report.files[1];
+    assert_eq!(crossing.path, Path::new("/mnt/share/backup"));
+    assert!(matches!(
+        crossing.outcome,
+        FileOutcome::Skipped(SkipReason::OtherFilesystem)
+    ));
+    assert!(fs.is_locked(Path::new("/mnt/share/backup/b")).unwrap());
+
+    // A sweep started on the mount itself stays inside it
+    let report = Repairer::new(
+        &fs,
+        &fs,
+        RepairOptions {
+            one_file_system: true,
+            ..RepairOptions::default()
+        },
+    )
+    .repair_directory(Path::new("/mnt/share/backup"), true)
+    .unwrap();
+    assert_eq!(report.repaired(), 1);
+}
+
+#[test]
+fn scan_kept_on_one_filesystem_records_skipped_mounts() {
+    let fs = MemoryFs::new();
+    fs.add_locked_file("/mnt/share/a", b"a");
+    fs.add_mount("/mnt/share/backup");
+    fs.add_locked_file("/mnt/share/backup/b", b"b");
+
+    let report = Scanner::new(&fs, &fs)
+        .scan_directory(Path::new("/mnt/share"), true)
+        .unwrap();
+    assert_eq!(report.locked.len(), 2);
+    assert!(report.skipped.is_empty());
+
+    let report = Scanner::new(&fs, &fs)
+        .with_one_file_system(true)
+        .scan_directory(Path::new("/mnt/share"), true)
+        .unwrap();
+    assert_eq!(report.locked.len(), 1);
+    assert_eq!(report.skipped.len(), 1);
+    assert_eq!(report.skipped[0].path, Path::new("/mnt/share/backup"));
+}
+
+#[test]
+fn fast_scan_finds_the_same_locks_in_path_order() {
+    let fs = MemoryFs::new();
+    for i in 0..20 {
+        fs.add_file(format!("/mnt/share/{:02}", i), b"idle");
+    }
+    fs.add_locked_file("/mnt/share/sub/b", b"busy");
+    fs.add_locked_file("/mnt/share/a", b"busy");
+    fs.fail_path(
+        Operation::LockInfo,
+        "/mnt/share/07",
+        ErrorKind::PermissionDenied,
+    );
+
+    let scanner = Scanner::new(&fs, &fs);
+    let report = scanner
+        .scan_directory_fast(Path::new("/mnt/share"), true, 4.try_into().unwrap())
+        .unwrap();
+    let locked: Vec<&Path> = report.locked.iter().map(|f| f.path.as_path()).collect();
+    assert_eq!(
+        locked,
+        [Path::new("/mnt/share/a"), Path::new("/mnt/share/sub/b")]
+    );
+    assert_eq!(report.locked[1].size, 4);
+    assert_eq!(report.scanned, 22);
+    assert_eq!(report.errors.len(), 1);
+}